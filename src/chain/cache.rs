@@ -41,6 +41,14 @@ impl<Base: ChainState> ChainState for Cache<Base> {
             .clone()
     }
 
+    fn block_hash(&self, number: u64) -> U256 {
+        self.state_set_mut()
+            .block_hashes
+            .entry(number)
+            .or_insert_with(|| self.base.block_hash(number))
+            .clone()
+    }
+
     fn nonce(&self, address: &U256) -> usize {
         *self
             .state_set_mut()
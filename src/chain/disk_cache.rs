@@ -0,0 +1,249 @@
+//! Disk cached chain
+//!
+//! Takes a given read-only chain implementation pinned to a block and
+//! persists balance, code, storage and block hash reads to a directory. State
+//! at a historical block never changes, so the cache is never invalidated and
+//! can be shared between runs. The directory is named after the block hash,
+//! which tells apart chains and blocks that were reorganized away.
+
+use super::{BlockInfo, ChainState};
+use crate::prelude::*;
+use std::{
+    convert::TryFrom,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub struct DiskCache<Base: ChainState> {
+    base:      Base,
+    /// Directory for the pinned block, entries are one file per read.
+    directory: PathBuf,
+}
+
+impl<Base: ChainState> DiskCache<Base> {
+    /// Cache reads of `base` at the block with `block_hash` in `cache_dir`
+    pub fn new(base: Base, cache_dir: &Path, block_hash: &U256) -> Self {
+        Self {
+            base,
+            directory: cache_dir.join(hex::encode(block_hash.to_bytes_be())),
+        }
+    }
+
+    pub fn inner(&self) -> &Base {
+        &self.base
+    }
+
+    /// Remove all cached entries for the pinned block
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.directory) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Store code read elsewhere, e.g. fetched ahead of execution
+    pub fn insert_code(&self, address: &U256, code: &[u8]) {
+        self.put(&code_key(address), code);
+    }
+
+    /// Store storage read elsewhere, e.g. fetched ahead of execution
+    pub fn insert_storage(&self, address: &U256, slot: &U256, value: &U256) {
+        self.put(&storage_key(address, slot), &value.to_bytes_be());
+    }
+
+    pub fn contains_storage(&self, address: &U256, slot: &U256) -> bool {
+        self.directory.join(storage_key(address, slot)).exists()
+    }
+
+    /// Read the entry `key`, or fetch and store it
+    fn get_or_fetch(&self, key: &str, fetch: impl FnOnce() -> Vec<u8>) -> Vec<u8> {
+        if let Ok(value) = fs::read(self.directory.join(key)) {
+            return value;
+        }
+        let value = fetch();
+        self.put(key, &value);
+        value
+    }
+
+    /// Read the word entry `key`, or fetch and store it
+    ///
+    /// Entries that are not a word are corrupt and fetched again.
+    fn get_or_fetch_word(&self, key: &str, fetch: impl FnOnce() -> U256) -> U256 {
+        if let Ok(value) = fs::read(self.directory.join(key)) {
+            if let Ok(word) = <[u8; 32]>::try_from(value.as_slice()) {
+                return U256::from_bytes_be(&word);
+            }
+        }
+        let value = fetch();
+        self.put(key, &value.to_bytes_be());
+        value
+    }
+
+    /// Write the entry `key`
+    ///
+    /// The entry is written to a temporary file first and then renamed, so
+    /// readers never see a partial entry. Failures to write the cache are
+    /// logged and otherwise ignored.
+    fn put(&self, key: &str, value: &[u8]) {
+        let path = self.directory.join(key);
+        let temp = self
+            .directory
+            .join(format!("{}.{}.tmp", key, std::process::id()));
+        let result = fs::create_dir_all(&self.directory)
+            .and_then(|()| fs::write(&temp, value))
+            .and_then(|()| fs::rename(&temp, &path));
+        if let Err(err) = result {
+            warn!("Could not write cache entry {}: {}", path.display(), err);
+            let _ = fs::remove_file(&temp);
+        }
+    }
+}
+
+impl<Base: ChainState> ChainState for DiskCache<Base> {
+    fn block(&self) -> BlockInfo {
+        self.base.block()
+    }
+
+    fn block_hash(&self, number: u64) -> U256 {
+        let key = format!("blockhash-{}", number);
+        self.get_or_fetch_word(&key, || self.base.block_hash(number))
+    }
+
+    fn nonce(&self, address: &U256) -> usize {
+        self.base.nonce(address)
+    }
+
+    fn balance(&self, address: &U256) -> U256 {
+        let key = format!("balance-{}", hex::encode(address.to_bytes_be()));
+        self.get_or_fetch_word(&key, || self.base.balance(address))
+    }
+
+    fn code(&self, address: &U256) -> Vec<u8> {
+        self.get_or_fetch(&code_key(address), || self.base.code(address))
+    }
+
+    fn storage(&self, address: &U256, slot: &U256) -> U256 {
+        self.get_or_fetch_word(&storage_key(address, slot), || {
+            self.base.storage(address, slot)
+        })
+    }
+}
+
+fn code_key(address: &U256) -> String {
+    format!("code-{}", hex::encode(address.to_bytes_be()))
+}
+
+fn storage_key(address: &U256, slot: &U256) -> String {
+    format!(
+        "storage-{}-{}",
+        hex::encode(address.to_bytes_be()),
+        hex::encode(slot.to_bytes_be())
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chain::Empty, test::prelude::assert_eq};
+    use std::cell::Cell;
+
+    /// Chain with one funded contract that counts reads
+    #[derive(Default)]
+    struct Counting {
+        reads: Cell<usize>,
+    }
+
+    impl ChainState for Counting {
+        fn block(&self) -> BlockInfo {
+            BlockInfo::default()
+        }
+
+        fn block_hash(&self, _number: u64) -> U256 {
+            U256::zero()
+        }
+
+        fn nonce(&self, _address: &U256) -> usize {
+            0
+        }
+
+        fn balance(&self, _address: &U256) -> U256 {
+            self.reads.set(self.reads.get() + 1);
+            U256::from(42)
+        }
+
+        fn code(&self, _address: &U256) -> Vec<u8> {
+            self.reads.set(self.reads.get() + 1);
+            vec![0x00]
+        }
+
+        fn storage(&self, _address: &U256, slot: &U256) -> U256 {
+            self.reads.set(self.reads.get() + 1);
+            slot + U256::one()
+        }
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sutro-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = cache_dir("disk-cache");
+        let address = U256::from(1);
+        let cache = DiskCache::new(Counting::default(), &dir, &U256::from(100));
+        for _ in 0..2 {
+            assert_eq!(cache.balance(&address), U256::from(42));
+            assert_eq!(cache.code(&address), vec![0x00]);
+            assert_eq!(cache.storage(&address, &U256::from(2)), U256::from(3));
+        }
+        assert_eq!(cache.inner().reads.get(), 3);
+
+        // Entries persist across instances
+        let cache = DiskCache::new(Empty, &dir, &U256::from(100));
+        assert_eq!(cache.balance(&address), U256::from(42));
+        assert_eq!(cache.storage(&address, &U256::from(2)), U256::from(3));
+
+        // But are separate per block
+        let other = DiskCache::new(Empty, &dir, &U256::from(101));
+        assert_eq!(other.balance(&address), U256::zero());
+
+        cache.clear().unwrap();
+        assert_eq!(cache.balance(&address), U256::zero());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_entry() {
+        let dir = cache_dir("disk-cache-truncated");
+        let address = U256::from(1);
+        let cache = DiskCache::new(Counting::default(), &dir, &U256::from(100));
+        assert_eq!(cache.balance(&address), U256::from(42));
+        let path = cache
+            .directory
+            .join(format!("balance-{}", hex::encode(address.to_bytes_be())));
+        fs::write(&path, [0_u8; 7]).unwrap();
+        // Fetched again instead of read as zero
+        assert_eq!(cache.balance(&address), U256::from(42));
+        assert_eq!(cache.inner().reads.get(), 2);
+        assert_eq!(fs::read(&path).unwrap().len(), 32);
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(&cache.directory).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_insert() {
+        let dir = cache_dir("disk-cache-insert");
+        let address = U256::from(1);
+        let cache = DiskCache::new(Counting::default(), &dir, &U256::from(100));
+        assert!(!cache.contains_storage(&address, &U256::from(2)));
+        cache.insert_storage(&address, &U256::from(2), &U256::from(7));
+        cache.insert_code(&address, &[0x60, 0x00]);
+        assert!(cache.contains_storage(&address, &U256::from(2)));
+        assert_eq!(cache.storage(&address, &U256::from(2)), U256::from(7));
+        assert_eq!(cache.code(&address), vec![0x60, 0x00]);
+        assert_eq!(cache.inner().reads.get(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
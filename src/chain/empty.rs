@@ -11,9 +11,19 @@ use crate::prelude::*;
 #[derive(Clone, Default, Debug)]
 pub struct Empty;
 
+/// Block gas limit of the empty chain
+const GAS_LIMIT: u64 = 30_000_000;
+
 impl ChainState for Empty {
     fn block(&self) -> BlockInfo {
-        BlockInfo::default()
+        BlockInfo {
+            gas_limit: GAS_LIMIT,
+            ..BlockInfo::default()
+        }
+    }
+
+    fn block_hash(&self, _number: u64) -> U256 {
+        U256::zero()
     }
 
     fn nonce(&self, _address: &U256) -> usize {
@@ -3,14 +3,63 @@
 //! Takes a given read-only chain implementation and implements and in-memory
 //! change buffer on top of it. The new chain acts as a fork of the underlying
 //! chain.
+//!
+//! Every write is recorded in a journal together with the value it replaced,
+//! so the fork can be rolled back to any earlier snapshot.
+//!
+//! The changes can be saved to a file and loaded into another fork of the
+//! same chain, to reuse a fixture without setting it up again.
+//!
+//! The state root is computed from the accounts written to the fork, see
+//! [`Fork::state_root`].
 
-use super::{BlockInfo, ChainState, StateSet, WriteableChainState};
-use crate::prelude::*;
+use super::{
+    state_trie::{self, TrieAccount},
+    BlockInfo, ChainState, StateSet, WriteableChainState,
+};
+use crate::{
+    chain::types::{
+        rpc::{AccountProof, Bytes, Hex, StorageProof},
+        Address,
+    },
+    prelude::*,
+    utils::keccak256,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::Hash,
+    path::Path,
+};
+
+/// Version of the state file format, incremented on incompatible changes
+const STATE_VERSION: u64 = 1;
 
 #[derive(Clone, Debug)]
 pub struct Fork<Base: ChainState> {
-    base:  Base,
-    state: StateSet,
+    base:    Base,
+    state:   StateSet,
+    journal: Vec<JournalEntry>,
+}
+
+/// Changes of a fork as saved to a file, sorted by address and slot
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+struct StateFile {
+    version:  u64,
+    block:    Option<BlockInfo>,
+    nonces:   Vec<(Hex<U256>, Hex<u64>)>,
+    balances: Vec<(Hex<U256>, Hex<U256>)>,
+    codes:    Vec<(Hex<U256>, Bytes)>,
+    storage:  Vec<(Hex<U256>, Hex<U256>, Hex<U256>)>,
+}
+
+/// A single write to the [`StateSet`] and the value it replaced.
+#[derive(Clone, Debug)]
+enum JournalEntry {
+    Nonce(U256, Option<usize>),
+    Balance(U256, Option<U256>),
+    Code(U256, Option<Vec<u8>>),
+    Storage((U256, U256), Option<U256>),
 }
 
 impl<Base: ChainState> Fork<Base> {
@@ -25,6 +74,134 @@ impl<Base: ChainState> Fork<Base> {
     pub fn into_inner(self) -> Base {
         self.base
     }
+
+    /// Write the changes made on top of the base chain to `path` as JSON
+    pub fn save_state(&self, path: &Path) -> AnyResult<()> {
+        let state = &self.state;
+        let file = StateFile {
+            version:  STATE_VERSION,
+            block:    state.block.clone(),
+            nonces:   sorted(&state.nonces)
+                .map(|(address, nonce)| (address.clone().into(), (*nonce as u64).into()))
+                .collect(),
+            balances: sorted(&state.balances)
+                .map(|(address, balance)| (address.clone().into(), balance.clone().into()))
+                .collect(),
+            codes:    sorted(&state.codes)
+                .map(|(address, code)| (address.clone().into(), code.clone().into()))
+                .collect(),
+            storage:  sorted(&state.storages)
+                .map(|((address, slot), value)| {
+                    (address.clone().into(), slot.clone().into(), value.clone().into())
+                })
+                .collect(),
+        };
+        let json = serde_json::to_string_pretty(&file).context("Serializing state")?;
+        fs::write(path, json).with_context(|| format!("Writing state to {}", path.display()))
+    }
+
+    /// Replace the changes with the ones saved to `path` by
+    /// [`Fork::save_state`]
+    ///
+    /// Earlier snapshots are invalidated.
+    pub fn load_state(&mut self, path: &Path) -> AnyResult<()> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Reading state from {}", path.display()))?;
+        // Going through `Value` lets the hex visitors see strings
+        let value: serde_json::Value = serde_json::from_str(&json).context("Parsing state")?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+        require!(
+            version == Some(STATE_VERSION),
+            anyhow!(
+                "Unsupported state file version {:?}, expected {}",
+                version,
+                STATE_VERSION
+            )
+        );
+        let file: StateFile = serde_json::from_value(value).context("Parsing state")?;
+        let mut state = StateSet {
+            block: file.block,
+            ..StateSet::default()
+        };
+        for (address, nonce) in file.nonces {
+            state
+                .nonces
+                .insert(address.into_inner(), nonce.into_inner() as usize);
+        }
+        for (address, balance) in file.balances {
+            state
+                .balances
+                .insert(address.into_inner(), balance.into_inner());
+        }
+        for (address, code) in file.codes {
+            state.codes.insert(address.into_inner(), code.to_vec());
+        }
+        for (address, slot, value) in file.storage {
+            state
+                .storages
+                .insert((address.into_inner(), slot.into_inner()), value.into_inner());
+        }
+        self.state = state;
+        self.journal.clear();
+        Ok(())
+    }
+
+    /// Root of the state trie of the accounts written to the fork
+    ///
+    /// Accounts and slots that are only in the base chain are not in the
+    /// trie, so this is the root of the whole state for a fork of
+    /// [`Empty`](super::Empty) that all accounts were written to. Empty
+    /// accounts are left out (EIP-161).
+    pub fn state_root(&self) -> U256 {
+        let accounts = self.trie_accounts();
+        state_trie::state_root(accounts.iter().map(|(address, account)| (*address, account)))
+    }
+
+    /// Root of the storage trie of the slots of `address` written to the fork
+    pub fn storage_root(&self, address: &U256) -> U256 {
+        state_trie::storage_root(self.written_slots(address))
+    }
+
+    /// Accounts written to with their storage roots
+    fn trie_accounts(&self) -> Vec<(&U256, TrieAccount)> {
+        let mut storages = BTreeMap::<&U256, Vec<_>>::new();
+        for ((address, slot), value) in &self.state.storages {
+            storages.entry(address).or_default().push((slot, value));
+        }
+        self.state
+            .nonces
+            .keys()
+            .chain(self.state.balances.keys())
+            .chain(self.state.codes.keys())
+            .chain(storages.keys().copied())
+            .unique()
+            .map(|address| {
+                let slots = storages.get(address).map_or(&[][..], Vec::as_slice);
+                let storage_root = state_trie::storage_root(slots.iter().copied());
+                (address, self.trie_account(address, storage_root))
+            })
+            .collect()
+    }
+
+    fn written_slots<'a>(
+        &'a self,
+        address: &'a U256,
+    ) -> impl Iterator<Item = (&'a U256, &'a U256)> {
+        self.state
+            .storages
+            .iter()
+            .filter(move |((account, _), _)| account == address)
+            .map(|((_, slot), value)| (slot, value))
+    }
+
+    fn trie_account(&self, address: &U256, storage_root: U256) -> TrieAccount {
+        TrieAccount {
+            nonce: self.nonce(address) as u64,
+            balance: self.balance(address),
+            storage_root,
+            code_hash: keccak256(&self.code(address)),
+        }
+    }
 }
 
 impl<Base: ChainState> From<Base> for Fork<Base> {
@@ -32,6 +209,7 @@ impl<Base: ChainState> From<Base> for Fork<Base> {
         Self {
             base,
             state: StateSet::default(),
+            journal: Vec::new(),
         }
     }
 }
@@ -44,6 +222,10 @@ impl<Base: ChainState> ChainState for Fork<Base> {
             .unwrap_or_else(|| self.base.block())
     }
 
+    fn block_hash(&self, number: u64) -> U256 {
+        self.base.block_hash(number)
+    }
+
     fn nonce(&self, address: &U256) -> usize {
         self.state
             .nonces
@@ -79,21 +261,261 @@ impl<Base: ChainState> ChainState for Fork<Base> {
 
 impl<Base: ChainState> WriteableChainState for Fork<Base> {
     fn set_nonce(&mut self, address: &U256, nonce: usize) {
-        let _previous = self.state.nonces.insert(address.clone(), nonce);
+        let previous = self.state.nonces.insert(address.clone(), nonce);
+        self.journal
+            .push(JournalEntry::Nonce(address.clone(), previous));
     }
 
     fn set_balance(&mut self, address: &U256, balance: &U256) {
-        let _previous = self.state.balances.insert(address.clone(), balance.clone());
+        let previous = self.state.balances.insert(address.clone(), balance.clone());
+        self.journal
+            .push(JournalEntry::Balance(address.clone(), previous));
     }
 
     fn set_code(&mut self, address: &U256, code: &[u8]) {
-        let _previous = self.state.codes.insert(address.clone(), code.to_vec());
+        let previous = self.state.codes.insert(address.clone(), code.to_vec());
+        self.journal
+            .push(JournalEntry::Code(address.clone(), previous));
     }
 
     fn set_storage(&mut self, address: &U256, slot: &U256, value: &U256) {
-        let _previous = self
-            .state
-            .storages
-            .insert((address.clone(), slot.clone()), value.clone());
+        let key = (address.clone(), slot.clone());
+        let previous = self.state.storages.insert(key.clone(), value.clone());
+        self.journal.push(JournalEntry::Storage(key, previous));
+    }
+
+    fn snapshot(&mut self) -> usize {
+        self.journal.len()
+    }
+
+    fn is_written(&self, address: &U256) -> bool {
+        self.state.nonces.contains_key(address)
+            || self.state.balances.contains_key(address)
+            || self.state.codes.contains_key(address)
+            || self.written_slots(address).next().is_some()
+    }
+
+    /// Slots that were not written to are absent from the storage trie, so
+    /// they are proven to be zero even if the base chain has a value.
+    fn proof(&self, address: &U256, slots: &[U256]) -> AccountProof {
+        let accounts = self.trie_accounts();
+        let account_proof = state_trie::state_proof(
+            accounts.iter().map(|(address, account)| (*address, account)),
+            address,
+        );
+        let account = self.trie_account(address, self.storage_root(address));
+        let storage_proof = slots
+            .iter()
+            .map(|slot| {
+                let value = self
+                    .state
+                    .storages
+                    .get(&(address.clone(), slot.clone()))
+                    .cloned()
+                    .unwrap_or_else(U256::zero);
+                StorageProof {
+                    key:   slot.clone().into(),
+                    value: value.into(),
+                    proof: state_trie::storage_proof(self.written_slots(address), slot)
+                        .into_iter()
+                        .map(Bytes::from)
+                        .collect(),
+                }
+            })
+            .collect();
+        AccountProof {
+            address: Address::from(address),
+            account_proof: account_proof.into_iter().map(Bytes::from).collect(),
+            balance: account.balance.into(),
+            code_hash: account.code_hash,
+            nonce: account.nonce.into(),
+            storage_hash: account.storage_root,
+            storage_proof,
+        }
+    }
+
+    fn revert_to(&mut self, snapshot: usize) {
+        while self.journal.len() > snapshot {
+            match self.journal.pop().unwrap() {
+                JournalEntry::Nonce(key, previous) => {
+                    restore(&mut self.state.nonces, key, previous);
+                }
+                JournalEntry::Balance(key, previous) => {
+                    restore(&mut self.state.balances, key, previous);
+                }
+                JournalEntry::Code(key, previous) => restore(&mut self.state.codes, key, previous),
+                JournalEntry::Storage(key, previous) => {
+                    restore(&mut self.state.storages, key, previous);
+                }
+            }
+        }
+    }
+}
+
+/// Entries of `map` in order of their keys
+fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> impl Iterator<Item = (&K, &V)> {
+    map.iter().sorted_by(|(a, _), (b, _)| a.cmp(b))
+}
+
+fn restore<K: Hash + Eq, V>(map: &mut HashMap<K, V>, key: K, previous: Option<V>) {
+    match previous {
+        Some(value) => map.insert(key, value),
+        None => map.remove(&key),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chain::Empty, test::prelude::assert_eq};
+
+    #[test]
+    fn test_revert_to() {
+        let address = U256::from(1);
+        let slot = U256::from(2);
+        let mut fork = Fork::from(Empty);
+        fork.set_balance(&address, &U256::from(10));
+        let snapshot = fork.snapshot();
+        fork.set_balance(&address, &U256::from(20));
+        fork.set_nonce(&address, 1);
+        fork.set_code(&address, &[0x00]);
+        fork.set_storage(&address, &slot, &U256::from(3));
+        fork.revert_to(snapshot);
+        assert_eq!(fork.balance(&address), U256::from(10));
+        assert_eq!(fork.nonce(&address), 0);
+        assert_eq!(fork.code(&address), Vec::<u8>::new());
+        assert_eq!(fork.storage(&address, &slot), U256::zero());
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        let path = std::env::temp_dir().join(format!("sutro-state-{}.json", std::process::id()));
+        let address = U256::from(1);
+        let slot = U256::from(2);
+        let mut fork = Fork::from(Empty);
+        fork.set_balance(&address, &u256h!(
+            "00000000000000000000000000000000000000000000d3c21bcecceda100000b"
+        ));
+        fork.set_nonce(&address, 3);
+        fork.set_code(&address, &[0x60, 0x00]);
+        fork.set_storage(&address, &slot, &U256::from(42));
+        fork.set_storage(&U256::from(5), &slot, &U256::zero());
+        fork.save_state(&path).unwrap();
+
+        let mut loaded = Fork::from(Empty);
+        loaded.set_balance(&U256::from(9), &U256::one());
+        loaded.load_state(&path).unwrap();
+        assert_eq!(loaded.balance(&address), fork.balance(&address));
+        assert_eq!(loaded.nonce(&address), 3);
+        assert_eq!(loaded.code(&address), vec![0x60, 0x00]);
+        assert_eq!(loaded.storage(&address, &slot), U256::from(42));
+        assert_eq!(loaded.state.storages.len(), 2);
+        // Changes made before loading are replaced
+        assert_eq!(loaded.balance(&U256::from(9)), U256::zero());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_state_version() {
+        let path = std::env::temp_dir().join(format!("sutro-version-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "version": 0, "nonces": [] }"#).unwrap();
+        let error = Fork::from(Empty).load_state(&path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported state file version Some(0), expected 1"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_state_root() {
+        let mut fork = Fork::from(Empty);
+        assert_eq!(
+            fork.state_root(),
+            u256h!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+        let (a, b, c) = (U256::from(0xaa), U256::from(0xbb), U256::from(0xcc));
+        fork.set_balance(&a, &U256::from(1_000_000_000_000_000_000_u64));
+        assert_eq!(
+            fork.state_root(),
+            u256h!("95ffff1af791dc6c2380fcb9a055e60457fcf804094ebfbb58296532b15a0203")
+        );
+        fork.set_nonce(&b, 1);
+        fork.set_code(&b, &hex!("600160005500"));
+        fork.set_storage(&b, &U256::from(1), &U256::from(0x2a));
+        // Zero slots and empty accounts are not in the trie
+        fork.set_storage(&b, &U256::from(2), &U256::zero());
+        fork.set_balance(&c, &U256::zero());
+        assert_eq!(
+            fork.storage_root(&b),
+            u256h!("fcbdb9e7191a6bc6efbe2e1903a50bd3c79312366db1e46acf7e94788c2b4c3e")
+        );
+        assert_eq!(
+            fork.state_root(),
+            u256h!("95449ccc49c9c620ca5516047174339b5fcbcccb0d4f725123acb225d9d5a0f6")
+        );
+    }
+
+    #[test]
+    fn test_proof() {
+        let mut fork = Fork::from(Empty);
+        for account in 1..20_u64 {
+            fork.set_balance(&U256::from(account), &U256::from(account * 100));
+        }
+        let address = U256::from(0xbb);
+        fork.set_nonce(&address, 1);
+        fork.set_code(&address, &hex!("600160005500"));
+        fork.set_storage(&address, &U256::from(1), &U256::from(0x2a));
+        fork.set_storage(&address, &U256::from(2), &U256::from(0x2b));
+        let proof = fork.proof(&address, &[U256::from(1), U256::from(3)]);
+        assert_eq!(proof.nonce, 1.into());
+        assert_eq!(proof.storage_hash, fork.storage_root(&address));
+
+        let nodes = |proof: &[Bytes]| proof.iter().cloned().map(Bytes::to_vec).collect::<Vec<_>>();
+        let key = Address::from(&address).to_array();
+        let root = fork.state_root();
+        let account = state_trie::verify_proof(&root, &key, &nodes(&proof.account_proof))
+            .unwrap()
+            .unwrap();
+        let expected = fork.trie_account(&address, fork.storage_root(&address));
+        assert_eq!(account, crate::serde::rlp::to_rlp(&expected).unwrap());
+
+        let (present, absent) = (&proof.storage_proof[0], &proof.storage_proof[1]);
+        assert_eq!(present.value, U256::from(0x2a).into());
+        let value = state_trie::verify_proof(
+            &proof.storage_hash,
+            &present.key.as_ref().to_bytes_be(),
+            &nodes(&present.proof),
+        );
+        assert_eq!(value.unwrap(), Some(vec![0x2a]));
+        assert_eq!(absent.value, U256::zero().into());
+        let value = state_trie::verify_proof(
+            &proof.storage_hash,
+            &absent.key.as_ref().to_bytes_be(),
+            &nodes(&absent.proof),
+        );
+        assert_eq!(value.unwrap(), None);
+
+        // Accounts that do not exist are proven absent
+        let proof = fork.proof(&U256::from(0xcc), &[]);
+        let key = Address::from(&U256::from(0xcc)).to_array();
+        let account = state_trie::verify_proof(&root, &key, &nodes(&proof.account_proof));
+        assert_eq!(account.unwrap(), None);
+        assert!(!fork.is_written(&U256::from(0xcc)));
+        assert!(fork.is_written(&address));
+    }
+
+    #[test]
+    fn test_nested_snapshots() {
+        let address = U256::from(1);
+        let mut fork = Fork::from(Empty);
+        let outer = fork.snapshot();
+        fork.set_nonce(&address, 1);
+        let inner = fork.snapshot();
+        fork.set_nonce(&address, 2);
+        fork.revert_to(inner);
+        assert_eq!(fork.nonce(&address), 1);
+        fork.revert_to(outer);
+        assert_eq!(fork.nonce(&address), 0);
     }
 }
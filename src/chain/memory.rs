@@ -0,0 +1,141 @@
+//! In-memory chain
+//!
+//! Chain state loaded from a genesis-style JSON file mapping addresses to
+//! accounts. Requires no remote node, which makes it useful for offline
+//! testing.
+
+use super::{BlockInfo, ChainState, Empty, StateSet};
+use crate::{
+    chain::types::{
+        rpc::{Bytes, Hex},
+        Address,
+    },
+    prelude::*,
+};
+use std::collections::HashMap;
+
+/// Chain state with a fixed set of accounts
+///
+/// Accounts that are not in the set are empty.
+#[derive(Clone, Default, Debug)]
+pub struct MemoryChain {
+    block: BlockInfo,
+    state: StateSet,
+}
+
+/// Account entry in a state file, all fields are optional.
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
+struct AccountState {
+    balance: Hex<U256>,
+    nonce:   Hex<u64>,
+    code:    Bytes,
+    storage: HashMap<Hex<U256>, Hex<U256>>,
+}
+
+impl MemoryChain {
+    /// Parse a JSON object mapping addresses to `{balance, nonce, code,
+    /// storage}`.
+    pub fn from_json(json: &str) -> AnyResult<Self> {
+        // Going through `Value` lets the hex visitors see strings
+        let value: serde_json::Value = serde_json::from_str(json).context("Parsing chain state")?;
+        let accounts: HashMap<Address, AccountState> =
+            serde_json::from_value(value).context("Parsing accounts")?;
+        let mut state = StateSet::default();
+        for (address, account) in accounts {
+            let address = U256::from(&address);
+            state
+                .balances
+                .insert(address.clone(), account.balance.into_inner());
+            state
+                .nonces
+                .insert(address.clone(), account.nonce.into_inner() as usize);
+            state
+                .codes
+                .insert(address.clone(), account.code.to_vec());
+            for (slot, value) in account.storage {
+                state
+                    .storages
+                    .insert((address.clone(), slot.into_inner()), value.into_inner());
+            }
+        }
+        Ok(Self {
+            block: Empty.block(),
+            state,
+        })
+    }
+}
+
+impl ChainState for MemoryChain {
+    fn block(&self) -> BlockInfo {
+        self.block.clone()
+    }
+
+    fn block_hash(&self, _number: u64) -> U256 {
+        U256::zero()
+    }
+
+    fn nonce(&self, address: &U256) -> usize {
+        self.state.nonces.get(address).copied().unwrap_or_default()
+    }
+
+    fn balance(&self, address: &U256) -> U256 {
+        self.state
+            .balances
+            .get(address)
+            .cloned()
+            .unwrap_or_else(U256::zero)
+    }
+
+    fn code(&self, address: &U256) -> Vec<u8> {
+        self.state.codes.get(address).cloned().unwrap_or_default()
+    }
+
+    fn storage(&self, address: &U256, slot: &U256) -> U256 {
+        self.state
+            .storages
+            .get(&(address.clone(), slot.clone()))
+            .cloned()
+            .unwrap_or_else(U256::zero)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_from_json() {
+        let chain = MemoryChain::from_json(
+            r#"{
+                "0x00000000000000000000000000000000000000aa": {
+                    "balance": "0xde0b6b3a7640000",
+                    "nonce": "0x2",
+                    "code": "0x6000",
+                    "storage": { "0x1": "0x2a" }
+                },
+                "0x00000000000000000000000000000000000000bb": {
+                    "balance": "0x10"
+                }
+            }"#,
+        )
+        .unwrap();
+        let contract = U256::from(0xaa);
+        assert_eq!(chain.balance(&contract), U256::from(1_000_000_000_000_000_000_u64));
+        assert_eq!(chain.nonce(&contract), 2);
+        assert_eq!(chain.code(&contract), vec![0x60, 0x00]);
+        assert_eq!(chain.storage(&contract, &U256::one()), U256::from(42));
+        assert_eq!(chain.storage(&contract, &U256::zero()), U256::zero());
+        let account = U256::from(0xbb);
+        assert_eq!(chain.balance(&account), U256::from(16));
+        assert_eq!(chain.nonce(&account), 0);
+        assert_eq!(chain.code(&account), Vec::<u8>::new());
+        assert_eq!(chain.balance(&U256::from(0xcc)), U256::zero());
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        assert!(MemoryChain::from_json(r#"{ "0xaa": { "nonce": "0x1" } }"#).is_err());
+    }
+}
@@ -5,38 +5,99 @@
 // ignoring parameters.
 
 mod cache;
+mod disk_cache;
 mod empty;
 mod fork;
+mod memory;
+pub mod prefetch;
+mod rewind;
 mod rpc_chain;
 mod state_set;
+pub mod state_trie;
 pub mod types;
 
-pub use self::{cache::Cache, empty::Empty, fork::Fork, rpc_chain::RpcChain, state_set::StateSet};
+pub use self::{
+    cache::Cache,
+    disk_cache::DiskCache,
+    empty::Empty,
+    fork::Fork,
+    memory::MemoryChain,
+    rewind::{Recorder, Rewound},
+    rpc_chain::RpcChain,
+    state_set::StateSet,
+};
 
-use self::types::rpc::BlockNumber;
+use self::types::rpc::{AccountProof, BlockNumber};
 use crate::{prelude::*, rpc};
+use std::path::Path;
 
 /// Constant for the current block
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct BlockInfo {
-    pub timestamp: u64,
+    #[serde(default)]
+    pub number:     u64,
+    pub timestamp:  u64,
+    /// Base fee per gas (EIP-1559), zero before London.
+    #[serde(default)]
+    pub base_fee:   U256,
+    #[serde(default)]
+    pub gas_limit:  u64,
+    #[serde(default)]
+    pub coinbase:   U256,
+    /// Difficulty, or the previous RANDAO mix after the Merge (EIP-4399).
+    #[serde(default)]
+    pub difficulty: U256,
+    /// Chain id (EIP-155), configured on the node rather than read from the
+    /// block.
+    #[serde(default)]
+    pub chain_id:   u64,
 }
 
 /// Read only chain state
+///
+/// The interpreter only accesses the chain through this trait and
+/// [`WriteableChainState`]. Backends are layered on top of each other, e.g. a
+/// [`Fork`] of a [`Cache`] of an [`RpcChain`]. Reads are synchronous, backends
+/// that talk to a node block on the request.
+///
+/// Accounts that do not exist read as zero with empty code.
 #[allow(clippy::module_name_repetitions)]
 pub trait ChainState {
+    /// Constants of the block transactions execute in
     fn block(&self) -> BlockInfo;
+    /// Hash of an earlier block, only called for the 256 most recent ones.
+    fn block_hash(&self, number: u64) -> U256;
     fn nonce(&self, address: &U256) -> usize;
     fn balance(&self, address: &U256) -> U256;
     fn code(&self, address: &U256) -> Vec<u8>;
     fn storage(&self, address: &U256, slot: &U256) -> U256;
 }
 
+/// Chain state that can be modified by execution
+///
+/// Writes are visible to subsequent reads through [`ChainState`].
 pub trait WriteableChainState: ChainState {
     fn set_nonce(&mut self, address: &U256, nonce: usize);
     fn set_balance(&mut self, address: &U256, balance: &U256);
     fn set_code(&mut self, address: &U256, code: &[u8]);
     fn set_storage(&mut self, address: &U256, slot: &U256, value: &U256);
+
+    /// Identifier for the current state that can be passed to
+    /// [`WriteableChainState::revert_to`].
+    fn snapshot(&mut self) -> usize;
+
+    /// Undo all writes made since `snapshot` was taken.
+    ///
+    /// Later snapshots are invalidated, earlier ones remain valid.
+    fn revert_to(&mut self, snapshot: usize);
+
+    /// Whether anything of the account was written, otherwise it is the same
+    /// as in the base chain.
+    fn is_written(&self, address: &U256) -> bool;
+
+    /// Proof of the account and storage slots (EIP-1186) against the state
+    /// root of the accounts written, see [`Fork::state_root`].
+    fn proof(&self, address: &U256, slots: &[U256]) -> AccountProof;
 }
 
 /// Create an empty chain
@@ -44,24 +105,47 @@ pub async fn new() -> AnyResult<Fork<Empty>> {
     Ok(Fork::from(Empty))
 }
 
+/// Create a chain from a JSON file of accounts, see [`MemoryChain`].
+pub async fn load(path: &Path) -> AnyResult<Fork<MemoryChain>> {
+    let json = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Reading chain state from {}", path.display()))?;
+    Ok(Fork::from(MemoryChain::from_json(&json)?))
+}
+
 /// Create a fork from a JSON-RPC URL.
-pub async fn fork(url: &str) -> AnyResult<Fork<Cache<RpcChain>>> {
+///
+/// The fork is pinned to `block_number`, or the latest block if `None`. Reads
+/// from the node are persisted in `cache_dir`, after removing the ones of the
+/// pinned block if `clear_cache` is set.
+pub async fn fork(
+    url: &str,
+    cache_dir: &Path,
+    block_number: Option<u64>,
+    clear_cache: bool,
+) -> AnyResult<Fork<Cache<DiskCache<RpcChain>>>> {
     let client = rpc::client(url)
         .await
         .context("Creating RPC client to fork from")?;
 
-    // Pin to latest block
-    let latest = client
-        .get_block_by_number(BlockNumber::Latest, false)
+    // Pin to the requested block, or the latest one
+    let pinned = block_number.map_or(BlockNumber::Latest, BlockNumber::Number);
+    let block = client
+        .get_block_by_number(pinned, true)
         .await
         .map_err(|err| anyhow!("Error: {}", err))
-        .context("Fetching latest block number")?
-        .ok_or_else(|| anyhow!("Latest block not found"))?
-        .header
-        .number;
-    info!("Forking from block number {}", latest);
-    let block_number = BlockNumber::Number(latest);
+        .context("Fetching block to fork from")?
+        .ok_or_else(|| anyhow!("Block to fork from not found"))?;
+    let number = block.header.number;
+    info!("Forking from block number {}", number);
+
+    let disk_cache = DiskCache::new(RpcChain::new(client, number), cache_dir, &block.hash);
+    if clear_cache {
+        disk_cache
+            .clear()
+            .with_context(|| format!("Clearing cache of block {}", number))?;
+    }
 
     // Create monad stack
-    Ok(Fork::from(Cache::from(RpcChain::new(client, block_number))))
+    Ok(Fork::from(Cache::from(disk_cache)))
 }
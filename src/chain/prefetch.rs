@@ -0,0 +1,274 @@
+//! Concurrent reads ahead of execution
+//!
+//! The interpreter reads state one value at a time, and against a node every
+//! read is a round-trip. Reads that can be predicted before execution are
+//! fetched concurrently into the [`DiskCache`], where the interpreter finds
+//! them. Reads that are not predicted are made during execution as usual.
+
+use super::{types::AccessListItem, ChainState, DiskCache, RpcChain};
+use crate::{evm::Opcode, prelude::*};
+
+/// Maximum number of requests in flight
+const CONCURRENT_REQUESTS: usize = 16;
+
+/// Chain whose reads can be made concurrently
+pub trait Fetch: ChainState + Sync {
+    fn fetch_code<'a>(&'a self, address: &'a U256) -> future::BoxFuture<'a, AnyResult<Vec<u8>>>;
+    fn fetch_storage<'a>(
+        &'a self,
+        address: &'a U256,
+        slot: &'a U256,
+    ) -> future::BoxFuture<'a, AnyResult<U256>>;
+}
+
+impl Fetch for RpcChain {
+    // The inherent methods take precedence over the trait ones
+    fn fetch_code<'a>(&'a self, address: &'a U256) -> future::BoxFuture<'a, AnyResult<Vec<u8>>> {
+        Self::fetch_code(self, address).boxed()
+    }
+
+    fn fetch_storage<'a>(
+        &'a self,
+        address: &'a U256,
+        slot: &'a U256,
+    ) -> future::BoxFuture<'a, AnyResult<U256>> {
+        Self::fetch_storage(self, address, slot).boxed()
+    }
+}
+
+/// Storage slots that are constant operands of `SLOAD`
+///
+/// Only slots pushed right before the `SLOAD` are found, which covers plain
+/// state variables but not mappings or arrays.
+pub fn predict_slots(code: &[u8]) -> Vec<U256> {
+    let mut slots = Vec::new();
+    let mut pushed = None;
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = Opcode::from(code[pc]);
+        pushed = match op {
+            Opcode::Push(n) => {
+                // Code is implicitly zero padded
+                let n = n as usize;
+                let data = &code[pc + 1..(pc + 1 + n).min(code.len())];
+                let mut bytes = [0_u8; 32];
+                bytes[32 - n..32 - n + data.len()].copy_from_slice(data);
+                Some(U256::from_bytes_be(&bytes))
+            }
+            Opcode::SLoad => {
+                if let Some(slot) = pushed {
+                    slots.push(slot);
+                }
+                None
+            }
+            _ => None,
+        };
+        pc += op.encoded_size();
+    }
+    slots.sort();
+    slots.dedup();
+    slots
+}
+
+/// Fetch the code and predicted storage of `address` and the accounts and
+/// slots of the access list
+///
+/// Returns the number of storage slots fetched.
+pub async fn prefetch<Base: Fetch>(
+    cache: &DiskCache<Base>,
+    address: &U256,
+    access_list: &[AccessListItem],
+) -> AnyResult<usize> {
+    let chain = cache.inner();
+    let mut addresses = vec![address.clone()];
+    addresses.extend(
+        access_list
+            .iter()
+            .map(|item| U256::from(&item.address)),
+    );
+    addresses.sort();
+    addresses.dedup();
+    let codes = stream::iter(&addresses)
+        .map(|address| {
+            async move {
+                let code = chain.fetch_code(address).await?;
+                cache.insert_code(address, &code);
+                AnyResult::<_>::Ok((address, code))
+            }
+        })
+        .buffer_unordered(CONCURRENT_REQUESTS)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut slots = codes
+        .iter()
+        .flat_map(|(address, code)| {
+            predict_slots(code)
+                .into_iter()
+                .map(move |slot| ((*address).clone(), slot))
+        })
+        .chain(access_list.iter().flat_map(|item| {
+            let address = U256::from(&item.address);
+            item.storage_keys
+                .iter()
+                .map(move |slot| (address.clone(), slot.clone()))
+        }))
+        .filter(|(address, slot)| !cache.contains_storage(address, slot))
+        .collect::<Vec<_>>();
+    slots.sort();
+    slots.dedup();
+    debug!("Prefetching {} storage slots", slots.len());
+    stream::iter(&slots)
+        .map(|(address, slot)| {
+            async move {
+                let value = chain.fetch_storage(address, slot).await?;
+                cache.insert_storage(address, slot, &value);
+                AnyResult::<_>::Ok(())
+            }
+        })
+        .buffer_unordered(CONCURRENT_REQUESTS)
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(slots.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_predict_slots() {
+        // SLOAD(1) SLOAD(0x1234) SLOAD(CALLDATALOAD(0)) SLOAD(1)
+        let code = hex!("600154 61123454 60003554 600154 00");
+        assert_eq!(predict_slots(&code), vec![U256::from(1), U256::from(0x1234)]);
+    }
+
+    #[test]
+    fn test_predict_slots_push_data() {
+        // PUSH2 0x6054 SLOAD: the SLOAD byte inside the push data is skipped
+        assert_eq!(predict_slots(&hex!("61605454")), vec![U256::from(0x6054)]);
+        // Truncated push at the end of the code
+        assert_eq!(predict_slots(&hex!("6201")), Vec::<U256>::new());
+    }
+}
+
+#[cfg(feature = "bench")]
+pub mod bench {
+    use super::*;
+    use crate::{
+        bench::prelude::*,
+        chain::{BlockInfo, Cache, Empty, Fork, WriteableChainState},
+        evm::{execute, CallInfo, TransactionInfo},
+    };
+    use std::{thread, time::Duration};
+
+    /// Round-trip time of a read from a node
+    const LATENCY: Duration = Duration::from_millis(1);
+
+    /// Chain where every read takes [`LATENCY`]
+    #[derive(Clone)]
+    struct Remote(Fork<Empty>);
+
+    impl ChainState for Remote {
+        fn block(&self) -> BlockInfo {
+            thread::sleep(LATENCY);
+            self.0.block()
+        }
+
+        fn block_hash(&self, number: u64) -> U256 {
+            thread::sleep(LATENCY);
+            self.0.block_hash(number)
+        }
+
+        fn nonce(&self, address: &U256) -> usize {
+            thread::sleep(LATENCY);
+            self.0.nonce(address)
+        }
+
+        fn balance(&self, address: &U256) -> U256 {
+            thread::sleep(LATENCY);
+            self.0.balance(address)
+        }
+
+        fn code(&self, address: &U256) -> Vec<u8> {
+            thread::sleep(LATENCY);
+            self.0.code(address)
+        }
+
+        fn storage(&self, address: &U256, slot: &U256) -> U256 {
+            thread::sleep(LATENCY);
+            self.0.storage(address, slot)
+        }
+    }
+
+    impl Fetch for Remote {
+        fn fetch_code<'a>(
+            &'a self,
+            address: &'a U256,
+        ) -> future::BoxFuture<'a, AnyResult<Vec<u8>>> {
+            async move {
+                tokio::time::sleep(LATENCY).await;
+                Ok(self.0.code(address))
+            }
+            .boxed()
+        }
+
+        fn fetch_storage<'a>(
+            &'a self,
+            address: &'a U256,
+            slot: &'a U256,
+        ) -> future::BoxFuture<'a, AnyResult<U256>> {
+            async move {
+                tokio::time::sleep(LATENCY).await;
+                Ok(self.0.storage(address, slot))
+            }
+            .boxed()
+        }
+    }
+
+    /// Execute a call that reads constant storage slots on an empty cache,
+    /// with and without prefetching
+    pub fn group(criterion: &mut Criterion) {
+        const SLOTS: u8 = 32;
+        let address = U256::from(0x1234);
+        let mut base = Fork::from(Empty);
+        let mut code = Vec::new();
+        for slot in 0..SLOTS {
+            // PUSH1 slot SLOAD POP
+            code.extend_from_slice(&[0x60, slot, 0x54, 0x50]);
+            base.set_storage(&address, &U256::from(slot), &U256::one());
+        }
+        code.push(0x00);
+        base.set_code(&address, &code);
+        let remote = Remote(base);
+        let call = CallInfo {
+            address: address.clone(),
+            code_address: address.clone(),
+            initial_gas: 10_000_000,
+            ..CallInfo::default()
+        };
+        let cache_dir = std::env::temp_dir().join("sutro-bench-prefetch");
+        let runtime = tokio::runtime::Runtime::new().expect("Creating Tokio runtime");
+        for &(name, prefetch) in &[("cold replay", false), ("cold replay prefetched", true)] {
+            criterion.bench_function(name, |bencher| {
+                bencher.iter(|| {
+                    let cache = DiskCache::new(remote.clone(), &cache_dir, &U256::zero());
+                    cache.clear().expect("Clearing cache");
+                    if prefetch {
+                        runtime
+                            .block_on(super::prefetch(&cache, &address, &[]))
+                            .expect("Prefetching");
+                    }
+                    let mut chain = Fork::from(Cache::from(cache));
+                    black_box(execute(
+                        &mut chain,
+                        &call,
+                        &BlockInfo::default(),
+                        &TransactionInfo::default(),
+                    ))
+                });
+            });
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Earlier state of a chain
+//!
+//! A [`Recorder`] keeps the values that writes replace, and a [`Rewound`]
+//! chain reads those values instead of the current ones. Together they give
+//! a read-only view of the state before a transaction, without reverting the
+//! transactions after it.
+
+use super::{BlockInfo, ChainState, StateSet, WriteableChainState};
+use crate::{chain::types::rpc::AccountProof, prelude::*};
+
+/// Chain that records the value before the first write to every account
+/// field and storage slot
+pub struct Recorder<'a> {
+    chain:    &'a mut dyn WriteableChainState,
+    replaced: StateSet,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(chain: &'a mut dyn WriteableChainState) -> Self {
+        Self {
+            chain,
+            replaced: StateSet::default(),
+        }
+    }
+
+    /// Values that were overwritten, as they were before the first write
+    pub fn into_replaced(self) -> StateSet {
+        self.replaced
+    }
+}
+
+impl ChainState for Recorder<'_> {
+    fn block(&self) -> BlockInfo {
+        self.chain.block()
+    }
+
+    fn block_hash(&self, number: u64) -> U256 {
+        self.chain.block_hash(number)
+    }
+
+    fn nonce(&self, address: &U256) -> usize {
+        self.chain.nonce(address)
+    }
+
+    fn balance(&self, address: &U256) -> U256 {
+        self.chain.balance(address)
+    }
+
+    fn code(&self, address: &U256) -> Vec<u8> {
+        self.chain.code(address)
+    }
+
+    fn storage(&self, address: &U256, slot: &U256) -> U256 {
+        self.chain.storage(address, slot)
+    }
+}
+
+impl WriteableChainState for Recorder<'_> {
+    fn set_nonce(&mut self, address: &U256, nonce: usize) {
+        if !self.replaced.nonces.contains_key(address) {
+            let previous = self.chain.nonce(address);
+            self.replaced.nonces.insert(address.clone(), previous);
+        }
+        self.chain.set_nonce(address, nonce);
+    }
+
+    fn set_balance(&mut self, address: &U256, balance: &U256) {
+        if !self.replaced.balances.contains_key(address) {
+            let previous = self.chain.balance(address);
+            self.replaced.balances.insert(address.clone(), previous);
+        }
+        self.chain.set_balance(address, balance);
+    }
+
+    fn set_code(&mut self, address: &U256, code: &[u8]) {
+        if !self.replaced.codes.contains_key(address) {
+            let previous = self.chain.code(address);
+            self.replaced.codes.insert(address.clone(), previous);
+        }
+        self.chain.set_code(address, code);
+    }
+
+    fn set_storage(&mut self, address: &U256, slot: &U256, value: &U256) {
+        let key = (address.clone(), slot.clone());
+        if !self.replaced.storages.contains_key(&key) {
+            let previous = self.chain.storage(address, slot);
+            self.replaced.storages.insert(key, previous);
+        }
+        self.chain.set_storage(address, slot, value);
+    }
+
+    fn snapshot(&mut self) -> usize {
+        self.chain.snapshot()
+    }
+
+    fn revert_to(&mut self, snapshot: usize) {
+        self.chain.revert_to(snapshot);
+    }
+
+    fn is_written(&self, address: &U256) -> bool {
+        self.chain.is_written(address)
+    }
+
+    fn proof(&self, address: &U256, slots: &[U256]) -> AccountProof {
+        self.chain.proof(address, slots)
+    }
+}
+
+/// Chain as it was before the writes recorded by [`Recorder`]
+///
+/// Wrap it in a [`Fork`](super::Fork) to execute on it.
+pub struct Rewound<'a, Base: ChainState + ?Sized> {
+    base:     &'a Base,
+    replaced: StateSet,
+}
+
+impl<'a, Base: ChainState + ?Sized> Rewound<'a, Base> {
+    /// Undo the writes of `replaced`, which are ordered from the oldest to
+    /// the most recent
+    pub fn new(base: &'a Base, replaced: impl DoubleEndedIterator<Item = StateSet>) -> Self {
+        // Older values take precedence
+        let mut state = StateSet::default();
+        for replaced in replaced.rev() {
+            state.nonces.extend(replaced.nonces);
+            state.balances.extend(replaced.balances);
+            state.codes.extend(replaced.codes);
+            state.storages.extend(replaced.storages);
+        }
+        Self {
+            base,
+            replaced: state,
+        }
+    }
+}
+
+impl<Base: ChainState + ?Sized> ChainState for Rewound<'_, Base> {
+    fn block(&self) -> BlockInfo {
+        self.base.block()
+    }
+
+    fn block_hash(&self, number: u64) -> U256 {
+        self.base.block_hash(number)
+    }
+
+    fn nonce(&self, address: &U256) -> usize {
+        self.replaced
+            .nonces
+            .get(address)
+            .copied()
+            .unwrap_or_else(|| self.base.nonce(address))
+    }
+
+    fn balance(&self, address: &U256) -> U256 {
+        self.replaced
+            .balances
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| self.base.balance(address))
+    }
+
+    fn code(&self, address: &U256) -> Vec<u8> {
+        self.replaced
+            .codes
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| self.base.code(address))
+    }
+
+    fn storage(&self, address: &U256, slot: &U256) -> U256 {
+        self.replaced
+            .storages
+            .get(&(address.clone(), slot.clone()))
+            .cloned()
+            .unwrap_or_else(|| self.base.storage(address, slot))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{Empty, Fork},
+        test::prelude::assert_eq,
+    };
+
+    #[test]
+    fn test_rewind() {
+        let address = U256::from(0xaa);
+        let slot = U256::one();
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&address, &U256::from(1));
+
+        let mut replaced = Vec::new();
+        for value in 2..=3 {
+            let mut recorder = Recorder::new(&mut chain);
+            recorder.set_balance(&address, &U256::from(value));
+            recorder.set_balance(&address, &U256::from(value * 10));
+            recorder.set_storage(&address, &slot, &U256::from(value));
+            replaced.push(recorder.into_replaced());
+        }
+        assert_eq!(chain.balance(&address), U256::from(30));
+
+        // Before the first write of each recording
+        let rewound = Rewound::new(&chain, replaced.iter().cloned());
+        assert_eq!(rewound.balance(&address), U256::from(1));
+        assert_eq!(rewound.storage(&address, &slot), U256::zero());
+        let rewound = Rewound::new(&chain, replaced[1..].iter().cloned());
+        assert_eq!(rewound.balance(&address), U256::from(20));
+        assert_eq!(rewound.storage(&address, &slot), U256::from(2));
+        // The chain itself is unchanged
+        assert_eq!(chain.balance(&address), U256::from(30));
+        assert_eq!(chain.storage(&address, &slot), U256::from(3));
+    }
+}
@@ -1,53 +1,160 @@
 use super::{BlockInfo, ChainState};
-use crate::{chain::types::rpc::BlockNumber, prelude::*, rpc::EthereumRpcClient};
+use crate::{
+    chain::types::{
+        rpc::{BlockNumber, Hex},
+        Address, RpcBlock,
+    },
+    prelude::*,
+    rpc::EthereumRpcClient,
+};
 use futures::executor::block_on;
 
+/// Chain state read from a JSON-RPC node
+///
+/// All reads are made at the pinned block number, so the state stays
+/// consistent while the node follows the chain.
 pub struct RpcChain {
     client:       EthereumRpcClient,
-    block_number: BlockNumber,
+    block_number: u64,
 }
 
 impl RpcChain {
-    pub fn new(client: EthereumRpcClient, block_number: BlockNumber) -> Self {
+    pub const fn new(client: EthereumRpcClient, block_number: u64) -> Self {
         Self {
             client,
             block_number,
         }
     }
+
+    const fn pinned(&self) -> BlockNumber {
+        BlockNumber::Number(self.block_number)
+    }
+
+    /// Read code without blocking, to make reads concurrently
+    pub async fn fetch_code(&self, address: &U256) -> AnyResult<Vec<u8>> {
+        Ok(self
+            .client
+            .get_code(Address::from(address), self.pinned())
+            .await
+            .map_err(|err| anyhow!("Error: {}", err))
+            .context("Fetching code")?
+            .to_vec())
+    }
+
+    /// Read storage without blocking, to make reads concurrently
+    pub async fn fetch_storage(&self, address: &U256, slot: &U256) -> AnyResult<U256> {
+        Ok(self
+            .client
+            .get_storage_at(Address::from(address), slot.clone().into(), self.pinned())
+            .await
+            .map_err(|err| anyhow!("Error: {}", err))
+            .context("Fetching storage")?
+            .into_inner())
+    }
 }
 
 // TODO: Async & Result ?
 impl ChainState for RpcChain {
     fn block(&self) -> BlockInfo {
-        todo!();
-        // let result: AnyResult<BlockHeader> = block_on(async {
-        //     Ok(self
-        //         .client
-        //         .get_block_by_number(BlockNumber::Latest, false)
-        //         .await
-        //         .map_err(|err| anyhow!("Error: {}", err))
-        //         .context("Fetching latest block number")?
-        //         .ok_or_else(|| anyhow!("Latest block not found"))?)
-        // });
-        // let block = result.unwrap();
-        // BlockInfo {
-        //     timestamp: block.timestamp.into_inner(),
-        // }
+        // Full blocks are requested, as that is the type the client parses
+        let block = block_on(self.client.get_block_by_number(self.pinned(), true))
+            .expect("Fetching pinned block")
+            .expect("Pinned block not found");
+        BlockInfo::from(&block)
+    }
+
+    fn block_hash(&self, number: u64) -> U256 {
+        block_on(
+            self.client
+                .get_block_by_number(BlockNumber::Number(number), true),
+        )
+        .expect("Fetching block hash")
+        .map_or_else(U256::zero, |block| block.hash)
     }
 
-    fn nonce(&self, _address: &U256) -> usize {
-        todo!()
+    fn nonce(&self, address: &U256) -> usize {
+        let nonce = block_on(
+            self.client
+                .get_nonce(Address::from(address), self.pinned()),
+        )
+        .expect("Fetching nonce");
+        nonce.into_inner() as usize
     }
 
-    fn balance(&self, _address: &U256) -> U256 {
-        todo!()
+    fn balance(&self, address: &U256) -> U256 {
+        block_on(
+            self.client
+                .get_balance(Address::from(address), self.pinned()),
+        )
+        .expect("Fetching balance")
+        .into_inner()
     }
 
-    fn code(&self, _address: &U256) -> Vec<u8> {
-        todo!()
+    fn code(&self, address: &U256) -> Vec<u8> {
+        block_on(self.fetch_code(address)).expect("Fetching code")
     }
 
-    fn storage(&self, _address: &U256, _slot: &U256) -> U256 {
-        todo!()
+    fn storage(&self, address: &U256, slot: &U256) -> U256 {
+        block_on(self.fetch_storage(address, slot)).expect("Fetching storage")
+    }
+}
+
+/// Block constants from a block returned by the node
+///
+/// The chain id is not part of the block and left zero.
+impl<T> From<&RpcBlock<T>> for BlockInfo {
+    fn from(block: &RpcBlock<T>) -> Self {
+        let header = &block.header;
+        // After the Merge the mix hash holds the previous RANDAO mix (EIP-4399)
+        let difficulty = if header.difficulty == 0 {
+            header.mix_hash.clone()
+        } else {
+            U256::from(header.difficulty)
+        };
+        Self {
+            number: header.number,
+            timestamp: header.timestamp,
+            base_fee: block
+                .base_fee_per_gas
+                .clone()
+                .map_or_else(U256::zero, Hex::into_inner),
+            gas_limit: header.gas_limit,
+            coinbase: U256::from(&header.beneficiary),
+            difficulty,
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chain::types::FullBlock, test::prelude::assert_eq};
+    use serde_json::{from_value, json};
+
+    #[test]
+    fn test_block_info() {
+        let block: FullBlock = from_value(json!({
+            "baseFeePerGas": "0x7",
+            "difficulty": "0x0",
+            "gasLimit": "0x1c9c380",
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "miner": "0x00000000000000000000000000000000000000aa",
+            "mixHash": "0x00000000000000000000000000000000000000000000000000000000000000bb",
+            "number": "0xf4240",
+            "size": "0x0",
+            "timestamp": "0x5f5e100",
+            "totalDifficulty": "0x0",
+            "transactions": [],
+            "uncles": []
+        }))
+        .unwrap();
+        let info = BlockInfo::from(&block);
+        assert_eq!(info.number, 1_000_000);
+        assert_eq!(info.timestamp, 100_000_000);
+        assert_eq!(info.base_fee, U256::from(7));
+        assert_eq!(info.gas_limit, 30_000_000);
+        assert_eq!(info.coinbase, U256::from(0xaa));
+        assert_eq!(info.difficulty, U256::from(0xbb));
     }
 }
@@ -0,0 +1,384 @@
+//! State and storage tries (Yellow Paper appendix D)
+//!
+//! Both tries are "secure": accounts are keyed by the keccak of their address
+//! and slots by the keccak of the slot. Values are RLP encoded.
+//!
+//! Roots are computed by the `trie` crate. Proofs, the nodes on the path to a
+//! key (EIP-1186), are built by encoding the trie here, as the crate does not
+//! expose its nodes by path.
+
+use crate::{
+    chain::types::rpc::Bytes,
+    prelude::*,
+    serde::{
+        fixed_u256,
+        rlp::{de::Deserializer, encode_list, to_rlp},
+        short_u256, short_u64,
+    },
+    utils::keccak256,
+};
+use std::collections::HashMap;
+
+/// Account as it is encoded in the state trie
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TrieAccount {
+    #[serde(with = "short_u64")]
+    pub nonce:        u64,
+    #[serde(with = "short_u256")]
+    pub balance:      U256,
+    #[serde(with = "fixed_u256")]
+    pub storage_root: U256,
+    #[serde(with = "fixed_u256")]
+    pub code_hash:    U256,
+}
+
+impl TrieAccount {
+    /// No code, nonce or balance, such accounts are not in the trie (EIP-161)
+    pub fn is_empty(&self) -> bool {
+        self.nonce == 0 && self.balance.is_zero() && self.code_hash == keccak256(&[])
+    }
+}
+
+/// Storage value as it is encoded in the storage trie
+#[derive(Serialize)]
+struct StorageValue<'a>(#[serde(with = "short_u256")] &'a U256);
+
+/// Root of the state trie, leaving out empty accounts
+pub fn state_root<'a>(
+    accounts: impl IntoIterator<Item = (&'a U256, &'a TrieAccount)> + 'a,
+) -> U256 {
+    secure_root(state_entries(accounts))
+}
+
+/// Nodes of the state trie on the path to `address`, see [`verify_proof`]
+pub fn state_proof<'a>(
+    accounts: impl IntoIterator<Item = (&'a U256, &'a TrieAccount)> + 'a,
+    address: &U256,
+) -> Vec<Vec<u8>> {
+    secure_proof(state_entries(accounts), &address_key(address))
+}
+
+/// Root of a storage trie, leaving out slots that are zero
+pub fn storage_root<'a>(slots: impl IntoIterator<Item = (&'a U256, &'a U256)> + 'a) -> U256 {
+    secure_root(storage_entries(slots))
+}
+
+/// Nodes of a storage trie on the path to `slot`, see [`verify_proof`]
+pub fn storage_proof<'a>(
+    slots: impl IntoIterator<Item = (&'a U256, &'a U256)> + 'a,
+    slot: &U256,
+) -> Vec<Vec<u8>> {
+    secure_proof(storage_entries(slots), &slot.to_bytes_be())
+}
+
+/// Value at `key` in the secure trie with `root`, none if the proof shows it
+/// is absent
+///
+/// The key is hashed like in [`state_proof`] and [`storage_proof`]: state
+/// proofs take the 20 byte address and storage proofs the 32 byte slot.
+pub fn verify_proof(root: &U256, key: &[u8], proof: &[Vec<u8>]) -> AnyResult<Option<Vec<u8>>> {
+    let path = nibbles(&keccak256(key).to_bytes_be());
+    let mut path = &path[..];
+    let mut proof = proof.iter();
+    let mut node: &[u8] = proof.next().context("Empty proof")?;
+    require!(
+        &keccak256(node) == root,
+        anyhow!("First node of the proof is not the root")
+    );
+    loop {
+        let items = list_items(node)?;
+        let child = match items.len() {
+            17 => {
+                if path.is_empty() {
+                    let value = bytes_item(items[16])?;
+                    return Ok(Some(value.to_vec()).filter(|value| !value.is_empty()));
+                }
+                let child = items[path[0] as usize];
+                path = &path[1..];
+                child
+            }
+            2 => {
+                let (prefix, leaf) = decode_hex_prefix(bytes_item(items[0])?)?;
+                if !path.starts_with(&prefix) {
+                    return Ok(None);
+                }
+                path = &path[prefix.len()..];
+                if leaf {
+                    let value = bytes_item(items[1])?;
+                    return Ok(if path.is_empty() {
+                        Some(value.to_vec())
+                    } else {
+                        None
+                    });
+                }
+                items[1]
+            }
+            n => return Err(anyhow!("Node with {} items in proof", n)),
+        };
+        // Short nodes are embedded, others referenced by hash
+        if child.first().map_or(false, |prefix| *prefix >= 0xc0) {
+            node = child;
+        } else {
+            let hash = bytes_item(child)?;
+            if hash.is_empty() {
+                return Ok(None);
+            }
+            node = proof.next().context("Proof ends before the key")?;
+            require!(
+                keccak256(node).to_bytes_be()[..] == *hash,
+                anyhow!("Node of the proof does not match its hash")
+            );
+        }
+    }
+}
+
+fn address_key(address: &U256) -> Vec<u8> {
+    address.to_bytes_be()[12..].to_vec()
+}
+
+fn state_entries<'a>(
+    accounts: impl IntoIterator<Item = (&'a U256, &'a TrieAccount)> + 'a,
+) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+    accounts
+        .into_iter()
+        .filter(|(_, account)| !account.is_empty())
+        .map(|(address, account)| (address_key(address), to_rlp(account).unwrap()))
+}
+
+fn storage_entries<'a>(
+    slots: impl IntoIterator<Item = (&'a U256, &'a U256)> + 'a,
+) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+    slots
+        .into_iter()
+        .filter(|(_, value)| !value.is_zero())
+        .map(|(slot, value)| {
+            (
+                slot.to_bytes_be().to_vec(),
+                to_rlp(&StorageValue(value)).unwrap(),
+            )
+        })
+}
+
+/// Root of the trie with the keys hashed
+fn secure_root(entries: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> U256 {
+    let map = entries
+        .map(|(key, value)| (keccak256(&key).to_bytes_be().to_vec(), value))
+        .collect::<HashMap<_, _>>();
+    let (root, _) = trie::build(&map);
+    U256::from_bytes_be(&root.0)
+}
+
+/// Nodes on the path to `key` in the trie with the keys hashed, starting with
+/// the root
+///
+/// Nodes embedded in their parent are not listed separately.
+fn secure_proof(entries: impl Iterator<Item = (Vec<u8>, Vec<u8>)>, key: &[u8]) -> Vec<Vec<u8>> {
+    let entries = entries
+        .map(|(key, value)| (nibbles(&keccak256(&key).to_bytes_be()), value))
+        .sorted()
+        .collect::<Vec<_>>();
+    let items = entries
+        .iter()
+        .map(|(key, value)| (key.as_slice(), value.as_slice()))
+        .collect::<Vec<_>>();
+    let path = nibbles(&keccak256(key).to_bytes_be());
+    let mut proof = Vec::new();
+    let root = encode_node(&items, Some(&path), &mut proof);
+    proof.push(root);
+    proof.reverse();
+    proof
+}
+
+/// RLP encoding of the node holding `items`, sorted by their remaining key
+///
+/// Nodes on `path` that are referenced by hash are pushed to `proof`, the
+/// deepest first.
+fn encode_node(
+    items: &[(&[u8], &[u8])],
+    path: Option<&[u8]>,
+    proof: &mut Vec<Vec<u8>>,
+) -> Vec<u8> {
+    match items {
+        [] => encode_bytes(&[]),
+        [(key, value)] => {
+            let mut payload = encode_bytes(&hex_prefix(key, true));
+            payload.extend(encode_bytes(value));
+            encode_list(&payload)
+        }
+        _ => {
+            // Keys are sorted, so the first and last share the common prefix
+            let (first, last) = (items[0].0, items[items.len() - 1].0);
+            let shared = first.iter().zip(last).take_while(|(a, b)| a == b).count();
+            if shared > 0 {
+                let rest = items
+                    .iter()
+                    .map(|(key, value)| (&key[shared..], *value))
+                    .collect::<Vec<_>>();
+                let path = path
+                    .filter(|path| path.starts_with(&first[..shared]))
+                    .map(|path| &path[shared..]);
+                let mut payload = encode_bytes(&hex_prefix(&first[..shared], false));
+                let child = encode_node(&rest, path, proof);
+                payload.extend(reference(child, path, proof));
+                return encode_list(&payload);
+            }
+            let mut payload = Vec::new();
+            for nibble in 0..16 {
+                let children = items
+                    .iter()
+                    .filter(|(key, _)| key.first() == Some(&nibble))
+                    .map(|(key, value)| (&key[1..], *value))
+                    .collect::<Vec<_>>();
+                if children.is_empty() {
+                    payload.extend(encode_bytes(&[]));
+                } else {
+                    let path = path
+                        .filter(|path| path.first() == Some(&nibble))
+                        .map(|path| &path[1..]);
+                    let child = encode_node(&children, path, proof);
+                    payload.extend(reference(child, path, proof));
+                }
+            }
+            let value = items.iter().find(|(key, _)| key.is_empty());
+            payload.extend(encode_bytes(value.map_or(&[][..], |(_, value)| value)));
+            encode_list(&payload)
+        }
+    }
+}
+
+/// How a parent refers to a child node: embedded if its encoding is shorter
+/// than a hash, by hash otherwise
+fn reference(node: Vec<u8>, path: Option<&[u8]>, proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    if node.len() < 32 {
+        return node;
+    }
+    let hash = encode_bytes(&keccak256(&node).to_bytes_be());
+    if path.is_some() {
+        proof.push(node);
+    }
+    hash
+}
+
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    to_rlp(&Bytes::from(bytes.to_vec())).unwrap()
+}
+
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|byte| vec![byte >> 4, byte & 0x0f]).collect()
+}
+
+/// Hex-prefix encoding of a path with its kind of node (Yellow Paper
+/// appendix C)
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let (mut result, rest) = if nibbles.len() % 2 == 1 {
+        (vec![((flag + 1) << 4) | nibbles[0]], &nibbles[1..])
+    } else {
+        (vec![flag << 4], nibbles)
+    };
+    result.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    result
+}
+
+/// Path and whether it is a leaf
+fn decode_hex_prefix(bytes: &[u8]) -> AnyResult<(Vec<u8>, bool)> {
+    let first = *bytes.first().context("Empty hex-prefix path")?;
+    let flag = first >> 4;
+    require!(flag < 4, anyhow!("Invalid hex-prefix flag {}", flag));
+    let mut path = if flag % 2 == 1 {
+        vec![first & 0x0f]
+    } else {
+        Vec::new()
+    };
+    path.extend(nibbles(&bytes[1..]));
+    Ok((path, flag >= 2))
+}
+
+/// Encodings of the items of an RLP list
+fn list_items(rlp: &[u8]) -> AnyResult<Vec<&[u8]>> {
+    let mut list = Deserializer::from_rlp(rlp);
+    let payload = list.parse_list()?;
+    list.finish()?;
+    let mut items = Deserializer::from_rlp(payload);
+    let mut result = Vec::new();
+    while !items.is_empty() {
+        result.push(items.parse_item()?);
+    }
+    Ok(result)
+}
+
+fn bytes_item(rlp: &[u8]) -> AnyResult<&[u8]> {
+    let mut item = Deserializer::from_rlp(rlp);
+    let bytes = item.parse_bytes()?;
+    item.finish()?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_empty() {
+        let empty = u256h!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+        assert_eq!(state_root(vec![]), empty);
+        assert_eq!(storage_root(vec![(&U256::one(), &U256::zero())]), empty);
+    }
+
+    #[test]
+    fn test_hex_prefix() {
+        assert_eq!(hex_prefix(&[1, 2, 3], false), vec![0x11, 0x23]);
+        assert_eq!(hex_prefix(&[0, 1, 2, 3], true), vec![0x20, 0x01, 0x23]);
+        assert_eq!(decode_hex_prefix(&[0x3f, 0x1c]).unwrap(), (vec![0xf, 1, 0xc], true));
+    }
+
+    #[test]
+    fn test_proof() {
+        let slots = (0..50_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot * 1000 + 1)))
+            .collect::<Vec<_>>();
+        let slots = || slots.iter().map(|(slot, value)| (slot, value));
+        let root = storage_root(slots());
+        for (slot, value) in slots() {
+            let proof = storage_proof(slots(), slot);
+            assert_eq!(keccak256(&proof[0]), root);
+            let key = slot.to_bytes_be();
+            assert_eq!(
+                verify_proof(&root, &key, &proof).unwrap(),
+                Some(to_rlp(&StorageValue(value)).unwrap())
+            );
+        }
+        // Absent slot
+        let absent = U256::from(1_000);
+        let proof = storage_proof(slots(), &absent);
+        assert_eq!(verify_proof(&root, &absent.to_bytes_be(), &proof).unwrap(), None);
+        // Tampered value
+        let slot = U256::from(7);
+        let mut proof = storage_proof(slots(), &slot);
+        let last = proof.last_mut().unwrap();
+        let index = last.len() - 1;
+        last[index] ^= 1;
+        assert!(verify_proof(&root, &slot.to_bytes_be(), &proof).is_err());
+    }
+
+    #[test]
+    fn test_single_entry_proof() {
+        let value = U256::from(0x2a);
+        let proof = storage_proof(vec![(&U256::one(), &value)], &U256::one());
+        assert_eq!(proof.len(), 1);
+        let root = storage_root(vec![(&U256::one(), &value)]);
+        assert_eq!(
+            verify_proof(&root, &U256::one().to_bytes_be(), &proof).unwrap(),
+            Some(vec![0x2a])
+        );
+    }
+
+    #[test]
+    fn test_storage_root() {
+        assert_eq!(
+            storage_root(vec![(&U256::one(), &U256::from(0x2a))]),
+            u256h!("fcbdb9e7191a6bc6efbe2e1903a50bd3c79312366db1e46acf7e94788c2b4c3e")
+        );
+    }
+}
@@ -11,6 +11,23 @@ impl From<[u8; 20]> for Address {
     }
 }
 
+impl From<&Address> for U256 {
+    fn from(address: &Address) -> Self {
+        let mut bytes = [0_u8; 32];
+        bytes[12..].copy_from_slice(&address.0);
+        Self::from_bytes_be(&bytes)
+    }
+}
+
+/// Truncates to the lower 160 bits.
+impl From<&U256> for Address {
+    fn from(value: &U256) -> Self {
+        let mut result = [0_u8; 20];
+        result.copy_from_slice(&value.to_bytes_be()[12..]);
+        Self(result)
+    }
+}
+
 impl Address {
     pub fn to_array(self) -> [u8; 20] {
         self.0
@@ -69,4 +86,15 @@ mod test {
         let de: Address = from_value(json).unwrap();
         assert_eq!(de, obj);
     }
+
+    #[test]
+    fn test_u256_round_trip() {
+        let address = Address::from(hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6"));
+        let value = U256::from(&address);
+        assert_eq!(
+            value,
+            u256h!("0000000000000000000000000f572e5295c57f15886f9b263e2f6d2d6c7b5ec6")
+        );
+        assert_eq!(Address::from(&value), address);
+    }
 }
@@ -1,4 +1,4 @@
-use super::{BlockHeader, RpcTransaction, Transaction};
+use super::{rpc::Hex, BlockHeader, RpcTransaction, Transaction};
 use crate::{
     prelude::*,
     serde::{fixed_u256, short_u64},
@@ -31,10 +31,31 @@ pub struct RpcBlock<T> {
     pub total_difficulty: u64,
     #[serde(with = "short_u64")]
     pub size:             u64,
+    /// Only present from London onwards (EIP-1559).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee_per_gas: Option<Hex<U256>>,
     pub transactions:     Vec<T>,
     #[serde(rename = "uncles")]
     pub ommers:           Vec<U256>,
 }
 
+/// Transaction of a [`RpcBlock`], either the hash or the full transaction as
+/// requested.
+#[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockTransaction {
+    Hash(#[serde(with = "fixed_u256")] U256),
+    Full(Box<RpcTransaction>),
+}
+
+impl BlockTransaction {
+    pub fn hash(&self) -> &U256 {
+        match self {
+            Self::Hash(hash) => hash,
+            Self::Full(transaction) => &transaction.hash,
+        }
+    }
+}
+
 pub type ConciseBlock = RpcBlock<U256>;
 pub type FullBlock = RpcBlock<RpcTransaction>;
@@ -1,4 +1,5 @@
-use crate::{prelude::*, serde::fixed256};
+use super::Address;
+use crate::{prelude::*, serde::fixed256, utils::keccak256};
 use std::{fmt, fmt::Debug};
 
 #[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Serialize, Deserialize)]
@@ -8,6 +9,51 @@ impl BloomFilter {
     pub fn empty() -> Self {
         Self([0; 256])
     }
+
+    /// Add an address or topic to the filter
+    ///
+    /// Sets three bits selected by the hash of the input, see the
+    /// yellow paper section 4.3.1.
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (index, mask) in &Self::bits(input) {
+            self.0[*index] |= mask;
+        }
+    }
+
+    /// Add the address and topics of a log
+    pub fn accrue_log(&mut self, address: &Address, topics: &[U256]) {
+        self.accrue(address.as_slice());
+        for topic in topics {
+            self.accrue(&topic.to_bytes_be());
+        }
+    }
+
+    /// Add everything added to `other`, e.g. the receipts of a block
+    pub fn accrue_bloom(&mut self, other: &Self) {
+        for (byte, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *byte |= other;
+        }
+    }
+
+    /// Whether the input may have been added
+    ///
+    /// False positives are possible, false negatives are not.
+    pub fn contains(&self, input: &[u8]) -> bool {
+        Self::bits(input)
+            .iter()
+            .all(|(index, mask)| self.0[*index] & mask != 0)
+    }
+
+    /// Byte index and mask of the three bits for the input
+    fn bits(input: &[u8]) -> [(usize, u8); 3] {
+        let hash = keccak256(input).to_bytes_be();
+        let mut result = [(0, 0); 3];
+        for (i, bit) in result.iter_mut().enumerate() {
+            let position = (usize::from(hash[2 * i]) << 8 | usize::from(hash[2 * i + 1])) & 2047;
+            *bit = (255 - position / 8, 1 << (position % 8));
+        }
+        result
+    }
 }
 
 impl From<[u8; 256]> for BloomFilter {
@@ -27,3 +73,45 @@ impl Debug for BloomFilter {
         write!(f, "BloomFilter::from(hex!(\"{}\"))", hex::encode(self.0))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_accrue() {
+        let mut bloom = BloomFilter::empty();
+        bloom.accrue(b"testtest");
+        let bits = bloom.0.iter().copied().map(u8::count_ones).sum::<u32>();
+        assert!((1..=3).contains(&bits));
+        assert!(bloom.contains(b"testtest"));
+        assert!(!BloomFilter::empty().contains(b"testtest"));
+    }
+
+    #[test]
+    fn test_accrue_log() {
+        let address = Address::from(hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6"));
+        let mut bloom = BloomFilter::empty();
+        bloom.accrue_log(&address, &[U256::from(7)]);
+        let mut expected = [0_u8; 256];
+        for (index, mask) in &[(90, 8), (202, 128), (91, 128), (50, 16), (218, 1), (66, 32)] {
+            expected[*index] |= mask;
+        }
+        assert_eq!(bloom, BloomFilter::from(expected));
+        assert!(bloom.contains(address.as_slice()));
+        assert!(bloom.contains(&U256::from(7).to_bytes_be()));
+        assert!(!bloom.contains(&U256::from(8).to_bytes_be()));
+    }
+
+    #[test]
+    fn test_accrue_bloom() {
+        let mut first = BloomFilter::empty();
+        first.accrue(b"first");
+        let mut second = BloomFilter::empty();
+        second.accrue(b"second");
+        first.accrue_bloom(&second);
+        assert!(first.contains(b"first"));
+        assert!(first.contains(b"second"));
+    }
+}
@@ -3,12 +3,14 @@ mod block;
 mod block_header;
 mod bloom_filter;
 pub mod rpc;
+mod signed_transaction;
 mod transaction;
 
 pub use self::{
     address::Address,
-    block::{Block, ConciseBlock, FullBlock},
+    block::{Block, BlockTransaction, ConciseBlock, FullBlock, RpcBlock},
     block_header::BlockHeader,
     bloom_filter::BloomFilter,
+    signed_transaction::{AccessListItem, SignedTransaction},
     transaction::{RpcTransaction, Transaction},
 };
@@ -1,11 +1,43 @@
-use super::super::{Address, Transaction};
+use super::{super::Address, Bytes, Hex};
 use crate::prelude::*;
 
 /// Call request
+///
+/// Omitted `from` defaults to the zero address and omitted `value` to zero.
+///
+/// See <https://eth.wiki/json-rpc/API#eth_call>
 #[allow(clippy::module_name_repetitions)]
 #[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct CallRequest {
-    pub from:        Address,
-    pub transaction: Transaction,
+    pub from:      Address,
+    pub to:        Option<Address>,
+    pub gas:       Option<Hex<u64>>,
+    pub gas_price: Option<Hex<U256>>,
+    pub value:     Hex<U256>,
+    #[serde(alias = "input")]
+    pub data:      Bytes,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+    use serde_json::{from_value, json};
+
+    #[test]
+    fn test_decode_defaults() {
+        let de: CallRequest = from_value(json!({
+            "to": "0x0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6",
+            "data": "0x70a08231"
+        }))
+        .unwrap();
+        assert_eq!(de, CallRequest {
+            to: Some(Address::from(hex!(
+                "0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6"
+            ))),
+            data: hex!("70a08231").to_vec().into(),
+            ..CallRequest::default()
+        });
+    }
 }
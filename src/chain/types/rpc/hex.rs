@@ -1,10 +1,12 @@
-use super::Hexable;
+use super::{Hexable, Prefix};
 use crate::prelude::*;
 use serde::{de, ser};
 use std::{fmt, marker::PhantomData};
 
 /// Serialize number types as hex strings with prefix and no leading zeros.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+///
+/// Byte arrays keep their leading zeros, see [`Hexable`].
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
 pub struct Hex<T: Hexable>(T);
 
 impl<T: Hexable> From<T> for Hex<T> {
@@ -50,15 +52,16 @@ impl<'de, T: Hexable> Deserialize<'de> for Hex<T> {
             type Value = Hex<T>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                write!(formatter, "a hexadeximal number string")
+                write!(formatter, "a 0x prefixed hexadecimal string")
             }
 
             fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                let t = <T as Hexable>::from_hex(s)
-                    .map_err(|_err| de::Error::invalid_value(de::Unexpected::Str(s), &self))?;
+                let t = <T as Hexable>::parse_hex(s, Prefix::Required).map_err(|err| {
+                    de::Error::custom(format_args!("invalid hex string {:?}: {}", s, err))
+                })?;
                 Ok(Hex(t))
             }
         }
@@ -116,4 +119,45 @@ mod test {
         let de: Hex<U256> = from_value(json).unwrap();
         assert_eq!(de, obj);
     }
+
+    #[test]
+    fn test_address_zero() {
+        let obj = Hex([0_u8; 20]);
+        let json = to_value(&obj).unwrap();
+        assert_eq!(&json, &json!("0x0000000000000000000000000000000000000000"));
+        let de: Hex<[u8; 20]> = from_value(json).unwrap();
+        assert_eq!(de, obj);
+    }
+
+    #[test]
+    fn test_bytes_leading_zeros() {
+        let obj = Hex(vec![0_u8, 0, 1]);
+        let json = to_value(&obj).unwrap();
+        assert_eq!(&json, &json!("0x000001"));
+        let de: Hex<Vec<u8>> = from_value(json).unwrap();
+        assert_eq!(de, obj);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |json| from_value::<Hex<U256>>(json).unwrap_err().to_string();
+        assert_eq!(
+            error(json!("0xzz")),
+            "invalid hex string \"0xzz\": invalid hexadecimal digit 'z'"
+        );
+        assert_eq!(
+            error(json!("0x")),
+            "invalid hex string \"0x\": empty hexadecimal string"
+        );
+        assert_eq!(
+            error(json!("2a")),
+            "invalid hex string \"2a\": missing 0x prefix"
+        );
+        assert_eq!(
+            from_value::<Hex<Vec<u8>>>(json!("0xabc"))
+                .unwrap_err()
+                .to_string(),
+            "invalid hex string \"0xabc\": odd number of hexadecimal digits"
+        );
+    }
 }
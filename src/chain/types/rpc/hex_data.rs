@@ -0,0 +1,108 @@
+use super::{Hexable, Prefix};
+use crate::prelude::*;
+use serde::{de, ser};
+use std::fmt;
+
+/// Serialize bytes as hex strings with prefix and two digits for every byte.
+///
+/// This is the DATA encoding of JSON-RPC. Unlike the QUANTITY encoding of
+/// [`super::Hex`] leading zeros are kept, so a single zero byte is `0x00`
+/// and no bytes are `0x`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct HexData(Vec<u8>);
+
+impl From<Vec<u8>> for HexData {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&[u8]> for HexData {
+    fn from(value: &[u8]) -> Self {
+        Self(value.to_vec())
+    }
+}
+
+impl HexData {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for HexData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for HexData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.0.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for HexData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = HexData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a 0x prefixed hexadecimal string")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                let bytes = Vec::<u8>::parse_hex(s, Prefix::Required).map_err(|err| {
+                    de::Error::custom(format_args!("invalid hex string {:?}: {}", s, err))
+                })?;
+                Ok(HexData(bytes))
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::Hex, *};
+    use crate::test::prelude::assert_eq;
+    use serde_json::{from_value, json, to_value};
+
+    #[test]
+    fn test_zero_byte() {
+        let obj = HexData::from(vec![0_u8]);
+        let json = to_value(&obj).unwrap();
+        assert_eq!(&json, &json!("0x00"));
+        let de: HexData = from_value(json).unwrap();
+        assert_eq!(de, obj);
+        // The quantity zero is encoded differently
+        assert_eq!(to_value(Hex::from(U256::zero())).unwrap(), json!("0x0"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let obj = HexData::default();
+        let json = to_value(&obj).unwrap();
+        assert_eq!(&json, &json!("0x"));
+        let de: HexData = from_value(json).unwrap();
+        assert_eq!(de, obj);
+    }
+
+    #[test]
+    fn test_odd_length() {
+        assert!(from_value::<HexData>(json!("0x0")).is_err());
+    }
+}
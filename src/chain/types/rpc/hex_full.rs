@@ -1,4 +1,4 @@
-use super::Hexable;
+use super::{Hexable, Prefix};
 use crate::prelude::*;
 use serde::{de, ser};
 use std::{fmt, marker::PhantomData};
@@ -39,7 +39,7 @@ impl<T: Hexable> Serialize for HexFull<T> {
         let hex_str = self.as_ref().to_hex();
         let nibbles = &hex_str[2..];
         let target = T::nibbles();
-        let padding = target - nibbles.len();
+        let padding = target.saturating_sub(nibbles.len());
         let mut result = String::from("0x");
         result.extend(std::iter::repeat('0').take(padding));
         result.push_str(nibbles);
@@ -57,15 +57,16 @@ impl<'de, T: Hexable> Deserialize<'de> for HexFull<T> {
             type Value = HexFull<T>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                write!(formatter, "a hexadeximal number string")
+                write!(formatter, "a 0x prefixed hexadecimal string")
             }
 
             fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                let t = <T as Hexable>::from_hex(s)
-                    .map_err(|_err| de::Error::invalid_value(de::Unexpected::Str(s), &self))?;
+                let t = <T as Hexable>::parse_hex(s, Prefix::Required).map_err(|err| {
+                    de::Error::custom(format_args!("invalid hex string {:?}: {}", s, err))
+                })?;
                 Ok(HexFull(t))
             }
         }
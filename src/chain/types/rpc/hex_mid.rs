@@ -1,4 +1,4 @@
-use super::Hexable;
+use super::{Hexable, Prefix};
 use crate::prelude::*;
 use serde::{de, ser};
 use std::{fmt, marker::PhantomData};
@@ -54,22 +54,35 @@ impl<'de> Deserialize<'de> for HexMid {
             type Value = HexMid;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                write!(formatter, "a hexadecimal number string")
+                write!(formatter, "a hexadecimal number string with optional prefix")
             }
 
             fn visit_str<E>(self, str: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                let str = str.strip_prefix("0x").unwrap_or(str);
-                let mut nibbles = [b'0'; 64];
-                let zeros = nibbles.len() - str.len();
-                nibbles[zeros..].copy_from_slice(str.as_bytes());
-                let mut result = [0; 32];
-                hex::decode_to_slice(&nibbles, &mut result).map_err(de::Error::custom)?;
-                Ok(U256::from_bytes_be(&result).into())
+                let value = U256::parse_hex(str, Prefix::Optional).map_err(|err| {
+                    de::Error::custom(format_args!("invalid hex string {:?}: {}", str, err))
+                })?;
+                Ok(value.into())
             }
         }
         deserializer.deserialize_str(Visitor)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+    use serde_json::{from_value, json};
+
+    #[test]
+    fn test_optional_prefix() {
+        let parse = |json| from_value::<HexMid>(json).map(HexMid::into_inner);
+        assert_eq!(parse(json!("0x2a")).unwrap(), U256::from(42));
+        assert_eq!(parse(json!("2a")).unwrap(), U256::from(42));
+        assert!(parse(json!("0xzz")).is_err());
+        assert!(parse(json!(format!("0x1{}", "0".repeat(64)))).is_err());
+    }
+}
@@ -1,12 +1,59 @@
 use crate::prelude::*;
-use std::num::ParseIntError;
 
+/// Error parsing a hexadecimal string
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum HexError {
+    #[error("empty hexadecimal string")]
+    Empty,
+    #[error("missing 0x prefix")]
+    MissingPrefix,
+    #[error("invalid hexadecimal digit {0:?}")]
+    InvalidDigit(char),
+    #[error("odd number of hexadecimal digits")]
+    OddLength,
+    #[error("expected {expected} hexadecimal digits, got {actual}")]
+    Length { expected: usize, actual: usize },
+    #[error("number too large")]
+    Overflow,
+}
+
+/// Whether parsing requires the `0x` prefix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prefix {
+    Required,
+    Optional,
+}
+
+/// Types with a `0x` prefixed hexadecimal encoding
+///
+/// Numbers are encoded without leading zeros, byte arrays with two digits for
+/// every byte.
 pub trait Hexable: Sized {
+    /// Number of digits in the full width encoding, zero for variable length
+    /// types.
     fn nibbles() -> usize;
 
     fn to_hex(&self) -> String;
 
-    fn from_hex(str: &str) -> Result<Self, ParseIntError>;
+    /// Parse digits without prefix, which are known to be hexadecimal.
+    fn from_digits(digits: &str) -> Result<Self, HexError>;
+
+    /// Parse with an optional `0x` prefix
+    fn from_hex(str: &str) -> Result<Self, HexError> {
+        Self::parse_hex(str, Prefix::Optional)
+    }
+
+    fn parse_hex(str: &str, prefix: Prefix) -> Result<Self, HexError> {
+        let digits = match (str.strip_prefix("0x"), prefix) {
+            (Some(digits), _) => digits,
+            (None, Prefix::Optional) => str,
+            (None, Prefix::Required) => return Err(HexError::MissingPrefix),
+        };
+        if let Some(invalid) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(HexError::InvalidDigit(invalid));
+        }
+        Self::from_digits(digits)
+    }
 }
 
 #[allow(clippy::use_self)] // False positive due to macro expansion?
@@ -19,9 +66,11 @@ impl Hexable for u64 {
         format!("{:#x}", self)
     }
 
-    fn from_hex(str: &str) -> Result<Self, ParseIntError> {
-        let str = str.strip_prefix("0x").unwrap_or(str);
-        Self::from_str_radix(str, 16)
+    fn from_digits(digits: &str) -> Result<Self, HexError> {
+        if digits.is_empty() {
+            return Err(HexError::Empty);
+        }
+        Self::from_str_radix(digits, 16).map_err(|_| HexError::Overflow)
     }
 }
 
@@ -42,8 +91,110 @@ impl Hexable for U256 {
         format!("0x{}", str)
     }
 
-    fn from_hex(str: &str) -> Result<Self, ParseIntError> {
-        let str = str.strip_prefix("0x").unwrap_or(str);
-        Ok(Self::from_hex_str(str))
+    fn from_digits(digits: &str) -> Result<Self, HexError> {
+        if digits.is_empty() {
+            return Err(HexError::Empty);
+        }
+        let digits = digits.trim_start_matches('0');
+        if digits.len() > 64 {
+            return Err(HexError::Overflow);
+        }
+        Ok(Self::from_hex_str(if digits.is_empty() { "0" } else { digits }))
+    }
+}
+
+/// Addresses, with all leading zeros
+impl Hexable for [u8; 20] {
+    fn nibbles() -> usize {
+        40
+    }
+
+    fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self))
+    }
+
+    fn from_digits(digits: &str) -> Result<Self, HexError> {
+        if digits.len() != Self::nibbles() {
+            return Err(HexError::Length {
+                expected: Self::nibbles(),
+                actual:   digits.len(),
+            });
+        }
+        let mut result = [0_u8; 20];
+        hex::decode_to_slice(digits, &mut result).map_err(|_| HexError::OddLength)?;
+        Ok(result)
+    }
+}
+
+impl Hexable for Vec<u8> {
+    fn nibbles() -> usize {
+        0
+    }
+
+    fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self))
+    }
+
+    fn from_digits(digits: &str) -> Result<Self, HexError> {
+        hex::decode(digits).map_err(|_| HexError::OddLength)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_u256_from_hex() {
+        assert_eq!(U256::from_hex("0x2a").unwrap(), U256::from(42));
+        assert_eq!(U256::from_hex("0x0").unwrap(), U256::zero());
+        assert_eq!(
+            U256::from_hex(&format!("0x{:0>70}", "1")).unwrap(),
+            U256::one()
+        );
+        assert_eq!(U256::from_hex("0xzz"), Err(HexError::InvalidDigit('z')));
+        assert_eq!(U256::from_hex("0x"), Err(HexError::Empty));
+        assert_eq!(
+            U256::from_hex(&format!("0x1{}", "0".repeat(64))),
+            Err(HexError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_address_keeps_width() {
+        assert_eq!(
+            [0_u8; 20].to_hex(),
+            "0x0000000000000000000000000000000000000000"
+        );
+        assert_eq!(
+            <[u8; 20]>::from_hex("0x0000000000000000000000000000000000000000"),
+            Ok([0_u8; 20])
+        );
+        assert_eq!(
+            <[u8; 20]>::from_hex("0x0"),
+            Err(HexError::Length {
+                expected: 40,
+                actual:   1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!(vec![0_u8].to_hex(), "0x00");
+        assert_eq!(Vec::<u8>::new().to_hex(), "0x");
+        assert_eq!(Vec::<u8>::from_hex("0x00ff"), Ok(vec![0x00, 0xff]));
+        assert_eq!(Vec::<u8>::from_hex("0x0"), Err(HexError::OddLength));
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(u64::parse_hex("2a", Prefix::Optional), Ok(42));
+        assert_eq!(u64::parse_hex("0x2a", Prefix::Required), Ok(42));
+        assert_eq!(
+            u64::parse_hex("2a", Prefix::Required),
+            Err(HexError::MissingPrefix)
+        );
     }
 }
@@ -1,4 +1,4 @@
-use super::{super::Address, Hex};
+use super::{super::Address, Hex, HexData};
 use crate::prelude::*;
 
 /// See <https://eth.wiki/json-rpc/API#eth_getfilterchanges>
@@ -7,7 +7,7 @@ use crate::prelude::*;
 pub struct Log {
     pub address: Address,
     pub topics:  Vec<U256>,
-    pub data:    Vec<u8>,
+    pub data:    HexData,
     pub removed: bool,
 
     #[serde(flatten)]
@@ -1,5 +1,8 @@
-use super::{BlockNumber, ValueOrArray};
-use crate::{chain::types::Address, prelude::*};
+use super::{BlockNumber, Log, ValueOrArray};
+use crate::{
+    chain::types::{Address, BloomFilter},
+    prelude::*,
+};
 
 /// Log Filter
 #[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -13,6 +16,39 @@ pub struct LogFilter {
     pub limit:      Option<usize>,
 }
 
+impl LogFilter {
+    /// Whether the address and topics of `log` match the filter
+    ///
+    /// Every topic position matches any of the given values, or anything if
+    /// `null`. Logs with fewer topics than the filter do not match. Blocks are
+    /// left to the caller.
+    pub fn matches(&self, log: &Log) -> bool {
+        let address = self
+            .address
+            .as_ref()
+            .map_or(true, |address| address.matches(&log.address));
+        let topics = self.topics.as_ref().map_or(true, |topics| {
+            topics.len() <= log.topics.len()
+                && topics
+                    .iter()
+                    .zip(&log.topics)
+                    .all(|(filter, topic)| filter.matches(topic))
+        });
+        address && topics
+    }
+
+    /// Whether a block with the bloom filter may contain matching logs
+    pub fn may_match(&self, bloom: &BloomFilter) -> bool {
+        let address = self.address.as_ref().map_or(true, |address| {
+            address.is_empty() || address.iter().any(|address| bloom.contains(address.as_slice()))
+        });
+        let topics = self.topics.iter().flatten().all(|topic| {
+            topic.is_empty() || topic.iter().any(|topic| bloom.contains(&topic.to_bytes_be()))
+        });
+        address && topics
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -37,4 +73,36 @@ mod test {
         }))
         .unwrap();
     }
+
+    #[test]
+    fn test_matches_topics() {
+        let log = Log {
+            topics: vec![U256::from(1), U256::from(2)],
+            ..Log::default()
+        };
+        let filter = |topics: serde_json::Value| -> LogFilter {
+            from_value(json!({ "topics": topics })).unwrap()
+        };
+        let one = "0x0000000000000000000000000000000000000000000000000000000000000001";
+        let two = "0x0000000000000000000000000000000000000000000000000000000000000002";
+        assert!(filter(json!([one])).matches(&log));
+        assert!(filter(json!([null, two])).matches(&log));
+        assert!(filter(json!([[two, one], [one, two]])).matches(&log));
+        assert!(!filter(json!([two])).matches(&log));
+        assert!(!filter(json!([one, two, null])).matches(&log));
+    }
+
+    #[test]
+    fn test_may_match() {
+        let mut bloom = BloomFilter::empty();
+        bloom.accrue_log(&Address::default(), &[U256::from(1)]);
+        let filter = |topics: serde_json::Value| -> LogFilter {
+            from_value(json!({ "topics": topics })).unwrap()
+        };
+        let one = "0x0000000000000000000000000000000000000000000000000000000000000001";
+        let two = "0x0000000000000000000000000000000000000000000000000000000000000002";
+        assert!(filter(json!([null, [two, one]])).may_match(&bloom));
+        assert!(!filter(json!([two])).may_match(&bloom));
+        assert!(LogFilter::default().may_match(&bloom));
+    }
 }
@@ -7,12 +7,15 @@ mod bytes;
 mod call;
 mod genesis_config;
 mod hex;
+mod hex_data;
 mod hex_full;
 mod hex_mid;
 mod hexable;
 mod log;
 mod log_filter;
+mod proof;
 mod storage_range;
+mod trace;
 mod transaction;
 mod transaction_receipt;
 mod value_or_array;
@@ -24,12 +27,15 @@ pub use self::{
     call::CallRequest,
     genesis_config::GenesisConfig,
     hex::Hex,
+    hex_data::HexData,
     hex_full::HexFull,
     hex_mid::HexMid,
-    hexable::Hexable,
+    hexable::{HexError, Hexable, Prefix},
     log::{Log, LogBlock},
     log_filter::LogFilter,
+    proof::{AccountProof, StorageProof},
     storage_range::{StorageRange, StorageSlot},
+    trace::{StructLog, TraceConfig, TransactionTrace},
     transaction::Transaction,
     transaction_receipt::{TransactionReceipt, TransactionStatus},
     value_or_array::ValueOrArray,
@@ -0,0 +1,29 @@
+use super::{super::Address, Bytes, Hex};
+use crate::prelude::*;
+
+/// Result of `eth_getProof`
+///
+/// See <https://eips.ethereum.org/EIPS/eip-1186>
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_field_names)] // Field names of EIP-1186
+pub struct AccountProof {
+    pub address:       Address,
+    /// RLP encoded nodes of the state trie from the root to the account
+    pub account_proof: Vec<Bytes>,
+    pub balance:       Hex<U256>,
+    pub code_hash:     U256,
+    pub nonce:         Hex<u64>,
+    pub storage_hash:  U256,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+/// Proof of a storage slot against the storage hash of its account
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageProof {
+    pub key:   Hex<U256>,
+    pub value: Hex<U256>,
+    /// RLP encoded nodes of the storage trie from the root to the slot
+    pub proof: Vec<Bytes>,
+}
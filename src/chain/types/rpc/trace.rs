@@ -0,0 +1,90 @@
+use crate::prelude::*;
+use std::path::PathBuf;
+
+/// Options for `debug_traceTransaction`
+///
+/// See <https://geth.ethereum.org/docs/rpc/ns-debug#debug_tracetransaction>
+#[allow(clippy::module_name_repetitions)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TraceConfig {
+    pub disable_stack:  bool,
+    pub disable_memory: bool,
+    /// Write the trace to a new file as it is produced instead of returning
+    /// the struct logs, for transactions whose trace does not fit in memory.
+    /// Not supported by Geth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_file:    Option<PathBuf>,
+}
+
+/// Result of `debug_traceTransaction` in Geth's struct log format
+#[allow(clippy::module_name_repetitions)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTrace {
+    pub gas:          u64,
+    pub failed:       bool,
+    /// Hex encoded without `0x` prefix, like Geth.
+    pub return_value: String,
+    pub struct_logs:  Vec<StructLog>,
+}
+
+/// State before executing an instruction
+///
+/// Stack values and memory words are hex encoded without `0x` prefix.
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc:       u64,
+    pub op:       String,
+    pub gas:      u64,
+    pub gas_cost: u64,
+    pub depth:    u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack:    Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory:   Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+    use serde_json::{from_value, json, to_value};
+
+    #[test]
+    fn test_decode_config() {
+        let de: TraceConfig = from_value(json!({ "disableStack": true })).unwrap();
+        assert_eq!(de, TraceConfig {
+            disable_stack:  true,
+            disable_memory: false,
+            output_file:    None,
+        });
+        let de: TraceConfig = from_value(json!({ "outputFile": "/tmp/trace.json" })).unwrap();
+        assert_eq!(de.output_file, Some(PathBuf::from("/tmp/trace.json")));
+    }
+
+    #[test]
+    fn test_encode_struct_log() {
+        let log = StructLog {
+            pc:       2,
+            op:       "ADD".into(),
+            gas:      100,
+            gas_cost: 3,
+            depth:    1,
+            stack:    Some(vec!["01".into()]),
+            memory:   None,
+        };
+        assert_eq!(
+            to_value(&log).unwrap(),
+            json!({
+                "pc": 2,
+                "op": "ADD",
+                "gas": 100,
+                "gasCost": 3,
+                "depth": 1,
+                "stack": ["01"],
+            })
+        );
+    }
+}
@@ -24,6 +24,21 @@ impl<T: Clone> FromIterator<T> for ValueOrArray<T> {
     }
 }
 
+impl<T: Clone + PartialEq> ValueOrArray<T> {
+    /// Whether `value` is one of the values, `null` matches anything.
+    pub fn matches(&self, value: &T) -> bool {
+        self.0.is_empty() || self.0.contains(value)
+    }
+}
+
+impl<T: Clone> std::ops::Deref for ValueOrArray<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 enum Representation<T: Clone> {
@@ -86,4 +101,13 @@ mod test {
         let de: ValueOrArray<u32> = from_value(json).unwrap();
         assert_eq!(de, obj);
     }
+
+    #[test]
+    fn test_matches() {
+        let none = ValueOrArray::<u32>::default();
+        assert!(none.matches(&1));
+        let many = vec![1, 2].into_iter().collect::<ValueOrArray<u32>>();
+        assert!(many.matches(&2));
+        assert!(!many.matches(&3));
+    }
 }
@@ -0,0 +1,462 @@
+//! Signed transactions in their raw encoding, see EIP-2718
+
+use super::Address;
+use crate::{
+    evm::{
+        precompiles::{ecrecover, keccak256},
+        TransactionInfo,
+    },
+    prelude::*,
+    serde::{
+        bytes,
+        rlp::{de::Deserializer, encode_list, from_rlp, to_rlp},
+        short_u256, short_u64,
+    },
+};
+use secp256k1::{Message, SecretKey};
+
+/// Type byte of access list transactions (EIP-2930)
+const ACCESS_LIST_TYPE: u8 = 1;
+
+/// Type byte of dynamic fee transactions (EIP-1559)
+const DYNAMIC_FEE_TYPE: u8 = 2;
+
+/// Account and storage slots a transaction declares it will access
+#[derive(Clone, Default, PartialEq, PartialOrd, Eq, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address:      Address,
+    #[serde(with = "storage_keys")]
+    pub storage_keys: Vec<U256>,
+}
+
+/// Transaction of any type, with the sender recovered from its signature
+///
+/// Legacy and access list transactions have the gas price as both fee caps.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct SignedTransaction {
+    pub hash:                     U256,
+    pub sender:                   Address,
+    /// Chain the signature is valid on, any chain for legacy transactions
+    /// without EIP-155 replay protection.
+    pub chain_id:                 Option<u64>,
+    pub nonce:                    u64,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas:          U256,
+    pub gas_limit:                u64,
+    /// `None` for contract creation
+    pub to:                       Option<Address>,
+    pub value:                    U256,
+    pub data:                     Vec<u8>,
+    pub access_list:              Vec<AccessListItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Legacy {
+    #[serde(with = "short_u64")]
+    nonce:     u64,
+    #[serde(with = "short_u256")]
+    gas_price: U256,
+    #[serde(with = "short_u64")]
+    gas_limit: u64,
+    #[serde(with = "recipient")]
+    to:        Option<Address>,
+    #[serde(with = "short_u256")]
+    value:     U256,
+    #[serde(with = "bytes")]
+    data:      Vec<u8>,
+    #[serde(with = "short_u64")]
+    v:         u64,
+    #[serde(with = "short_u256")]
+    r:         U256,
+    #[serde(with = "short_u256")]
+    s:         U256,
+}
+
+#[derive(Deserialize)]
+#[allow(clippy::struct_field_names)] // Field names of the encoding
+struct AccessList {
+    #[serde(with = "short_u64")]
+    chain_id:    u64,
+    #[serde(with = "short_u64")]
+    nonce:       u64,
+    #[serde(with = "short_u256")]
+    gas_price:   U256,
+    #[serde(with = "short_u64")]
+    gas_limit:   u64,
+    #[serde(with = "recipient")]
+    to:          Option<Address>,
+    #[serde(with = "short_u256")]
+    value:       U256,
+    #[serde(with = "bytes")]
+    data:        Vec<u8>,
+    access_list: Vec<AccessListItem>,
+    #[serde(with = "short_u64")]
+    y_parity:    u64,
+    #[serde(with = "short_u256")]
+    r:           U256,
+    #[serde(with = "short_u256")]
+    s:           U256,
+}
+
+#[derive(Deserialize)]
+struct DynamicFee {
+    #[serde(with = "short_u64")]
+    chain_id:                 u64,
+    #[serde(with = "short_u64")]
+    nonce:                    u64,
+    #[serde(with = "short_u256")]
+    max_priority_fee_per_gas: U256,
+    #[serde(with = "short_u256")]
+    max_fee_per_gas:          U256,
+    #[serde(with = "short_u64")]
+    gas_limit:                u64,
+    #[serde(with = "recipient")]
+    to:                       Option<Address>,
+    #[serde(with = "short_u256")]
+    value:                    U256,
+    #[serde(with = "bytes")]
+    data:                     Vec<u8>,
+    access_list:              Vec<AccessListItem>,
+    #[serde(with = "short_u64")]
+    y_parity:                 u64,
+    #[serde(with = "short_u256")]
+    r:                        U256,
+    #[serde(with = "short_u256")]
+    s:                        U256,
+}
+
+impl SignedTransaction {
+    /// Decode a legacy RLP list or a typed transaction envelope
+    pub fn decode(raw: &[u8]) -> AnyResult<Self> {
+        let hash = keccak256(raw);
+        let transaction = match raw.first() {
+            None => return Err(anyhow!("Empty transaction")),
+            Some(prefix) if *prefix >= 0xc0 => Self::decode_legacy(raw)?,
+            Some(&ACCESS_LIST_TYPE) => {
+                let payload = &raw[1..];
+                let tx: AccessList = from_rlp(payload)?;
+                let signing_hash = typed_signing_hash(ACCESS_LIST_TYPE, payload)?;
+                Self {
+                    sender: recover(&signing_hash, tx.y_parity, &tx.r, &tx.s)?,
+                    chain_id: Some(tx.chain_id),
+                    nonce: tx.nonce,
+                    max_priority_fee_per_gas: tx.gas_price.clone(),
+                    max_fee_per_gas: tx.gas_price,
+                    gas_limit: tx.gas_limit,
+                    to: tx.to,
+                    value: tx.value,
+                    data: tx.data,
+                    access_list: tx.access_list,
+                    ..Self::default()
+                }
+            }
+            Some(&DYNAMIC_FEE_TYPE) => {
+                let payload = &raw[1..];
+                let tx: DynamicFee = from_rlp(payload)?;
+                require!(
+                    tx.max_priority_fee_per_gas <= tx.max_fee_per_gas,
+                    anyhow!("Max priority fee per gas is higher than max fee per gas")
+                );
+                let signing_hash = typed_signing_hash(DYNAMIC_FEE_TYPE, payload)?;
+                Self {
+                    sender: recover(&signing_hash, tx.y_parity, &tx.r, &tx.s)?,
+                    chain_id: Some(tx.chain_id),
+                    nonce: tx.nonce,
+                    max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+                    max_fee_per_gas: tx.max_fee_per_gas,
+                    gas_limit: tx.gas_limit,
+                    to: tx.to,
+                    value: tx.value,
+                    data: tx.data,
+                    access_list: tx.access_list,
+                    ..Self::default()
+                }
+            }
+            Some(kind) => return Err(anyhow!("Unsupported transaction type {}", kind)),
+        };
+        Ok(Self { hash, ..transaction })
+    }
+
+    /// Sign as a legacy transaction and return the raw encoding
+    ///
+    /// Uses replay protection (EIP-155) if the chain id is set. The fee caps
+    /// must be equal.
+    pub fn sign_legacy(&self, secret_key: &SecretKey) -> AnyResult<Vec<u8>> {
+        require!(
+            self.max_fee_per_gas == self.max_priority_fee_per_gas,
+            anyhow!("Legacy transactions have a single gas price")
+        );
+        let mut tx = Legacy {
+            nonce:     self.nonce,
+            gas_price: self.max_fee_per_gas.clone(),
+            gas_limit: self.gas_limit,
+            to:        self.to.clone(),
+            value:     self.value.clone(),
+            data:      self.data.clone(),
+            v:         self.chain_id.unwrap_or_default(),
+            r:         U256::zero(),
+            s:         U256::zero(),
+        };
+        let unsigned = to_rlp(&tx)?;
+        let signing_hash = match self.chain_id {
+            Some(_) => keccak256(&unsigned),
+            None => keccak256(&encode_list(&list_items(&unsigned)?[..6].concat())),
+        };
+        let (signature, recovery_id) = secp256k1::sign(
+            &Message::parse(&signing_hash.to_bytes_be()),
+            secret_key,
+        );
+        let parity = u64::from(recovery_id.serialize());
+        tx.v = self
+            .chain_id
+            .map_or(27 + parity, |chain_id| 35 + 2 * chain_id + parity);
+        tx.r = U256::from_bytes_be(&signature.r.b32());
+        tx.s = U256::from_bytes_be(&signature.s.b32());
+        Ok(to_rlp(&tx)?)
+    }
+
+    /// Legacy transactions sign the first six fields, followed by the chain id
+    /// and two zeros with EIP-155 replay protection.
+    fn decode_legacy(raw: &[u8]) -> AnyResult<Self> {
+        let tx: Legacy = from_rlp(raw)?;
+        let (chain_id, parity) = match tx.v {
+            27 | 28 => (None, tx.v - 27),
+            v if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+            v => return Err(anyhow!("Invalid signature v {}", v)),
+        };
+        let mut unsigned = list_items(raw)?[..6].concat();
+        if let Some(chain_id) = chain_id {
+            unsigned.extend(to_rlp(&chain_id)?);
+            unsigned.extend(&[0x80, 0x80]);
+        }
+        let signing_hash = keccak256(&encode_list(&unsigned));
+        Ok(Self {
+            sender: recover(&signing_hash, parity, &tx.r, &tx.s)?,
+            chain_id,
+            nonce: tx.nonce,
+            max_priority_fee_per_gas: tx.gas_price.clone(),
+            max_fee_per_gas: tx.gas_price,
+            gas_limit: tx.gas_limit,
+            to: tx.to,
+            value: tx.value,
+            data: tx.data,
+            ..Self::default()
+        })
+    }
+
+    /// Constants for executing the transaction
+    pub fn info(&self) -> TransactionInfo {
+        TransactionInfo {
+            access_list: self.access_list.clone(),
+            ..TransactionInfo::eip1559(
+                U256::from(&self.sender),
+                self.max_fee_per_gas.clone(),
+                self.max_priority_fee_per_gas.clone(),
+            )
+        }
+    }
+}
+
+/// Typed transactions sign the type followed by all fields but the
+/// signature.
+fn typed_signing_hash(kind: u8, payload: &[u8]) -> AnyResult<U256> {
+    let items = list_items(payload)?;
+    let unsigned = items[..items.len() - 3].concat();
+    let mut message = vec![kind];
+    message.extend(encode_list(&unsigned));
+    Ok(keccak256(&message))
+}
+
+/// Encoded items of an RLP list
+fn list_items(rlp: &[u8]) -> AnyResult<Vec<&[u8]>> {
+    let mut deserializer = Deserializer::from_rlp(rlp);
+    let payload = deserializer.parse_list()?;
+    deserializer.finish()?;
+    let mut items = Vec::new();
+    let mut deserializer = Deserializer::from_rlp(payload);
+    while !deserializer.is_empty() {
+        items.push(deserializer.parse_item()?);
+    }
+    Ok(items)
+}
+
+/// Recover the sender from a signature with a y-parity of zero or one
+fn recover(signing_hash: &U256, parity: u64, r: &U256, s: &U256) -> AnyResult<Address> {
+    require!(parity <= 1, anyhow!("Invalid signature parity {}", parity));
+    // Only the lower half of the curve order is valid, see EIP-2
+    let half_order = u256h!("7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0");
+    require!(s <= &half_order, anyhow!("Invalid signature s, too high"));
+    let mut input = [0_u8; 128];
+    input[..32].copy_from_slice(&signing_hash.to_bytes_be());
+    input[63] = if parity == 0 { 27 } else { 28 };
+    input[64..96].copy_from_slice(&r.to_bytes_be());
+    input[96..].copy_from_slice(&s.to_bytes_be());
+    let output = ecrecover(&input);
+    require!(output.len() == 32, anyhow!("Invalid signature"));
+    let mut address = [0_u8; 20];
+    address.copy_from_slice(&output[12..]);
+    Ok(Address::from(address))
+}
+
+/// An empty recipient is contract creation
+mod recipient {
+    use super::Address;
+    use crate::serde::bytes;
+    use serde::{de, ser};
+
+    #[allow(clippy::ref_option)] // Signature required by serde
+    pub fn serialize<S>(recipient: &Option<Address>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        bytes::serialize(
+            &recipient.as_ref().map_or(&[][..], Address::as_slice),
+            serializer,
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Address>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let recipient: Vec<u8> = bytes::deserialize(deserializer)?;
+        match recipient.len() {
+            0 => Ok(None),
+            20 => {
+                let mut address = [0_u8; 20];
+                address.copy_from_slice(&recipient);
+                Ok(Some(Address::from(address)))
+            }
+            n => Err(de::Error::invalid_length(n, &"an empty or 20 byte address")),
+        }
+    }
+}
+
+/// Storage keys are encoded with all 32 bytes
+mod storage_keys {
+    use crate::{prelude::*, serde::fixed_u256};
+    use serde::{de, ser};
+
+    #[derive(Serialize, Deserialize)]
+    struct Key(#[serde(with = "fixed_u256")] U256);
+
+    pub fn serialize<S>(keys: &[U256], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.collect_seq(keys.iter().cloned().map(Key))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<U256>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let keys = Vec::<Key>::deserialize(deserializer)?;
+        Ok(keys.into_iter().map(|key| key.0).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    /// Key `0x4646..46` from the EIP-155 example
+    const SENDER: [u8; 20] = hex!("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f");
+
+    #[test]
+    fn test_decode_eip155() {
+        // Example from EIP-155
+        let raw = hex!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        let transaction = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(transaction, SignedTransaction {
+            hash:                     u256h!(
+                "33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788"
+            ),
+            sender:                   Address::from(SENDER),
+            chain_id:                 Some(1),
+            nonce:                    9,
+            max_priority_fee_per_gas: U256::from(20_000_000_000_u64),
+            max_fee_per_gas:          U256::from(20_000_000_000_u64),
+            gas_limit:                21000,
+            to:                       Some(Address::from([0x35; 20])),
+            value:                    U256::from(1_000_000_000_000_000_000_u64),
+            data:                     Vec::new(),
+            access_list:              Vec::new(),
+        });
+    }
+
+    #[test]
+    fn test_decode_access_list() {
+        // Creation with init code `6000` and two slots of one account listed
+        let raw = hex!(
+            "01f8ad820539030782c3508080826000f85bf859940f572e5295c57f15886f9b263e2f6d2d6c7b5ec6f8
+             42a00000000000000000000000000000000000000000000000000000000000000000a000000000000000
+             0000000000000000000000000000000000000000000000000101a058ad8336878c8395a66fd316f020e2
+             63e735567b74b37c54a2e54761a8429887a01eeabf8beda7938f2305ad2ace6915acf9c5d638759864fa
+             8164c2a9a69619c0"
+        );
+        let transaction = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(transaction.sender, Address::from(SENDER));
+        assert_eq!(transaction.chain_id, Some(1337));
+        assert_eq!(transaction.nonce, 3);
+        assert_eq!(transaction.to, None);
+        assert_eq!(transaction.data, vec![0x60, 0x00]);
+        assert_eq!(transaction.access_list, vec![AccessListItem {
+            address:      Address::from(hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6")),
+            storage_keys: vec![U256::zero(), U256::one()],
+        }]);
+        assert_eq!(
+            transaction.hash,
+            u256h!("7c3d52e6a11735e9010f0e66cb948537c5e8a699b361725857758b4db600ae59")
+        );
+    }
+
+    #[test]
+    fn test_sign_eip155() {
+        // Example from EIP-155, signatures are deterministic (RFC 6979)
+        let raw = hex!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        let transaction = SignedTransaction::decode(&raw).unwrap();
+        let secret_key = SecretKey::parse(&[0x46; 32]).unwrap();
+        assert_eq!(transaction.sign_legacy(&secret_key).unwrap(), raw.to_vec());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(SignedTransaction::decode(&[]).is_err());
+        assert!(SignedTransaction::decode(&hex!("03c0")).is_err());
+        assert!(SignedTransaction::decode(&hex!("c0")).is_err());
+    }
+
+    #[test]
+    fn test_decode_high_s() {
+        // Example from EIP-155 with the other, high s and flipped parity
+        let raw = hex!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000
+             8026a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a098341627668089
+             e51348fccfb4c7ff31c55912f2d2e47ef09652acf665fad3be"
+        );
+        let error = SignedTransaction::decode(&raw).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid signature s, too high");
+    }
+
+    #[test]
+    fn test_decode_priority_fee_above_max_fee() {
+        // Priority fee 2 and max fee 1
+        let raw = hex!("02e2018002018252089435353535353535353535353535353535353535358080c0800101");
+        let error = SignedTransaction::decode(&raw).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Max priority fee per gas is higher than max fee per gas"
+        );
+    }
+}
@@ -1,29 +1,32 @@
-use super::Address;
+use super::{AccessListItem, Address};
 use crate::{
     prelude::*,
     serde::{bytes, fixed_u256, short_u256, short_u64},
 };
+use serde::de;
 
 #[derive(Clone, Default, PartialEq, PartialOrd, Eq, Ord, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
     #[serde(with = "short_u64")]
-    nonce:     u64,
+    pub nonce:     u64,
     #[serde(with = "short_u64")]
-    gas_price: u64,
+    pub gas_price: u64,
     #[serde(with = "short_u64", rename = "gas")]
-    gas_limit: u64,
-    to:        Address, // To do: encode as null for contract creation
+    pub gas_limit: u64,
+    /// Zero for contract creation, which is `null` in JSON
+    #[serde(deserialize_with = "nullable_address")]
+    pub to:        Address, // To do: encode as null for contract creation
     #[serde(with = "short_u256")]
-    value:     U256,
+    pub value:     U256,
     #[serde(rename = "input", with = "bytes")]
-    data:      Vec<u8>,
+    pub data:      Vec<u8>,
     #[serde(with = "short_u64")]
-    v:         u64, // TODO u8
+    pub v:         u64, // TODO u8
     #[serde(with = "fixed_u256")]
-    r:         U256,
+    pub r:         U256,
     #[serde(with = "fixed_u256")]
-    s:         U256,
+    pub s:         U256,
 }
 
 #[derive(Clone, Default, PartialEq, PartialOrd, Eq, Ord, Debug, Serialize, Deserialize)]
@@ -41,4 +44,83 @@ pub struct RpcTransaction {
     pub from:              Address,
     #[serde(with = "fixed_u256")]
     pub hash:              U256,
+    /// Only present on access list and dynamic fee transactions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub access_list:       Vec<AccessListItem>,
+}
+
+/// Read a `null` address as zero, for contract creations in JSON-RPC.
+fn nullable_address<'de, D>(deserializer: D) -> Result<Address, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        Ok(Option::<Address>::deserialize(deserializer)?.unwrap_or_default())
+    } else {
+        Address::deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+    use serde_json::{from_value, json};
+
+    #[test]
+    fn test_deserialize_creation() {
+        let transaction: RpcTransaction = from_value(json!({
+            "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "blockNumber": "0x2",
+            "from": "0x00000000000000000000000000000000000000aa",
+            "gas": "0x5208",
+            "gasPrice": "0x1",
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+            "input": "0x6000",
+            "nonce": "0x0",
+            "r": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "s": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "to": null,
+            "transactionIndex": "0x0",
+            "v": "0x1b",
+            "value": "0x0"
+        }))
+        .unwrap();
+        assert_eq!(transaction.transaction.to, Address::default());
+        assert_eq!(transaction.transaction.data, vec![0x60, 0x00]);
+        assert_eq!(transaction.block_number, 2);
+        assert_eq!(transaction.access_list, vec![]);
+    }
+
+    #[test]
+    fn test_deserialize_access_list() {
+        let transaction: RpcTransaction = from_value(json!({
+            "accessList": [{
+                "address": "0x0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6",
+                "storageKeys": [
+                    "0x0000000000000000000000000000000000000000000000000000000000000001"
+                ]
+            }],
+            "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "blockNumber": "0x2",
+            "from": "0x00000000000000000000000000000000000000aa",
+            "gas": "0x5208",
+            "gasPrice": "0x1",
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+            "input": "0x",
+            "nonce": "0x0",
+            "r": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "s": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "to": "0x0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6",
+            "transactionIndex": "0x0",
+            "type": "0x1",
+            "v": "0x0",
+            "value": "0x0"
+        }))
+        .unwrap();
+        assert_eq!(transaction.access_list, vec![AccessListItem {
+            address:      Address::from(hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6")),
+            storage_keys: vec![U256::one()],
+        }]);
+    }
 }
@@ -0,0 +1,569 @@
+//! Encoding of calls and their return values, see the contract ABI
+//! specification <https://docs.soliditylang.org/en/latest/abi-spec.html>
+//!
+//! Types and values mirror the ones of the `ethabi` crate, which is not a
+//! dependency yet. Its integers are `ethereum_types::U256`, so wrapping it
+//! means converting tokens at the edge, and the variants here are named like
+//! its ones to keep that a mechanical change. Decoding checks offsets and
+//! lengths against the output, so malformed output is an error and not a
+//! panic.
+
+use crate::{chain::types::Address, evm::precompiles::keccak256, prelude::*};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// Type of a parameter or return value
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParamType {
+    Address,
+    Bytes,
+    /// Signed integer of the given number of bits
+    Int(usize),
+    /// Unsigned integer of the given number of bits
+    Uint(usize),
+    Bool,
+    String,
+    Array(Box<Self>),
+    /// Byte array of the given length
+    FixedBytes(usize),
+    FixedArray(Box<Self>, usize),
+    Tuple(Vec<Self>),
+}
+
+/// Value of a parameter or return value
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Token {
+    Address(Address),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    /// Two's complement of the value
+    Int(U256),
+    Uint(U256),
+    Bool(bool),
+    String(String),
+    FixedArray(Vec<Self>),
+    Array(Vec<Self>),
+    Tuple(Vec<Self>),
+}
+
+impl ParamType {
+    /// Types that are encoded in the tail, referenced by an offset
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            Self::Bytes | Self::String | Self::Array(_) => true,
+            Self::FixedArray(kind, _) => kind.is_dynamic(),
+            Self::Tuple(kinds) => kinds.iter().any(Self::is_dynamic),
+            _ => false,
+        }
+    }
+}
+
+/// Canonical form used in signatures, e.g. `uint256` for `uint`
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Address => write!(f, "address"),
+            Self::Bytes => write!(f, "bytes"),
+            Self::Int(bits) => write!(f, "int{}", bits),
+            Self::Uint(bits) => write!(f, "uint{}", bits),
+            Self::Bool => write!(f, "bool"),
+            Self::String => write!(f, "string"),
+            Self::Array(kind) => write!(f, "{}[]", kind),
+            Self::FixedBytes(size) => write!(f, "bytes{}", size),
+            Self::FixedArray(kind, size) => write!(f, "{}[{}]", kind, size),
+            Self::Tuple(kinds) => write!(f, "({})", kinds.iter().join(",")),
+        }
+    }
+}
+
+impl FromStr for ParamType {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> AnyResult<Self> {
+        let name = name.trim();
+        if let Some(inner) = name.strip_suffix(']') {
+            let open = inner
+                .rfind('[')
+                .ok_or_else(|| anyhow!("Unbalanced brackets in type {}", name))?;
+            let kind = Box::new(inner[..open].parse()?);
+            return Ok(match &inner[open + 1..] {
+                "" => Self::Array(kind),
+                size => Self::FixedArray(kind, size.parse().context("Parsing array size")?),
+            });
+        }
+        if let Some(inner) = name.strip_prefix('(') {
+            let inner = inner
+                .strip_suffix(')')
+                .ok_or_else(|| anyhow!("Unbalanced parentheses in type {}", name))?;
+            return Ok(Self::Tuple(parse_params(inner)?));
+        }
+        let bits = |digits: &str| -> AnyResult<usize> {
+            if digits.is_empty() {
+                return Ok(256);
+            }
+            let bits = digits.parse().context("Parsing integer size")?;
+            require!(
+                bits > 0 && bits <= 256 && bits % 8 == 0,
+                anyhow!("Invalid integer size {}", bits)
+            );
+            Ok(bits)
+        };
+        Ok(match name {
+            "address" => Self::Address,
+            "bool" => Self::Bool,
+            "string" => Self::String,
+            "bytes" => Self::Bytes,
+            _ => {
+                if let Some(digits) = name.strip_prefix("uint") {
+                    Self::Uint(bits(digits)?)
+                } else if let Some(digits) = name.strip_prefix("int") {
+                    Self::Int(bits(digits)?)
+                } else if let Some(digits) = name.strip_prefix("bytes") {
+                    let size = digits.parse().context("Parsing bytes size")?;
+                    require!(size > 0 && size <= 32, anyhow!("Invalid bytes size {}", size));
+                    Self::FixedBytes(size)
+                } else {
+                    return Err(anyhow!("Unknown type {}", name));
+                }
+            }
+        })
+    }
+}
+
+impl Token {
+    /// Whether the value can be encoded as `kind`
+    #[must_use]
+    pub fn type_check(&self, kind: &ParamType) -> bool {
+        match (self, kind) {
+            (Self::Address(_), ParamType::Address)
+            | (Self::Bytes(_), ParamType::Bytes)
+            | (Self::Bool(_), ParamType::Bool)
+            | (Self::String(_), ParamType::String)
+            | (Self::Int(_), ParamType::Int(_)) => true,
+            (Self::Uint(value), ParamType::Uint(bits)) => value.bits() <= *bits,
+            (Self::FixedBytes(bytes), ParamType::FixedBytes(size)) => bytes.len() == *size,
+            (Self::Array(tokens), ParamType::Array(kind)) => {
+                tokens.iter().all(|token| token.type_check(kind))
+            }
+            (Self::FixedArray(tokens), ParamType::FixedArray(kind, size)) => {
+                tokens.len() == *size && tokens.iter().all(|token| token.type_check(kind))
+            }
+            (Self::Tuple(tokens), ParamType::Tuple(kinds)) => {
+                tokens.len() == kinds.len()
+                    && tokens
+                        .iter()
+                        .zip(kinds)
+                        .all(|(token, kind)| token.type_check(kind))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Self::Bytes(_) | Self::String(_) | Self::Array(_) => true,
+            Self::FixedArray(tokens) | Self::Tuple(tokens) => tokens.iter().any(Self::is_dynamic),
+            _ => false,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Address(address) => U256::from(address).to_bytes_be().to_vec(),
+            Self::Int(value) | Self::Uint(value) => value.to_bytes_be().to_vec(),
+            Self::Bool(value) => word(usize::from(*value)).to_vec(),
+            Self::FixedBytes(bytes) => padded(bytes),
+            Self::Bytes(bytes) => {
+                let mut result = word(bytes.len()).to_vec();
+                result.extend(padded(bytes));
+                result
+            }
+            Self::String(string) => Self::Bytes(string.as_bytes().to_vec()).encode(),
+            Self::Array(tokens) => {
+                let mut result = word(tokens.len()).to_vec();
+                result.extend(encode(tokens));
+                result
+            }
+            Self::FixedArray(tokens) | Self::Tuple(tokens) => encode(tokens),
+        }
+    }
+}
+
+/// Encode values as the parameters of a call, without selector
+pub fn encode(tokens: &[Token]) -> Vec<u8> {
+    let encoded = tokens.iter().map(Token::encode).collect::<Vec<_>>();
+    let head_size: usize = tokens
+        .iter()
+        .zip(&encoded)
+        .map(|(token, encoded)| if token.is_dynamic() { 32 } else { encoded.len() })
+        .sum();
+    let mut head = Vec::with_capacity(head_size);
+    let mut tail = Vec::new();
+    for (token, encoded) in tokens.iter().zip(encoded) {
+        if token.is_dynamic() {
+            head.extend_from_slice(&word(head_size + tail.len()));
+            tail.extend(encoded);
+        } else {
+            head.extend(encoded);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+/// Calldata calling the function with `signature` on `tokens`
+///
+/// The signature is of the form `transfer(address,uint256)`, the values must
+/// match its parameter types.
+pub fn encode_call(signature: &str, tokens: &[Token]) -> AnyResult<Vec<u8>> {
+    let (name, params) = parse_signature(signature)?;
+    require!(
+        params.len() == tokens.len(),
+        anyhow!(
+            "{} takes {} parameters, got {}",
+            name,
+            params.len(),
+            tokens.len()
+        )
+    );
+    for (index, (token, param)) in tokens.iter().zip(&params).enumerate() {
+        require!(
+            token.type_check(param),
+            anyhow!(
+                "Parameter {} of {} is not a {}: {:?}",
+                index,
+                name,
+                param,
+                token
+            )
+        );
+    }
+    let mut calldata = selector(name, &params).to_vec();
+    calldata.extend(encode(tokens));
+    Ok(calldata)
+}
+
+/// Decode return values, or parameters without selector
+///
+/// Returns the tokens of this module, which mirror `ethabi::Token`, see the
+/// module documentation.
+pub fn decode(kinds: &[ParamType], output: &[u8]) -> AnyResult<Vec<Token>> {
+    let mut offset = 0;
+    kinds
+        .iter()
+        .map(|kind| {
+            let token = decode_param(kind, output, offset)?;
+            offset += head_size(kind);
+            Ok(token)
+        })
+        .collect()
+}
+
+/// First four bytes of the hash of the canonical signature
+#[must_use]
+pub fn selector(name: &str, params: &[ParamType]) -> [u8; 4] {
+    let signature = format!("{}({})", name, params.iter().join(","));
+    let mut selector = [0_u8; 4];
+    selector.copy_from_slice(&keccak256(signature.as_bytes()).to_bytes_be()[..4]);
+    selector
+}
+
+/// Split `name(type,...)` into the name and the parameter types
+pub fn parse_signature(signature: &str) -> AnyResult<(&str, Vec<ParamType>)> {
+    let open = signature
+        .find('(')
+        .ok_or_else(|| anyhow!("Missing parameters in signature {}", signature))?;
+    let params = signature[open + 1..]
+        .strip_suffix(')')
+        .ok_or_else(|| anyhow!("Unbalanced parentheses in signature {}", signature))?;
+    Ok((signature[..open].trim(), parse_params(params)?))
+}
+
+/// Parse a comma separated list of types, tuples may be nested.
+fn parse_params(params: &str) -> AnyResult<Vec<ParamType>> {
+    if params.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut result = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (index, c) in params.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("Unbalanced parentheses in {}", params))?;
+            }
+            ',' if depth == 0 => {
+                result.push(params[start..index].parse()?);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(params[start..].parse()?);
+    Ok(result)
+}
+
+/// Size in the head, dynamic values are replaced by their offset.
+fn head_size(kind: &ParamType) -> usize {
+    match kind {
+        _ if kind.is_dynamic() => 32,
+        ParamType::FixedArray(kind, size) => head_size(kind) * size,
+        ParamType::Tuple(kinds) => kinds.iter().map(head_size).sum(),
+        _ => 32,
+    }
+}
+
+/// Decode the value in the head of `data` at `offset`
+///
+/// Offsets of dynamic values are relative to the start of `data`.
+fn decode_param(kind: &ParamType, data: &[u8], offset: usize) -> AnyResult<Token> {
+    let offset = if kind.is_dynamic() {
+        read_usize(data, offset)?
+    } else {
+        offset
+    };
+    let data = data
+        .get(offset..)
+        .ok_or_else(|| anyhow!("Offset out of bounds for {}", kind))?;
+    decode_value(kind, data)
+}
+
+/// Decode the value encoded at the start of `data`
+fn decode_value(kind: &ParamType, data: &[u8]) -> AnyResult<Token> {
+    let bytes = || -> AnyResult<Vec<u8>> {
+        let length = read_usize(data, 0)?;
+        Ok(32_usize
+            .checked_add(length)
+            .and_then(|end| data.get(32..end))
+            .ok_or_else(|| anyhow!("Length out of bounds for {}", kind))?
+            .to_vec())
+    };
+    Ok(match kind {
+        ParamType::Address => {
+            let word = read_word(data, 0)?;
+            require!(
+                word[..12].iter().all(|b| *b == 0),
+                anyhow!("Invalid address")
+            );
+            Token::Address(Address::from(&U256::from_bytes_be(&word)))
+        }
+        ParamType::Int(_) => Token::Int(U256::from_bytes_be(&read_word(data, 0)?)),
+        ParamType::Uint(bits) => {
+            let value = U256::from_bytes_be(&read_word(data, 0)?);
+            require!(
+                value.bits() <= *bits,
+                anyhow!("Value out of range for uint{}", bits)
+            );
+            Token::Uint(value)
+        }
+        ParamType::Bool => {
+            let value = U256::from_bytes_be(&read_word(data, 0)?);
+            require!(value <= U256::one(), anyhow!("Invalid bool"));
+            Token::Bool(value == U256::one())
+        }
+        ParamType::FixedBytes(size) => Token::FixedBytes(read_word(data, 0)?[..*size].to_vec()),
+        ParamType::Bytes => Token::Bytes(bytes()?),
+        ParamType::String => {
+            Token::String(String::from_utf8(bytes()?).context("Decoding string")?)
+        }
+        ParamType::Array(kind) => {
+            let length = read_usize(data, 0)?;
+            Token::Array(decode_elements(kind, length, &data[32..])?)
+        }
+        ParamType::FixedArray(kind, size) => Token::FixedArray(decode_elements(kind, *size, data)?),
+        ParamType::Tuple(kinds) => Token::Tuple(decode(kinds, data)?),
+    })
+}
+
+fn decode_elements(kind: &ParamType, length: usize, data: &[u8]) -> AnyResult<Vec<Token>> {
+    // Bound the length before allocating
+    let size = head_size(kind);
+    require!(
+        length
+            .checked_mul(size)
+            .map_or(false, |total| total <= data.len()),
+        anyhow!("Length out of bounds for {}[{}]", kind, length)
+    );
+    (0..length)
+        .map(|index| decode_param(kind, data, index * size))
+        .collect()
+}
+
+fn read_word(data: &[u8], offset: usize) -> AnyResult<[u8; 32]> {
+    let slice = offset
+        .checked_add(32)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| anyhow!("Output too short"))?;
+    let mut word = [0_u8; 32];
+    word.copy_from_slice(slice);
+    Ok(word)
+}
+
+/// Read a length or offset
+fn read_usize(data: &[u8], offset: usize) -> AnyResult<usize> {
+    let word = read_word(data, offset)?;
+    require!(
+        word[..24].iter().all(|b| *b == 0),
+        anyhow!("Length or offset too large")
+    );
+    let mut bytes = [0_u8; 8];
+    bytes.copy_from_slice(&word[24..]);
+    usize::try_from(u64::from_be_bytes(bytes)).context("Length or offset too large")
+}
+
+/// Big-endian word of a length or offset
+fn word(value: usize) -> [u8; 32] {
+    let mut word = [0_u8; 32];
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+/// Right pad to a multiple of 32 bytes
+fn padded(bytes: &[u8]) -> Vec<u8> {
+    let mut result = bytes.to_vec();
+    result.resize((bytes.len() + 31) / 32 * 32, 0);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_encode_transfer() {
+        let to = Address::from(hex!("70997970c51812dc3a010c7d01b50e0d17dc79c8"));
+        let calldata = encode_call("transfer(address,uint256)", &[
+            Token::Address(to),
+            Token::Uint(U256::from(1000)),
+        ])
+        .unwrap();
+        assert_eq!(&calldata[..4], &hex!("a9059cbb"));
+        assert_eq!(
+            calldata,
+            hex!(
+                "a9059cbb
+                00000000000000000000000070997970c51812dc3a010c7d01b50e0d17dc79c8
+                00000000000000000000000000000000000000000000000000000000000003e8"
+            )
+            .to_vec()
+        );
+        // `uint` is an alias of `uint256`
+        assert_eq!(
+            &encode_call("transfer(address,uint)", &[
+                Token::Address(Address::default()),
+                Token::Uint(U256::zero())
+            ])
+            .unwrap()[..4],
+            &hex!("a9059cbb")
+        );
+    }
+
+    #[test]
+    fn test_encode_dynamic() {
+        // Example from the ABI specification
+        let calldata = encode_call("f(uint256,uint32[],bytes10,bytes)", &[
+            Token::Uint(U256::from(0x123)),
+            Token::Array(vec![
+                Token::Uint(U256::from(0x456)),
+                Token::Uint(U256::from(0x789)),
+            ]),
+            Token::FixedBytes(b"1234567890".to_vec()),
+            Token::Bytes(b"Hello, world!".to_vec()),
+        ])
+        .unwrap();
+        assert_eq!(
+            calldata,
+            hex!(
+                "8be65246
+                0000000000000000000000000000000000000000000000000000000000000123
+                0000000000000000000000000000000000000000000000000000000000000080
+                3132333435363738393000000000000000000000000000000000000000000000
+                00000000000000000000000000000000000000000000000000000000000000e0
+                0000000000000000000000000000000000000000000000000000000000000002
+                0000000000000000000000000000000000000000000000000000000000000456
+                0000000000000000000000000000000000000000000000000000000000000789
+                000000000000000000000000000000000000000000000000000000000000000d
+                48656c6c6f2c20776f726c642100000000000000000000000000000000000000"
+            )
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_encode_mismatch() {
+        assert!(encode_call("transfer(address,uint256)", &[Token::Bool(true)]).is_err());
+        assert!(encode_call("transfer(address,uint8)", &[
+            Token::Address(Address::default()),
+            Token::Uint(U256::from(256)),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_type() {
+        assert_eq!(
+            "(uint,bytes32)[2][]".parse::<ParamType>().unwrap().to_string(),
+            "(uint256,bytes32)[2][]"
+        );
+        assert!("uint7".parse::<ParamType>().is_err());
+        assert!("bytes33".parse::<ParamType>().is_err());
+    }
+
+    #[test]
+    fn test_decode_static() {
+        let output = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001
+            00000000000000000000000000000000000000000000000000000000000003e8"
+        );
+        assert_eq!(
+            decode(&[ParamType::Bool, ParamType::Uint(256)], &output).unwrap(),
+            vec![Token::Bool(true), Token::Uint(U256::from(1000))]
+        );
+        assert!(decode(&vec![ParamType::Uint(256); 3], &output).is_err());
+    }
+
+    #[test]
+    fn test_decode_uint_and_string() {
+        let output = hex!(
+            "00000000000000000000000000000000000000000000000000000000000003e8
+            0000000000000000000000000000000000000000000000000000000000000040
+            000000000000000000000000000000000000000000000000000000000000000b
+            48656c6c6f20776f726c64000000000000000000000000000000000000000000"
+        );
+        assert_eq!(
+            decode(&[ParamType::Uint(256), ParamType::String], &output).unwrap(),
+            vec![
+                Token::Uint(U256::from(1000)),
+                Token::String("Hello world".into())
+            ]
+        );
+        // The length exceeds the output
+        let mut truncated = output.to_vec();
+        truncated[95] = 0x40;
+        assert!(decode(&[ParamType::Uint(256), ParamType::String], &truncated).is_err());
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        let kinds = parse_params("bytes,uint32[],(string,bool)[2],uint8[2][]").unwrap();
+        let tokens = vec![
+            Token::Bytes(b"Hello, world!".to_vec()),
+            Token::Array(vec![
+                Token::Uint(U256::from(0x456)),
+                Token::Uint(U256::from(0x789)),
+            ]),
+            Token::FixedArray(vec![
+                Token::Tuple(vec![Token::String("a".into()), Token::Bool(true)]),
+                Token::Tuple(vec![Token::String(String::new()), Token::Bool(false)]),
+            ]),
+            Token::Array(vec![Token::FixedArray(vec![
+                Token::Uint(U256::one()),
+                Token::Uint(U256::from(2)),
+            ])]),
+        ];
+        assert_eq!(decode(&kinds, &encode(&tokens)).unwrap(), tokens);
+    }
+}
@@ -0,0 +1,153 @@
+use super::CallInfo;
+use crate::{chain::types::Address, prelude::*};
+
+/// Gas of calls that do not set it, the gas limit of mainnet blocks
+pub const DEFAULT_GAS: usize = 30_000_000;
+
+/// Account addresses in any of the representations used in the crate
+pub trait IntoAccount {
+    fn into_account(self) -> U256;
+}
+
+impl IntoAccount for U256 {
+    fn into_account(self) -> U256 {
+        self
+    }
+}
+
+impl IntoAccount for &U256 {
+    fn into_account(self) -> U256 {
+        self.clone()
+    }
+}
+
+impl IntoAccount for [u8; 20] {
+    fn into_account(self) -> U256 {
+        U256::from(&Address::from(self))
+    }
+}
+
+impl IntoAccount for Address {
+    fn into_account(self) -> U256 {
+        U256::from(&self)
+    }
+}
+
+impl IntoAccount for &Address {
+    fn into_account(self) -> U256 {
+        U256::from(self)
+    }
+}
+
+/// Builder for a [`CallInfo`], see [`CallInfo::builder`]
+///
+/// The value defaults to zero and the gas to [`DEFAULT_GAS`].
+#[derive(Clone, Debug)]
+pub struct CallBuilder {
+    call: CallInfo,
+}
+
+impl Default for CallBuilder {
+    fn default() -> Self {
+        Self {
+            call: CallInfo {
+                initial_gas: DEFAULT_GAS,
+                ..CallInfo::default()
+            },
+        }
+    }
+}
+
+impl CallInfo {
+    #[must_use]
+    pub fn builder() -> CallBuilder {
+        CallBuilder::default()
+    }
+}
+
+impl CallBuilder {
+    #[must_use]
+    pub fn sender(mut self, sender: impl IntoAccount) -> Self {
+        self.call.sender = sender.into_account();
+        self
+    }
+
+    /// Account to call, which is also the one the code is loaded from
+    #[must_use]
+    pub fn to(mut self, to: impl IntoAccount) -> Self {
+        let to = to.into_account();
+        self.call.address = to.clone();
+        self.call.code_address = to;
+        self
+    }
+
+    #[must_use]
+    pub const fn value(mut self, value: U256) -> Self {
+        self.call.call_value = value;
+        self
+    }
+
+    /// Gas available to execution, excluding the intrinsic gas
+    #[must_use]
+    pub const fn gas(mut self, gas: usize) -> Self {
+        self.call.initial_gas = gas;
+        self
+    }
+
+    #[must_use]
+    pub fn input(mut self, input: impl AsRef<[u8]>) -> Self {
+        self.call.input = input.as_ref().to_vec();
+        self
+    }
+
+    /// Run `init_code` and deploy the code it returns at the `to` account
+    #[must_use]
+    pub fn create(mut self, init_code: impl AsRef<[u8]>) -> Self {
+        self.call.init_code = Some(init_code.as_ref().to_vec());
+        self
+    }
+
+    #[must_use]
+    pub const fn is_static(mut self, is_static: bool) -> Self {
+        self.call.is_static = is_static;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> CallInfo {
+        self.call
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_to_and_input() {
+        let to = hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6");
+        let call = CallInfo::builder().to(to).input(hex!("01ff")).build();
+        let address = u256h!("0000000000000000000000000f572e5295c57f15886f9b263e2f6d2d6c7b5ec6");
+        assert_eq!(call.address, address);
+        assert_eq!(call.code_address, address);
+        assert_eq!(call.sender, U256::zero());
+        assert_eq!(call.call_value, U256::zero());
+        assert_eq!(call.initial_gas, DEFAULT_GAS);
+        assert_eq!(call.input, vec![0x01, 0xff]);
+        assert_eq!(call.init_code, None);
+        assert!(!call.is_static);
+    }
+
+    #[test]
+    fn test_address_types() {
+        let bytes = hex!("00000000000000000000000000000000000000aa");
+        let from_bytes = CallInfo::builder().sender(bytes).to(bytes).build();
+        let from_u256 = CallInfo::builder()
+            .sender(U256::from(0xaa))
+            .to(Address::from(bytes))
+            .build();
+        assert_eq!(from_bytes.sender, from_u256.sender);
+        assert_eq!(from_bytes.address, from_u256.address);
+    }
+}
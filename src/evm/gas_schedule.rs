@@ -0,0 +1,208 @@
+//! Gas costs that changed between hardforks
+//!
+//! Costs that never changed, like memory expansion and copies, are constants
+//! in the interpreter. Account existence follows EIP-161 in all schedules.
+
+use crate::evm::{Hardfork, Opcode};
+
+/// Gas costs of a hardfork
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GasSchedule {
+    /// Fork whose opcodes are available
+    pub hardfork:             Hardfork,
+    /// SLOAD of a warm slot, or of any slot before Berlin
+    pub sload:                usize,
+    /// SLOAD of a cold slot, also added to SSTORE on a cold slot (EIP-2929).
+    /// Zero before Berlin, which does not distinguish cold accesses.
+    pub cold_sload:           usize,
+    pub balance:              usize,
+    /// EXTCODESIZE and EXTCODECOPY
+    pub ext_code:             usize,
+    pub ext_code_hash:        usize,
+    /// CALL, CALLCODE, DELEGATECALL and STATICCALL
+    pub call_base:            usize,
+    pub selfdestruct:         usize,
+    /// First access of an account in a transaction (EIP-2929). Zero before
+    /// Berlin, which does not distinguish cold accesses.
+    pub cold_account_access:  usize,
+    /// SSTORE from zero to non-zero
+    pub sstore_set:           usize,
+    /// Other SSTOREs that change the slot
+    pub sstore_reset:         usize,
+    /// Refund for clearing a slot
+    pub sstore_clears_refund: usize,
+    /// Charge SSTORE by the value at the start of the transaction (EIP-2200)
+    pub net_sstore:           bool,
+    pub selfdestruct_refund:  usize,
+    /// Refunds are capped to this fraction of the gas used
+    pub max_refund_quotient:  usize,
+    /// Gas per byte of the EXP exponent
+    pub exp_byte:             usize,
+    /// Calls and creates get at most 63/64 of the remaining gas (EIP-150)
+    pub call_gas_cap:         bool,
+    /// Intrinsic gas per non-zero byte of calldata or init code
+    pub tx_data_non_zero:     u64,
+    /// Intrinsic gas on top for contract creation transactions
+    pub tx_create:            u64,
+    /// Intrinsic gas per word of init code of creation transactions
+    pub initcode_word:        u64,
+}
+
+impl GasSchedule {
+    #[must_use]
+    pub const fn frontier() -> Self {
+        Self {
+            hardfork:             Hardfork::Frontier,
+            sload:                50,
+            cold_sload:           0,
+            balance:              20,
+            ext_code:             20,
+            ext_code_hash:        400,
+            call_base:            40,
+            selfdestruct:         0,
+            cold_account_access:  0,
+            sstore_set:           20000,
+            sstore_reset:         5000,
+            sstore_clears_refund: 15000,
+            net_sstore:           false,
+            selfdestruct_refund:  24000,
+            max_refund_quotient:  2,
+            exp_byte:             10,
+            call_gas_cap:         false,
+            tx_data_non_zero:     68,
+            tx_create:            0,
+            initcode_word:        0,
+        }
+    }
+
+    /// Creation transactions pay for the contract, see EIP-2
+    #[must_use]
+    pub const fn homestead() -> Self {
+        Self {
+            hardfork: Hardfork::Homestead,
+            tx_create: 32000,
+            ..Self::frontier()
+        }
+    }
+
+    /// Repricing of state access, see EIP-150
+    #[must_use]
+    pub const fn tangerine() -> Self {
+        Self {
+            hardfork: Hardfork::Tangerine,
+            sload: 200,
+            balance: 400,
+            ext_code: 700,
+            call_base: 700,
+            selfdestruct: 5000,
+            call_gas_cap: true,
+            ..Self::homestead()
+        }
+    }
+
+    /// Repricing of EXP, see EIP-160
+    #[must_use]
+    pub const fn spurious() -> Self {
+        Self {
+            hardfork: Hardfork::Spurious,
+            exp_byte: 50,
+            ..Self::tangerine()
+        }
+    }
+
+    /// Repricing of trie size dependent opcodes, net gas metering for SSTORE
+    /// and cheaper calldata, see EIP-1884, EIP-2200 and EIP-2028
+    #[must_use]
+    pub const fn istanbul() -> Self {
+        Self {
+            hardfork: Hardfork::Istanbul,
+            sload: 800,
+            balance: 700,
+            ext_code_hash: 700,
+            net_sstore: true,
+            tx_data_non_zero: 16,
+            ..Self::spurious()
+        }
+    }
+
+    /// Cold and warm state access, see EIP-2929
+    #[must_use]
+    pub const fn berlin() -> Self {
+        Self {
+            hardfork: Hardfork::Berlin,
+            sload: 100,
+            cold_sload: 2100,
+            balance: 100,
+            ext_code: 100,
+            ext_code_hash: 100,
+            call_base: 100,
+            cold_account_access: 2600,
+            sstore_reset: 5000 - 2100,
+            ..Self::istanbul()
+        }
+    }
+
+    /// Reduced refunds, see EIP-3529
+    #[must_use]
+    pub const fn london() -> Self {
+        Self {
+            hardfork: Hardfork::London,
+            sstore_clears_refund: 4800,
+            selfdestruct_refund: 0,
+            max_refund_quotient: 5,
+            ..Self::berlin()
+        }
+    }
+
+    /// Metering of init code, see EIP-3860
+    #[must_use]
+    pub const fn shanghai() -> Self {
+        Self {
+            hardfork: Hardfork::Shanghai,
+            initcode_word: 2,
+            ..Self::london()
+        }
+    }
+
+    /// Minimum gas consumed by the opcode, like [`Opcode::base_gas`]
+    ///
+    /// SSTORE is charged entirely as dynamic gas.
+    #[must_use]
+    pub const fn base_gas(&self, op: Opcode) -> usize {
+        match op {
+            Opcode::SLoad => self.sload,
+            Opcode::SStore => 0,
+            Opcode::Balance => self.balance,
+            Opcode::ExtCodeSize | Opcode::ExtCodeCopy => self.ext_code,
+            Opcode::ExtCodeHash => self.ext_code_hash,
+            Opcode::Call | Opcode::CallCode | Opcode::DelegateCall | Opcode::StaticCall => {
+                self.call_base
+            }
+            Opcode::SelfDestruct => self.selfdestruct,
+            op => op.base_gas(),
+        }
+    }
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self::london()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_base_gas() {
+        // Berlin charges the warm cost up front
+        for op in &[Opcode::SLoad, Opcode::Balance, Opcode::ExtCodeHash, Opcode::Call] {
+            assert_eq!(GasSchedule::berlin().base_gas(*op), op.base_gas());
+        }
+        assert_eq!(GasSchedule::frontier().base_gas(Opcode::Call), 40);
+        assert_eq!(GasSchedule::istanbul().base_gas(Opcode::SLoad), 800);
+        assert_eq!(GasSchedule::london().base_gas(Opcode::Add), 3);
+    }
+}
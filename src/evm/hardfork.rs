@@ -0,0 +1,186 @@
+//! Protocol upgrades that changed the EVM
+
+use crate::{
+    evm::{GasSchedule, Opcode},
+    prelude::*,
+};
+use std::str::FromStr;
+
+/// Chain id of Ethereum mainnet
+const MAINNET_CHAIN_ID: u64 = 1;
+
+/// First block of each fork on mainnet, in order
+///
+/// Constantinople activated together with Petersburg, which removed its net
+/// gas metering for SSTORE again.
+const MAINNET_FORKS: [(u64, Hardfork); 10] = [
+    (1_150_000, Hardfork::Homestead),
+    (2_463_000, Hardfork::Tangerine),
+    (2_675_000, Hardfork::Spurious),
+    (4_370_000, Hardfork::Byzantium),
+    (7_280_000, Hardfork::Petersburg),
+    (9_069_000, Hardfork::Istanbul),
+    (12_244_000, Hardfork::Berlin),
+    (12_965_000, Hardfork::London),
+    (17_034_870, Hardfork::Shanghai),
+    (19_426_587, Hardfork::Cancun),
+];
+
+/// Hardforks that changed opcodes or gas costs, in order of activation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hardfork {
+    Frontier,
+    Homestead,
+    Tangerine,
+    Spurious,
+    Byzantium,
+    Petersburg,
+    Istanbul,
+    Berlin,
+    London,
+    Shanghai,
+    Cancun,
+}
+
+impl Hardfork {
+    /// Fork active at `block_number` on mainnet
+    #[must_use]
+    pub fn mainnet(block_number: u64) -> Self {
+        MAINNET_FORKS
+            .iter()
+            .rev()
+            .find(|(first_block, _)| block_number >= *first_block)
+            .map_or(Self::Frontier, |(_, fork)| *fork)
+    }
+
+    /// Fork active at `block_number` on the chain
+    ///
+    /// Other chains than mainnet are assumed to run the latest fork.
+    #[must_use]
+    pub fn detect(chain_id: u64, block_number: u64) -> Self {
+        if chain_id == MAINNET_CHAIN_ID {
+            Self::mainnet(block_number)
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Gas costs of the fork
+    #[must_use]
+    pub const fn schedule(self) -> GasSchedule {
+        let schedule = match self {
+            Self::Frontier => GasSchedule::frontier(),
+            Self::Homestead => GasSchedule::homestead(),
+            Self::Tangerine => GasSchedule::tangerine(),
+            Self::Spurious | Self::Byzantium | Self::Petersburg => GasSchedule::spurious(),
+            Self::Istanbul => GasSchedule::istanbul(),
+            Self::Berlin => GasSchedule::berlin(),
+            Self::London => GasSchedule::london(),
+            Self::Shanghai | Self::Cancun => GasSchedule::shanghai(),
+        };
+        GasSchedule {
+            hardfork: self,
+            ..schedule
+        }
+    }
+
+    /// Fork that introduced `op`
+    #[must_use]
+    pub const fn introducing(op: Opcode) -> Self {
+        match op {
+            Opcode::DelegateCall => Self::Homestead,
+            Opcode::Revert
+            | Opcode::ReturnDataSize
+            | Opcode::ReturnDataCopy
+            | Opcode::StaticCall => Self::Byzantium,
+            Opcode::Shl | Opcode::Shr | Opcode::Sar | Opcode::Create2 | Opcode::ExtCodeHash => {
+                Self::Petersburg
+            }
+            Opcode::ChainId | Opcode::SelfBalance => Self::Istanbul,
+            Opcode::BaseFee => Self::London,
+            Opcode::Push(0) => Self::Shanghai,
+            Opcode::TLoad | Opcode::TStore | Opcode::MCopy => Self::Cancun,
+            _ => Self::Frontier,
+        }
+    }
+
+    /// Decode `byte`, opcodes introduced by later forks are undefined.
+    #[must_use]
+    pub fn decode(self, byte: u8) -> Opcode {
+        let op = Opcode::from(byte);
+        if Self::introducing(op) <= self {
+            op
+        } else {
+            Opcode::Unknown(byte)
+        }
+    }
+}
+
+impl Default for Hardfork {
+    fn default() -> Self {
+        Self::Cancun
+    }
+}
+
+impl FromStr for Hardfork {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> AnyResult<Self> {
+        Ok(match name.to_lowercase().as_str() {
+            "frontier" => Self::Frontier,
+            "homestead" => Self::Homestead,
+            "tangerine" => Self::Tangerine,
+            "spurious" => Self::Spurious,
+            "byzantium" => Self::Byzantium,
+            "petersburg" | "constantinople" => Self::Petersburg,
+            "istanbul" => Self::Istanbul,
+            "berlin" => Self::Berlin,
+            "london" => Self::London,
+            "shanghai" => Self::Shanghai,
+            "cancun" => Self::Cancun,
+            _ => return Err(anyhow!("Unknown hardfork {}", name)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_mainnet() {
+        assert_eq!(Hardfork::mainnet(0), Hardfork::Frontier);
+        assert_eq!(Hardfork::mainnet(1_149_999), Hardfork::Frontier);
+        assert_eq!(Hardfork::mainnet(1_150_000), Hardfork::Homestead);
+        assert_eq!(Hardfork::mainnet(4_370_000), Hardfork::Byzantium);
+        assert_eq!(Hardfork::mainnet(9_068_999), Hardfork::Petersburg);
+        assert_eq!(Hardfork::mainnet(9_069_000), Hardfork::Istanbul);
+        assert_eq!(Hardfork::mainnet(12_500_000), Hardfork::Berlin);
+        assert_eq!(Hardfork::mainnet(15_000_000), Hardfork::London);
+        assert_eq!(Hardfork::mainnet(17_034_870), Hardfork::Shanghai);
+        assert_eq!(Hardfork::mainnet(19_426_587), Hardfork::Cancun);
+        assert_eq!(Hardfork::detect(1337, 0), Hardfork::Cancun);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Hardfork::Petersburg.decode(0x46), Opcode::Unknown(0x46));
+        assert_eq!(Hardfork::Istanbul.decode(0x46), Opcode::ChainId);
+        assert_eq!(Hardfork::Berlin.decode(0x48), Opcode::Unknown(0x48));
+        assert_eq!(Hardfork::London.decode(0x48), Opcode::BaseFee);
+        assert_eq!(Hardfork::London.decode(0x5e), Opcode::Unknown(0x5e));
+        assert_eq!(Hardfork::Cancun.decode(0x5e), Opcode::MCopy);
+        assert_eq!(Hardfork::London.decode(0x5f), Opcode::Unknown(0x5f));
+        assert_eq!(Hardfork::Shanghai.decode(0x5f), Opcode::Push(0));
+        assert_eq!(Hardfork::Shanghai.decode(0x5e), Opcode::Unknown(0x5e));
+        assert_eq!(Hardfork::Frontier.decode(0x01), Opcode::Add);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("Istanbul".parse::<Hardfork>().unwrap(), Hardfork::Istanbul);
+        assert!("paris".parse::<Hardfork>().is_err());
+        assert_eq!(Hardfork::Byzantium.schedule().hardfork, Hardfork::Byzantium);
+    }
+}
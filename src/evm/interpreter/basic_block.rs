@@ -0,0 +1,143 @@
+//! Basic block analysis for charging gas per block
+//!
+//! Code is split into straight-line blocks like [`Opcode::is_block_final`]
+//! does for the JIT. Blocks additionally end after every opcode that observes
+//! the remaining gas or can halt before being charged (e.g. static call
+//! violations), so prepaying a block can not change the outcome of a call.
+//!
+//! The blocks of the JIT's `Program` are not reused. It only finds blocks
+//! reachable through jumps it resolves symbolically and fails on computed
+//! jumps, it panics on PUSH data truncated by the end of the code, and its gas
+//! does not depend on the hardfork. The interpreter needs a block at every
+//! position execution can enter, for any code a contract may have.
+
+use crate::evm::{GasSchedule, Opcode};
+
+/// Requirements of a straight-line run of opcodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct BasicBlock {
+    /// Number of opcodes in the block
+    pub ops:       usize,
+    /// Sum of the base gas of the opcodes
+    pub gas:       usize,
+    /// Stack items the block consumes from before it started
+    pub stack_min: usize,
+    /// Maximum number of items the stack grows by during the block
+    pub stack_max: usize,
+}
+
+impl BasicBlock {
+    /// Can the block run on a stack of `height` items without over- or
+    /// underflowing
+    pub const fn fits(&self, height: usize, limit: usize) -> bool {
+        height >= self.stack_min && height + self.stack_max <= limit
+    }
+
+    /// Append `op`, where `height` is the stack height assuming the block
+    /// started with exactly `stack_min` items.
+    fn push(&mut self, op: Opcode, schedule: &GasSchedule, height: &mut usize) {
+        let (consume, produce) = op.stack();
+        if consume > *height {
+            self.stack_min += consume - *height;
+            *height = consume;
+        }
+        *height = *height - consume + produce;
+        let growth = height.saturating_sub(self.stack_min);
+        self.stack_max = std::cmp::max(self.stack_max, growth);
+        self.gas += schedule.base_gas(op);
+        self.ops += 1;
+    }
+}
+
+/// Does the block end after `op`
+const fn ends_block(op: Opcode) -> bool {
+    op.is_block_final()
+        || matches!(
+            op,
+            Opcode::JumpI
+                | Opcode::Gas
+                | Opcode::SStore
+                | Opcode::Log(_)
+                | Opcode::ReturnDataCopy
+                | Opcode::Call
+                | Opcode::CallCode
+                | Opcode::DelegateCall
+                | Opcode::StaticCall
+                | Opcode::Create
+                | Opcode::Create2
+                | Opcode::SelfDestruct
+        )
+}
+
+/// Basic blocks of `code` indexed by their first position, with the gas of
+/// `schedule`
+///
+/// Execution only enters code at the start of a block, at position zero, a
+/// JUMPDEST or after an opcode that ends a block. A vector is used instead of
+/// a map since the lookup happens on every block entry.
+pub(super) fn basic_blocks(code: &[u8], schedule: &GasSchedule) -> Vec<Option<BasicBlock>> {
+    let mut blocks = vec![None; code.len()];
+    let mut start = 0;
+    let mut current = BasicBlock::default();
+    let mut height = 0;
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = schedule.hardfork.decode(code[pc]);
+        if op == Opcode::JumpDest && current.ops > 0 {
+            blocks[start] = Some(current);
+            start = pc;
+            current = BasicBlock::default();
+            height = 0;
+        }
+        current.push(op, schedule, &mut height);
+        pc += op.encoded_size();
+        if ends_block(op) {
+            blocks[start] = Some(current);
+            start = pc;
+            current = BasicBlock::default();
+            height = 0;
+        }
+    }
+    if current.ops > 0 {
+        blocks[start] = Some(current);
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_basic_blocks() {
+        // PUSH1 10 JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH1 2 JUMPI POP ADD
+        let blocks = basic_blocks(
+            &hex!("600a 5b 6001 90 03 80 6002 57 50 01"),
+            &GasSchedule::default(),
+        );
+        assert_eq!(blocks.iter().flatten().count(), 3);
+        assert_eq!(blocks[0].unwrap(), BasicBlock {
+            ops:       1,
+            gas:       3,
+            stack_min: 0,
+            stack_max: 1,
+        });
+        assert_eq!(blocks[2].unwrap(), BasicBlock {
+            ops:       7,
+            gas:       1 + 3 + 3 + 3 + 3 + 3 + 10,
+            stack_min: 1,
+            stack_max: 2,
+        });
+        assert_eq!(blocks[11].unwrap(), BasicBlock {
+            ops:       2,
+            gas:       2 + 3,
+            stack_min: 3,
+            stack_max: 0,
+        });
+        assert!(blocks[2].unwrap().fits(1, 1024));
+        assert!(!blocks[2].unwrap().fits(0, 1024));
+        assert!(!blocks[2].unwrap().fits(1023, 1024));
+    }
+}
@@ -1,50 +1,509 @@
 //! Simple EVM interpreter
 // TODO: Error handling
 
+mod basic_block;
+
+use self::basic_block::{basic_blocks, BasicBlock};
 use crate::{
-    chain::{BlockInfo, ChainState},
-    evm::{precompiles::keccak256, CallInfo, ExecutionResult, Opcode, TransactionInfo},
+    chain::{types::Address, BlockInfo, WriteableChainState},
+    evm::{
+        precompiles::{keccak256, precompile},
+        tracer::{StepContext, Tracer},
+        CallInfo, CallResult, ExecutionResult, GasSchedule, HaltReason, Hardfork, Log, Opcode,
+        TransactionInfo,
+    },
     prelude::*,
+    utils::RlpHash,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
 };
 
+// Gas costs for state access that do not depend on the schedule, see
+// EIP-2929 and EIP-2200
+const WARM_STORAGE_READ_COST: usize = 100;
+const SSTORE_SENTRY_GAS: usize = 2300;
+
+// Gas costs for calls and copies
+const CALL_VALUE_TRANSFER_GAS: usize = 9000;
+const CALL_NEW_ACCOUNT_GAS: usize = 25000;
+const CALL_STIPEND: usize = 2300;
+const COPY_GAS: usize = 3;
+const SHA3_WORD_GAS: usize = 6;
+const LOG_DATA_GAS: usize = 8;
+
+// Memory expansion, see Appendix H of the Yellow Paper
+const MEMORY_GAS: usize = 3;
+const QUAD_COEFF_DIV: usize = 512;
+
+// Contract creation, see EIP-170
+const CODE_DEPOSIT_GAS: usize = 200;
+const MAX_CODE_SIZE: usize = 0x6000;
+
+/// Maximum number of items on the stack
+const STACK_LIMIT: usize = 1024;
+
+/// Maximum number of calls nested in the outermost frame, deeper calls and
+/// creates fail.
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// Number of recent blocks whose hash is available to BLOCKHASH.
+const BLOCK_HASH_HISTORY: u64 = 256;
+
+/// Number of precompiled contracts, which start out warm.
+const PRECOMPILES: u64 = 9;
+
 /// Variables during execution
 struct ExecutionState<'a> {
-    chain:       &'a mut dyn ChainState,
+    chain:       &'a mut dyn WriteableChainState,
     block:       &'a BlockInfo,
     transaction: &'a TransactionInfo,
+    substate:    &'a mut Substate,
+    tracer:      &'a mut dyn Tracer,
     call:        &'a CallInfo,
     code:        &'a [u8],
+    /// Positions in `code` that are valid jump targets
+    jump_dests:  Vec<bool>,
+    /// Basic blocks by their start, empty unless executing in [`Mode::Block`]
+    blocks:      Vec<Option<BasicBlock>>,
+    /// Opcodes left in the current block whose base gas is already paid
+    prepaid_ops: usize,
+    prepaid_gas: usize,
     pc:          usize,
     gas:         usize,
+    depth:       usize,
     stack:       Vec<U256>,
     memory:      Vec<u8>,
+    /// Size of the active memory in bytes, always a multiple of 32.
+    memory_size: usize,
     return_data: Vec<u8>,
 }
 
+/// Variables shared by all call frames of a transaction
+#[derive(Default)]
+struct Substate {
+    accessed_addresses: HashSet<U256>,
+    accessed_storage:   HashSet<(U256, U256)>,
+    /// Storage values from before the transaction, used to price SSTORE.
+    original_storage:   HashMap<(U256, U256), U256>,
+    /// Accesses in order, so they can be undone when a frame reverts.
+    journal:            Vec<Access>,
+    /// Gas to refund at the end of the transaction, before capping.
+    refund:             usize,
+    /// Number of call frames currently executing
+    depth:              usize,
+    /// Accounts created in this transaction
+    created:            HashSet<U256>,
+    /// Accounts that executed SELFDESTRUCT
+    destructed:         HashSet<U256>,
+    /// Storage that is discarded at the end of the transaction (EIP-1153)
+    transient_storage:  HashMap<(U256, U256), U256>,
+    /// First opcode of a later hardfork that was executed
+    unavailable_opcode: Option<Opcode>,
+    logs:               Vec<Log>,
+    mode:               Mode,
+    schedule:           GasSchedule,
+    limits:             Limits,
+}
+
+/// Resource limits of an execution on top of the gas limit
+///
+/// The defaults are the limits of the protocol, where memory is only bounded
+/// by gas. Tighter limits keep untrusted code from exhausting host memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of the memory of a frame in bytes, expanding past it halts
+    /// like running out of gas.
+    pub max_memory: usize,
+    /// Maximum number of calls nested in the outermost frame, deeper calls
+    /// and creates fail.
+    pub max_depth:  usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_memory: usize::MAX,
+            max_depth:  MAX_CALL_DEPTH,
+        }
+    }
+}
+
+/// How base gas and stack bounds are checked, the outcome is the same
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Check every opcode on its own, required for tracing
+    Step,
+    /// Check each basic block once when entering it, which is faster
+    Block,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Step
+    }
+}
+
+/// Position in the [`Substate`] to revert to
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    journal: usize,
+    refund:  usize,
+    logs:    usize,
+}
+
+enum Access {
+    Address(U256),
+    Storage((U256, U256)),
+    Created(U256),
+    Destructed(U256),
+    /// Transient storage slot with its previous value
+    TransientStorage((U256, U256), U256),
+}
+
+impl Substate {
+    /// Mark an address as accessed and return `true` if it was cold.
+    fn access_address(&mut self, address: &U256) -> bool {
+        let cold = self.accessed_addresses.insert(address.clone());
+        if cold {
+            self.journal.push(Access::Address(address.clone()));
+        }
+        cold
+    }
+
+    /// Mark a storage slot as accessed and return `true` if it was cold.
+    fn access_storage(&mut self, key: (U256, U256)) -> bool {
+        let cold = self.accessed_storage.insert(key.clone());
+        if cold {
+            self.journal.push(Access::Storage(key));
+        }
+        cold
+    }
+
+    fn mark_created(&mut self, address: &U256) {
+        if self.created.insert(address.clone()) {
+            self.journal.push(Access::Created(address.clone()));
+        }
+    }
+
+    /// Mark an account for deletion and return `true` if it was not already.
+    fn mark_destructed(&mut self, address: &U256) -> bool {
+        let first = self.destructed.insert(address.clone());
+        if first {
+            self.journal.push(Access::Destructed(address.clone()));
+        }
+        first
+    }
+
+    fn transient_storage(&self, address: &U256, slot: &U256) -> U256 {
+        self.transient_storage
+            .get(&(address.clone(), slot.clone()))
+            .cloned()
+            .unwrap_or_else(U256::zero)
+    }
+
+    fn set_transient_storage(&mut self, address: &U256, slot: &U256, value: U256) {
+        let key = (address.clone(), slot.clone());
+        let previous = self
+            .transient_storage
+            .insert(key.clone(), value)
+            .unwrap_or_else(U256::zero);
+        self.journal.push(Access::TransientStorage(key, previous));
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            journal: self.journal.len(),
+            refund:  self.refund,
+            logs:    self.logs.len(),
+        }
+    }
+
+    /// Undo accesses, transient storage, refunds and logs since the checkpoint
+    fn revert_to(&mut self, checkpoint: Checkpoint) {
+        self.refund = checkpoint.refund;
+        self.logs.truncate(checkpoint.logs);
+        while self.journal.len() > checkpoint.journal {
+            match self.journal.pop().unwrap() {
+                Access::Address(address) => {
+                    self.accessed_addresses.remove(&address);
+                }
+                Access::Storage(key) => {
+                    self.accessed_storage.remove(&key);
+                }
+                Access::Created(address) => {
+                    self.created.remove(&address);
+                }
+                Access::Destructed(address) => {
+                    self.destructed.remove(&address);
+                }
+                Access::TransientStorage(key, previous) => {
+                    self.transient_storage.insert(key, previous);
+                }
+            }
+        }
+    }
+}
+
+/// Execute a transaction with the gas costs of `schedule`
+///
+/// State changes are written to `chain` and rolled back if the call reverts.
+/// The accessed addresses and storage slots start out empty, except for the
+/// origin, the callee, the precompiles and the transaction's access list.
+///
+/// If `call.init_code` is set, the transaction creates a contract at
+/// `call.address`.
 pub fn evaluate(
-    chain: &mut dyn ChainState,
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    call: &CallInfo,
+    schedule: &GasSchedule,
+) -> ExecutionResult {
+    evaluate_with_tracer(chain, block, transaction, call, schedule, &mut ())
+}
+
+/// Execute a transaction and report each step to `tracer`
+pub fn evaluate_with_tracer(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    call: &CallInfo,
+    schedule: &GasSchedule,
+    tracer: &mut dyn Tracer,
+) -> ExecutionResult {
+    evaluate_with_limits(chain, block, transaction, call, schedule, &Limits::default(), tracer)
+}
+
+/// Execute a transaction within `limits` and report each step to `tracer`
+pub fn evaluate_with_limits(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    call: &CallInfo,
+    schedule: &GasSchedule,
+    limits: &Limits,
+    tracer: &mut dyn Tracer,
+) -> ExecutionResult {
+    let substate = Substate {
+        limits: *limits,
+        ..Substate::default()
+    };
+    evaluate_inner(chain, block, transaction, call, schedule, tracer, substate)
+}
+
+/// Execute a transaction, checking gas and stack bounds according to `mode`
+pub fn evaluate_with_mode(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    call: &CallInfo,
+    schedule: &GasSchedule,
+    mode: Mode,
+) -> ExecutionResult {
+    let substate = Substate {
+        mode,
+        ..Substate::default()
+    };
+    evaluate_inner(chain, block, transaction, call, schedule, &mut (), substate)
+}
+
+fn evaluate_inner(
+    chain: &mut dyn WriteableChainState,
     block: &BlockInfo,
     transaction: &TransactionInfo,
     call: &CallInfo,
+    schedule: &GasSchedule,
+    tracer: &mut dyn Tracer,
+    mut substate: Substate,
 ) -> ExecutionResult {
-    let code = chain.code(&call.address);
+    substate.schedule = *schedule;
+    substate
+        .accessed_addresses
+        .insert(transaction.origin.clone());
+    substate.accessed_addresses.insert(call.address.clone());
+    substate
+        .accessed_addresses
+        .extend((1..=PRECOMPILES).map(U256::from));
+    for item in &transaction.access_list {
+        let address = U256::from(&item.address);
+        substate
+            .accessed_storage
+            .extend(item.storage_keys.iter().map(|key| (address.clone(), key.clone())));
+        substate.accessed_addresses.insert(address);
+    }
+    let (result, gas_left) = if call.init_code.is_some() {
+        create(chain, block, transaction, &mut substate, tracer, call)
+    } else {
+        execute(chain, block, transaction, &mut substate, tracer, call)
+    };
+    delete_destructed(chain, &substate);
+    // The refund can exceed the execution gas, so it is taken from the total
+    let intrinsic_gas = usize::try_from(transaction.intrinsic_gas).unwrap_or(usize::MAX);
+    let gas_used = intrinsic_gas.saturating_add(call.initial_gas - gas_left);
+    let gas_refunded = std::cmp::min(substate.refund, gas_used / schedule.max_refund_quotient);
+    ExecutionResult {
+        result,
+        logs: substate.logs,
+        accessed_addresses: substate.accessed_addresses,
+        accessed_storage: substate.accessed_storage,
+        unavailable_opcode: substate.unavailable_opcode,
+        gas_used: gas_used - gas_refunded,
+        gas_refunded,
+    }
+}
+
+/// Run init code and deploy the returned code at `call.address`
+///
+/// The sender's nonce and the value transfer are left to the caller. The new
+/// account is rolled back if the init code fails or the code can not be
+/// deployed, the latter consuming all gas.
+fn create(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    substate: &mut Substate,
+    tracer: &mut dyn Tracer,
+    call: &CallInfo,
+) -> (CallResult, usize) {
+    let snapshot = chain.snapshot();
+    let checkpoint = substate.checkpoint();
+    substate.mark_created(&call.address);
+    // New accounts start with nonce one, see EIP-161
+    chain.set_nonce(&call.address, 1);
+    let (result, gas_left) = execute(chain, block, transaction, substate, tracer, call);
+    if let CallResult::Return(code) = &result {
+        let deposit_gas = CODE_DEPOSIT_GAS.saturating_mul(code.len());
+        if code.len() <= MAX_CODE_SIZE && deposit_gas <= gas_left {
+            chain.set_code(&call.address, code);
+            return (result, gas_left - deposit_gas);
+        }
+    }
+    chain.revert_to(snapshot);
+    substate.revert_to(checkpoint);
+    match result {
+        CallResult::Return(code) if code.len() > MAX_CODE_SIZE => {
+            (CallResult::Halt(HaltReason::CodeSizeExceeded), 0)
+        }
+        CallResult::Return(_) => (CallResult::Halt(HaltReason::OutOfGas), 0),
+        revert => (revert, gas_left),
+    }
+}
+
+/// Remove accounts that were destroyed in the transaction
+///
+/// From Cancun on only accounts that were also created in the transaction
+/// are removed, others keep their code and storage, see EIP-6780.
+///
+/// Storage is cleared where the transaction wrote to it, which is all of it
+/// for created accounts. Earlier storage of other accounts can not be
+/// enumerated through the chain state and stays readable.
+fn delete_destructed(chain: &mut dyn WriteableChainState, substate: &Substate) {
+    let deleted: Vec<&U256> = if substate.schedule.hardfork >= Hardfork::Cancun {
+        substate.destructed.intersection(&substate.created).collect()
+    } else {
+        substate.destructed.iter().collect()
+    };
+    for address in deleted {
+        chain.set_balance(address, &U256::zero());
+        chain.set_nonce(address, 0);
+        chain.set_code(address, &[]);
+        for (account, slot) in substate.original_storage.keys() {
+            if account == address {
+                chain.set_storage(account, slot, &U256::zero());
+            }
+        }
+    }
+}
+
+/// Execute a call frame
+///
+/// Calls to precompiled contracts are executed natively. State changes,
+/// accesses and refunds are rolled back if the call reverts.
+///
+/// Returns the result and the remaining gas.
+fn execute(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    substate: &mut Substate,
+    tracer: &mut dyn Tracer,
+    call: &CallInfo,
+) -> (CallResult, usize) {
+    tracer.on_call(call, substate.depth + 1);
+    let (result, gas_left) = execute_frame(chain, block, transaction, substate, tracer, call);
+    match &result {
+        CallResult::Return(output) => tracer.on_return(output, gas_left),
+        CallResult::Revert(output) => tracer.on_revert(output, gas_left),
+        CallResult::Halt(_) => tracer.on_revert(&[], gas_left),
+    }
+    (result, gas_left)
+}
+
+/// Execute a call frame without reporting it to the tracer
+fn execute_frame(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+    substate: &mut Substate,
+    tracer: &mut dyn Tracer,
+    call: &CallInfo,
+) -> (CallResult, usize) {
+    if let Some(precompile) = precompile(&call.code_address) {
+        let cost = (precompile.gas)(&call.input);
+        return if cost > call.initial_gas {
+            (CallResult::Halt(HaltReason::OutOfGas), 0)
+        } else {
+            (
+                CallResult::Return((precompile.run)(&call.input)),
+                call.initial_gas - cost,
+            )
+        };
+    }
+    let snapshot = chain.snapshot();
+    let checkpoint = substate.checkpoint();
+    substate.depth += 1;
+    let code = call
+        .init_code
+        .clone()
+        .unwrap_or_else(|| chain.code(&call.code_address));
+    let schedule = substate.schedule;
+    let blocks = match substate.mode {
+        Mode::Step => Vec::new(),
+        Mode::Block => basic_blocks(&code, &schedule),
+    };
     let mut exec = ExecutionState {
         chain,
         block,
         transaction,
+        depth: substate.depth,
+        substate,
+        tracer,
         call,
         code: code.as_slice(),
+        jump_dests: jump_dests(&code),
+        blocks,
+        prepaid_ops: 0,
+        prepaid_gas: 0,
         pc: 0,
         gas: call.initial_gas,
         stack: Vec::new(),
-        memory: vec![0_u8; 1_000_000],
+        memory: Vec::new(),
+        memory_size: 0,
         return_data: Vec::new(),
     };
-    exec.run()
+    let result = exec.run();
+    exec.substate.depth -= 1;
+    if !matches!(result, CallResult::Return(_)) {
+        exec.chain.revert_to(snapshot);
+        exec.substate.revert_to(checkpoint);
+    }
+    (result, exec.gas)
 }
 
 impl<'a> ExecutionState<'a> {
-    pub fn run(&mut self) -> ExecutionResult {
+    pub fn run(&mut self) -> CallResult {
         loop {
             if let Some(result) = self.step() {
                 return result;
@@ -53,65 +512,131 @@ impl<'a> ExecutionState<'a> {
     }
 
     #[allow(clippy::too_many_lines)] // TODO: Simplify
-    pub fn step(&mut self) -> Option<ExecutionResult> {
-        // Read from zero-extended bytecode
+    pub fn step(&mut self) -> Option<CallResult> {
+        // Read from zero-extended bytecode, opcodes from later forks are
+        // undefined
         // NOTE: Does the zero-extending work for Push(..) too?
+        let pc = self.pc;
+        let hardfork = self.substate.schedule.hardfork;
         let op = self
             .code
             .get(self.pc)
-            .map_or(Opcode::Stop, |b| Opcode::from(*b));
+            .map_or(Opcode::Stop, |b| hardfork.decode(*b));
+        if let Opcode::Unknown(byte) = op {
+            let later = Opcode::from(byte);
+            if later != op && self.substate.unavailable_opcode.is_none() {
+                self.substate.unavailable_opcode = Some(later);
+            }
+        }
         // match op {
         // Opcode::Push(_) => {}
         // op => println!("{:05} {}", self.pc, op),
         // }
         self.pc += 1;
+        let base_gas = self.substate.schedule.base_gas(op);
+
+        // In block mode, base gas and stack bounds are checked once per block
+        if self.prepaid_ops == 0 {
+            self.prepay_block(pc);
+        }
+        let prepaid = self.prepaid_ops > 0;
+        if prepaid {
+            self.prepaid_ops -= 1;
+            self.prepaid_gas -= base_gas;
+        }
+
+        // Operands are checked up front, so opcodes can pop without checks
+        if !prepaid {
+            if let Some(reason) = self.stack_error(op) {
+                return Some(self.halt(reason));
+            }
+        }
+
+        // Static calls halt on state modifications, which can depend on the
+        // operands
+        if self.call.is_static && self.modifies_state(op) {
+            return Some(self.halt(HaltReason::WriteProtection));
+        }
+
+        // Charge gas
+        let memory_size = std::cmp::max(self.memory_size, self.memory_required(op));
+        let mut cost = self
+            .dynamic_gas(op)
+            .saturating_add(memory_cost(memory_size) - memory_cost(self.memory_size));
+        if !prepaid {
+            cost = cost.saturating_add(base_gas);
+        } else if cost > self.gas {
+            // Give the prepaid gas back, so this fails exactly like it would
+            // when charging per opcode
+            self.gas += self.prepaid_gas + base_gas;
+            self.prepaid_ops = 0;
+            self.prepaid_gas = 0;
+            cost = cost.saturating_add(base_gas);
+        }
+        self.tracer.on_step(&StepContext {
+            pc,
+            op,
+            gas: self.gas,
+            gas_cost: cost,
+            depth: self.depth,
+            stack: &self.stack,
+            memory: &self.memory[..self.memory_size],
+        });
+        if cost > self.gas || memory_size > self.substate.limits.max_memory {
+            return Some(self.halt(HaltReason::OutOfGas));
+        }
+        self.gas -= cost;
+        if memory_size > self.memory.len() {
+            self.memory.resize(memory_size, 0);
+        }
+        self.memory_size = memory_size;
 
         // Dispatch opcode
         #[allow(clippy::match_same_arms)]
         match op {
-            Opcode::Stop => todo!(),
+            Opcode::Stop => return Some(CallResult::Return(Vec::new())),
             Opcode::Add => self.op2(|left, right| left + right),
             Opcode::Mul => self.op2(|left, right| left * right),
             Opcode::Sub => self.op2(|left, right| left - right),
-            Opcode::Div => self.op2(|left, right| left / right),
-            Opcode::SDiv => todo!(), // self.op2(|left, right| ),
-            Opcode::Mod => self.op2(|left, right| left % right),
-            Opcode::SMod => todo!(),   // self.op2(|left, right| ),
-            Opcode::AddMod => todo!(), // self.op3(|left, right, modulus| ),
+            Opcode::Div => self.op2(div),
+            Opcode::SDiv => self.op2(sdiv),
+            Opcode::Mod => self.op2(rem),
+            Opcode::SMod => self.op2(smod),
+            Opcode::AddMod => self.op3(|left, right, modulus| addmod(left, right, &modulus)),
             Opcode::MulMod => self.op3(|left, right, modulus| left.mulmod(&right, &modulus)),
-            Opcode::Exp => todo!(),        // self.op2(|base, exponent| ),
-            Opcode::SignExtend => todo!(), // self.op2(|value, bytes| ),
+            Opcode::Exp => self.op2(|base, exponent| exp(&base, &exponent)),
+            Opcode::SignExtend => self.op2(|size, value| signextend(&size, value)),
 
             Opcode::Lt => self.op2(|left, right| left < right),
             Opcode::Gt => self.op2(|left, right| left > right),
-            Opcode::SLt => todo!(), // self.op2(|left, right| ),
-            Opcode::SGt => todo!(), // self.op2(|left, right| ),
+            Opcode::SLt => self.op2(|left, right| slt(&left, &right)),
+            Opcode::SGt => self.op2(|left, right| slt(&right, &left)),
             Opcode::Eq => self.op2(|left, right| left == right),
             Opcode::IsZero => self.op1(|value| value.is_zero()),
             Opcode::And => self.op2(|left, right| left & right),
             Opcode::Or => self.op2(|left, right| left | right),
             Opcode::Xor => self.op2(|left, right| left ^ right),
             Opcode::Not => self.op1(|value| !value),
-            Opcode::Byte => todo!(),
-            // TODO: Fix truncation of large shift amounts
-            Opcode::Shl => self.op2(|shift, value| value << shift.as_usize()),
-            Opcode::Shr => self.op2(|shift, value| value >> shift.as_usize()),
-            Opcode::Sar => todo!(), // self.op2(|shift, value|),
+            Opcode::Byte => self.op2(|index, value| byte(&index, &value)),
+            Opcode::Shl => self.op2(|shift, value| shl(&shift, value)),
+            Opcode::Shr => self.op2(|shift, value| shr(&shift, value)),
+            Opcode::Sar => self.op2(|shift, value| sar(&shift, value)),
 
-            // TODO: Fix overly large offsets/sizes
+            // Charges 30 plus 6 per word, see `dynamic_gas`
             Opcode::Sha3 => {
-                let source = self.stack.pop().unwrap().as_usize();
-                let size = self.stack.pop().unwrap().as_usize();
-                let bytes = &self.memory[source..source + size];
-                self.stack.push(keccak256(bytes))
+                let offset = self.stack.pop().unwrap();
+                let size = self.stack.pop().unwrap();
+                let hash = keccak256(self.memory_range(&offset, &size));
+                self.stack.push(hash);
             }
 
             Opcode::Push(n) => {
-                // Read payload for Push instructions
-                // TODO: Does this also zero extend?
+                // Read payload for Push instructions, code past the end is zero
                 let n = n as usize;
                 let mut padded = [0_u8; 32];
-                padded[(32 - n)..].copy_from_slice(&self.code[self.pc..self.pc + n]);
+                let payload = self.code.get(self.pc..).unwrap_or_default();
+                let available = std::cmp::min(n, payload.len());
+                padded[(32 - n)..(32 - n + available)].copy_from_slice(&payload[..available]);
                 let argument = U256::from_bytes_be(&padded);
                 // println!("{:05} {} {}", self.pc - 1, op, argument);
                 self.pc += n;
@@ -122,6 +647,11 @@ impl<'a> ExecutionState<'a> {
                 let value = self.stack.pop().unwrap().to_bytes_be();
                 self.memory[offset..offset + 32].copy_from_slice(&value);
             }
+            Opcode::MStore8 => {
+                let offset = self.stack.pop().unwrap().as_usize();
+                let value = self.stack.pop().unwrap().to_bytes_be();
+                self.memory[offset] = value[31];
+            }
             Opcode::MLoad => {
                 let offset = self.stack.pop().unwrap().as_usize();
                 let mut bytes32 = [0_u8; 32];
@@ -142,23 +672,62 @@ impl<'a> ExecutionState<'a> {
                 let i = top - (i as usize);
                 self.stack.swap(i, top);
             }
-            Opcode::JumpDest => {
-                // TODO: Require on jump
-            }
+            Opcode::JumpDest => {}
             Opcode::Jump => {
                 let target = self.stack.pop().unwrap();
-                self.pc = target.as_usize();
+                return self.jump(&target);
             }
             Opcode::JumpI => {
                 let target = self.stack.pop().unwrap();
                 let condition = self.stack.pop().unwrap();
                 if !condition.is_zero() {
-                    self.pc = target.as_usize();
+                    return self.jump(&target);
                 }
             }
+            Opcode::BlockHash => {
+                let number = self.stack.pop().unwrap();
+                let hash = self.block_hash(&number);
+                self.stack.push(hash);
+            }
+            Opcode::Coinbase => {
+                self.stack.push(self.block.coinbase.clone());
+            }
             Opcode::Timestamp => {
                 self.stack.push(U256::from(self.block.timestamp));
             }
+            Opcode::Number => {
+                self.stack.push(U256::from(self.block.number));
+            }
+            Opcode::Difficulty => {
+                self.stack.push(self.block.difficulty.clone());
+            }
+            Opcode::GasLimit => {
+                self.stack.push(U256::from(self.block.gas_limit));
+            }
+            Opcode::ChainId => {
+                self.stack.push(U256::from(self.block.chain_id));
+            }
+            Opcode::BaseFee => {
+                // Zero before London
+                self.stack.push(self.block.base_fee.clone());
+            }
+            Opcode::SelfBalance => {
+                // Always warm, so no access gas
+                self.stack.push(self.chain.balance(&self.call.address));
+            }
+            Opcode::GasPrice => {
+                self.stack
+                    .push(self.transaction.effective_gas_price(&self.block.base_fee));
+            }
+            Opcode::Address => {
+                self.stack.push(self.call.address.clone());
+            }
+            Opcode::Origin => {
+                self.stack.push(self.transaction.origin.clone());
+            }
+            Opcode::Caller => {
+                self.stack.push(self.call.sender.clone());
+            }
             Opcode::CallValue => {
                 self.stack.push(self.call.call_value.clone());
             }
@@ -166,12 +735,12 @@ impl<'a> ExecutionState<'a> {
                 self.stack.push(U256::from(self.call.input.len()));
             }
             Opcode::CallDataLoad => {
-                // TODO: Pad input by 32 zero bytes to avoid this half-copy nonsense
-                let source = self.stack.pop().unwrap().as_usize();
+                // Read a word from zero-extended input
+                let source = saturating_usize(&self.stack.pop().unwrap());
+                let input = self.call.input.get(source..).unwrap_or_default();
+                let available = std::cmp::min(32, input.len());
                 let mut bytes32 = [0_u8; 32];
-                for (i, b) in bytes32.iter_mut().enumerate() {
-                    *b = self.call.input.get(source + i).cloned().unwrap_or_default();
-                }
+                bytes32[..available].copy_from_slice(&input[..available]);
                 self.stack.push(U256::from_bytes_be(&bytes32));
             }
             Opcode::ReturnDataSize => {
@@ -179,81 +748,422 @@ impl<'a> ExecutionState<'a> {
             }
             Opcode::CallDataCopy => self.handle_copy(&self.call.input),
             Opcode::ReturnDataCopy => {
+                // Unlike the other copies, reading past the end fails (EIP-211)
+                let end =
+                    saturating_usize(self.peek(1)).saturating_add(saturating_usize(self.peek(2)));
+                if end > self.return_data.len() {
+                    return Some(self.halt(HaltReason::ReturnDataOutOfBounds));
+                }
                 // HACK: Temporarily swap out return_data without cloning.
                 let mut return_data = Vec::new();
                 std::mem::swap(&mut self.return_data, &mut return_data);
                 self.handle_copy(&return_data);
                 std::mem::swap(&mut self.return_data, &mut return_data);
             }
+            Opcode::CodeSize => self.stack.push(U256::from(self.code.len())),
             Opcode::CodeCopy => self.handle_copy(self.code),
             Opcode::SLoad => {
                 let slot = self.stack.pop().unwrap();
-                println!("SLOAD {:?}", slot);
                 self.stack
                     .push(self.chain.storage(&self.call.address, &slot));
             }
+            Opcode::SStore => {
+                let slot = self.stack.pop().unwrap();
+                let value = self.stack.pop().unwrap();
+                self.chain.set_storage(&self.call.address, &slot, &value);
+            }
+            Opcode::TLoad => {
+                let slot = self.stack.pop().unwrap();
+                let value = self.substate.transient_storage(&self.call.address, &slot);
+                self.stack.push(value);
+            }
+            Opcode::TStore => {
+                let slot = self.stack.pop().unwrap();
+                let value = self.stack.pop().unwrap();
+                self.substate.set_transient_storage(&self.call.address, &slot, value);
+            }
+            Opcode::Balance => {
+                let address = self.stack.pop().unwrap();
+                self.stack.push(self.chain.balance(&address));
+            }
             Opcode::ExtCodeSize => {
                 let address = self.stack.pop().unwrap();
                 let size = self.chain.code(&address).len();
                 self.stack.push(U256::from(size));
             }
-            Opcode::StaticCall => {
-                let initial_gas = self.stack.pop().unwrap().as_usize();
+            // Accounts that do not exist hash to zero, see EIP-1052
+            Opcode::ExtCodeHash => {
                 let address = self.stack.pop().unwrap();
-                let in_offset = self.stack.pop().unwrap().as_usize();
-                let in_size = self.stack.pop().unwrap().as_usize();
-                let out_offset = self.stack.pop().unwrap().as_usize();
-                let out_size = self.stack.pop().unwrap().as_usize();
-                let call = CallInfo {
-                    sender: self.call.address.clone(),
-                    initial_gas,
-                    call_value: U256::zero(),
-                    address,
-                    input: self.memory[in_offset..in_offset + in_size].to_vec(),
+                let hash = if self.is_empty(&address) {
+                    U256::zero()
+                } else {
+                    keccak256(&self.chain.code(&address))
                 };
-                // TODO: Print using bytes4-dictionary based ABI decoder.
-                info!("Calling {:?} {}", &call.address, hex::encode(&call.input));
-                let result = evaluate(self.chain, self.block, self.transaction, &call);
-                self.stack.push(match result {
-                    ExecutionResult::Return(_) => U256::one(),
-                    ExecutionResult::Revert(_) => U256::zero(),
+                self.stack.push(hash);
+            }
+            Opcode::ExtCodeCopy => {
+                let address = self.stack.pop().unwrap();
+                let code = self.chain.code(&address);
+                self.handle_copy(&code);
+            }
+            Opcode::Call | Opcode::CallCode | Opcode::DelegateCall | Opcode::StaticCall => {
+                self.handle_call(op);
+            }
+            Opcode::Create | Opcode::Create2 => self.handle_create(op),
+            Opcode::Log(n) => {
+                let offset = self.stack.pop().unwrap();
+                let size = self.stack.pop().unwrap();
+                let topics = (0..n).map(|_| self.stack.pop().unwrap()).collect();
+                self.substate.logs.push(Log {
+                    address: self.call.address.clone(),
+                    topics,
+                    data: self.memory_range(&offset, &size).to_vec(),
                 });
-                self.return_data = match result {
-                    ExecutionResult::Return(a) => a,
-                    ExecutionResult::Revert(a) => a,
-                };
-                // TODO: Use slice::fill
-                for byte in self.memory[out_offset..out_offset + out_size].iter_mut() {
-                    *byte = 0;
+            }
+            Opcode::SelfDestruct => {
+                let beneficiary = self.stack.pop().unwrap();
+                let address = self.call.address.clone();
+                let balance = self.chain.balance(&address);
+                self.chain.set_balance(&address, &U256::zero());
+                let beneficiary_balance = self.chain.balance(&beneficiary);
+                self.chain
+                    .set_balance(&beneficiary, &(beneficiary_balance + balance));
+                if self.substate.mark_destructed(&address) {
+                    self.substate.refund += self.substate.schedule.selfdestruct_refund;
                 }
-                self.memory
-                    [out_offset..out_offset + std::cmp::min(out_size, self.return_data.len())]
-                    .copy_from_slice(self.return_data.as_slice());
+                return Some(CallResult::Return(Vec::new()));
             }
             Opcode::Return => {
-                let offset = self.stack.pop().unwrap().as_usize();
-                let size = self.stack.pop().unwrap().as_usize();
-                let return_data = &self.memory[offset..offset + size];
-                println!("Return 0x{}", hex::encode(return_data));
-                return Some(ExecutionResult::Return(return_data.to_vec()));
+                let offset = self.stack.pop().unwrap();
+                let size = self.stack.pop().unwrap();
+                let return_data = self.memory_range(&offset, &size);
+                return Some(CallResult::Return(return_data.to_vec()));
             }
             Opcode::Revert => {
-                let offset = self.stack.pop().unwrap().as_usize();
-                let size = self.stack.pop().unwrap().as_usize();
-                let return_data = &self.memory[offset..offset + size];
-                println!("Revert 0x{}", hex::encode(return_data));
-                return Some(ExecutionResult::Revert(return_data.to_vec()));
+                let offset = self.stack.pop().unwrap();
+                let size = self.stack.pop().unwrap();
+                let return_data = self.memory_range(&offset, &size);
+                return Some(CallResult::Revert(return_data.to_vec()));
+            }
+            Opcode::PC => self.stack.push(U256::from(pc)),
+            Opcode::Gas => self.stack.push(U256::from(self.gas)),
+            Opcode::MSize => self.stack.push(U256::from(self.memory_size)),
+            Opcode::MCopy => {
+                let destination = self.stack.pop().unwrap();
+                let source = self.stack.pop().unwrap();
+                let size = self.stack.pop().unwrap();
+                if !size.is_zero() {
+                    // Overlapping ranges are copied as if through a buffer
+                    let source = source.as_usize();
+                    let size = size.as_usize();
+                    self.memory.copy_within(source..source + size, destination.as_usize());
+                }
             }
-            Opcode::Gas => {
-                // Fake no gas consumption
-                self.stack.push(U256::from(self.gas))
+            Opcode::Invalid | Opcode::Unknown(_) => {
+                return Some(self.halt(HaltReason::InvalidOpcode));
             }
-            op => todo!("opcode {:?} is not yet implemented", op),
         };
 
         None
     }
 
+    /// Whether the opcode is forbidden in a static call
+    fn modifies_state(&self, op: Opcode) -> bool {
+        match op {
+            Opcode::SStore
+            | Opcode::TStore
+            | Opcode::Log(_)
+            | Opcode::Create
+            | Opcode::Create2
+            | Opcode::SelfDestruct => true,
+            Opcode::Call => !self.peek(2).is_zero(),
+            _ => false,
+        }
+    }
+
+    /// Hash of one of the 256 blocks before the current one, zero otherwise.
+    fn block_hash(&self, number: &U256) -> U256 {
+        let current = self.block.number;
+        if number.bits() > 64 {
+            return U256::zero();
+        }
+        let number = number.as_u64();
+        if number >= current || number + BLOCK_HASH_HISTORY < current {
+            return U256::zero();
+        }
+        self.chain.block_hash(number)
+    }
+
+    /// Continue at `target`, halting if it is not a JUMPDEST
+    fn jump(&mut self, target: &U256) -> Option<CallResult> {
+        let target = saturating_usize(target);
+        if self.jump_dests.get(target).copied().unwrap_or(false) {
+            self.pc = target;
+            None
+        } else {
+            Some(self.halt(HaltReason::InvalidJump))
+        }
+    }
+
+    /// Halt exceptionally, consuming all gas
+    fn halt(&mut self, reason: HaltReason) -> CallResult {
+        self.gas = 0;
+        CallResult::Halt(reason)
+    }
+
+    /// Charge the base gas of the basic block starting at `pc`, if any
+    ///
+    /// Blocks that run out of gas or over- or underflow the stack are left
+    /// to the per opcode checks, so they fail at the right opcode.
+    fn prepay_block(&mut self, pc: usize) {
+        if let Some(Some(block)) = self.blocks.get(pc) {
+            if block.gas <= self.gas && block.fits(self.stack.len(), STACK_LIMIT) {
+                self.gas -= block.gas;
+                self.prepaid_ops = block.ops;
+                self.prepaid_gas = block.gas;
+            }
+        }
+    }
+
+    /// Check that the stack holds the operands of `op` and leaves room for
+    /// its results
+    fn stack_error(&self, op: Opcode) -> Option<HaltReason> {
+        let (consume, produce) = op.stack();
+        if self.stack.len() < consume {
+            Some(HaltReason::StackUnderflow)
+        } else if self.stack.len() - consume + produce > STACK_LIMIT {
+            Some(HaltReason::StackOverflow)
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self, depth: usize) -> &U256 {
+        &self.stack[self.stack.len() - 1 - depth]
+    }
+
+    /// Gas on top of [`GasSchedule::base_gas`]
+    ///
+    /// Marks accessed accounts and storage as warm.
+    fn dynamic_gas(&mut self, op: Opcode) -> usize {
+        let schedule = self.substate.schedule;
+        match op {
+            Opcode::SLoad => {
+                let key = (self.call.address.clone(), self.peek(0).clone());
+                if self.substate.access_storage(key) {
+                    schedule.cold_sload.saturating_sub(schedule.sload)
+                } else {
+                    0
+                }
+            }
+            Opcode::SStore => self.sstore_gas(),
+            Opcode::Exp => schedule.exp_byte * ((self.peek(1).bits() + 7) / 8),
+            Opcode::Balance | Opcode::ExtCodeSize | Opcode::ExtCodeHash => {
+                self.account_access_gas(0)
+            }
+            Opcode::ExtCodeCopy => {
+                let size = saturating_usize(self.peek(3));
+                self.account_access_gas(0) + COPY_GAS * words(size)
+            }
+            Opcode::CallDataCopy | Opcode::CodeCopy | Opcode::ReturnDataCopy | Opcode::MCopy => {
+                COPY_GAS * words(saturating_usize(self.peek(2)))
+            }
+            Opcode::Sha3 => SHA3_WORD_GAS * words(saturating_usize(self.peek(1))),
+            // Init code is hashed to compute the address
+            Opcode::Create2 => SHA3_WORD_GAS * words(saturating_usize(self.peek(2))),
+            Opcode::Log(_) => LOG_DATA_GAS.saturating_mul(saturating_usize(self.peek(1))),
+            Opcode::Call => {
+                let address = self.peek(1).clone();
+                let mut cost = self.account_access_gas(1);
+                if !self.peek(2).is_zero() {
+                    cost += CALL_VALUE_TRANSFER_GAS;
+                    if self.is_empty(&address) {
+                        cost += CALL_NEW_ACCOUNT_GAS;
+                    }
+                }
+                cost.saturating_add(self.requested_call_gas())
+            }
+            // The value is sent to the calling account itself, so it exists
+            Opcode::CallCode => {
+                let mut cost = self.account_access_gas(1);
+                if !self.peek(2).is_zero() {
+                    cost += CALL_VALUE_TRANSFER_GAS;
+                }
+                cost.saturating_add(self.requested_call_gas())
+            }
+            Opcode::DelegateCall | Opcode::StaticCall => {
+                self.account_access_gas(1)
+                    .saturating_add(self.requested_call_gas())
+            }
+            Opcode::SelfDestruct => {
+                let beneficiary = self.peek(0).clone();
+                let mut cost = 0;
+                if self.substate.access_address(&beneficiary) {
+                    cost += schedule.cold_account_access;
+                }
+                if !self.chain.balance(&self.call.address).is_zero() && self.is_empty(&beneficiary)
+                {
+                    cost += CALL_NEW_ACCOUNT_GAS;
+                }
+                cost
+            }
+            _ => 0,
+        }
+    }
+
+    /// Memory size in bytes the instruction needs, rounded up to words
+    fn memory_required(&self, op: Opcode) -> usize {
+        match op {
+            Opcode::MLoad | Opcode::MStore => memory_end(saturating_usize(self.peek(0)), 32),
+            Opcode::MStore8 => memory_end(saturating_usize(self.peek(0)), 1),
+            Opcode::Sha3 | Opcode::Return | Opcode::Revert | Opcode::Log(_) => {
+                self.stack_memory_end(0, 1)
+            }
+            Opcode::CallDataCopy | Opcode::CodeCopy | Opcode::ReturnDataCopy => {
+                self.stack_memory_end(0, 2)
+            }
+            Opcode::MCopy => {
+                std::cmp::max(self.stack_memory_end(0, 2), self.stack_memory_end(1, 2))
+            }
+            Opcode::ExtCodeCopy => self.stack_memory_end(1, 3),
+            Opcode::Create | Opcode::Create2 => self.stack_memory_end(1, 2),
+            Opcode::Call | Opcode::CallCode => {
+                std::cmp::max(self.stack_memory_end(3, 4), self.stack_memory_end(5, 6))
+            }
+            Opcode::DelegateCall | Opcode::StaticCall => {
+                std::cmp::max(self.stack_memory_end(2, 3), self.stack_memory_end(4, 5))
+            }
+            _ => 0,
+        }
+    }
+
+    /// Active memory at `offset`, empty ranges may have any offset.
+    ///
+    /// Memory was expanded to cover non-empty ranges when charging gas.
+    fn memory_range(&self, offset: &U256, size: &U256) -> &[u8] {
+        if size.is_zero() {
+            return &[];
+        }
+        let offset = offset.as_usize();
+        &self.memory[offset..offset + size.as_usize()]
+    }
+
+    /// [`memory_end`] with offset and size read from the stack.
+    fn stack_memory_end(&self, offset: usize, size: usize) -> usize {
+        memory_end(
+            saturating_usize(self.peek(offset)),
+            saturating_usize(self.peek(size)),
+        )
+    }
+
+    /// Extra gas for accessing the account at stack position `depth` cold.
+    fn account_access_gas(&mut self, depth: usize) -> usize {
+        let address = self.peek(depth).clone();
+        if self.substate.access_address(&address) {
+            let schedule = &self.substate.schedule;
+            schedule
+                .cold_account_access
+                .saturating_sub(WARM_STORAGE_READ_COST)
+        } else {
+            0
+        }
+    }
+
+    /// Gas that can be given to a nested create
+    ///
+    /// Before EIP-150 creates get all of the gas that is left.
+    fn forwardable_gas(&self) -> usize {
+        if self.substate.schedule.call_gas_cap {
+            all_but_one_64th(self.gas)
+        } else {
+            self.gas
+        }
+    }
+
+    /// Gas a call requests that is charged with the opcode
+    ///
+    /// Before EIP-150 the requested gas is part of the cost of a call, so
+    /// requesting more than is left runs out of gas. Since then requests are
+    /// capped instead, see [`Self::forwardable_gas`].
+    fn requested_call_gas(&self) -> usize {
+        if self.substate.schedule.call_gas_cap {
+            0
+        } else {
+            saturating_usize(self.peek(0))
+        }
+    }
+
+    /// Empty accounts as defined in EIP-161
+    fn is_empty(&self, address: &U256) -> bool {
+        self.chain.nonce(address) == 0
+            && self.chain.balance(address).is_zero()
+            && self.chain.code(address).is_empty()
+    }
+
+    /// Gas for SSTORE, see EIP-2200 as amended by EIP-2929.
+    ///
+    /// Without net gas metering only the current value matters. Also updates
+    /// the refund counter.
+    fn sstore_gas(&mut self) -> usize {
+        let schedule = self.substate.schedule;
+        if schedule.net_sstore && self.gas <= SSTORE_SENTRY_GAS {
+            // Always out of gas
+            return usize::MAX;
+        }
+        let key = (self.call.address.clone(), self.peek(0).clone());
+        let value = self.peek(1).clone();
+        let current = self.chain.storage(&key.0, &key.1);
+        let original = self
+            .substate
+            .original_storage
+            .entry(key.clone())
+            .or_insert_with(|| current.clone());
+        let original = original.clone();
+        let cost = if !schedule.net_sstore {
+            if !current.is_zero() && value.is_zero() {
+                self.substate.refund += schedule.sstore_clears_refund;
+            }
+            if current.is_zero() && !value.is_zero() {
+                schedule.sstore_set
+            } else {
+                schedule.sstore_reset
+            }
+        } else if value == current {
+            schedule.sload
+        } else if original == current {
+            if !original.is_zero() && value.is_zero() {
+                self.substate.refund += schedule.sstore_clears_refund;
+            }
+            if original.is_zero() {
+                schedule.sstore_set
+            } else {
+                schedule.sstore_reset
+            }
+        } else {
+            // Slot is already dirty
+            if !original.is_zero() {
+                if current.is_zero() {
+                    self.substate.refund -= schedule.sstore_clears_refund;
+                } else if value.is_zero() {
+                    self.substate.refund += schedule.sstore_clears_refund;
+                }
+            }
+            if original == value {
+                self.substate.refund += if original.is_zero() {
+                    schedule.sstore_set - schedule.sload
+                } else {
+                    schedule.sstore_reset - schedule.sload
+                };
+            }
+            schedule.sload
+        };
+        if self.substate.access_storage(key) {
+            cost + schedule.cold_sload
+        } else {
+            cost
+        }
+    }
+
     fn op1<F, T>(&mut self, f: F)
     where
         F: FnOnce(U256) -> T,
@@ -287,19 +1197,2368 @@ impl<'a> ExecutionState<'a> {
         self.stack.push(result.into());
     }
 
-    /// Handle copy operations from a source array to memory
+    /// Handle the `CALL` family of opcodes
+    ///
+    /// DELEGATECALL runs the callee's code with the current address, sender
+    /// and call value. CALLCODE also runs it with the current address, but
+    /// like CALL the sender is the current account and the value is new.
+    /// Static calls make all nested calls static.
+    #[allow(clippy::too_many_lines)]
+    fn handle_call(&mut self, op: Opcode) {
+        let requested = saturating_usize(&self.stack.pop().unwrap());
+        let initial_gas = if self.substate.schedule.call_gas_cap {
+            std::cmp::min(requested, self.forwardable_gas())
+        } else {
+            // Charged with the opcode, it is taken again below if the call
+            // is made
+            self.gas += requested;
+            requested
+        };
+        let address = self.stack.pop().unwrap();
+        let value = match op {
+            Opcode::Call | Opcode::CallCode => self.stack.pop().unwrap(),
+            _ => U256::zero(),
+        };
+        // Value transfers come with a gas stipend
+        let stipend = if value.is_zero() { 0 } else { CALL_STIPEND };
+        let in_offset = self.stack.pop().unwrap();
+        let in_size = self.stack.pop().unwrap();
+        let out_offset = self.stack.pop().unwrap().as_usize();
+        let out_size = self.stack.pop().unwrap().as_usize();
+        // Too deep calls fail without using the gas
+        if self.depth > self.substate.limits.max_depth {
+            self.stack.push(U256::zero());
+            self.return_data = Vec::new();
+            return;
+        }
+        let input = self.memory_range(&in_offset, &in_size).to_vec();
+        let call = match op {
+            Opcode::Call => {
+                CallInfo {
+                    sender: self.call.address.clone(),
+                    address: address.clone(),
+                    code_address: address,
+                    initial_gas: initial_gas + stipend,
+                    call_value: value,
+                    input,
+                    is_static: self.call.is_static,
+                    init_code: None,
+                }
+            }
+            Opcode::CallCode => {
+                CallInfo {
+                    sender: self.call.address.clone(),
+                    address: self.call.address.clone(),
+                    code_address: address,
+                    initial_gas: initial_gas + stipend,
+                    call_value: value,
+                    input,
+                    is_static: self.call.is_static,
+                    init_code: None,
+                }
+            }
+            Opcode::DelegateCall => {
+                CallInfo {
+                    sender: self.call.sender.clone(),
+                    address: self.call.address.clone(),
+                    code_address: address,
+                    call_value: self.call.call_value.clone(),
+                    initial_gas,
+                    input,
+                    is_static: self.call.is_static,
+                    init_code: None,
+                }
+            }
+            Opcode::StaticCall => {
+                CallInfo {
+                    sender: self.call.address.clone(),
+                    address: address.clone(),
+                    code_address: address,
+                    call_value: U256::zero(),
+                    initial_gas,
+                    input,
+                    is_static: true,
+                    init_code: None,
+                }
+            }
+            _ => unreachable!(),
+        };
+        // TODO: Print using bytes4-dictionary based ABI decoder.
+        info!(
+            "Calling {:?} {}",
+            &call.code_address,
+            hex::encode(&call.input)
+        );
+
+        // Transfer value, the call fails if the balance is insufficient.
+        // For CALLCODE the transfer is to itself, but the balance still counts.
+        let snapshot = self.chain.snapshot();
+        if matches!(op, Opcode::Call | Opcode::CallCode) && !call.call_value.is_zero() {
+            let balance = self.chain.balance(&call.sender);
+            if balance < call.call_value {
+                self.stack.push(U256::zero());
+                self.return_data = Vec::new();
+                return;
+            }
+            self.chain
+                .set_balance(&call.sender, &(balance - &call.call_value));
+            let balance = self.chain.balance(&call.address);
+            self.chain
+                .set_balance(&call.address, &(balance + &call.call_value));
+        }
+        self.gas -= initial_gas;
+        let (result, gas_left) = execute(
+            self.chain,
+            self.block,
+            self.transaction,
+            self.substate,
+            self.tracer,
+            &call,
+        );
+        self.gas += gas_left;
+        if !matches!(result, CallResult::Return(_)) {
+            self.chain.revert_to(snapshot);
+        }
+        self.stack.push(match result {
+            CallResult::Return(_) => U256::one(),
+            CallResult::Revert(_) | CallResult::Halt(_) => U256::zero(),
+        });
+        self.return_data = match result {
+            CallResult::Return(a) | CallResult::Revert(a) => a,
+            CallResult::Halt(_) => Vec::new(),
+        };
+        // Output is truncated to the buffer, the rest is only available through
+        // RETURNDATACOPY. Memory past a short output is left untouched.
+        let size = std::cmp::min(out_size, self.return_data.len());
+        if size > 0 {
+            self.memory[out_offset..out_offset + size].copy_from_slice(&self.return_data[..size]);
+        }
+    }
+
+    fn handle_create(&mut self, op: Opcode) {
+        let value = self.stack.pop().unwrap();
+        let offset = self.stack.pop().unwrap();
+        let size = self.stack.pop().unwrap();
+        let init_code = self.memory_range(&offset, &size).to_vec();
+        let sender = self.call.address.clone();
+        let nonce = self.chain.nonce(&sender);
+        let address = match op {
+            Opcode::Create => create_address(&sender, nonce),
+            Opcode::Create2 => {
+                let salt = self.stack.pop().unwrap();
+                create2_address(&sender, &salt, &init_code)
+            }
+            _ => unreachable!(),
+        };
+        self.return_data = Vec::new();
+        let balance = self.chain.balance(&sender);
+        if self.depth > self.substate.limits.max_depth || balance < value {
+            self.stack.push(U256::zero());
+            return;
+        }
+        self.chain.set_nonce(&sender, nonce + 1);
+        self.substate.access_address(&address);
+
+        // Deploying over an existing contract fails and consumes the gas
+        // given to it
+        let initial_gas = self.forwardable_gas();
+        if self.chain.nonce(&address) != 0 || !self.chain.code(&address).is_empty() {
+            self.gas -= initial_gas;
+            self.stack.push(U256::zero());
+            return;
+        }
+
+        let snapshot = self.chain.snapshot();
+        self.chain.set_balance(&sender, &(balance - &value));
+        let balance = self.chain.balance(&address);
+        self.chain.set_balance(&address, &(balance + &value));
+        let call = CallInfo {
+            sender,
+            address: address.clone(),
+            code_address: address.clone(),
+            call_value: value,
+            initial_gas,
+            input: Vec::new(),
+            is_static: false,
+            init_code: Some(init_code),
+        };
+        self.gas -= initial_gas;
+        let (result, gas_left) = create(
+            self.chain,
+            self.block,
+            self.transaction,
+            self.substate,
+            self.tracer,
+            &call,
+        );
+        self.gas += gas_left;
+        match result {
+            CallResult::Return(_) => self.stack.push(address),
+            CallResult::Revert(output) => {
+                self.chain.revert_to(snapshot);
+                self.stack.push(U256::zero());
+                self.return_data = output;
+            }
+            CallResult::Halt(_) => {
+                self.chain.revert_to(snapshot);
+                self.stack.push(U256::zero());
+                self.return_data = Vec::new();
+            }
+        }
+    }
+
+    /// Handle copy operations from a source array to memory
     ///
     /// Offsets and sizes are popped from stack. `source` is implicitly
     /// zero extended.
     fn handle_copy(&mut self, source: &[u8]) {
-        let offset = self.stack.pop().unwrap().as_usize();
-        let source_offset = self.stack.pop().unwrap().as_usize();
-        let want_size = self.stack.pop().unwrap().as_usize();
-        let size = std::cmp::min(want_size, source.len() - source_offset);
-        let source_slice = &source[source_offset..source_offset + size];
-        self.memory[offset..offset + size].copy_from_slice(source_slice);
-        for byte in self.memory[offset + size..offset + want_size].iter_mut() {
+        let offset = self.stack.pop().unwrap();
+        let source_offset = saturating_usize(&self.stack.pop().unwrap());
+        let size = self.stack.pop().unwrap();
+        if size.is_zero() {
+            return;
+        }
+        let offset = offset.as_usize();
+        let size = size.as_usize();
+        let available = source.get(source_offset..).unwrap_or_default();
+        let copied = std::cmp::min(size, available.len());
+        self.memory[offset..offset + copied].copy_from_slice(&available[..copied]);
+        for byte in &mut self.memory[offset + copied..offset + size] {
             *byte = 0;
         }
     }
 }
+
+#[cfg(feature = "bench")]
+pub mod bench {
+    use super::*;
+    use crate::{
+        bench::prelude::*,
+        chain::{Empty, Fork},
+    };
+
+    /// Compare the execution modes on a tight countdown loop
+    pub fn group(criterion: &mut Criterion) {
+        // PUSH2 0xffff loop: JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH1 3 JUMPI
+        let address = U256::from(0x1234);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&address, &hex!("61ffff 5b 6001 90 03 80 6003 57"));
+        let call = CallInfo {
+            address: address.clone(),
+            code_address: address,
+            initial_gas: 10_000_000,
+            ..CallInfo::default()
+        };
+        for &(name, mode) in &[("loop step", Mode::Step), ("loop block", Mode::Block)] {
+            criterion.bench_function(name, |bencher| {
+                bencher.iter(|| {
+                    black_box(evaluate_with_mode(
+                        &mut chain,
+                        &BlockInfo::default(),
+                        &TransactionInfo::default(),
+                        &call,
+                        &GasSchedule::default(),
+                        mode,
+                    ))
+                });
+            });
+        }
+    }
+}
+
+/// Mark the JUMPDEST instructions in `code`
+///
+/// Bytes that are part of PUSH data are skipped, even if they equal the
+/// JUMPDEST opcode.
+fn jump_dests(code: &[u8]) -> Vec<bool> {
+    let mut valid = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = Opcode::from(code[pc]);
+        valid[pc] = op == Opcode::JumpDest;
+        pc += op.encoded_size();
+    }
+    valid
+}
+
+/// Gas that can be given to a nested call or create, see EIP-150
+///
+/// The caller always retains a 64th of its gas.
+const fn all_but_one_64th(gas: usize) -> usize {
+    gas - gas / 64
+}
+
+/// Convert to `usize`, saturating on overflow
+fn saturating_usize(value: &U256) -> usize {
+    if value.bits() > 64 {
+        usize::MAX
+    } else {
+        usize::try_from(value.as_u64()).unwrap_or(usize::MAX)
+    }
+}
+
+/// Unsigned division, division by zero is zero
+fn div(left: U256, right: U256) -> U256 {
+    if right.is_zero() {
+        U256::zero()
+    } else {
+        left / right
+    }
+}
+
+/// Unsigned remainder, modulo zero is zero
+fn rem(left: U256, right: U256) -> U256 {
+    if right.is_zero() {
+        U256::zero()
+    } else {
+        left % right
+    }
+}
+
+/// Addition modulo `modulus` without overflow, modulo zero is zero
+fn addmod(left: U256, right: U256, modulus: &U256) -> U256 {
+    if modulus.is_zero() {
+        return U256::zero();
+    }
+    let left = rem(left, modulus.clone());
+    let right = rem(right, modulus.clone());
+    // Both are below the modulus, so one subtraction suffices. If the sum
+    // wrapped around, so does the subtraction.
+    let sum = left.clone() + right;
+    if sum < left || &sum >= modulus {
+        sum - modulus.clone()
+    } else {
+        sum
+    }
+}
+
+/// Extend the sign of the two's complement value in the lowest `size + 1`
+/// bytes
+fn signextend(size: &U256, value: U256) -> U256 {
+    if size.bits() > 8 || size.as_usize() >= 31 {
+        return value;
+    }
+    let sign_byte = 31 - size.as_usize();
+    let mut bytes = value.to_bytes_be();
+    let fill = if bytes[sign_byte] & 0x80 == 0 { 0 } else { 0xff };
+    for byte in &mut bytes[..sign_byte] {
+        *byte = fill;
+    }
+    U256::from_bytes_be(&bytes)
+}
+
+/// Byte of `value` at `index`, counting from the most significant, zero past
+/// the end
+fn byte(index: &U256, value: &U256) -> U256 {
+    if index.bits() > 8 || index.as_usize() >= 32 {
+        U256::zero()
+    } else {
+        U256::from(value.to_bytes_be()[index.as_usize()])
+    }
+}
+
+/// Is the two's complement value negative
+fn is_negative(value: &U256) -> bool {
+    value.bits() == 256
+}
+
+/// Two's complement negation, the minimum value is its own negation
+fn negate(value: U256) -> U256 {
+    if value.is_zero() {
+        value
+    } else {
+        !(value - U256::one())
+    }
+}
+
+/// Absolute value as an unsigned number
+fn abs(value: U256) -> U256 {
+    if is_negative(&value) {
+        negate(value)
+    } else {
+        value
+    }
+}
+
+/// Signed division, rounding towards zero
+///
+/// Division by zero is zero and `MIN / -1` overflows to `MIN`.
+fn sdiv(left: U256, right: U256) -> U256 {
+    let negative = is_negative(&left) != is_negative(&right);
+    let quotient = div(abs(left), abs(right));
+    if negative {
+        negate(quotient)
+    } else {
+        quotient
+    }
+}
+
+/// Signed remainder, it takes the sign of the dividend
+fn smod(left: U256, right: U256) -> U256 {
+    let negative = is_negative(&left);
+    let remainder = rem(abs(left), abs(right));
+    if negative {
+        negate(remainder)
+    } else {
+        remainder
+    }
+}
+
+/// Signed less than
+fn slt(left: &U256, right: &U256) -> bool {
+    match (is_negative(left), is_negative(right)) {
+        (true, false) => true,
+        (false, true) => false,
+        // Two's complement preserves the order within the same sign
+        _ => left < right,
+    }
+}
+
+/// Exponentiation modulo 2^256 by square and multiply
+fn exp(base: &U256, exponent: &U256) -> U256 {
+    let mut result = U256::one();
+    let bytes = exponent.to_bytes_be();
+    for byte in bytes.iter().skip_while(|byte| **byte == 0) {
+        for bit in (0..8).rev() {
+            result = result.clone() * result;
+            if (byte >> bit) & 1 == 1 {
+                result *= base;
+            }
+        }
+    }
+    result
+}
+
+/// Shift left, shifts of 256 bits or more clear the value
+fn shl(shift: &U256, value: U256) -> U256 {
+    if shift.bits() > 8 {
+        U256::zero()
+    } else {
+        value << shift.as_usize()
+    }
+}
+
+/// Logical shift right, shifts of 256 bits or more clear the value
+fn shr(shift: &U256, value: U256) -> U256 {
+    if shift.bits() > 8 {
+        U256::zero()
+    } else {
+        value >> shift.as_usize()
+    }
+}
+
+/// Arithmetic shift right of a two's complement value
+///
+/// Shifts of 256 bits or more leave only the sign, i.e. zero or minus one.
+fn sar(shift: &U256, value: U256) -> U256 {
+    if value.bits() < 256 {
+        shr(shift, value)
+    } else {
+        // Shift in ones by shifting the complement
+        !shr(shift, !value)
+    }
+}
+
+/// Address of a contract created by CREATE
+///
+/// The lower 160 bits of the hash of the RLP encoded sender and nonce.
+pub fn create_address(sender: &U256, nonce: usize) -> U256 {
+    let hash = (Address::from(sender), nonce as u64).rlp_hash();
+    U256::from(&Address::from(&hash))
+}
+
+/// Address of a contract created by CREATE2, see EIP-1014
+fn create2_address(sender: &U256, salt: &U256, init_code: &[u8]) -> U256 {
+    let mut preimage = Vec::with_capacity(85);
+    preimage.push(0xff);
+    preimage.extend_from_slice(Address::from(sender).as_slice());
+    preimage.extend_from_slice(&salt.to_bytes_be());
+    preimage.extend_from_slice(&keccak256(init_code).to_bytes_be());
+    U256::from(&Address::from(&keccak256(&preimage)))
+}
+
+/// Memory size in bytes needed to access a range, rounded up to words
+///
+/// Empty ranges need no memory.
+const fn memory_end(offset: usize, size: usize) -> usize {
+    if size == 0 {
+        0
+    } else {
+        words(offset.saturating_add(size)).saturating_mul(32)
+    }
+}
+
+/// Total gas for `size` bytes of active memory, quadratic in the word count
+///
+/// Expanding memory costs the difference to the current size.
+const fn memory_cost(size: usize) -> usize {
+    let words = words(size);
+    words
+        .saturating_mul(MEMORY_GAS)
+        .saturating_add(words.saturating_mul(words) / QUAD_COEFF_DIV)
+}
+
+/// Number of 32-byte words needed to hold `size` bytes
+const fn words(size: usize) -> usize {
+    size.saturating_add(31) / 32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{types::AccessListItem, ChainState, Empty, Fork},
+        evm::{abi, tracer::StructLogger, Hardfork},
+        test::prelude::assert_eq,
+    };
+
+    const CONTRACT: U256 = U256::from_limbs([0x1234, 0, 0, 0]);
+
+    /// Last schedule with the refunds the tests were written for
+    const SCHEDULE: GasSchedule = GasSchedule::berlin();
+
+    fn run(chain: &mut Fork<Empty>, code: &[u8]) -> ExecutionResult {
+        run_with(
+            chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            code,
+        )
+    }
+
+    fn run_with(
+        chain: &mut Fork<Empty>,
+        block: &BlockInfo,
+        transaction: &TransactionInfo,
+        code: &[u8],
+    ) -> ExecutionResult {
+        chain.set_code(&CONTRACT, code);
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        evaluate(chain, block, transaction, &call, &SCHEDULE)
+    }
+
+    #[test]
+    fn test_sload_schedule() {
+        // PUSH1 0 SLOAD STOP
+        let sload_gas = |schedule: &GasSchedule| {
+            let mut chain = Fork::from(Empty);
+            chain.set_code(&CONTRACT, &hex!("600054 00"));
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 1_000_000,
+                ..CallInfo::default()
+            };
+            let result = evaluate(
+                &mut chain,
+                &BlockInfo::default(),
+                &TransactionInfo::default(),
+                &call,
+                schedule,
+            );
+            result.gas_used() - 3
+        };
+        assert_eq!(sload_gas(&GasSchedule::frontier()), 50);
+        assert_eq!(sload_gas(&GasSchedule::tangerine()), 200);
+        // Raised from 200 by EIP-1884
+        assert_eq!(sload_gas(&GasSchedule::istanbul()), 800);
+        // The slot is cold on first access
+        assert_eq!(sload_gas(&GasSchedule::berlin()), 2100);
+        assert_eq!(sload_gas(&GasSchedule::london()), 2100);
+    }
+
+    #[test]
+    fn test_sstore_schedule() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 PUSH1 0 SSTORE STOP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6001600055 6000600055 00"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &GasSchedule::tangerine(),
+        );
+        // Without net metering the second store is a reset, refunding the clear
+        let gas_used = 4 * 3 + 20000 + 5000;
+        assert_eq!(result.gas_refunded(), gas_used / 2);
+        assert_eq!(result.gas_used(), gas_used - gas_used / 2);
+    }
+
+    #[test]
+    fn test_sstore() {
+        // PUSH1 1 PUSH1 0 SSTORE STOP
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600160005500"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::one());
+    }
+
+    #[test]
+    fn test_sstore_reverted() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 PUSH1 0 REVERT
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600160005560006000fd"));
+        assert!(matches!(result.result, CallResult::Revert(_)));
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_transient_storage() {
+        let run_cancun = |chain: &mut Fork<Empty>, input: &[u8]| {
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 1_000_000,
+                input: input.to_vec(),
+                ..CallInfo::default()
+            };
+            let schedule = Hardfork::Cancun.schedule();
+            evaluate(chain, &BlockInfo::default(), &TransactionInfo::default(), &call, &schedule)
+        };
+        let output = |result: ExecutionResult| {
+            match result.result {
+                CallResult::Return(output) => output,
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+
+        // TSTORE(0, 7) TLOAD(0)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6007 6000 5d 6000 5c"));
+        let result = run_cancun(&mut chain, &[]);
+        assert_eq!(result.gas_used(), 3 + 3 + 100 + 3 + 100);
+
+        // Without input: TSTORE(0, 7) CALL(GAS, ADDRESS, 0, 0, 1, 0, 32) RETURN(0, 32)
+        // With input: 28: JUMPDEST MSTORE(0, TLOAD(0)) RETURN(0, 32)
+        let code = [
+            hex!("36601c57 60076000 5d 6020600060016000600030 5a f1 50 60206000f3").as_ref(),
+            hex!("5b 6000 5c 600052 60206000f3").as_ref(),
+        ]
+        .concat();
+        chain.set_code(&CONTRACT, &code);
+        // The nested call sees the value stored by its caller
+        let stored = U256::from(7).to_bytes_be().to_vec();
+        assert_eq!(output(run_cancun(&mut chain, &[])), stored);
+        // The next transaction does not
+        assert_eq!(output(run_cancun(&mut chain, &[1])), vec![0_u8; 32]);
+
+        // Not available before Cancun
+        let result = run(&mut chain, &hex!("6000 5c"));
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::InvalidOpcode)
+        ));
+    }
+
+    #[test]
+    fn test_transient_storage_reverted() {
+        let mut substate = Substate::default();
+        let slot = U256::one();
+        substate.set_transient_storage(&CONTRACT, &slot, U256::from(1));
+        let checkpoint = substate.checkpoint();
+        substate.set_transient_storage(&CONTRACT, &slot, U256::from(2));
+        substate.set_transient_storage(&CONTRACT, &U256::zero(), U256::from(3));
+        assert_eq!(substate.transient_storage(&CONTRACT, &slot), U256::from(2));
+        substate.revert_to(checkpoint);
+        assert_eq!(substate.transient_storage(&CONTRACT, &slot), U256::from(1));
+        assert_eq!(substate.transient_storage(&CONTRACT, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_sload_cold_warm() {
+        // PUSH1 0 SLOAD POP PUSH1 0 SLOAD POP
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6000545060005450"));
+        assert_eq!(result.gas_used(), 3 + 2100 + 2 + 3 + 100 + 2);
+        assert!(result.accessed_storage.contains(&(CONTRACT, U256::zero())));
+    }
+
+    #[test]
+    fn test_sload_access_list() {
+        // PUSH1 0 SLOAD
+        let mut chain = Fork::from(Empty);
+        let transaction = TransactionInfo {
+            access_list: vec![AccessListItem {
+                address:      Address::from(&CONTRACT),
+                storage_keys: vec![U256::zero()],
+            }],
+            ..TransactionInfo::default()
+        };
+        let result = run_with(&mut chain, &BlockInfo::default(), &transaction, &hex!("600054"));
+        assert_eq!(result.gas_used(), 3 + 100);
+    }
+
+    #[test]
+    fn test_sstore_cold_warm() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 2 PUSH1 0 SSTORE
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6001600055 6002600055"));
+        assert_eq!(result.gas_used(), 6 + 22100 + 6 + 100);
+        assert_eq!(result.gas_refunded(), 0);
+    }
+
+    #[test]
+    fn test_sstore_refund_capped() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 PUSH1 0 SSTORE
+        let code = hex!("6001600055 6000600055");
+        let execution = 6 + 22100 + 6 + 100;
+        let run_after = |intrinsic_gas: u64| {
+            let mut chain = Fork::from(Empty);
+            let transaction = TransactionInfo {
+                intrinsic_gas,
+                ..TransactionInfo::default()
+            };
+            run_with(&mut chain, &BlockInfo::default(), &transaction, &code)
+        };
+
+        // Restoring the original value refunds 19900, capped at half of the
+        // gas used including the intrinsic gas
+        let result = run_after(21000);
+        assert_eq!(result.gas_refunded(), 19900);
+        assert_eq!(result.gas_used(), 21000 + execution - 19900);
+
+        let result = run_after(1000);
+        let capped = (1000 + execution) / 2;
+        assert_eq!(result.gas_refunded(), capped);
+        assert_eq!(result.gas_used(), 1000 + execution - capped);
+    }
+
+    #[test]
+    fn test_refund_exceeds_execution_gas() {
+        // SELFDESTRUCT(ORIGIN)
+        let code = hex!("32 ff");
+        let mut chain = Fork::from(Empty);
+        let transaction = TransactionInfo {
+            intrinsic_gas: 21000,
+            ..TransactionInfo::default()
+        };
+        let result = run_with(&mut chain, &BlockInfo::default(), &transaction, &code);
+        let total = 21000 + 2 + 5000;
+        assert_eq!(result.gas_refunded(), total / 2);
+        assert_eq!(result.gas_used(), total - total / 2);
+    }
+
+    #[test]
+    fn test_push0() {
+        // PUSH0 STOP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("5f 00"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        let mut tracer = StructLogger::default();
+        let result = evaluate_with_tracer(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &Hardfork::Shanghai.schedule(),
+            &mut tracer,
+        );
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(result.gas_used(), 2);
+        assert_eq!(tracer.logs[0].op, "PUSH0");
+        assert_eq!(tracer.logs[0].gas_cost, 2);
+        assert_eq!(tracer.logs[1].stack, Some(vec![hex::encode([0_u8; 32])]));
+
+        // Available on dev chains, which run the latest fork
+        let schedule = Hardfork::detect(1337, 0).schedule();
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &schedule,
+        );
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(result.gas_used(), 2);
+
+        // Not available before Shanghai
+        let result = run(&mut chain, &hex!("5f 00"));
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::InvalidOpcode)
+        ));
+    }
+
+    #[test]
+    fn test_loop_gas() {
+        // PUSH1 10
+        // loop: JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH1 2 JUMPI
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600a 5b 6001 90 03 80 6002 57"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(result.gas_used(), 3 + 10 * (1 + 3 + 3 + 3 + 3 + 3 + 10));
+    }
+
+    #[test]
+    fn test_mcopy() {
+        // MSTORE(0, 0x0102..20) MCOPY(destination, source, 8) RETURN(0, 32)
+        let mcopy = |schedule: &GasSchedule, destination: u8, source: u8| {
+            let mut code = vec![0x7f];
+            code.extend(1..=32_u8);
+            code.extend(&hex!("600052 6008"));
+            code.extend(&[0x60, source, 0x60, destination]);
+            code.extend(&hex!("5e 60206000f3"));
+            let mut chain = Fork::from(Empty);
+            chain.set_code(&CONTRACT, &code);
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 1_000_000,
+                ..CallInfo::default()
+            };
+            let transaction = TransactionInfo::default();
+            evaluate(&mut chain, &BlockInfo::default(), &transaction, &call, schedule)
+        };
+        let cancun = Hardfork::Cancun.schedule();
+        let original = (1..=32_u8).collect::<Vec<_>>();
+
+        // Forward overlap, copying byte by byte would repeat the first bytes
+        let result = mcopy(&cancun, 4, 0);
+        let mut expected = original.clone();
+        expected[4..12].copy_from_slice(&original[0..8]);
+        match &result.result {
+            CallResult::Return(output) => assert_eq!(output, &expected),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(result.gas_used(), 3 + 3 + 6 + 9 + (3 + 3) + 6);
+
+        // Backward overlap
+        let result = mcopy(&cancun, 0, 4);
+        let mut expected = original.clone();
+        expected[0..8].copy_from_slice(&original[4..12]);
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Copying past the end expands memory to cover the destination
+        let result = mcopy(&cancun, 30, 0);
+        assert_eq!(result.gas_used(), 3 + 3 + 6 + 9 + (3 + 3 + 3) + 6);
+
+        // Not available before Cancun
+        let result = mcopy(&GasSchedule::london(), 4, 0);
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::InvalidOpcode)
+        ));
+    }
+
+    #[test]
+    fn test_memory_expansion_gas() {
+        // MSTORE(0x1000, 1) MSTORE(0, 1) MSTORE8(0x101f, 1)
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6001611000 52 6001600052 600161101f53"));
+        // Memory grows to 129 words once, later accesses are within it
+        let expansion = 129 * MEMORY_GAS + 129 * 129 / QUAD_COEFF_DIV;
+        assert_eq!(expansion, 419);
+        assert_eq!(result.gas_used(), 9 * 3 + expansion);
+        assert_eq!(memory_cost(0), 0);
+        assert_eq!(memory_cost(1), 3);
+        assert_eq!(memory_cost(32 * 1024), 1024 * 3 + 2048);
+    }
+
+    #[test]
+    fn test_jump_dests() {
+        // PUSH1 0x5b JUMPDEST PUSH2 0x5b5b STOP JUMPDEST
+        let valid = jump_dests(&hex!("605b 5b 615b5b 00 5b"));
+        assert_eq!(valid, vec![false, false, true, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_jump_into_push_data() {
+        // PUSH1 4 JUMP PUSH1 0x5b
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600456 605b"));
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::InvalidJump)
+        ));
+        assert_eq!(result.gas_used(), 1_000_000);
+    }
+
+    #[test]
+    fn test_jump_to_non_jumpdest() {
+        // PUSH1 1 PUSH1 5 JUMPI STOP STOP, and the same jumping past the end
+        for code in &[&hex!("6001600557 00 00")[..], &hex!("6001602057")[..]] {
+            let mut chain = Fork::from(Empty);
+            let result = run(&mut chain, code);
+            assert!(matches!(
+                result.result,
+                CallResult::Halt(HaltReason::InvalidJump)
+            ));
+            assert_eq!(result.gas_used(), 1_000_000);
+        }
+        // Not taken, so the target is not checked
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6000602057 00"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+    }
+
+    #[test]
+    fn test_block_mode() {
+        let programs: [&[u8]; 6] = [
+            // Countdown loop from 10
+            &hex!("600a 5b 6001 90 03 80 6002 57"),
+            // MSTORE(0x1000, 1) GAS PUSH1 0 MSTORE RETURN(0, 32)
+            &hex!("6001611000 52 5a600052 60206000f3"),
+            // CALL(GAS, 0x77, 0, 0, 0, 0, 0) POP SSTORE(0, 1)
+            &hex!("600060006000600060006077 5af1 50 6001600055"),
+            // PUSH1 1 ADD, underflows
+            &hex!("600101"),
+            // PUSH1 1 INVALID
+            &hex!("6001fe"),
+            // PUSH1 4 JUMP PUSH1 0x5b
+            &hex!("600456 605b"),
+        ];
+        for code in &programs {
+            for &gas in &[5, 30, 500, 30_000, 1_000_000] {
+                let run_mode = |mode| {
+                    let mut chain = Fork::from(Empty);
+                    chain.set_code(&CONTRACT, code);
+                    chain.set_code(&U256::from(0x77), &hex!("5b600056"));
+                    let call = CallInfo {
+                        address: CONTRACT,
+                        code_address: CONTRACT,
+                        initial_gas: gas,
+                        ..CallInfo::default()
+                    };
+                    let result = evaluate_with_mode(
+                        &mut chain,
+                        &BlockInfo::default(),
+                        &TransactionInfo::default(),
+                        &call,
+                        &SCHEDULE,
+                        mode,
+                    );
+                    (format!("{:?}", result.result), result.gas_used())
+                };
+                assert_eq!(run_mode(Mode::Block), run_mode(Mode::Step));
+            }
+        }
+    }
+
+    #[test]
+    fn test_access_persists_across_frames() {
+        // STATICCALL(0, 0x99, 0, 0, 0, 0) POP BALANCE(0x99) POP
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600060006000600060996000fa50 60993150"));
+        assert_eq!(result.gas_used(), 18 + 2600 + 2 + 3 + 100 + 2);
+        assert!(result.accessed_addresses.contains(&U256::from(0x99)));
+    }
+
+    #[test]
+    fn test_reverted_access_is_cold() {
+        // Callee: BALANCE(0x97) POP REVERT(0, 0)
+        // Caller: STATICCALL(0xffff, 0x98, 0, 0, 0, 0) POP BALANCE(0x97) POP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x98), &hex!("6097315060006000fd"));
+        let result = run(
+            &mut chain,
+            &hex!("60006000600060006098 61fffffa50 60973150"),
+        );
+        let callee = 3 + 2600 + 2 + 3 + 3;
+        assert_eq!(result.gas_used(), 18 + 2600 + callee + 2 + 3 + 2600 + 2);
+    }
+
+    #[test]
+    fn test_gas_price_effective() {
+        // GASPRICE PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        let block = BlockInfo {
+            base_fee: U256::from(30),
+            ..BlockInfo::default()
+        };
+        let transaction = TransactionInfo::eip1559(U256::zero(), U256::from(100), U256::from(2));
+        let mut chain = Fork::from(Empty);
+        let result = run_with(
+            &mut chain,
+            &block,
+            &transaction,
+            &hex!("3a60005260206000f3"),
+        );
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, U256::from(32).to_bytes_be().to_vec()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_info() {
+        // MSTORE(0, COINBASE) MSTORE(32, TIMESTAMP) MSTORE(64, NUMBER)
+        // MSTORE(96, DIFFICULTY) MSTORE(128, GASLIMIT) RETURN(0, 160)
+        let block = BlockInfo {
+            number: 5,
+            timestamp: 6,
+            gas_limit: 7,
+            coinbase: U256::from(8),
+            difficulty: U256::from(9),
+            ..BlockInfo::default()
+        };
+        let mut chain = Fork::from(Empty);
+        let result = run_with(
+            &mut chain,
+            &block,
+            &TransactionInfo::default(),
+            &hex!("41600052 42602052 43604052 44606052 45608052 60a06000f3"),
+        );
+        let expected = [8_u64, 6, 5, 9, 7]
+            .iter()
+            .flat_map(|value| U256::from(*value).to_bytes_be().to_vec())
+            .collect::<Vec<_>>();
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chain_id() {
+        // MSTORE(0, CHAINID) RETURN(0, 32)
+        let block = BlockInfo {
+            chain_id: 1337,
+            ..BlockInfo::default()
+        };
+        let mut chain = Fork::from(Empty);
+        let result = run_with(
+            &mut chain,
+            &block,
+            &TransactionInfo::default(),
+            &hex!("46600052 60206000f3"),
+        );
+        match result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, U256::from(1337).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_base_fee() {
+        // MSTORE(0, BASEFEE) RETURN(0, 32)
+        let block = BlockInfo {
+            base_fee: U256::from(7_000_000_000_u64),
+            ..BlockInfo::default()
+        };
+        // BASEFEE was added in London
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("48600052 60206000f3"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        let london = GasSchedule::london();
+        let result = evaluate(&mut chain, &block, &TransactionInfo::default(), &call, &london);
+        match result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, block.base_fee.to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_self_balance() {
+        // MSTORE(0, SELFBALANCE) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(42));
+        let result = run(&mut chain, &hex!("47600052 60206000f3"));
+        match result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, chain.balance(&CONTRACT).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_hash() {
+        /// Chain where every block hash is its number plus one
+        struct Hashes;
+
+        impl ChainState for Hashes {
+            fn block(&self) -> BlockInfo {
+                BlockInfo::default()
+            }
+
+            fn block_hash(&self, number: u64) -> U256 {
+                U256::from(number + 1)
+            }
+
+            fn nonce(&self, _address: &U256) -> usize {
+                0
+            }
+
+            fn balance(&self, _address: &U256) -> U256 {
+                U256::zero()
+            }
+
+            fn code(&self, _address: &U256) -> Vec<u8> {
+                Vec::new()
+            }
+
+            fn storage(&self, _address: &U256, _slot: &U256) -> U256 {
+                U256::zero()
+            }
+        }
+
+        // MSTORE(0, BLOCKHASH(CALLDATALOAD(0))) RETURN(0, 32)
+        let mut chain = Fork::from(Hashes);
+        chain.set_code(&CONTRACT, &hex!("600035 40 600052 60206000f3"));
+        let block = BlockInfo {
+            number: 1000,
+            ..BlockInfo::default()
+        };
+        let mut block_hash = |number: u64| {
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 100_000,
+                input: U256::from(number).to_bytes_be().to_vec(),
+                ..CallInfo::default()
+            };
+            match evaluate(
+                &mut chain,
+                &block,
+                &TransactionInfo::default(),
+                &call,
+                &SCHEDULE,
+            )
+            .result
+            {
+                CallResult::Return(output) => {
+                    let mut word = [0_u8; 32];
+                    word.copy_from_slice(&output);
+                    U256::from_bytes_be(&word)
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+        assert_eq!(block_hash(999), U256::from(1000));
+        assert_eq!(block_hash(744), U256::from(745));
+        assert_eq!(block_hash(743), U256::zero());
+        assert_eq!(block_hash(1000), U256::zero());
+        assert_eq!(block_hash(1001), U256::zero());
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        // POP, and ADD with one operand
+        for code in &[&hex!("50")[..], &hex!("600101")[..]] {
+            let mut chain = Fork::from(Empty);
+            let result = run(&mut chain, code);
+            assert!(matches!(
+                result.result,
+                CallResult::Halt(HaltReason::StackUnderflow)
+            ));
+            assert_eq!(result.gas_used(), 1_000_000);
+        }
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        // PUSH1 1 INVALID, and PUSH1 1 followed by the undefined 0x0c
+        for code in &[&hex!("6001fe")[..], &hex!("60010c")[..]] {
+            let mut chain = Fork::from(Empty);
+            let result = run(&mut chain, code);
+            assert!(matches!(
+                result.result,
+                CallResult::Halt(HaltReason::InvalidOpcode)
+            ));
+            assert_eq!(result.gas_used(), 1_000_000);
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // ADDRESS repeated, one more than fits
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &[0x30; STACK_LIMIT]);
+        assert!(matches!(result.result, CallResult::Return(_)));
+        let result = run(&mut chain, &[0x30; STACK_LIMIT + 1]);
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_code() {
+        // Every prefix of a program mixing pushes, arithmetic, memory and calls
+        let code = hex!(
+            "6001 6002 01 600052
+            7f0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20 50
+            6000600060006000600060776161a8f1 50 90 80 60206000f3"
+        );
+        for end in 0..=code.len() {
+            let mut chain = Fork::from(Empty);
+            run(&mut chain, &code[..end]);
+        }
+
+        // PUSH2 with one byte of payload is zero extended to 0xaa00
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("61aa"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1000,
+            ..CallInfo::default()
+        };
+        let mut tracer = StructLogger::default();
+        evaluate_with_tracer(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+            &mut tracer,
+        );
+        let word = hex::encode(U256::from(0xaa00).to_bytes_be());
+        assert_eq!(tracer.logs[1].stack, Some(vec![word]));
+    }
+
+    #[test]
+    fn test_sha3() {
+        // MSTORE(0, SHA3(0, 0)) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6000 6000 20 600052 60206000f3"));
+        // Keccak-256 of the empty string, it differs from the final SHA3-256
+        let empty = hex!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+        match &result.result {
+            CallResult::Return(output) => assert_eq!(output, &empty.to_vec()),
+            other => panic!("unexpected {:?}", other),
+        }
+        // Expanding memory to one word for MSTORE costs 3
+        assert_eq!(result.gas_used(), 3 + 3 + 30 + 3 + 3 + 3 + 3 + 3);
+    }
+
+    #[test]
+    fn test_sha3_words() {
+        // MSTORE(0, SHA3(0, 33)) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("6021 6000 20 600052 60206000f3"));
+        match &result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, &keccak256(&[0_u8; 33]).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // 33 bytes are two words, expanding memory to two words costs 6
+        assert_eq!(result.gas_used(), 3 + 3 + (30 + 2 * 6 + 6) + 3 + 3 + 3 + 3);
+    }
+
+    #[test]
+    fn test_shifts() {
+        let one = U256::one();
+        let minus_one = !U256::zero();
+        let min = one.clone() << 255;
+        let (zero, bits_255, bits_256) = (U256::zero(), U256::from(255), U256::from(256));
+
+        assert_eq!(shl(&zero, one.clone()), one);
+        assert_eq!(shl(&bits_255, one.clone()), min);
+        assert_eq!(shl(&bits_256, one.clone()), U256::zero());
+        assert_eq!(shl(&minus_one, one.clone()), U256::zero());
+
+        assert_eq!(shr(&zero, min.clone()), min);
+        assert_eq!(shr(&bits_255, min.clone()), one);
+        assert_eq!(shr(&bits_256, min.clone()), U256::zero());
+        assert_eq!(shr(&minus_one, min.clone()), U256::zero());
+
+        assert_eq!(sar(&zero, min.clone()), min);
+        assert_eq!(sar(&bits_255, min.clone()), minus_one);
+        assert_eq!(sar(&bits_256, min.clone()), minus_one);
+        assert_eq!(sar(&minus_one, min.clone()), minus_one);
+
+        let max = !min;
+        assert_eq!(sar(&zero, max.clone()), max);
+        assert_eq!(sar(&bits_255, max.clone()), U256::zero());
+        assert_eq!(sar(&bits_256, max), U256::zero());
+    }
+
+    #[test]
+    fn test_addmod_signextend_byte() {
+        let max = !U256::zero();
+        let (zero, one, two) = (U256::zero(), U256::one(), U256::from(2));
+        // The sum overflows 256 bits
+        assert_eq!(addmod(max.clone(), two.clone(), &U256::from(10)), U256::from(7));
+        assert_eq!(addmod(max.clone(), max.clone(), &max), zero);
+        assert_eq!(addmod(max.clone(), one.clone(), &(max.clone() - one.clone())), two);
+        assert_eq!(addmod(U256::from(5), U256::from(6), &zero), zero);
+
+        assert_eq!(signextend(&zero, U256::from(0xff)), max);
+        assert_eq!(signextend(&zero, U256::from(0x17f)), U256::from(0x7f));
+        assert_eq!(signextend(&one, U256::from(0x8000)), max.clone() << 15);
+        assert_eq!(signextend(&U256::from(31), max.clone()), max);
+        assert_eq!(signextend(&max, U256::from(0xff)), U256::from(0xff));
+
+        let value = U256::from(0x1234);
+        assert_eq!(byte(&U256::from(31), &value), U256::from(0x34));
+        assert_eq!(byte(&U256::from(30), &value), U256::from(0x12));
+        assert_eq!(byte(&zero, &value), zero);
+        assert_eq!(byte(&U256::from(32), &max), zero);
+    }
+
+    #[test]
+    fn test_environment_opcodes() {
+        // MSTORE(0, ORIGIN) MSTORE(32, CODESIZE) MSTORE(64, PC) RETURN(0, 96)
+        let mut chain = Fork::from(Empty);
+        let transaction = TransactionInfo {
+            origin: U256::from(0xaa),
+            ..TransactionInfo::default()
+        };
+        let code = hex!("32600052 38602052 58604052 60606000f3");
+        let result = run_with(&mut chain, &BlockInfo::default(), &transaction, &code);
+        let mut expected = U256::from(0xaa).to_bytes_be().to_vec();
+        expected.extend(&U256::from(code.len()).to_bytes_be());
+        expected.extend(&U256::from(8).to_bytes_be());
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_signed_arithmetic() {
+        let int = |value: i64| {
+            let magnitude = U256::from(value.unsigned_abs());
+            if value < 0 {
+                negate(magnitude)
+            } else {
+                magnitude
+            }
+        };
+        let min = U256::one() << 255;
+        let cases = [
+            // (left, right, sdiv, smod)
+            (int(7), int(2), int(3), int(1)),
+            (int(-7), int(2), int(-3), int(-1)),
+            (int(7), int(-2), int(-3), int(1)),
+            (int(-7), int(-2), int(3), int(-1)),
+            (int(-7), int(0), int(0), int(0)),
+            (int(0), int(-3), int(0), int(0)),
+            (min.clone(), int(-1), min.clone(), int(0)),
+            (min.clone(), int(1), min.clone(), int(0)),
+            (min.clone(), int(3), negate(min.clone() / U256::from(3)), int(-2)),
+        ];
+        for (left, right, quotient, remainder) in &cases {
+            assert_eq!(sdiv(left.clone(), right.clone()), *quotient);
+            assert_eq!(smod(left.clone(), right.clone()), *remainder);
+        }
+
+        assert!(slt(&int(-1), &int(0)));
+        assert!(slt(&int(-2), &int(-1)));
+        assert!(slt(&min, &int(-1)));
+        assert!(slt(&int(1), &int(2)));
+        assert!(!slt(&int(0), &int(-1)));
+        assert!(!slt(&int(-1), &int(-1)));
+        assert!(!slt(&!min.clone(), &min));
+        assert_eq!(div(int(7), int(0)), int(0));
+        assert_eq!(rem(int(7), int(0)), int(0));
+    }
+
+    #[test]
+    fn test_exp() {
+        let two = U256::from(2);
+        assert_eq!(exp(&U256::from(3), &U256::zero()), U256::one());
+        assert_eq!(exp(&U256::zero(), &U256::zero()), U256::one());
+        assert_eq!(exp(&two, &U256::from(10)), U256::from(1024));
+        assert_eq!(exp(&two, &U256::from(255)), U256::one() << 255);
+        assert_eq!(exp(&two, &U256::from(256)), U256::zero());
+        assert_eq!(exp(&!U256::zero(), &!U256::zero()), !U256::zero());
+        assert_eq!(
+            exp(&U256::from(3), &U256::from(256)),
+            U256::from_hex_str("c7adeeb80d4fff81fed242815e55bc8375a205de07597d51d2105f2f0730f401")
+        );
+        assert_eq!(
+            exp(&U256::from(7), &U256::from(0x0123_4567)),
+            U256::from_hex_str("c66657b199811434cd26de590edc3c47aadcb6c6614fa033a2a3ce8722645ff7")
+        );
+    }
+
+    #[test]
+    fn test_exp_gas() {
+        // MSTORE(0, EXP(3, exponent)) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        let zero = run(&mut chain, &hex!("6000 6003 0a 600052 60206000f3"));
+        match &zero.result {
+            CallResult::Return(output) => {
+                assert_eq!(*output, U256::one().to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let two_bytes = run(&mut chain, &hex!("610100 6003 0a 600052 60206000f3"));
+        assert_eq!(two_bytes.gas_used() - zero.gas_used(), 2 * SCHEDULE.exp_byte);
+    }
+
+    #[test]
+    fn test_signed_opcodes() {
+        // MSTORE(0, SDIV(NOT(0), 2)) MSTORE(32, SGT(1, NOT(0))) RETURN(0, 64)
+        let mut chain = Fork::from(Empty);
+        let result = run(
+            &mut chain,
+            &hex!("6002600019 05 600052 600019600113 602052 60406000f3"),
+        );
+        let mut expected = vec![0_u8; 64];
+        expected[63] = 1;
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sar_opcode() {
+        // MSTORE(0, SAR(4, NOT(15))) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("600f19 60041d 600052 60206000f3"));
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, vec![0xff; 32]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_identity_round_trip() {
+        // MSTORE(0, 0xab) STATICCALL(0xffff, 4, 0, 32, 32, 32) POP RETURN(32, 32)
+        let mut chain = Fork::from(Empty);
+        let result = run(
+            &mut chain,
+            &hex!("60ab600052 6020602060206000600461fffffa50 60206020f3"),
+        );
+        match result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, U256::from(0xab).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_identity_truncated() {
+        // MSTORE(0, 0xab) MSTORE(32, NOT(0))
+        // STATICCALL(0xffff, 4, 0, 32, 32, 16) POP RETURN(32, 32)
+        let mut chain = Fork::from(Empty);
+        let result = run(
+            &mut chain,
+            &hex!("60ab600052 600019602052 6010602060206000600461fffffa50 60206020f3"),
+        );
+        let mut expected = vec![0_u8; 16];
+        expected.extend_from_slice(&[0xff; 16]);
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delegatecall_proxy() {
+        // Logic: SSTORE(0, CALLER) SSTORE(1, CALLVALUE) MSTORE(0, ADDRESS) RETURN(0,
+        // 32) Proxy: DELEGATECALL(0xffff, LOGIC, 0, 0, 0, 32) POP RETURN(0, 32)
+        let logic = U256::from(0x77);
+        let sender = U256::from(0x55);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&logic, &hex!("3360005534600155 3060005260206000f3"));
+        chain.set_code(
+            &CONTRACT,
+            &hex!("60206000600060006077 61fffff450 60206000f3"),
+        );
+        let call = CallInfo {
+            sender:       sender.clone(),
+            address:      CONTRACT,
+            code_address: CONTRACT,
+            call_value:   U256::from(7),
+            initial_gas:  1_000_000,
+            input:        Vec::new(),
+            is_static:    false,
+            init_code:    None,
+        };
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+        );
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, CONTRACT.to_bytes_be().to_vec()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), sender);
+        assert_eq!(chain.storage(&CONTRACT, &U256::one()), U256::from(7));
+        assert_eq!(chain.storage(&logic, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_callcode_sender() {
+        // Logic: SSTORE(0, CALLER) SSTORE(1, CALLVALUE) STOP
+        // Caller: CALLCODE(0xffff, LOGIC, 3, 0, 0, 0, 0) STOP
+        let logic = U256::from(0x77);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&logic, &hex!("3360005534600155 00"));
+        chain.set_balance(&CONTRACT, &U256::from(10));
+        let result = run(&mut chain, &hex!("600060006000600060036077 61fffff2 00"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        // Unlike DELEGATECALL, the caller is the calling contract itself
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), CONTRACT);
+        assert_eq!(chain.storage(&CONTRACT, &U256::one()), U256::from(3));
+        assert_eq!(chain.storage(&logic, &U256::zero()), U256::zero());
+        assert_eq!(chain.balance(&CONTRACT), U256::from(10));
+        assert_eq!(chain.balance(&logic), U256::zero());
+    }
+
+    #[test]
+    fn test_code_copy_padded() {
+        // MSTORE(0, NOT(0)) CODECOPY(0, 0, 32) RETURN(0, 32)
+        let code = hex!("600019600052 60206000600039 60206000f3");
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &code);
+        let mut expected = code.to_vec();
+        expected.resize(32, 0);
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ext_code_copy_padded() {
+        // MSTORE(0, NOT(0)) EXTCODECOPY(0xaa, 0, 2, 32) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0xaa), &hex!("deadbeef"));
+        let result = run(
+            &mut chain,
+            &hex!("600019600052 602060026000 60aa3c 60206000f3"),
+        );
+        let mut expected = hex!("beef").to_vec();
+        expected.resize(32, 0);
+        match &result.result {
+            CallResult::Return(output) => assert_eq!(output, &expected),
+            other => panic!("unexpected {:?}", other),
+        }
+        // The account is cold, copying one word costs 3
+        let gas = 4 * 3 + 3 + 4 * 3 + SCHEDULE.cold_account_access + 3 + 2 * 3;
+        assert_eq!(result.gas_used(), gas);
+    }
+
+    #[test]
+    fn test_ext_code_hash() {
+        // RETURN(0, 32) after MSTORE(0, EXTCODEHASH(CALLDATALOAD(0)))
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6000353f 600052 60206000f3"));
+        chain.set_code(&U256::from(0xaa), &hex!("deadbeef"));
+        chain.set_balance(&U256::from(0xbb), &U256::one());
+        let mut code_hash = |address: u64| {
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 100_000,
+                input: U256::from(address).to_bytes_be().to_vec(),
+                ..CallInfo::default()
+            };
+            let result = evaluate(
+                &mut chain,
+                &BlockInfo::default(),
+                &TransactionInfo::default(),
+                &call,
+                &SCHEDULE,
+            );
+            // The account is cold
+            assert_eq!(
+                result.gas_used(),
+                2 * 3 + SCHEDULE.cold_account_access + 3 + 3 + 3 + 3 + 3
+            );
+            match result.result {
+                CallResult::Return(output) => output,
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+        let expected = keccak256(&hex!("deadbeef")).to_bytes_be().to_vec();
+        assert_eq!(code_hash(0xaa), expected);
+        let expected = keccak256(&[]).to_bytes_be().to_vec();
+        assert_eq!(code_hash(0xbb), expected);
+        assert_eq!(code_hash(0xcc), vec![0_u8; 32]);
+    }
+
+    #[test]
+    fn test_call_data_padded() {
+        let input = (1..=40).collect::<Vec<u8>>();
+        let mut chain = Fork::from(Empty);
+        let mut run_input = |code: &[u8]| {
+            chain.set_code(&CONTRACT, code);
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 100_000,
+                input: input.clone(),
+                ..CallInfo::default()
+            };
+            let result = evaluate(
+                &mut chain,
+                &BlockInfo::default(),
+                &TransactionInfo::default(),
+                &call,
+                &SCHEDULE,
+            );
+            match result.result {
+                CallResult::Return(output) => output,
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+        let padded = |bytes: &[u8]| {
+            let mut word = bytes.to_vec();
+            word.resize(32, 0);
+            word
+        };
+
+        // MSTORE(0, CALLDATALOAD(32)) RETURN(0, 32)
+        let output = run_input(&hex!("602035 600052 60206000f3"));
+        assert_eq!(output, padded(&input[32..]));
+        // MSTORE(0, CALLDATALOAD(100)) RETURN(0, 32)
+        let output = run_input(&hex!("606435 600052 60206000f3"));
+        assert_eq!(output, padded(&[]));
+        // MSTORE(0, CALLDATALOAD(NOT(0))) RETURN(0, 32)
+        let output = run_input(&hex!("60001935 600052 60206000f3"));
+        assert_eq!(output, padded(&[]));
+        // MSTORE(0, NOT(0)) CALLDATACOPY(0, 36, 32) RETURN(0, 32)
+        let output = run_input(&hex!("600019600052 60206024600037 60206000f3"));
+        assert_eq!(output, padded(&input[36..]));
+        // MSTORE(0, NOT(0)) CALLDATACOPY(0, 100, 32) RETURN(0, 32)
+        let output = run_input(&hex!("600019600052 60206064600037 60206000f3"));
+        assert_eq!(output, padded(&[]));
+    }
+
+    #[test]
+    fn test_return_data_copy() {
+        // Callee: MSTORE(0, 1) MSTORE(32, 2) RETURN(0, 64)
+        // Caller: CALL(0xffff, 0x77, 0, 0, 0, 0, 0) POP
+        //         RETURNDATACOPY(0, 0, RETURNDATASIZE) RETURN(0, RETURNDATASIZE)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("6001600052 6002602052 60406000f3"));
+        let result = run(
+            &mut chain,
+            &hex!("60006000600060006000607761fffff150 3d600060003e 3d6000f3"),
+        );
+        let expected = [1_u64, 2]
+            .iter()
+            .flat_map(|value| U256::from(*value).to_bytes_be().to_vec())
+            .collect::<Vec<_>>();
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_output_truncated() {
+        // Callee: MSTORE(0, 1) MSTORE(32, 2) RETURN(0, 64)
+        // Caller: MSTORE(0, NOT(0)) MSTORE(32, NOT(0))
+        //         CALL(0xffff, 0x77, 0, 0, 0, 0, 32) POP RETURN(0, 64)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("6001600052 6002602052 60406000f3"));
+        let result = run(
+            &mut chain,
+            &hex!("600019600052 600019602052 60206000600060006000607761fffff150 60406000f3"),
+        );
+        let mut expected = U256::one().to_bytes_be().to_vec();
+        expected.extend_from_slice(&[0xff; 32]);
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_output_short() {
+        // Callee: MSTORE8(0, 0xaa) RETURN(0, 1)
+        // Caller: MSTORE(0, NOT(0)) MSTORE(32, NOT(0))
+        //         CALL(0xffff, 0x77, 0, 0, 0, 0, 64) POP RETURN(0, 64)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("60aa600053 60016000f3"));
+        let result = run(
+            &mut chain,
+            &hex!("600019600052 600019602052 60406000600060006000607761fffff150 60406000f3"),
+        );
+        // Memory past the returned bytes is left untouched, not zeroed
+        let mut expected = vec![0xff; 64];
+        expected[0] = 0xaa;
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_call_out_of_gas() {
+        // Callee: loop forever
+        // Caller: MSTORE(0, CALL(100, 0x77, 0, 0, 0, 0, 0)) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("5b600056"));
+        let result = run(
+            &mut chain,
+            &hex!("60006000600060006000607760 64f1 600052 60206000f3"),
+        );
+        match &result.result {
+            CallResult::Return(output) => assert_eq!(output, &vec![0; 32]),
+            other => panic!("unexpected {:?}", other),
+        }
+        // Only the gas given to the callee is lost
+        assert!(result.gas_used() < 100 + SCHEDULE.cold_account_access + 100);
+    }
+
+    #[test]
+    fn test_call_retains_64th() {
+        // Callee: loop forever
+        // Caller: CALL(0xffffff, 0x77, 0, 0, 0, 0, 0) MSTORE(0, GAS) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("5b600056"));
+        let result = run(
+            &mut chain,
+            &hex!("600060006000600060006077 62ffffff f1 5a600052 60206000f3"),
+        );
+        // Gas left after the pushes and the cold account access
+        let available = 1_000_000 - 7 * 3 - SCHEDULE.cold_account_access;
+        let retained = available / 64;
+        match result.result {
+            CallResult::Return(output) => {
+                // Less the cost of the GAS opcode itself
+                assert_eq!(output, U256::from(retained - 2).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(all_but_one_64th(6400), 6300);
+    }
+
+    #[test]
+    fn test_call_gas_before_eip150() {
+        let run_homestead = |chain: &mut Fork<Empty>, code: &[u8]| {
+            chain.set_code(&CONTRACT, code);
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 1_000_000,
+                ..CallInfo::default()
+            };
+            let schedule = Hardfork::Homestead.schedule();
+            evaluate(chain, &BlockInfo::default(), &TransactionInfo::default(), &call, &schedule)
+        };
+        // Callee: MSTORE(0, GAS) RETURN(0, 32)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("5a600052 60206000f3"));
+
+        // CALL(0x1000, 0x77, 0, 0, 0, 0, 32) RETURN(0, 32)
+        let result = run_homestead(
+            &mut chain,
+            &hex!("60206000600060006000 6077 611000 f1 60206000f3"),
+        );
+        // The callee gets exactly the requested gas, less the GAS opcode
+        match &result.result {
+            CallResult::Return(output) => {
+                assert_eq!(output, &U256::from(0x1000 - 2).to_bytes_be().to_vec());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Requesting more than is left runs out of gas instead of being capped
+        let result = run_homestead(
+            &mut chain,
+            &hex!("60206000600060006000 6077 62ffffff f1 60206000f3"),
+        );
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::OutOfGas)
+        ));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        // SSTORE(0, SLOAD(0) + 1) CALL(GAS, ADDRESS, 0, 0, 0, 0, 0) STOP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6001600054016000 55 6000600060006000600030 5af1 00"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        // Start close to the limit, so only a few frames are needed
+        let mut substate = Substate {
+            depth: MAX_CALL_DEPTH - 4,
+            ..Substate::default()
+        };
+        let (result, _) = execute(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &mut substate,
+            &mut (),
+            &call,
+        );
+        assert!(matches!(result, CallResult::Return(_)));
+        // The outermost frame is at depth one, so the frame past the limit
+        // fails to call itself, but still succeeds
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::from(5));
+    }
+
+    #[test]
+    fn test_limits() {
+        let run_limited = |chain: &mut Fork<Empty>, code: &[u8], limits: &Limits| {
+            chain.set_code(&CONTRACT, code);
+            let call = CallInfo {
+                address: CONTRACT,
+                code_address: CONTRACT,
+                initial_gas: 1_000_000,
+                ..CallInfo::default()
+            };
+            evaluate_with_limits(
+                chain,
+                &BlockInfo::default(),
+                &TransactionInfo::default(),
+                &call,
+                &SCHEDULE,
+                limits,
+                &mut (),
+            )
+        };
+        let limits = Limits {
+            max_memory: 64,
+            max_depth:  2,
+        };
+        // MSTORE(0x20, 1) stays within the cap
+        let mut chain = Fork::from(Empty);
+        let result = run_limited(&mut chain, &hex!("6001602052 00"), &limits);
+        assert!(matches!(result.result, CallResult::Return(_)));
+        // MSTORE(0x100, 1) expands past it, which is affordable but halts
+        let result = run_limited(&mut chain, &hex!("600161010052 00"), &limits);
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::OutOfGas)
+        ));
+        assert_eq!(result.gas_used(), 1_000_000);
+        let result = run_limited(&mut chain, &hex!("600161010052 00"), &Limits::default());
+        assert!(matches!(result.result, CallResult::Return(_)));
+
+        // SSTORE(0, SLOAD(0) + 1) CALL(GAS, ADDRESS, 0, 0, 0, 0, 0) STOP
+        let code = hex!("6001600054016000 55 6000600060006000600030 5af1 00");
+        let result = run_limited(&mut chain, &code, &limits);
+        assert!(matches!(result.result, CallResult::Return(_)));
+        // The third frame is past the depth limit and fails to call itself
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::from(3));
+    }
+
+    #[test]
+    fn test_return_data_copy_out_of_bounds() {
+        // Same as above, but RETURNDATACOPY(0, 1, RETURNDATASIZE)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0x77), &hex!("6001600052 6002602052 60406000f3"));
+        let result = run(
+            &mut chain,
+            &hex!("60006000600060006000607761fffff150 3d600160003e 3d6000f3"),
+        );
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::ReturnDataOutOfBounds)
+        ));
+        assert_eq!(result.gas_used(), 1_000_000);
+    }
+
+    #[test]
+    fn test_revert_bubbles_up() {
+        // Callee: SSTORE(0, 1)
+        //         MSTORE(0, 0xcf479181 << 224) MSTORE(4, 5) MSTORE(36, 7) REVERT(0, 68)
+        // Caller: CALL(0xffff, 0x77, 0, 0, 0, 0, 0) ISZERO JUMPI(21) STOP
+        //         21: JUMPDEST RETURNDATACOPY(0, 0, RETURNDATASIZE)
+        //         REVERT(0, RETURNDATASIZE)
+        let callee = U256::from(0x77);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(
+            &callee,
+            &hex!("6001600055 63cf47918160e01b600052 6005600452 6007602452 60446000fd"),
+        );
+        let result = run(
+            &mut chain,
+            &hex!("60006000600060006000607761fffff1 15601557 00 5b 3d600060003e 3d6000fd"),
+        );
+        // InsufficientBalance(uint256 available, uint256 required)
+        let mut expected = hex!("cf479181").to_vec();
+        expected.extend(abi::encode(&[
+            abi::Token::Uint(U256::from(5)),
+            abi::Token::Uint(U256::from(7)),
+        ]));
+        match result.result {
+            CallResult::Revert(output) => assert_eq!(output, expected),
+            other => panic!("unexpected {:?}", other),
+        }
+        // The write of the reverted callee is undone
+        assert_eq!(chain.storage(&callee, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_revert_returns_gas() {
+        // Caller: CALL(0xffff, 0x77, 0, 0, 0, 0, 0) POP STOP
+        let code = hex!("60006000600060006000607761fffff1 50 00");
+        let call_gas = 7 * 3 + 2600 + 2;
+        let callee = U256::from(0x77);
+
+        // Callee: SSTORE(0, 1) SSTORE(0, 0) REVERT(0, 0)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&callee, &hex!("6001600055 6000600055 60006000fd"));
+        let result = run(&mut chain, &code);
+        assert!(matches!(result.result, CallResult::Return(_)));
+        // The caller gets back the gas the callee did not use, but not the
+        // refund for clearing the slot
+        assert_eq!(result.gas_used(), call_gas + 6 + 22100 + 6 + 100 + 6);
+        assert_eq!(result.gas_refunded(), 0);
+
+        // Callee: SSTORE(0, 1) SSTORE(0, 0) INVALID
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&callee, &hex!("6001600055 6000600055 fe"));
+        let result = run(&mut chain, &code);
+        assert_eq!(result.gas_used(), call_gas + 0xffff);
+        assert_eq!(result.gas_refunded(), 0);
+    }
+
+    #[test]
+    fn test_static_stack_underflow() {
+        // PUSH1 0 CALL, whose value is not on the stack
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6000 f1"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            is_static: true,
+            ..CallInfo::default()
+        };
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+        );
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::StackUnderflow)
+        ));
+    }
+
+    #[test]
+    fn test_staticcall_sstore_reverts() {
+        // Callee: SSTORE(0, 1) STOP
+        // Caller: STATICCALL(0xffff, CALLEE, 0, 0, 0, 0) MSTORE(0, success) RETURN(0,
+        // 32)
+        let callee = U256::from(0x77);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&callee, &hex!("600160005500"));
+        let result = run(
+            &mut chain,
+            &hex!("6000600060006000607761fffffa 600052 60206000f3"),
+        );
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, vec![0; 32]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(chain.storage(&callee, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_static_write_protection() {
+        // SSTORE(0, 1) STOP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("600160005500"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            is_static: true,
+            ..CallInfo::default()
+        };
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+        );
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::WriteProtection)
+        ));
+        assert_eq!(result.gas_used(), 1_000_000);
+    }
+
+    #[test]
+    fn test_staticcall_inherited() {
+        // Inner: SSTORE(0, 1) STOP
+        // Outer: CALL(0xffff, INNER, 0, 0, 0, 0, 0) MSTORE(0, success) RETURN(0, 32)
+        // Caller: STATICCALL(0xffff, OUTER, 0, 0, 0, 32) POP RETURN(0, 32)
+        let inner = U256::from(0x77);
+        let outer = U256::from(0x78);
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&inner, &hex!("600160005500"));
+        chain.set_code(
+            &outer,
+            &hex!("60006000600060006000607761fffff1 600052 60206000f3"),
+        );
+        let result = run(
+            &mut chain,
+            &hex!("6020600060006000607861fffffa50 60206000f3"),
+        );
+        match result.result {
+            CallResult::Return(output) => assert_eq!(output, vec![0; 32]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(chain.storage(&inner, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_call_value() {
+        // CALL(0xffff, 0x77, 5, 0, 0, 0, 0) STOP
+        let callee = U256::from(0x77);
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(8));
+        let result = run(&mut chain, &hex!("600060006000600060056077 61fffff1 00"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(chain.balance(&CONTRACT), U256::from(3));
+        assert_eq!(chain.balance(&callee), U256::from(5));
+    }
+
+    #[test]
+    fn test_trace_steps() {
+        // PUSH1 1 PUSH1 0 MSTORE MSIZE
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6001600052 59"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1000,
+            ..CallInfo::default()
+        };
+        let mut tracer = StructLogger::default();
+        evaluate_with_tracer(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+            &mut tracer,
+        );
+        let summary = tracer
+            .logs
+            .iter()
+            .map(|log| (log.pc, log.op.as_str(), log.gas, log.gas_cost, log.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (0, "PUSH1", 1000, 3, 1),
+            (2, "PUSH1", 997, 3, 1),
+            (4, "MSTORE", 994, 6, 1),
+            (5, "MSIZE", 988, 2, 1),
+            (6, "STOP", 986, 0, 1),
+        ]);
+        let word = |value: u64| hex::encode(U256::from(value).to_bytes_be());
+        assert_eq!(tracer.logs[2].stack, Some(vec![word(1), word(0)]));
+        assert_eq!(tracer.logs[2].memory, Some(vec![]));
+        assert_eq!(tracer.logs[4].stack, Some(vec![word(32)]));
+        assert_eq!(tracer.logs[4].memory, Some(vec![word(1)]));
+    }
+
+    #[test]
+    fn test_trace_calls() {
+        #[derive(Default)]
+        struct CallRecorder(Vec<String>);
+
+        impl Tracer for CallRecorder {
+            fn on_call(&mut self, call: &CallInfo, depth: usize) {
+                self.0.push(format!("call {} {}", call.address, depth));
+            }
+
+            fn on_return(&mut self, output: &[u8], _gas_left: usize) {
+                self.0.push(format!("return {}", hex::encode(output)));
+            }
+
+            fn on_revert(&mut self, output: &[u8], _gas_left: usize) {
+                self.0.push(format!("revert {}", hex::encode(output)));
+            }
+        }
+
+        // STATICCALL(0xffff, 4, 0, 0, 0, 0) REVERT(0, 0)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, &hex!("6000600060006000600461ffff fa 60006000fd"));
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 100_000,
+            ..CallInfo::default()
+        };
+        let mut tracer = CallRecorder::default();
+        evaluate_with_tracer(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+            &mut tracer,
+        );
+        assert_eq!(tracer.0, vec![
+            format!("call {} 1", CONTRACT),
+            format!("call {} 2", U256::from(4)),
+            "return ".to_string(),
+            "revert ".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_create_address() {
+        let sender = u256h!("0000000000000000000000006ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        assert_eq!(
+            create_address(&sender, 0),
+            u256h!("000000000000000000000000cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(
+            create_address(&sender, 1),
+            u256h!("000000000000000000000000343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+    }
+
+    #[test]
+    fn test_create2_address() {
+        // Examples from EIP-1014
+        assert_eq!(
+            create2_address(&U256::zero(), &U256::zero(), &[0x00]),
+            u256h!("0000000000000000000000004d1a2e2bb4f88f0250f26ffff098b0b30b26bf38")
+        );
+        let sender = u256h!("000000000000000000000000deadbeef00000000000000000000000000000000");
+        assert_eq!(
+            create2_address(&sender, &U256::zero(), &[0x00]),
+            u256h!("000000000000000000000000b928f69bb1d91cd65274e3c79d8986362984fda3")
+        );
+    }
+
+    // Init code returning the runtime code `2a`:
+    // PUSH1 42 PUSH1 0 MSTORE PUSH1 1 PUSH1 31 RETURN
+    const INIT_CODE: [u8; 10] = hex!("602a6000526001601ff3");
+
+    #[test]
+    fn test_create() {
+        // MSTORE(0, INIT_CODE) CREATE(0, 22, 10) STOP
+        let mut chain = Fork::from(Empty);
+        let result = run(
+            &mut chain,
+            &hex!("69 602a6000526001601ff3 600052 600a60166000f0 00"),
+        );
+        assert!(matches!(result.result, CallResult::Return(_)));
+        let address = create_address(&CONTRACT, 0);
+        assert_eq!(chain.code(&address), vec![0x2a]);
+        assert_eq!(chain.nonce(&address), 1);
+        assert_eq!(chain.nonce(&CONTRACT), 1);
+    }
+
+    #[test]
+    fn test_create2() {
+        // MSTORE(0, INIT_CODE) CREATE2(0, 22, 10, 7) STOP
+        let mut chain = Fork::from(Empty);
+        run(
+            &mut chain,
+            &hex!("69 602a6000526001601ff3 600052 6007600a60166000f5 00"),
+        );
+        let address = create2_address(&CONTRACT, &U256::from(7), &INIT_CODE);
+        assert_eq!(chain.code(&address), vec![0x2a]);
+        assert_eq!(chain.nonce(&CONTRACT), 1);
+    }
+
+    #[test]
+    fn test_create_revert() {
+        // MSTORE(0, REVERT(0, 0)) SSTORE(0, CREATE(0, 27, 5) + 1)
+        let mut chain = Fork::from(Empty);
+        run(
+            &mut chain,
+            &hex!("64 60006000fd 600052 6005601b6000f0 600101 600055"),
+        );
+        let address = create_address(&CONTRACT, 0);
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::one());
+        assert_eq!(chain.nonce(&address), 0);
+        assert_eq!(chain.nonce(&CONTRACT), 1);
+    }
+
+    #[test]
+    fn test_create_code_size_exceeded() {
+        // RETURN(0, MAX_CODE_SIZE + 1)
+        let address = create_address(&CONTRACT, 0);
+        let mut chain = Fork::from(Empty);
+        let call = CallInfo {
+            sender: CONTRACT,
+            address: address.clone(),
+            code_address: address.clone(),
+            initial_gas: 1_000_000,
+            init_code: Some(hex!("616001 6000 f3").to_vec()),
+            ..CallInfo::default()
+        };
+        let result = evaluate(
+            &mut chain,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            &call,
+            &SCHEDULE,
+        );
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::CodeSizeExceeded)
+        ));
+        assert_eq!(result.gas_used(), 1_000_000);
+        assert!(chain.code(&address).is_empty());
+    }
+
+    #[test]
+    fn test_create_collision() {
+        // MSTORE(0, INIT_CODE) SSTORE(0, CREATE(0, 22, 10) + 1)
+        let mut chain = Fork::from(Empty);
+        let address = create_address(&CONTRACT, 0);
+        chain.set_code(&address, &[0x00]);
+        let result = run(
+            &mut chain,
+            &hex!("69 602a6000526001601ff3 600052 600a60166000f0 600101 600055"),
+        );
+        // Only a 64th of the gas is left, so the SSTORE runs out of gas
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::OutOfGas)
+        ));
+        assert_eq!(chain.code(&address), vec![0x00]);
+    }
+
+    #[test]
+    fn test_selfdestruct() {
+        // SELFDESTRUCT(0xaa)
+        let beneficiary = U256::from(0xaa);
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(100));
+        let result = run(&mut chain, &hex!("60aaff"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(chain.balance(&CONTRACT), U256::zero());
+        assert_eq!(chain.balance(&beneficiary), U256::from(100));
+        // Before Cancun the account is removed
+        assert_eq!(chain.code(&CONTRACT), Vec::<u8>::new());
+        // Cold and new beneficiary, the refund is capped to half
+        let gas = 3 + 5000 + SCHEDULE.cold_account_access + CALL_NEW_ACCOUNT_GAS;
+        assert_eq!(result.gas_refunded(), gas / 2);
+        assert_eq!(result.gas_used(), gas - gas / 2);
+    }
+
+    /// Run `code` in the Cancun hardfork, where only accounts created in the
+    /// same transaction are removed by SELFDESTRUCT
+    fn run_in_cancun(chain: &mut Fork<Empty>, code: &[u8]) -> ExecutionResult {
+        chain.set_code(&CONTRACT, code);
+        let call = CallInfo {
+            address: CONTRACT,
+            code_address: CONTRACT,
+            initial_gas: 1_000_000,
+            ..CallInfo::default()
+        };
+        let schedule = Hardfork::Cancun.schedule();
+        evaluate(chain, &BlockInfo::default(), &TransactionInfo::default(), &call, &schedule)
+    }
+
+    #[test]
+    fn test_selfdestruct_cancun() {
+        // SSTORE(0, 1) SELFDESTRUCT(0xaa)
+        let beneficiary = U256::from(0xaa);
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(100));
+        let result = run_in_cancun(&mut chain, &hex!("6001600055 60aaff"));
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(chain.balance(&CONTRACT), U256::zero());
+        assert_eq!(chain.balance(&beneficiary), U256::from(100));
+        // Not created in this transaction, so the code and storage stay
+        assert_eq!(chain.code(&CONTRACT), hex!("6001600055 60aaff").to_vec());
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::one());
+
+        // Before Cancun both are removed
+        let mut chain = Fork::from(Empty);
+        run(&mut chain, &hex!("6001600055 60aaff"));
+        assert_eq!(chain.code(&CONTRACT), Vec::<u8>::new());
+        assert_eq!(chain.storage(&CONTRACT, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_selfdestruct_to_self() {
+        // SELFDESTRUCT(ADDRESS)
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(100));
+        run_in_cancun(&mut chain, &hex!("30ff"));
+        assert_eq!(chain.balance(&CONTRACT), U256::from(100));
+        assert_eq!(chain.code(&CONTRACT), hex!("30ff").to_vec());
+
+        // Before Cancun the balance is burned with the account
+        run(&mut chain, &hex!("30ff"));
+        assert_eq!(chain.balance(&CONTRACT), U256::zero());
+        assert_eq!(chain.code(&CONTRACT), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_selfdestruct_created() {
+        // MSTORE(0, SSTORE(0, 1) SELFDESTRUCT(ADDRESS)) CREATE(10, 25, 7)
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&CONTRACT, &U256::from(100));
+        run(
+            &mut chain,
+            &hex!("66 600160005530ff 600052 60076019600af0 00"),
+        );
+        let address = create_address(&CONTRACT, 0);
+        assert_eq!(chain.balance(&CONTRACT), U256::from(90));
+        assert_eq!(chain.balance(&address), U256::zero());
+        assert_eq!(chain.nonce(&address), 0);
+        assert_eq!(chain.code(&address), Vec::<u8>::new());
+        assert_eq!(chain.storage(&address, &U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn test_log() {
+        // MSTORE(0, 0x2a) LOG2(31, 1, 1, 2)
+        let mut chain = Fork::from(Empty);
+        let result = run(&mut chain, &hex!("602a600052 600260016001601fa2"));
+        assert_eq!(result.logs, vec![Log {
+            address: CONTRACT,
+            topics:  vec![U256::from(1), U256::from(2)],
+            data:    vec![0x2a],
+        }]);
+        // The MSTORE expands memory to one word
+        assert_eq!(
+            result.gas_used(),
+            3 + 3 + 3 + MEMORY_GAS + 3 * 4 + 375 + 2 * 375 + LOG_DATA_GAS
+        );
+    }
+
+    #[test]
+    fn test_log_reverted() {
+        // CALL(0xffff, OTHER, 0, 0, 0, 0, 0) LOG0(0, 0)
+        // OTHER: LOG0(0, 0) REVERT(0, 0)
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(0xaa), &hex!("60006000a0 60006000fd"));
+        let result = run(
+            &mut chain,
+            &hex!("6000600060006000600060aa61fffff1 60006000a0"),
+        );
+        assert_eq!(result.logs, vec![Log {
+            address: CONTRACT,
+            ..Log::default()
+        }]);
+    }
+}
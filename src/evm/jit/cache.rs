@@ -0,0 +1,115 @@
+//! Programs shared between accounts with the same bytecode
+//!
+//! Proxies and tokens deployed by the same factory have identical code, so
+//! the control flow is recovered once per code hash instead of per account.
+
+use super::{Error, Program};
+use crate::{evm::precompiles::keccak256, prelude::*};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+
+/// Lookups answered from the cache and lookups that analyzed the code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits:   usize,
+    pub misses: usize,
+}
+
+/// Analyzed programs by the hash of their bytecode
+#[derive(Debug, Default)]
+pub struct ProgramCache {
+    programs: RwLock<HashMap<U256, Arc<Program>>>,
+    hits:     AtomicUsize,
+    misses:   AtomicUsize,
+}
+
+impl ProgramCache {
+    /// Program for `bytecode`, analyzed if it was not seen before
+    ///
+    /// Bytecode that fails to analyze is not cached.
+    pub fn get(&self, bytecode: &[u8]) -> Result<Arc<Program>, Error> {
+        let hash = keccak256(bytecode);
+        if let Some(program) = self.programs.read().expect("lock poisoned").get(&hash) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(program));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let program = Arc::new(Program::from(bytecode.to_vec())?);
+        // Another thread may have analyzed the same code in the meantime
+        Ok(Arc::clone(
+            self.programs
+                .write()
+                .expect("lock poisoned")
+                .entry(hash)
+                .or_insert(program),
+        ))
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits:   self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of distinct programs cached
+    pub fn len(&self) -> usize {
+        self.programs.read().expect("lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_shared_code() {
+        let cache = ProgramCache::default();
+        // PUSH1 1 STOP
+        let first = cache.get(&hex!("600100")).unwrap();
+        let second = cache.get(&hex!("600100")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+        let other = cache.get(&hex!("00")).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_eq!(cache.len(), 2);
+    }
+}
+
+#[cfg(feature = "bench")]
+pub mod bench {
+    use super::{super::ZERO_EX, *};
+    use crate::bench::prelude::*;
+
+    /// Analyze many instances of the same contract with and without cache
+    pub fn group(criterion: &mut Criterion) {
+        const INSTANCES: usize = 100;
+        criterion.bench_function("analyze instances", |bencher| {
+            bencher.iter(|| {
+                for _ in 0..INSTANCES {
+                    black_box(Program::from(ZERO_EX.to_vec()).unwrap());
+                }
+            });
+        });
+        criterion.bench_function("analyze instances cached", |bencher| {
+            bencher.iter(|| {
+                let cache = ProgramCache::default();
+                for _ in 0..INSTANCES {
+                    black_box(cache.get(ZERO_EX).unwrap());
+                }
+            });
+        });
+    }
+}
@@ -1,30 +1,403 @@
-mod interpreter;
-mod jit;
+pub mod abi;
+mod call_builder;
+mod gas_schedule;
+mod hardfork;
+pub mod interpreter;
+pub mod jit;
 mod opcode;
 pub mod precompiles;
+mod revert;
+pub mod signatures;
+pub mod source_map;
+pub mod trace_diff;
+pub mod tracer;
 
-pub use self::opcode::Opcode;
+pub use self::{
+    call_builder::{CallBuilder, IntoAccount, DEFAULT_GAS},
+    gas_schedule::GasSchedule,
+    hardfork::Hardfork,
+    interpreter::Limits,
+    opcode::Opcode,
+    revert::RevertReason,
+};
+use crate::chain::{types::AccessListItem, BlockInfo, WriteableChainState};
+use anyhow::{anyhow, Result as AnyResult};
+use std::collections::HashSet;
+use thiserror::Error;
 use zkp_u256::U256;
 
 /// Constants for the current transaction
+///
+/// Legacy transactions use their gas price for both fee caps.
 #[derive(Clone, Default, Debug)]
 pub struct TransactionInfo {
-    pub origin:    U256,
-    pub gas_price: U256,
+    pub origin:                   U256,
+    pub max_fee_per_gas:          U256,
+    pub max_priority_fee_per_gas: U256,
+    /// Addresses and storage slots that start out warm (EIP-2930)
+    pub access_list:              Vec<AccessListItem>,
+    /// Gas charged before execution, see [`intrinsic_gas`]. It counts
+    /// towards the gas used that caps the refund.
+    pub intrinsic_gas:            u64,
 }
 
-/// Constants for the current call
+impl TransactionInfo {
+    /// Transaction with a fixed gas price
+    #[must_use]
+    pub fn legacy(origin: U256, gas_price: U256) -> Self {
+        Self {
+            origin,
+            max_fee_per_gas: gas_price.clone(),
+            max_priority_fee_per_gas: gas_price,
+            access_list: Vec::new(),
+            intrinsic_gas: 0,
+        }
+    }
+
+    /// Transaction with fee caps (EIP-1559)
+    #[must_use]
+    pub const fn eip1559(
+        origin: U256,
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    ) -> Self {
+        Self {
+            origin,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            access_list: Vec::new(),
+            intrinsic_gas: 0,
+        }
+    }
+
+    /// Gas price paid given the block's base fee
+    #[must_use]
+    pub fn effective_gas_price(&self, base_fee: &U256) -> U256 {
+        let price = base_fee + &self.max_priority_fee_per_gas;
+        if price < self.max_fee_per_gas {
+            price
+        } else {
+            self.max_fee_per_gas.clone()
+        }
+    }
+}
+
+/// Constants for the current call, see [`CallInfo::builder`]
 #[derive(Clone, Default, Debug)]
 pub struct CallInfo {
-    pub sender:      U256,
-    pub address:     U256,
-    pub call_value:  U256,
-    pub initial_gas: usize,
-    pub input:       Vec<u8>,
+    pub sender:       U256,
+    pub address:      U256,
+    /// Account to load code from, differs from `address` for DELEGATECALL.
+    pub code_address: U256,
+    pub call_value:   U256,
+    pub initial_gas:  usize,
+    pub input:        Vec<u8>,
+    /// State modifications are not allowed, see STATICCALL.
+    pub is_static:    bool,
+    /// Init code to run instead of the code at `code_address`, see CREATE.
+    pub init_code:    Option<Vec<u8>>,
 }
 
+/// Outcome of a single call frame
 #[derive(Clone, Debug)]
-pub enum ExecutionResult {
+pub enum CallResult {
     Return(Vec<u8>),
     Revert(Vec<u8>),
+    /// The frame halted exceptionally and consumed all gas given to it. Like
+    /// a revert this only fails the frame, the caller continues.
+    Halt(HaltReason),
+}
+
+/// Cause of an exceptional halt, with the same messages as Geth
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum HaltReason {
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("stack underflow")]
+    StackUnderflow,
+    #[error("stack limit reached")]
+    StackOverflow,
+    #[error("invalid jump destination")]
+    InvalidJump,
+    #[error("invalid opcode")]
+    InvalidOpcode,
+    /// State modification inside a static call
+    #[error("write protection")]
+    WriteProtection,
+    #[error("return data out of bounds")]
+    ReturnDataOutOfBounds,
+    /// Deployed code is larger than the EIP-170 limit
+    #[error("max code size exceeded")]
+    CodeSizeExceeded,
+}
+
+/// Virtual machine with the opcodes and gas costs of a hardfork
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Evm {
+    schedule: GasSchedule,
+    limits:   Limits,
+}
+
+impl Evm {
+    #[must_use]
+    pub fn new(hardfork: Hardfork) -> Self {
+        Self::with_schedule(hardfork.schedule())
+    }
+
+    #[must_use]
+    pub fn with_schedule(schedule: GasSchedule) -> Self {
+        Self {
+            schedule,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Enforce tighter memory and call depth limits than the protocol
+    #[must_use]
+    pub const fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    #[must_use]
+    pub const fn schedule(&self) -> &GasSchedule {
+        &self.schedule
+    }
+
+    #[must_use]
+    pub const fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Execute a call as the top frame of a transaction
+    ///
+    /// The call starts with `call.initial_gas`, which excludes the intrinsic
+    /// gas. The nonce, value transfer and fees are left to the caller, as is
+    /// reverting the state if the call fails.
+    pub fn execute(
+        &self,
+        chain: &mut dyn WriteableChainState,
+        call: &CallInfo,
+        block: &BlockInfo,
+        transaction: &TransactionInfo,
+    ) -> ExecutionResult {
+        self.execute_with_tracer(chain, call, block, transaction, &mut ())
+    }
+
+    /// Like [`Evm::execute`], reporting each step to `tracer`
+    pub fn execute_with_tracer(
+        &self,
+        chain: &mut dyn WriteableChainState,
+        call: &CallInfo,
+        block: &BlockInfo,
+        transaction: &TransactionInfo,
+        tracer: &mut dyn tracer::Tracer,
+    ) -> ExecutionResult {
+        interpreter::evaluate_with_limits(
+            chain,
+            block,
+            transaction,
+            call,
+            &self.schedule,
+            &self.limits,
+            tracer,
+        )
+    }
+}
+
+/// Execute a call with the hardfork active at `block`, see [`Evm::execute`]
+pub fn execute(
+    chain: &mut dyn WriteableChainState,
+    call: &CallInfo,
+    block: &BlockInfo,
+    transaction: &TransactionInfo,
+) -> ExecutionResult {
+    let hardfork = Hardfork::detect(block.chain_id, block.number);
+    Evm::new(hardfork).execute(chain, call, block, transaction)
+}
+
+/// Intrinsic gas for every address in the access list (EIP-2930).
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2400;
+
+/// Intrinsic gas for every storage key in the access list (EIP-2930).
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1900;
+
+/// Gas charged before execution for the transaction, its calldata or init
+/// code and its access list, with the costs of `schedule`
+///
+/// The call should be started with the gas limit minus this.
+pub fn intrinsic_gas(
+    call: &CallInfo,
+    access_list: &[AccessListItem],
+    schedule: &GasSchedule,
+) -> u64 {
+    let data = call.init_code.as_ref().unwrap_or(&call.input);
+    let data_gas = data
+        .iter()
+        .map(|&byte| {
+            if byte == 0 {
+                4
+            } else {
+                schedule.tx_data_non_zero
+            }
+        })
+        .sum::<u64>();
+    let create_gas = call.init_code.as_ref().map_or(0, |init_code| {
+        let words = (init_code.len() as u64 + 31) / 32;
+        schedule.tx_create + schedule.initcode_word * words
+    });
+    let access_list_gas = access_list
+        .iter()
+        .map(|item| {
+            ACCESS_LIST_ADDRESS_GAS + ACCESS_LIST_STORAGE_KEY_GAS * item.storage_keys.len() as u64
+        })
+        .sum::<u64>();
+    21000 + data_gas + create_gas + access_list_gas
+}
+
+/// Event emitted by LOG0 to LOG4
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Log {
+    pub address: U256,
+    pub topics:  Vec<U256>,
+    pub data:    Vec<u8>,
+}
+
+/// Outcome of a transaction
+#[derive(Clone, Debug)]
+pub struct ExecutionResult {
+    pub result:             CallResult,
+    /// Logs emitted by frames that did not revert, in order
+    pub logs:               Vec<Log>,
+    /// Accounts accessed during the transaction (EIP-2929)
+    pub accessed_addresses: HashSet<U256>,
+    /// Storage slots accessed during the transaction as `(address, slot)`
+    pub accessed_storage:   HashSet<(U256, U256)>,
+    /// First executed opcode that the hardfork does not have yet. It halted
+    /// its frame like INVALID, as it did on the chain at the time.
+    pub unavailable_opcode: Option<Opcode>,
+    gas_used:               usize,
+    gas_refunded:           usize,
+}
+
+impl ExecutionResult {
+    /// Gas consumed by the transaction after refunds
+    ///
+    /// Includes the intrinsic gas of the transaction.
+    #[must_use]
+    pub const fn gas_used(&self) -> usize {
+        self.gas_used
+    }
+
+    /// Gas refunded at the end of the transaction, capped to a fraction
+    /// of the gas consumed including the intrinsic gas.
+    #[must_use]
+    pub const fn gas_refunded(&self) -> usize {
+        self.gas_refunded
+    }
+
+    /// Decode the output of a successful call, see [`abi::decode`]
+    pub fn decode_output(&self, kinds: &[abi::ParamType]) -> AnyResult<Vec<abi::Token>> {
+        match &self.result {
+            CallResult::Return(output) => abi::decode(kinds, output),
+            CallResult::Revert(output) => {
+                Err(anyhow!("Call reverted: {}", RevertReason::decode(output)))
+            }
+            CallResult::Halt(reason) => Err(anyhow!("Call halted: {}", reason)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{prelude::*, test::prelude::assert_eq};
+
+    #[test]
+    fn test_effective_gas_price_legacy() {
+        let transaction = TransactionInfo::legacy(U256::zero(), U256::from(50));
+        assert_eq!(
+            transaction.effective_gas_price(&U256::from(30)),
+            U256::from(50)
+        );
+    }
+
+    #[test]
+    fn test_effective_gas_price_eip1559() {
+        let transaction = TransactionInfo::eip1559(U256::zero(), U256::from(100), U256::from(2));
+        assert_eq!(
+            transaction.effective_gas_price(&U256::from(30)),
+            U256::from(32)
+        );
+        assert_eq!(
+            transaction.effective_gas_price(&U256::from(99)),
+            U256::from(100)
+        );
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let call = CallInfo {
+            input: vec![0x00, 0x01, 0x00, 0xff, 0x00],
+            ..CallInfo::default()
+        };
+        let london = GasSchedule::london();
+        assert_eq!(intrinsic_gas(&call, &[], &london), 21000 + 3 * 4 + 2 * 16);
+        let create = CallInfo {
+            init_code: Some(call.input),
+            ..CallInfo::default()
+        };
+        assert_eq!(intrinsic_gas(&create, &[], &london), 53000 + 3 * 4 + 2 * 16);
+        let access_list = [AccessListItem {
+            storage_keys: vec![U256::zero(), U256::one()],
+            ..AccessListItem::default()
+        }];
+        assert_eq!(
+            intrinsic_gas(&CallInfo::default(), &access_list, &london),
+            21000 + 2400 + 2 * 1900
+        );
+        // One word of init code
+        assert_eq!(
+            intrinsic_gas(&create, &[], &Hardfork::Shanghai.schedule()),
+            53000 + 3 * 4 + 2 * 16 + 2
+        );
+    }
+
+    #[test]
+    fn test_intrinsic_gas_before_istanbul() {
+        let call = CallInfo {
+            input: vec![0x00, 0x01, 0x00, 0xff, 0x00],
+            ..CallInfo::default()
+        };
+        let create = CallInfo {
+            init_code: Some(call.input.clone()),
+            ..CallInfo::default()
+        };
+        let schedule = Hardfork::Petersburg.schedule();
+        assert_eq!(intrinsic_gas(&call, &[], &schedule), 21000 + 3 * 4 + 2 * 68);
+        assert_eq!(intrinsic_gas(&create, &[], &schedule), 53000 + 3 * 4 + 2 * 68);
+        // Creation costs the same as a call in Frontier
+        let schedule = Hardfork::Frontier.schedule();
+        assert_eq!(intrinsic_gas(&create, &[], &schedule), 21000 + 3 * 4 + 2 * 68);
+    }
+
+    #[test]
+    fn test_decode_output() {
+        let address = U256::from(0x1234);
+        let mut chain = crate::chain::Fork::from(crate::chain::Empty);
+        // MSTORE(0, 42) RETURN(0, 32)
+        chain.set_code(&address, &hex!("602a60005260206000f3"));
+        let call = CallInfo::builder().to(&address).gas(100_000).build();
+        let result = Evm::default().execute(
+            &mut chain,
+            &call,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+        );
+        assert_eq!(
+            result.decode_output(&[abi::ParamType::Uint(256)]).unwrap(),
+            vec![abi::Token::Uint(U256::from(42))]
+        );
+        assert!(result.decode_output(&[abi::ParamType::String]).is_err());
+    }
 }
@@ -1,6 +1,6 @@
 /// Ethereum Virtual Machine Opcodes.
 /// See <https://ethereum.github.io/yellowpaper/paper.pdf>
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Opcode {
     // 0x00-0x0B: Stop and Arithmetic Operations
     Stop,
@@ -53,15 +53,18 @@ pub enum Opcode {
     ReturnDataCopy,
     ExtCodeHash,
 
-    // 0x40-0x45: Block Information
+    // 0x40-0x48: Block Information
     BlockHash,
     Coinbase,
     Timestamp,
     Number,
     Difficulty,
     GasLimit,
+    ChainId,
+    SelfBalance,
+    BaseFee,
 
-    // 0x50-0x5B: Stack, Memory, Storage and Flow Operations
+    // 0x50-0x5E: Stack, Memory, Storage and Flow Operations
     Pop,
     MLoad,
     MStore,
@@ -74,8 +77,11 @@ pub enum Opcode {
     MSize,
     Gas,
     JumpDest,
+    TLoad,
+    TStore,
+    MCopy,
 
-    // 0x60-0x7F: Push Operations
+    // 0x5F-0x7F: Push Operations, PUSH0 has no payload
     Push(u8),
 
     // 0x80-0x8F: Duplication Operations
@@ -163,6 +169,9 @@ impl From<u8> for Opcode {
             0x43 => Number,
             0x44 => Difficulty,
             0x45 => GasLimit,
+            0x46 => ChainId,
+            0x47 => SelfBalance,
+            0x48 => BaseFee,
 
             0x50 => Pop,
             0x51 => MLoad,
@@ -176,6 +185,10 @@ impl From<u8> for Opcode {
             0x59 => MSize,
             0x5a => Gas,
             0x5b => JumpDest,
+            0x5c => TLoad,
+            0x5d => TStore,
+            0x5e => MCopy,
+            0x5f => Push(0),
 
             0x60..=0x7F => Push(1 + opcode - 0x60),
             0x80..=0x8F => Dup(1 + opcode - 0x80),
@@ -208,6 +221,18 @@ impl Opcode {
         panic!("{:?} has no Opcode::from value.", self);
     }
 
+    /// Name as used in the Yellow Paper and by Geth, e.g. `PUSH1`
+    pub fn mnemonic(self) -> String {
+        match self {
+            Push(n) => format!("PUSH{}", n),
+            Dup(n) => format!("DUP{}", n),
+            Swap(n) => format!("SWAP{}", n),
+            Log(n) => format!("LOG{}", n),
+            Unknown(opcode) => format!("opcode 0x{:02x} not defined", opcode),
+            other => format!("{:?}", other).to_uppercase(),
+        }
+    }
+
     /// The encoded size of the opcode (how much it moves the program counter)
     pub const fn encoded_size(self) -> usize {
         match self {
@@ -226,15 +251,15 @@ impl Opcode {
         match self {
             Stop | JumpDest | Invalid | Unknown(_) => (0, 0),
             Address | Origin | Caller | CallValue | CallDataSize | CodeSize | GasPrice
-            | ReturnDataSize | Coinbase | Timestamp | Number | Difficulty | GasLimit | PC
-            | MSize | Gas | Push(_) => (0, 1),
+            | ReturnDataSize | Coinbase | Timestamp | Number | Difficulty | GasLimit | ChainId
+            | SelfBalance | BaseFee | PC | MSize | Gas | Push(_) => (0, 1),
             Pop | Jump | SelfDestruct => (1, 0),
             IsZero | Not | Balance | CallDataLoad | ExtCodeSize | ExtCodeHash | BlockHash
-            | MLoad | SLoad => (1, 1),
-            MStore | MStore8 | SStore | JumpI | Return | Revert => (2, 0),
+            | MLoad | SLoad | TLoad => (1, 1),
+            MStore | MStore8 | SStore | TStore | JumpI | Return | Revert => (2, 0),
             Add | Mul | Sub | Div | SDiv | Mod | SMod | Exp | SignExtend | Lt | Gt | SLt | SGt
             | Eq | And | Or | Xor | Byte | Shl | Shr | Sar | Sha3 => (2, 1),
-            CallDataCopy | CodeCopy | ReturnDataCopy => (3, 0),
+            CallDataCopy | CodeCopy | ReturnDataCopy | MCopy => (3, 0),
             AddMod | MulMod | Create => (3, 1),
             ExtCodeCopy => (4, 0),
             Create2 => (4, 1),
@@ -255,13 +280,13 @@ impl Opcode {
             Stop | Return | Revert => 0,
             // Base
             Address | Origin | Caller | CallValue | CallDataSize | CodeSize | GasPrice
-            | Coinbase | Timestamp | Number | Difficulty | GasLimit | ReturnDataSize | Pop | PC
-            | MSize | Gas => 2,
+            | Coinbase | Timestamp | Number | Difficulty | GasLimit | ChainId | BaseFee
+            | ReturnDataSize | Pop | PC | MSize | Gas | Push(0) => 2,
             // Very low
             Add | Sub | Not | Lt | Gt | SLt | SGt | Eq | IsZero | And | Or | Xor | Byte | Shl
             | Shr | Sar | CallDataLoad | MLoad | MStore | MStore8 | Push(_) | Dup(_) | Swap(_) => 3,
             // Low
-            Mul | Div | SDiv | Mod | SMod | SignExtend => 5,
+            Mul | Div | SDiv | Mod | SMod | SignExtend | SelfBalance => 5,
             // Mid
             AddMod | MulMod | Jump => 8,
             // High
@@ -269,20 +294,20 @@ impl Opcode {
             // Special cases with constant gas
             Create => 32000,
             JumpDest => 1,
-            SLoad => 200,
-            ExtCodeSize => 700,
-            ExtCodeHash => 400,
-            Balance => 400,
+            // Accounts and storage cost more on first access (EIP-2929)
+            SLoad | ExtCodeSize | ExtCodeHash | Balance => 100,
+            // Transient storage is always warm (EIP-1153)
+            TLoad | TStore => 100,
             BlockHash => 20,
             Invalid | Unknown(_) => 0,
             // Special cases with dynamic gas (returns minimum not accounting for refunds)
             // TODO: Some of these only depend on a size argument on the stack.
-            SStore => 5000,
+            SStore => 100,
             Exp => 10,
-            CallDataCopy | CodeCopy | ReturnDataCopy => 3,
-            ExtCodeCopy => 700,
+            CallDataCopy | CodeCopy | ReturnDataCopy | MCopy => 3,
+            ExtCodeCopy => 100,
             Log(n) => 375 + (n as usize) * 375,
-            Call | CallCode | DelegateCall | StaticCall => 700,
+            Call | CallCode | DelegateCall | StaticCall => 100,
             SelfDestruct => 5000,
             Create2 => 32000,
             Sha3 => 30,
@@ -1,5 +1,53 @@
+//! Precompiled contracts
+//!
+//! See <https://ethereum.github.io/yellowpaper/paper.pdf> appendix E.
+
+use crate::prelude::*;
+use num_bigint::BigUint;
+use ripemd160::Ripemd160;
+use secp256k1::{recover, Message, RecoveryId, Signature};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
 use tiny_keccak::{Hasher, Keccak};
-use zkp_u256::U256;
+
+/// Order of the secp256k1 curve
+const SECP256K1_N: U256 = U256::from_limbs([
+    0xbfd2_5e8c_d036_4141,
+    0xbaae_dce6_af48_a03b,
+    0xffff_ffff_ffff_fffe,
+    0xffff_ffff_ffff_ffff,
+]);
+
+/// Gas cost function of a precompile
+pub type GasFn = fn(&[u8]) -> usize;
+
+/// Execution function of a precompile
+pub type RunFn = fn(&[u8]) -> Vec<u8>;
+
+/// Precompiled contract
+#[derive(Clone, Copy)]
+pub struct Precompile {
+    /// Gas cost for the given input
+    pub gas: GasFn,
+    /// Output for the given input
+    pub run: RunFn,
+}
+
+/// Precompiled contract at `address`, if any
+pub fn precompile(address: &U256) -> Option<Precompile> {
+    if address.bits() > 64 {
+        return None;
+    }
+    let (gas, run): (GasFn, RunFn) = match address.as_u64() {
+        1 => (|_| 3000, ecrecover),
+        2 => (|input| 60 + 12 * words(input), sha256),
+        3 => (|input| 600 + 120 * words(input), ripemd160),
+        4 => (|input| 15 + 3 * words(input), <[u8]>::to_vec),
+        5 => (modexp_gas, modexp),
+        _ => return None,
+    };
+    Some(Precompile { gas, run })
+}
 
 /// Ethereum's Keccak256 hash function
 ///
@@ -12,3 +60,278 @@ pub fn keccak256(bytes: &[u8]) -> U256 {
     keccak.finalize(&mut output);
     U256::from_bytes_be(&output)
 }
+
+/// Recover the signer of a message hash
+///
+/// Input is `(hash, v, r, s)`, zero extended to 128 bytes. Returns the
+/// address left-padded to 32 bytes, or nothing if the signature is invalid.
+pub fn ecrecover(input: &[u8]) -> Vec<u8> {
+    let mut padded = [0_u8; 128];
+    let size = std::cmp::min(input.len(), padded.len());
+    padded[..size].copy_from_slice(&input[..size]);
+
+    // `v` must be 27 or 28, `r` and `s` must be in `[1, N)`
+    let v = word(&padded[32..64]);
+    if v != U256::from(27) && v != U256::from(28) {
+        return Vec::new();
+    }
+    let r = word(&padded[64..96]);
+    let s = word(&padded[96..128]);
+    if r.is_zero() || s.is_zero() || r >= SECP256K1_N || s >= SECP256K1_N {
+        return Vec::new();
+    }
+
+    let mut hash = [0_u8; 32];
+    hash.copy_from_slice(&padded[..32]);
+    let mut signature = [0_u8; 64];
+    signature.copy_from_slice(&padded[64..]);
+    let recovery_id = RecoveryId::parse(padded[63] - 27).unwrap();
+    let public_key = match recover(
+        &Message::parse(&hash),
+        &Signature::parse(&signature),
+        &recovery_id,
+    ) {
+        Ok(public_key) => public_key,
+        Err(_) => return Vec::new(),
+    };
+
+    // Address is the lower 160 bits of the hash of the uncompressed key
+    let mut output = keccak256(&public_key.serialize()[1..]).to_bytes_be();
+    for byte in &mut output[..12] {
+        *byte = 0;
+    }
+    output.to_vec()
+}
+
+/// SHA2-256 hash of the input
+pub fn sha256(input: &[u8]) -> Vec<u8> {
+    Sha256::digest(input).to_vec()
+}
+
+/// RIPEMD-160 hash of the input, left-padded to 32 bytes
+pub fn ripemd160(input: &[u8]) -> Vec<u8> {
+    let mut output = vec![0_u8; 12];
+    output.extend_from_slice(&Ripemd160::digest(input));
+    output
+}
+
+/// Modular exponentiation (EIP-198)
+///
+/// Input is the lengths of base, exponent and modulus as 32-byte words
+/// followed by their values. Input is zero extended and the result is
+/// left-padded to the length of the modulus.
+///
+/// Lengths are not bounded, so this must only run after charging gas.
+pub fn modexp(input: &[u8]) -> Vec<u8> {
+    let base_len = length(input, 0);
+    let exp_len = length(input, 32);
+    let mod_len = length(input, 64);
+    if base_len == 0 && mod_len == 0 {
+        return Vec::new();
+    }
+    let base = read_padded(input, 96, base_len);
+    let exp = read_padded(input, 96 + base_len, exp_len);
+    let modulus = BigUint::from_bytes_be(&read_padded(input, 96 + base_len + exp_len, mod_len));
+    let mut output = vec![0_u8; mod_len];
+    if modulus.is_zero() {
+        return output;
+    }
+    let result = BigUint::from_bytes_be(&base)
+        .modpow(&BigUint::from_bytes_be(&exp), &modulus)
+        .to_bytes_be();
+    output[mod_len - result.len()..].copy_from_slice(&result);
+    output
+}
+
+/// Gas for modular exponentiation (EIP-2565)
+///
+/// Saturates instead of overflowing for large lengths.
+pub fn modexp_gas(input: &[u8]) -> usize {
+    let base_len = length(input, 0);
+    let exp_len = length(input, 32);
+    let mod_len = length(input, 64);
+    let words = std::cmp::max(base_len, mod_len).saturating_add(7) / 8;
+    let complexity = words.saturating_mul(words);
+
+    // Only the exponent's first 32 bytes are read, the rest is priced by length
+    let head_len = std::cmp::min(exp_len, 32);
+    let head = read_padded(input, base_len.saturating_add(96), head_len);
+    let head_bits = BigUint::from_bytes_be(&head).bits() as usize;
+    let iterations = exp_len
+        .saturating_sub(32)
+        .saturating_mul(8)
+        .saturating_add(head_bits.saturating_sub(1));
+
+    let gas = complexity.saturating_mul(std::cmp::max(iterations, 1)) / 3;
+    std::cmp::max(gas, 200)
+}
+
+/// Read a length word, saturating if it doesn't fit
+fn length(input: &[u8], offset: usize) -> usize {
+    let length = word(&read_padded(input, offset, 32));
+    if length.bits() > 64 {
+        usize::MAX
+    } else {
+        usize::try_from(length.as_u64()).unwrap_or(usize::MAX)
+    }
+}
+
+/// Read `size` bytes at `offset`, zero extending the input
+fn read_padded(input: &[u8], offset: usize, size: usize) -> Vec<u8> {
+    let mut result = vec![0_u8; size];
+    if offset < input.len() {
+        let available = std::cmp::min(size, input.len() - offset);
+        result[..available].copy_from_slice(&input[offset..offset + available]);
+    }
+    result
+}
+
+/// Number of 32-byte words needed to hold the input
+const fn words(input: &[u8]) -> usize {
+    (input.len() + 31) / 32
+}
+
+fn word(bytes: &[u8]) -> U256 {
+    let mut word = [0_u8; 32];
+    word.copy_from_slice(bytes);
+    U256::from_bytes_be(&word)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    // Test vector from go-ethereum's `core/vm/contracts_test.go`
+    const ECRECOVER_INPUT: [u8; 128] = hex!(
+        "38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e
+         000000000000000000000000000000000000000000000000000000000000001b
+         38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e
+         789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02"
+    );
+
+    #[test]
+    fn test_ecrecover() {
+        assert_eq!(
+            ecrecover(&ECRECOVER_INPUT),
+            hex!("000000000000000000000000ceaccac640adf55b2028469bd36ba501f28b699d").to_vec()
+        );
+    }
+
+    #[test]
+    fn test_ecrecover_invalid_v() {
+        let mut input = ECRECOVER_INPUT;
+        input[63] = 29;
+        assert_eq!(ecrecover(&input), Vec::<u8>::new());
+        input[63] = 27;
+        input[32] = 1;
+        assert_eq!(ecrecover(&input), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_ecrecover_invalid_s() {
+        let mut input = ECRECOVER_INPUT;
+        input[96..].copy_from_slice(&SECP256K1_N.to_bytes_be());
+        assert_eq!(ecrecover(&input), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_ecrecover_short_input() {
+        assert_eq!(ecrecover(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b""),
+            hex!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855").to_vec()
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            hex!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad").to_vec()
+        );
+    }
+
+    #[test]
+    fn test_sha256_gas() {
+        let sha256 = precompile(&U256::from(2)).unwrap();
+        assert_eq!((sha256.gas)(b""), 60);
+        assert_eq!((sha256.gas)(&[0; 33]), 84);
+    }
+
+    #[test]
+    fn test_ripemd160() {
+        assert_eq!(
+            ripemd160(b""),
+            hex!("0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31").to_vec()
+        );
+        assert_eq!(
+            ripemd160(b"abc"),
+            hex!("0000000000000000000000008eb208f7e05d987a9b044a8e98c6b087f15a0bfc").to_vec()
+        );
+    }
+
+    #[test]
+    fn test_modexp() {
+        // 3^5 mod 7
+        let input = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000001
+             030507"
+        );
+        assert_eq!(modexp(&input), vec![5]);
+        assert_eq!(modexp_gas(&input), 200);
+    }
+
+    #[test]
+    fn test_modexp_zero_modulus() {
+        // Lengths one, values truncated to zero
+        let input = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000002
+             0305"
+        );
+        assert_eq!(modexp(&input), vec![0, 0]);
+        assert_eq!(modexp(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_modexp_padded() {
+        // Modulus is zero extended to 0x0100, 3^2 mod 256
+        let input = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000001
+             0000000000000000000000000000000000000000000000000000000000000002
+             030201"
+        );
+        assert_eq!(modexp(&input), vec![0, 9]);
+    }
+
+    #[test]
+    fn test_modexp_gas_large_exponent() {
+        // 32 byte base and modulus, 64 byte zero exponent
+        let mut input = vec![0_u8; 96];
+        input[31] = 32;
+        input[63] = 64;
+        input[95] = 32;
+        assert_eq!(modexp_gas(&input), 16 * 256 / 3);
+
+        // Exponent length does not fit
+        input[32] = 0xff;
+        assert!(modexp_gas(&input) > 1 << 60);
+    }
+
+    #[test]
+    fn test_modexp_gas_saturates() {
+        // One byte base and modulus, maximal exponent length with a nonzero
+        // first byte
+        let mut input = vec![0_u8; 98];
+        input[31] = 1;
+        input[32..64].copy_from_slice(&[0xff; 32]);
+        input[95] = 1;
+        input[97] = 1;
+        assert_eq!(modexp_gas(&input), usize::MAX / 3);
+    }
+}
@@ -0,0 +1,149 @@
+//! Decoding of revert data
+
+use crate::{chain::types::rpc::Hexable, prelude::*};
+use std::{convert::TryFrom, fmt};
+
+/// Selector of `Error(string)`, used by `require` and `revert`
+const ERROR_SELECTOR: [u8; 4] = hex!("08c379a0");
+
+/// Selector of `Panic(uint256)`, used by `assert` and checked arithmetic
+const PANIC_SELECTOR: [u8; 4] = hex!("4e487b71");
+
+/// Reason a call reverted, decoded from its output
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RevertReason {
+    /// Message of an `Error(string)`
+    Error(String),
+    /// Code of a `Panic(uint256)`
+    Panic(U256),
+    /// Custom errors and anything else that could not be decoded
+    Raw(Vec<u8>),
+}
+
+impl RevertReason {
+    pub fn decode(output: &[u8]) -> Self {
+        decode_error(output)
+            .map(Self::Error)
+            .or_else(|| decode_panic(output).map(Self::Panic))
+            .unwrap_or_else(|| Self::Raw(output.to_vec()))
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Error(message) => write!(f, "{}", message),
+            Self::Panic(code) => {
+                let message = panic_message(code).unwrap_or("unknown panic code");
+                write!(f, "{} ({})", message, code.to_hex())
+            }
+            Self::Raw(output) => write!(f, "0x{}", hex::encode(output)),
+        }
+    }
+}
+
+/// Description of the panic codes emitted by Solidity
+///
+/// See <https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require>
+fn panic_message(code: &U256) -> Option<&'static str> {
+    if code.bits() > 8 {
+        return None;
+    }
+    Some(match code.as_u64() {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop from empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized function",
+        _ => return None,
+    })
+}
+
+/// Decode the message from revert data of the form `Error(string)`.
+fn decode_error(output: &[u8]) -> Option<String> {
+    if !output.starts_with(&ERROR_SELECTOR) {
+        return None;
+    }
+    let payload = &output[ERROR_SELECTOR.len()..];
+    let offset = abi_word(payload, 0)?;
+    let length = abi_word(payload, offset)?;
+    let start = offset.checked_add(32)?;
+    let message = payload.get(start..start.checked_add(length)?)?;
+    String::from_utf8(message.to_vec()).ok()
+}
+
+/// Decode the code from revert data of the form `Panic(uint256)`.
+fn decode_panic(output: &[u8]) -> Option<U256> {
+    if !output.starts_with(&PANIC_SELECTOR) {
+        return None;
+    }
+    let payload = &output[PANIC_SELECTOR.len()..];
+    if payload.len() != 32 {
+        return None;
+    }
+    let mut word = [0_u8; 32];
+    word.copy_from_slice(payload);
+    Some(U256::from_bytes_be(&word))
+}
+
+/// Read the ABI encoded word at `offset` as an `usize`.
+fn abi_word(bytes: &[u8], offset: usize) -> Option<usize> {
+    let word = bytes.get(offset..offset.checked_add(32)?)?;
+    let (high, low) = word.split_at(24);
+    if high.iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let mut low_bytes = [0_u8; 8];
+    low_bytes.copy_from_slice(low);
+    usize::try_from(u64::from_be_bytes(low_bytes)).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_decode_error() {
+        let output = hex!(
+            "08c379a0
+            0000000000000000000000000000000000000000000000000000000000000020
+            0000000000000000000000000000000000000000000000000000000000000004
+            6e6f706500000000000000000000000000000000000000000000000000000000"
+        );
+        let reason = RevertReason::decode(&output);
+        assert_eq!(reason, RevertReason::Error("nope".into()));
+        assert_eq!(reason.to_string(), "nope");
+    }
+
+    #[test]
+    fn test_decode_panic() {
+        let output = hex!(
+            "4e487b71
+            0000000000000000000000000000000000000000000000000000000000000011"
+        );
+        let reason = RevertReason::decode(&output);
+        assert_eq!(reason, RevertReason::Panic(U256::from(0x11)));
+        assert!(reason
+            .to_string()
+            .starts_with("arithmetic overflow or underflow"));
+    }
+
+    #[test]
+    fn test_decode_custom() {
+        // InsufficientBalance(uint256)
+        let output = hex!(
+            "cf479181
+            0000000000000000000000000000000000000000000000000000000000000001"
+        );
+        let reason = RevertReason::decode(&output);
+        assert_eq!(reason, RevertReason::Raw(output.to_vec()));
+        assert_eq!(reason.to_string(), format!("0x{}", hex::encode(&output[..])));
+        assert_eq!(RevertReason::decode(&[]).to_string(), "0x");
+    }
+}
@@ -0,0 +1,141 @@
+//! Function and error signatures by selector
+//!
+//! Unknown selectors can be looked up in the 4byte directory, see
+//! <https://www.4byte.directory>.
+
+use crate::{evm::RevertReason, prelude::*};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+const FOUR_BYTE_URL: &str = "https://www.4byte.directory/api/v1/signatures/";
+
+/// Signatures that are resolved without a lookup
+const BUILTIN: [([u8; 4], &str); 2] = [
+    (hex!("08c379a0"), "Error(string)"),
+    (hex!("4e487b71"), "Panic(uint256)"),
+];
+
+#[derive(Debug, Deserialize)]
+struct FourByteResponse {
+    results: Vec<FourByteSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FourByteSignature {
+    id:             u64,
+    text_signature: String,
+}
+
+/// Resolves selectors to signatures, caching lookups
+#[derive(Debug, Default)]
+pub struct SignatureResolver {
+    /// Query the 4byte directory for selectors that are not cached.
+    online: bool,
+    cache:  Mutex<HashMap<[u8; 4], Option<String>>>,
+}
+
+impl SignatureResolver {
+    /// Create a resolver, it only makes network requests if `online` is set.
+    pub fn new(online: bool) -> Self {
+        Self {
+            online,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signature for `selector`, or the hex encoded selector if it is unknown.
+    ///
+    /// Failed lookups are logged and not cached, so they are retried. The
+    /// cache is not locked during a lookup, so concurrent misses for the same
+    /// selector may both make one.
+    pub async fn resolve(&self, selector: [u8; 4]) -> String {
+        if let Some((_, signature)) = BUILTIN.iter().find(|(known, _)| *known == selector) {
+            return (*signature).to_string();
+        }
+        let cached = self.cache().get(&selector).cloned();
+        let signature = match cached {
+            Some(signature) => signature,
+            None if self.online => {
+                match lookup(selector).await {
+                    Ok(signature) => {
+                        self.cache().insert(selector, signature.clone());
+                        signature
+                    }
+                    Err(err) => {
+                        warn!("Looking up selector 0x{}: {:#}", hex::encode(selector), err);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        signature.unwrap_or_else(|| format!("0x{}", hex::encode(selector)))
+    }
+
+    /// Like [`RevertReason`]'s `Display`, but with the signature of custom
+    /// errors resolved.
+    pub async fn describe(&self, reason: &RevertReason) -> String {
+        match reason {
+            RevertReason::Raw(output) if output.len() >= 4 => {
+                let mut selector = [0_u8; 4];
+                selector.copy_from_slice(&output[..4]);
+                format!(
+                    "{} 0x{}",
+                    self.resolve(selector).await,
+                    hex::encode(&output[4..])
+                )
+            }
+            reason => reason.to_string(),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<[u8; 4], Option<String>>> {
+        self.cache.lock().expect("Signature cache poisoned")
+    }
+}
+
+/// Query the 4byte directory, picking the oldest entry on collisions.
+async fn lookup(selector: [u8; 4]) -> AnyResult<Option<String>> {
+    let url = format!("{}?hex_signature=0x{}", FOUR_BYTE_URL, hex::encode(selector));
+    let response: FourByteResponse = reqwest::get(&url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Requesting 4byte directory")?
+        .json()
+        .await
+        .context("Parsing 4byte directory response")?;
+    Ok(response
+        .results
+        .into_iter()
+        .min_by_key(|signature| signature.id)
+        .map(|signature| signature.text_signature))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[tokio::test]
+    async fn test_resolve_offline() {
+        let resolver = SignatureResolver::new(false);
+        assert_eq!(resolver.resolve(hex!("4e487b71")).await, "Panic(uint256)");
+        assert_eq!(resolver.resolve(hex!("cf479181")).await, "0xcf479181");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_cached() {
+        let resolver = SignatureResolver::new(false);
+        resolver.cache.lock().unwrap().insert(
+            hex!("cf479181"),
+            Some("InsufficientBalance(uint256,uint256)".into()),
+        );
+        let reason = RevertReason::decode(&hex!("cf479181 2a"));
+        assert_eq!(
+            resolver.describe(&reason).await,
+            "InsufficientBalance(uint256,uint256) 0x2a"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Source locations of instructions from Solidity source maps
+//!
+//! See <https://docs.soliditylang.org/en/latest/internals/source_mappings.html>
+
+use crate::{evm::tracer::instructions, prelude::*};
+use std::collections::HashMap;
+
+/// Kind of jump an instruction is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Jump {
+    /// Into a function
+    In,
+    /// Returning from a function
+    Out,
+    /// A regular jump or no jump
+    Regular,
+}
+
+/// Range of source code an instruction was generated from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Byte offset in the source file
+    pub start:  usize,
+    /// Length in bytes
+    pub length: usize,
+    /// Index of the source file, none for generated code
+    pub file:   Option<usize>,
+    pub jump:   Jump,
+}
+
+impl SourceLocation {
+    /// Line and column of the start in `source`, both starting at one
+    pub fn line_column(&self, source: &str) -> (usize, usize) {
+        let before = source.as_bytes().get(..self.start).unwrap_or(source.as_bytes());
+        let line = before.split(|byte| *byte == b'\n').count();
+        let column = before.iter().rev().take_while(|byte| **byte != b'\n').count() + 1;
+        (line, column)
+    }
+}
+
+/// Source map of a contract, resolving program counters to source locations
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    /// By instruction index
+    locations:    Vec<SourceLocation>,
+    /// Instruction index of the program counters of the code
+    instructions: HashMap<usize, usize>,
+}
+
+impl SourceMap {
+    /// Parse the compressed source map `map` of `code`
+    ///
+    /// Entries are `s:l:f:j:m`, empty and missing fields repeat the previous
+    /// entry. The modifier depth `m` is ignored.
+    pub fn parse(map: &str, code: &[u8]) -> AnyResult<Self> {
+        let mut locations = Vec::new();
+        let mut location = SourceLocation {
+            start:  0,
+            length: 0,
+            file:   None,
+            jump:   Jump::Regular,
+        };
+        for (index, entry) in map.trim().split(';').enumerate() {
+            let context = || format!("Parsing source map entry {} {:?}", index, entry);
+            let mut fields = entry.split(':');
+            if let Some(start) = field(fields.next()) {
+                location.start = start.parse().with_context(context)?;
+            }
+            if let Some(length) = field(fields.next()) {
+                location.length = length.parse().with_context(context)?;
+            }
+            if let Some(file) = field(fields.next()) {
+                location.file = match file {
+                    "-1" => None,
+                    file => Some(file.parse().with_context(context)?),
+                };
+            }
+            if let Some(jump) = field(fields.next()) {
+                location.jump = match jump {
+                    "i" => Jump::In,
+                    "o" => Jump::Out,
+                    "-" => Jump::Regular,
+                    _ => return Err(anyhow!("Invalid jump {:?}", jump)).with_context(context),
+                };
+            }
+            locations.push(location);
+        }
+        let instructions = instructions(code)
+            .into_iter()
+            .enumerate()
+            .map(|(index, pc)| (pc, index))
+            .collect();
+        Ok(Self {
+            locations,
+            instructions,
+        })
+    }
+
+    /// Location of the instruction at `pc`
+    ///
+    /// None if `pc` is inside push data or past the end of the map.
+    pub fn location(&self, pc: usize) -> Option<&SourceLocation> {
+        self.locations.get(*self.instructions.get(&pc)?)
+    }
+}
+
+/// A field that is present and not empty
+fn field(field: Option<&str>) -> Option<&str> {
+    field.filter(|field| !field.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_location() {
+        // 0: PUSH1 0x80, 2: PUSH1 0x40, 4: MSTORE, 5: JUMPDEST, 6: STOP
+        let code = hex!("6080 6040 52 5b 00");
+        let map = SourceMap::parse("1:2:0:-;:9;12:1:1;;3::-1:o", &code).unwrap();
+        assert_eq!(
+            map.location(0),
+            Some(&SourceLocation {
+                start:  1,
+                length: 2,
+                file:   Some(0),
+                jump:   Jump::Regular,
+            })
+        );
+        // Push data
+        assert_eq!(map.location(1), None);
+        assert_eq!(map.location(2).map(|l| (l.start, l.length, l.file)), Some((1, 9, Some(0))));
+        assert_eq!(map.location(4).map(|l| (l.start, l.length, l.file)), Some((12, 1, Some(1))));
+        assert_eq!(map.location(5), map.location(4));
+        assert_eq!(
+            map.location(6),
+            Some(&SourceLocation {
+                start:  3,
+                length: 1,
+                file:   None,
+                jump:   Jump::Out,
+            })
+        );
+        assert_eq!(map.location(7), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(SourceMap::parse("1:2:x", &[]).is_err());
+        assert!(SourceMap::parse("1:2:0:q", &[]).is_err());
+    }
+
+    #[test]
+    fn test_line_column() {
+        let source = "contract A {\n    uint x;\n}\n";
+        let location = SourceLocation {
+            start:  17,
+            length: 6,
+            file:   Some(0),
+            jump:   Jump::Regular,
+        };
+        assert_eq!(location.line_column(source), (2, 5));
+    }
+}
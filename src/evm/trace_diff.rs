@@ -0,0 +1,362 @@
+//! Differential testing against Geth's struct logs
+//!
+//! A trace of `debug_traceTransaction` from Geth is compared step by step to
+//! the trace of the same transaction in sutro, reporting the first step where
+//! they diverge. Fields Geth omitted, like the memory which recent versions
+//! leave out by default, are not compared.
+
+use crate::chain::types::rpc::{StructLog, TransactionTrace};
+use std::fmt;
+
+/// Fields to compare, all by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DiffOptions {
+    /// Gas remaining before each step and gas used by the transaction
+    pub gas:          bool,
+    /// Geth includes the gas forwarded to calls in the cost of `CALL`, sutro
+    /// does not.
+    pub gas_cost:     bool,
+    pub stack:        bool,
+    pub memory:       bool,
+    pub return_value: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            gas:          true,
+            gas_cost:     true,
+            stack:        true,
+            memory:       true,
+            return_value: true,
+        }
+    }
+}
+
+/// First difference between the traces
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the step, none for the outcome of the transaction
+    pub step:     Option<usize>,
+    pub field:    String,
+    pub expected: String,
+    pub actual:   String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(step) = self.step {
+            write!(f, "Step {}: ", step)?;
+        }
+        write!(
+            f,
+            "{} is {} in Geth and {} in sutro",
+            self.field, self.expected, self.actual
+        )
+    }
+}
+
+/// Compare the trace of sutro to the one of Geth
+pub fn compare(
+    expected: &TransactionTrace,
+    actual: &TransactionTrace,
+    options: DiffOptions,
+) -> Option<Divergence> {
+    for (step, (expected, actual)) in expected
+        .struct_logs
+        .iter()
+        .zip(&actual.struct_logs)
+        .enumerate()
+    {
+        if let Some(mut divergence) = compare_step(expected, actual, options) {
+            divergence.step = Some(step);
+            return Some(divergence);
+        }
+    }
+    let outcome = |field: &str, expected: String, actual: String| {
+        Divergence {
+            step: None,
+            field: field.to_string(),
+            expected,
+            actual,
+        }
+    };
+    let (expected_steps, actual_steps) = (expected.struct_logs.len(), actual.struct_logs.len());
+    if expected_steps != actual_steps {
+        return Some(outcome(
+            "number of steps",
+            expected_steps.to_string(),
+            actual_steps.to_string(),
+        ));
+    }
+    if expected.failed != actual.failed {
+        return Some(outcome(
+            "failed",
+            expected.failed.to_string(),
+            actual.failed.to_string(),
+        ));
+    }
+    if options.gas && expected.gas != actual.gas {
+        return Some(outcome(
+            "gas used",
+            expected.gas.to_string(),
+            actual.gas.to_string(),
+        ));
+    }
+    if options.return_value
+        && normalize_data(&expected.return_value) != normalize_data(&actual.return_value)
+    {
+        return Some(outcome(
+            "return value",
+            expected.return_value.clone(),
+            actual.return_value.clone(),
+        ));
+    }
+    None
+}
+
+fn compare_step(
+    expected: &StructLog,
+    actual: &StructLog,
+    options: DiffOptions,
+) -> Option<Divergence> {
+    let diverged = |field: &str, expected: String, actual: String| {
+        Some(Divergence {
+            step: None,
+            field: field.to_string(),
+            expected,
+            actual,
+        })
+    };
+    if expected.pc != actual.pc {
+        return diverged("pc", expected.pc.to_string(), actual.pc.to_string());
+    }
+    if normalize_op(&expected.op) != normalize_op(&actual.op) {
+        return diverged("op", expected.op.clone(), actual.op.clone());
+    }
+    if expected.depth != actual.depth {
+        return diverged("depth", expected.depth.to_string(), actual.depth.to_string());
+    }
+    if options.gas && expected.gas != actual.gas {
+        return diverged("gas", expected.gas.to_string(), actual.gas.to_string());
+    }
+    if options.gas_cost && expected.gas_cost != actual.gas_cost {
+        return diverged(
+            "gas cost",
+            expected.gas_cost.to_string(),
+            actual.gas_cost.to_string(),
+        );
+    }
+    if options.stack {
+        if let (Some(expected), Some(actual)) = (&expected.stack, &actual.stack) {
+            if let Some((field, expected, actual)) = compare_words("stack", expected, actual) {
+                return diverged(&field, expected, actual);
+            }
+        }
+    }
+    if options.memory {
+        if let (Some(expected), Some(actual)) = (&expected.memory, &actual.memory) {
+            if let Some((field, expected, actual)) = compare_words("memory", expected, actual) {
+                return diverged(&field, expected, actual);
+            }
+        }
+    }
+    None
+}
+
+/// Compare hex encoded words, which Geth may or may not zero pad and prefix
+///
+/// The stack is compared from the top.
+fn compare_words(
+    name: &str,
+    expected: &[String],
+    actual: &[String],
+) -> Option<(String, String, String)> {
+    if expected.len() != actual.len() {
+        return Some((
+            format!("{} size", name),
+            expected.len().to_string(),
+            actual.len().to_string(),
+        ));
+    }
+    let pairs = expected.iter().zip(actual).enumerate();
+    let pairs: Box<dyn Iterator<Item = _>> = if name == "stack" {
+        Box::new(pairs.rev())
+    } else {
+        Box::new(pairs)
+    };
+    for (index, (expected, actual)) in pairs {
+        if normalize_word(expected) != normalize_word(actual) {
+            return Some((
+                format!("{}[{}]", name, index),
+                expected.clone(),
+                actual.clone(),
+            ));
+        }
+    }
+    None
+}
+
+fn normalize_word(word: &str) -> String {
+    let digits = word.trim_start_matches("0x").trim_start_matches('0');
+    if digits.is_empty() {
+        "0".to_string()
+    } else {
+        digits.to_lowercase()
+    }
+}
+
+fn normalize_data(data: &str) -> String {
+    data.trim_start_matches("0x").to_lowercase()
+}
+
+/// Geth renamed `SHA3` to `KECCAK256`
+fn normalize_op(op: &str) -> &str {
+    match op {
+        "KECCAK256" => "SHA3",
+        op => op,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{
+            types::{rpc::Bytes, Address, RpcTransaction, Transaction},
+            BlockInfo, Empty, Fork, WriteableChainState,
+        },
+        evm::{tracer::StructLogger, Hardfork},
+        prelude::*,
+        replay::replay,
+        test::prelude::assert_eq,
+    };
+
+    /// Transaction with the accounts it runs on and the trace expected of it
+    ///
+    /// The fixtures are written by hand in the format of Geth 1.10, they are
+    /// not recorded from a node.
+    #[derive(Debug, Deserialize)]
+    struct Fixture {
+        hardfork:    String,
+        accounts:    Vec<FixtureAccount>,
+        transaction: FixtureTransaction,
+        trace:       TransactionTrace,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct FixtureAccount {
+        address: Address,
+        code:    Bytes,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct FixtureTransaction {
+        from:  Address,
+        to:    Address,
+        input: Bytes,
+        gas:   u64,
+    }
+
+    /// Replay the transaction of a fixture and compare the traces
+    fn run_fixture(json: &str) -> Option<Divergence> {
+        // The hex types only deserialize from strings of a `Value`
+        let fixture: serde_json::Value = serde_json::from_str(json).unwrap();
+        let fixture: Fixture = serde_json::from_value(fixture).unwrap();
+        let mut chain = Fork::from(Empty);
+        for account in &fixture.accounts {
+            chain.set_code(&U256::from(&account.address), account.code.as_ref());
+        }
+        let transaction = RpcTransaction {
+            transaction: Transaction {
+                gas_limit: fixture.transaction.gas,
+                to: fixture.transaction.to,
+                data: fixture.transaction.input.to_vec(),
+                ..Transaction::default()
+            },
+            from: fixture.transaction.from,
+            ..RpcTransaction::default()
+        };
+        let hardfork: Hardfork = fixture.hardfork.parse().unwrap();
+        let mut tracer = StructLogger::default();
+        let (result, gas_used) = replay(
+            &mut chain,
+            &BlockInfo::default(),
+            &transaction,
+            &hardfork.schedule(),
+            &mut tracer,
+        )
+        .unwrap();
+        let actual = tracer.into_trace(&result, gas_used);
+        compare(&fixture.trace, &actual, DiffOptions::default())
+    }
+
+    #[test]
+    fn test_fixture_arithmetic() {
+        let fixture = include_str!("../../fixtures/trace/arithmetic.json");
+        assert_eq!(run_fixture(fixture), None);
+    }
+
+    #[test]
+    fn test_fixture_storage() {
+        let fixture = include_str!("../../fixtures/trace/storage.json");
+        assert_eq!(run_fixture(fixture), None);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let step = |pc: u64, op: &str, gas: u64, stack: &[&str]| {
+            StructLog {
+                pc,
+                op: op.into(),
+                gas,
+                gas_cost: 3,
+                depth: 1,
+                stack: Some(stack.iter().map(|word| (*word).to_string()).collect()),
+                memory: None,
+            }
+        };
+        let expected = TransactionTrace {
+            struct_logs: vec![
+                step(0, "PUSH1", 100, &[]),
+                step(2, "PUSH1", 97, &["0x1"]),
+                step(4, "ADD", 94, &["0x1", "0x2"]),
+            ],
+            ..TransactionTrace::default()
+        };
+        // Padding and prefix of words do not matter
+        let mut actual = expected.clone();
+        actual.struct_logs[2].stack = Some(vec![
+            format!("{:0>64}", "1"),
+            format!("{:0>64}", "2"),
+        ]);
+        assert_eq!(compare(&expected, &actual, DiffOptions::default()), None);
+
+        actual.struct_logs[2].stack = Some(vec!["1".into(), "3".into()]);
+        actual.struct_logs[2].gas = 95;
+        let divergence = compare(&expected, &actual, DiffOptions::default()).unwrap();
+        assert_eq!(divergence.step, Some(2));
+        assert_eq!(divergence.field, "gas");
+        assert_eq!(
+            divergence.to_string(),
+            "Step 2: gas is 94 in Geth and 95 in sutro"
+        );
+        let options = DiffOptions {
+            gas: false,
+            ..DiffOptions::default()
+        };
+        let divergence = compare(&expected, &actual, options).unwrap();
+        assert_eq!(divergence.field, "stack[1]");
+
+        actual.struct_logs.pop();
+        let options = DiffOptions {
+            gas: false,
+            stack: false,
+            ..DiffOptions::default()
+        };
+        let divergence = compare(&expected, &actual, options).unwrap();
+        assert_eq!(divergence.step, None);
+        assert_eq!(divergence.field, "number of steps");
+    }
+}
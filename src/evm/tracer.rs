@@ -0,0 +1,601 @@
+//! Execution tracing
+
+use crate::{
+    chain::types::{
+        rpc::{Hexable, StructLog, TraceConfig, TransactionTrace},
+        Address,
+    },
+    evm::{CallInfo, CallResult, ExecutionResult, Opcode},
+    prelude::*,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    hash::Hash,
+    io::{self, Write},
+};
+
+/// Hooks called by the interpreter during execution
+pub trait Tracer {
+    /// Called before each instruction is executed
+    fn on_step(&mut self, _step: &StepContext) {}
+
+    /// Called when a call frame is entered, including precompiles
+    ///
+    /// The transaction itself is the frame at depth one.
+    fn on_call(&mut self, _call: &CallInfo, _depth: usize) {}
+
+    /// Called when a call frame returns successfully
+    fn on_return(&mut self, _output: &[u8], _gas_left: usize) {}
+
+    /// Called when a call frame reverts or fails
+    fn on_revert(&mut self, _output: &[u8], _gas_left: usize) {}
+}
+
+/// Does nothing
+impl Tracer for () {}
+
+/// Forwards to the tracer if there is one
+impl<T: Tracer> Tracer for Option<T> {
+    fn on_step(&mut self, step: &StepContext) {
+        if let Some(tracer) = self {
+            tracer.on_step(step);
+        }
+    }
+
+    fn on_call(&mut self, call: &CallInfo, depth: usize) {
+        if let Some(tracer) = self {
+            tracer.on_call(call, depth);
+        }
+    }
+
+    fn on_return(&mut self, output: &[u8], gas_left: usize) {
+        if let Some(tracer) = self {
+            tracer.on_return(output, gas_left);
+        }
+    }
+
+    fn on_revert(&mut self, output: &[u8], gas_left: usize) {
+        if let Some(tracer) = self {
+            tracer.on_revert(output, gas_left);
+        }
+    }
+}
+
+/// Forwards to both tracers
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn on_step(&mut self, step: &StepContext) {
+        self.0.on_step(step);
+        self.1.on_step(step);
+    }
+
+    fn on_call(&mut self, call: &CallInfo, depth: usize) {
+        self.0.on_call(call, depth);
+        self.1.on_call(call, depth);
+    }
+
+    fn on_return(&mut self, output: &[u8], gas_left: usize) {
+        self.0.on_return(output, gas_left);
+        self.1.on_return(output, gas_left);
+    }
+
+    fn on_revert(&mut self, output: &[u8], gas_left: usize) {
+        self.0.on_revert(output, gas_left);
+        self.1.on_revert(output, gas_left);
+    }
+}
+
+/// Interpreter state before executing an instruction
+pub struct StepContext<'a> {
+    pub pc:       usize,
+    pub op:       Opcode,
+    /// Gas remaining before the instruction
+    pub gas:      usize,
+    pub gas_cost: usize,
+    /// Call depth, starting at one for the transaction
+    pub depth:    usize,
+    pub stack:    &'a [U256],
+    pub memory:   &'a [u8],
+}
+
+/// Records every step in Geth's struct log format
+#[derive(Default, Debug)]
+pub struct StructLogger {
+    pub config: TraceConfig,
+    pub logs:   Vec<StructLog>,
+}
+
+impl StructLogger {
+    #[must_use]
+    pub const fn new(config: TraceConfig) -> Self {
+        Self {
+            config,
+            logs: Vec::new(),
+        }
+    }
+
+    /// Result of `debug_traceTransaction` for a transaction that used
+    /// `gas_used` in total
+    #[must_use]
+    pub fn into_trace(self, result: &ExecutionResult, gas_used: u64) -> TransactionTrace {
+        TransactionTrace {
+            struct_logs: self.logs,
+            ..trace_outcome(result, gas_used)
+        }
+    }
+}
+
+impl Tracer for StructLogger {
+    fn on_step(&mut self, step: &StepContext) {
+        self.logs.push(struct_log(&self.config, step));
+    }
+}
+
+/// Passes every step in Geth's struct log format to a callback
+///
+/// Nothing is kept, so long traces can be consumed while the transaction
+/// executes, for example by sending the steps through a channel.
+pub struct StructLogStream<F: FnMut(StructLog)> {
+    config:   TraceConfig,
+    callback: F,
+}
+
+impl<F: FnMut(StructLog)> StructLogStream<F> {
+    pub fn new(config: TraceConfig, callback: F) -> Self {
+        Self { config, callback }
+    }
+}
+
+impl<F: FnMut(StructLog)> Tracer for StructLogStream<F> {
+    fn on_step(&mut self, step: &StepContext) {
+        (self.callback)(struct_log(&self.config, step));
+    }
+}
+
+/// Writes the result of `debug_traceTransaction` as JSON during execution
+///
+/// Steps are written as they happen and the outcome by
+/// [`JsonTraceWriter::finish`], so the trace is never held in memory.
+pub struct JsonTraceWriter<W: Write> {
+    config: TraceConfig,
+    writer: W,
+    steps:  usize,
+    /// First write error, later steps are not written.
+    error:  Option<io::Error>,
+}
+
+impl<W: Write> JsonTraceWriter<W> {
+    pub fn new(config: TraceConfig, mut writer: W) -> io::Result<Self> {
+        writer.write_all(b"{\"structLogs\":[")?;
+        Ok(Self {
+            config,
+            writer,
+            steps: 0,
+            error: None,
+        })
+    }
+
+    /// Complete the trace of a transaction that used `gas_used` in total
+    pub fn finish(mut self, result: &ExecutionResult, gas_used: u64) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let outcome = trace_outcome(result, gas_used);
+        write!(
+            self.writer,
+            "],\"gas\":{},\"failed\":{},\"returnValue\":\"{}\"}}",
+            outcome.gas, outcome.failed, outcome.return_value
+        )?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_step(&mut self, step: &StepContext) -> io::Result<()> {
+        if self.steps > 0 {
+            self.writer.write_all(b",")?;
+        }
+        self.steps += 1;
+        serde_json::to_writer(&mut self.writer, &struct_log(&self.config, step))?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Tracer for JsonTraceWriter<W> {
+    fn on_step(&mut self, step: &StepContext) {
+        if self.error.is_none() {
+            self.error = self.write_step(step).err();
+        }
+    }
+}
+
+/// Trace without struct logs of a transaction that used `gas_used` in total
+#[must_use]
+pub fn trace_outcome(result: &ExecutionResult, gas_used: u64) -> TransactionTrace {
+    let (failed, output) = match &result.result {
+        CallResult::Return(output) => (false, output.as_slice()),
+        CallResult::Revert(output) => (true, output.as_slice()),
+        CallResult::Halt(_) => (true, &[][..]),
+    };
+    TransactionTrace {
+        gas: gas_used,
+        failed,
+        return_value: hex::encode(output),
+        struct_logs: Vec::new(),
+    }
+}
+
+fn struct_log(config: &TraceConfig, step: &StepContext) -> StructLog {
+    let stack = if config.disable_stack {
+        None
+    } else {
+        Some(
+            step.stack
+                .iter()
+                .map(|value| hex::encode(value.to_bytes_be()))
+                .collect(),
+        )
+    };
+    let memory = if config.disable_memory {
+        None
+    } else {
+        Some(step.memory.chunks(32).map(hex::encode).collect())
+    };
+    StructLog {
+        pc: step.pc as u64,
+        op: step.op.mnemonic(),
+        gas: step.gas as u64,
+        gas_cost: step.gas_cost as u64,
+        depth: step.depth as u64,
+        stack,
+        memory,
+    }
+}
+
+/// Gas spent on a part of the execution and the number of executions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasUsage {
+    pub gas:   usize,
+    pub count: usize,
+}
+
+impl GasUsage {
+    fn add(&mut self, gas: usize) {
+        self.gas += gas;
+        self.count += 1;
+    }
+}
+
+/// Accumulates the gas spent per opcode, per basic block and per storage slot
+///
+/// Basic blocks start at the beginning of the code, at every `JUMPDEST` and
+/// after every `JUMPI`. The gas of an instruction excludes the gas forwarded to
+/// calls, which is attributed to the instructions of the callee.
+#[derive(Debug, Default)]
+pub struct GasProfiler {
+    opcodes: HashMap<Opcode, GasUsage>,
+    /// By code address and start of the block
+    blocks:  HashMap<(U256, usize), GasUsage>,
+    /// By account and slot, of `SLOAD` and `SSTORE`
+    slots:   HashMap<(U256, U256), GasUsage>,
+    frames:  Vec<ProfiledFrame>,
+}
+
+#[derive(Debug)]
+struct ProfiledFrame {
+    address:      U256,
+    code_address: U256,
+    block:        usize,
+    /// The next instruction starts a block
+    block_ended:  bool,
+}
+
+impl GasProfiler {
+    /// Opcodes by gas spent, most expensive first
+    #[must_use]
+    pub fn opcodes(&self) -> Vec<(Opcode, GasUsage)> {
+        sorted_by_gas(&self.opcodes, |op| op.mnemonic())
+    }
+
+    /// Basic blocks as `(code address, start)` by gas spent, most expensive
+    /// first
+    pub fn blocks(&self) -> Vec<((U256, usize), GasUsage)> {
+        sorted_by_gas(&self.blocks, Clone::clone)
+    }
+
+    /// Storage slots as `(address, slot)` by gas spent, most expensive first
+    pub fn slots(&self) -> Vec<((U256, U256), GasUsage)> {
+        sorted_by_gas(&self.slots, Clone::clone)
+    }
+}
+
+/// Ties are ordered by key, so the order does not depend on hashing.
+fn sorted_by_gas<K, O>(map: &HashMap<K, GasUsage>, key: impl Fn(&K) -> O) -> Vec<(K, GasUsage)>
+where
+    K: Clone + Eq + Hash,
+    O: Ord,
+{
+    let mut entries = map
+        .iter()
+        .map(|(k, usage)| (k.clone(), *usage))
+        .collect::<Vec<_>>();
+    entries.sort_by_cached_key(|(k, usage)| (Reverse(usage.gas), key(k)));
+    entries
+}
+
+impl Tracer for GasProfiler {
+    fn on_step(&mut self, step: &StepContext) {
+        // The transaction frame is entered before the first step
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if frame.block_ended || step.op == Opcode::JumpDest {
+            frame.block = step.pc;
+        }
+        frame.block_ended = matches!(step.op, Opcode::Jump | Opcode::JumpI);
+
+        // Running out of gas consumes what is left
+        let gas = step.gas_cost.min(step.gas);
+        self.opcodes.entry(step.op).or_default().add(gas);
+        self.blocks
+            .entry((frame.code_address.clone(), frame.block))
+            .or_default()
+            .add(gas);
+        if matches!(step.op, Opcode::SLoad | Opcode::SStore) {
+            if let Some(slot) = step.stack.last() {
+                self.slots
+                    .entry((frame.address.clone(), slot.clone()))
+                    .or_default()
+                    .add(gas);
+            }
+        }
+    }
+
+    fn on_call(&mut self, call: &CallInfo, _depth: usize) {
+        self.frames.push(ProfiledFrame {
+            address:      call.address.clone(),
+            code_address: call.code_address.clone(),
+            block:        0,
+            block_ended:  false,
+        });
+    }
+
+    fn on_return(&mut self, _output: &[u8], _gas_left: usize) {
+        self.frames.pop();
+    }
+
+    fn on_revert(&mut self, _output: &[u8], _gas_left: usize) {
+        self.frames.pop();
+    }
+}
+
+/// Summary of the ten most expensive opcodes, blocks and storage slots
+impl fmt::Display for GasProfiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const TOP: usize = 10;
+        writeln!(f, "Gas by opcode:")?;
+        for (op, usage) in self.opcodes().into_iter().take(TOP) {
+            writeln!(f, "  {:<14} {:>10} gas {:>8}x", op.mnemonic(), usage.gas, usage.count)?;
+        }
+        writeln!(f, "Gas by basic block:")?;
+        for ((address, start), usage) in self.blocks().into_iter().take(TOP) {
+            let location = format!("{}:{}", format_address(&address), start);
+            writeln!(f, "  {:<50} {:>10} gas {:>8}x", location, usage.gas, usage.count)?;
+        }
+        writeln!(f, "Gas by storage slot:")?;
+        for ((address, slot), usage) in self.slots().into_iter().take(TOP) {
+            writeln!(
+                f,
+                "  {} {:<66} {:>10} gas {:>8}x",
+                format_address(&address),
+                slot.to_hex(),
+                usage.gas,
+                usage.count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Program counters executed in a contract
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub pcs:       BTreeSet<usize>,
+    /// Jump destinations reached, a subset of `pcs`
+    pub jumpdests: BTreeSet<usize>,
+}
+
+impl Coverage {
+    /// Instructions of `code` that were not executed
+    #[must_use]
+    pub fn uncovered(&self, code: &[u8]) -> Vec<usize> {
+        instructions(code)
+            .into_iter()
+            .filter(|pc| !self.pcs.contains(pc))
+            .collect()
+    }
+}
+
+/// Records the program counters executed per code address, for bytecode
+/// coverage of test suites
+///
+/// Coverage is keyed by the address the code is loaded from, so delegate calls
+/// count towards the implementation and not the proxy. Init code is keyed by
+/// the address being created.
+#[derive(Debug, Default)]
+pub struct CoverageTracer {
+    coverage: HashMap<U256, Coverage>,
+    frames:   Vec<U256>,
+}
+
+impl CoverageTracer {
+    #[must_use]
+    pub fn coverage(&self, code_address: &U256) -> Option<&Coverage> {
+        self.coverage.get(code_address)
+    }
+
+    /// Code addresses that executed at least one instruction
+    pub fn addresses(&self) -> impl Iterator<Item = &U256> {
+        self.coverage.keys()
+    }
+}
+
+impl Tracer for CoverageTracer {
+    fn on_step(&mut self, step: &StepContext) {
+        let code_address = match self.frames.last() {
+            Some(code_address) => code_address,
+            None => return,
+        };
+        let coverage = self.coverage.entry(code_address.clone()).or_default();
+        coverage.pcs.insert(step.pc);
+        if step.op == Opcode::JumpDest {
+            coverage.jumpdests.insert(step.pc);
+        }
+    }
+
+    fn on_call(&mut self, call: &CallInfo, _depth: usize) {
+        self.frames.push(call.code_address.clone());
+    }
+
+    fn on_return(&mut self, _output: &[u8], _gas_left: usize) {
+        self.frames.pop();
+    }
+
+    fn on_revert(&mut self, _output: &[u8], _gas_left: usize) {
+        self.frames.pop();
+    }
+}
+
+/// Offsets of the instructions in `code`, skipping push data
+///
+/// The length is the total for coverage ratios.
+#[must_use]
+pub fn instructions(code: &[u8]) -> Vec<usize> {
+    let mut result = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        result.push(pc);
+        pc += Opcode::from(code[pc]).encoded_size();
+    }
+    result
+}
+
+fn format_address(address: &U256) -> String {
+    format!("0x{}", hex::encode(Address::from(address).as_slice()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{BlockInfo, Empty, Fork, WriteableChainState},
+        evm::{Evm, Hardfork, TransactionInfo},
+        test::prelude::assert_eq,
+    };
+
+    const CONTRACT: U256 = U256::from_limbs([0x1234, 0, 0, 0]);
+
+    fn execute(code: &[u8], input: &[u8], tracer: &mut dyn Tracer) -> ExecutionResult {
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&CONTRACT, code);
+        let call = CallInfo::builder()
+            .to(&CONTRACT)
+            .input(input)
+            .gas(100_000)
+            .build();
+        Evm::new(Hardfork::London).execute_with_tracer(
+            &mut chain,
+            &call,
+            &BlockInfo::default(),
+            &TransactionInfo::default(),
+            tracer,
+        )
+    }
+
+    fn trace<T: Tracer + Default>(code: &[u8], input: &[u8]) -> T {
+        let mut tracer = T::default();
+        execute(code, input, &mut tracer);
+        tracer
+    }
+
+    fn profile(code: &[u8]) -> GasProfiler {
+        trace(code, &[])
+    }
+
+    #[test]
+    fn test_profile_loop() {
+        // for (i = 10; i != 0; i--) {}
+        // 0: PUSH1 10, 2: JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH1 2 JUMPI, 11: STOP
+        let profiler = profile(&hex!("600a 5b 6001 90 03 80 6002 57 00"));
+        let (op, usage) = profiler.opcodes()[0];
+        assert_eq!(op, Opcode::JumpI);
+        assert_eq!(usage, GasUsage { gas: 100, count: 10 });
+
+        let blocks = profiler.blocks();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0], ((CONTRACT, 2), GasUsage {
+            gas:   10 * (1 + 5 * 3 + 10),
+            count: 10 * 7,
+        }));
+        assert!(profiler.to_string().contains("JUMPI"));
+    }
+
+    #[test]
+    fn test_profile_storage() {
+        // SSTORE(1, 1) SLOAD(1) SLOAD(2)
+        let profiler = profile(&hex!("600160015560015460025400"));
+        let slots = profiler.slots();
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].0, (CONTRACT, U256::one()));
+        assert_eq!(slots[0].1.count, 2);
+        assert_eq!(slots[1].0, (CONTRACT, U256::from(2)));
+    }
+
+    #[test]
+    fn test_coverage_branch() {
+        // if (CALLDATASIZE) { RETURN(0, 0) } else { STOP }
+        // 0: CALLDATASIZE PUSH1 7 JUMPI, 4: STOP, 5: INVALID INVALID,
+        // 7: JUMPDEST PUSH1 0 DUP1 RETURN
+        let code = hex!("36 6007 57 00 fefe 5b 6000 80 f3");
+        assert_eq!(instructions(&code), vec![0, 1, 3, 4, 5, 6, 7, 8, 10, 11]);
+
+        let tracer: CoverageTracer = trace(&code, &[]);
+        let coverage = tracer.coverage(&CONTRACT).unwrap();
+        assert_eq!(coverage.pcs, [0, 1, 3, 4].iter().copied().collect());
+        assert!(coverage.jumpdests.is_empty());
+        assert_eq!(coverage.uncovered(&code), vec![5, 6, 7, 8, 10, 11]);
+
+        let tracer: CoverageTracer = trace(&code, &[1]);
+        let coverage = tracer.coverage(&CONTRACT).unwrap();
+        assert_eq!(coverage.pcs, [0, 1, 3, 7, 8, 10, 11].iter().copied().collect());
+        assert_eq!(coverage.jumpdests, std::iter::once(7).collect());
+        assert!(!coverage.pcs.contains(&4));
+    }
+
+    #[test]
+    fn test_stream_struct_logs() {
+        // MSTORE(0, 1) RETURN(0, 32)
+        let code = hex!("6001600052 60206000f3");
+        let mut logger = StructLogger::default();
+        let result = execute(&code, &[], &mut logger);
+        let expected = logger.into_trace(&result, 21_000 + result.gas_used() as u64);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut stream = StructLogStream::new(TraceConfig::default(), move |log| {
+            sender.send(log).unwrap();
+        });
+        execute(&code, &[], &mut stream);
+        drop(stream);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), expected.struct_logs);
+
+        let mut writer = JsonTraceWriter::new(TraceConfig::default(), Vec::new()).unwrap();
+        let result = execute(&code, &[], &mut writer);
+        let json = writer
+            .finish(&result, 21_000 + result.gas_used() as u64)
+            .unwrap();
+        let trace: TransactionTrace = serde_json::from_slice(&json).unwrap();
+        assert_eq!(trace, expected);
+        assert_eq!(trace.struct_logs.len(), 6);
+    }
+}
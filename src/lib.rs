@@ -0,0 +1,431 @@
+//! Ethereum Virtual Machine with a JSON-RPC node for testing on forks
+//!
+//! The binary serves the node. As a library the interpreter executes calls
+//! against any [`ChainState`], for example a [`Fork`] of an [`Empty`] chain:
+//!
+//! ```
+//! use sutro::{BlockInfo, CallInfo, CallResult, Empty, Evm, Fork, Hardfork, TransactionInfo,
+//!     WriteableChainState, U256};
+//!
+//! let address = U256::from(0x1234);
+//! let mut chain = Fork::from(Empty);
+//! // MSTORE(0, 42) RETURN(0, 32)
+//! chain.set_code(&address, &[0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+//! let call = CallInfo::builder().to(&address).gas(100_000).build();
+//! let evm = Evm::new(Hardfork::London);
+//! let result = evm.execute(&mut chain, &call, &BlockInfo::default(), &TransactionInfo::default());
+//! assert!(matches!(result.result, CallResult::Return(output) if output[31] == 42));
+//! ```
+
+#![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
+#![allow(clippy::multiple_crate_versions)]
+// TODO
+#![allow(clippy::missing_errors_doc)]
+// TODO
+#![allow(dead_code)]
+
+mod chain;
+mod evm;
+mod fetch;
+mod replay;
+mod rpc;
+mod serde;
+mod server;
+mod state_test;
+mod utils;
+
+pub mod prelude {
+    pub use crate::{
+        require,
+        utils::{RlpHash as _, TrieHash as _},
+    };
+    pub use anyhow::{anyhow, Context as _, Result as AnyResult};
+    pub use futures::prelude::*;
+    pub use hex_literal::hex;
+    pub use itertools::Itertools as _;
+    pub use rand::prelude::*;
+    pub use rayon::prelude::*;
+    pub use serde::{Deserialize, Serialize};
+    pub use smallvec::{smallvec, SmallVec};
+    pub use thiserror::Error;
+    pub use tokio::io::{
+        AsyncBufRead, AsyncBufReadExt as _, AsyncRead, AsyncReadExt as _, AsyncSeekExt as _,
+        AsyncWrite, AsyncWriteExt as _,
+    };
+    pub use tracing::{debug, error, info, trace, warn};
+    pub use zkp_macros_decl::u256h;
+    pub use zkp_u256::{Binary as _, One as _, Pow as _, Zero as _, U256};
+}
+
+pub use crate::{
+    chain::{
+        types::{AccessListItem, Address},
+        BlockInfo, ChainState, Empty, Fork, MemoryChain, WriteableChainState,
+    },
+    evm::{
+        abi, execute, tracer, CallBuilder, CallInfo, CallResult, Evm, ExecutionResult, GasSchedule,
+        HaltReason, Hardfork, IntoAccount, Limits, Log, TransactionInfo, DEFAULT_GAS,
+    },
+};
+pub use zkp_u256::U256;
+
+use crate::{chain::types::rpc::Hexable as _, prelude::*};
+use once_cell::sync::OnceCell;
+use rand_pcg::Mcg128Xsl64;
+use std::{
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+use structopt::StructOpt;
+use tracing_subscriber::FmtSubscriber;
+
+#[macro_export]
+macro_rules! require {
+    ($condition:expr, $err:expr) => {
+        if !$condition {
+            return Err($err.into());
+        }
+    };
+}
+
+#[derive(Debug, PartialEq, StructOpt)]
+struct Options {
+    /// Verbose mode (-v, -vv, -vvv, etc.)
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: usize,
+
+    /// Number of compute threads to use (defaults to number of cores)
+    #[structopt(long)]
+    threads: Option<usize>,
+
+    /// Random seed for deterministic random number generation.
+    /// If not specified a seed is periodically generated from OS entropy.
+    #[structopt(long, parse(try_from_str = parse_hex_u64))]
+    seed: Option<u64>,
+
+    /// JSON-RPC url of the node to fork from, an empty chain is used if not
+    /// given
+    #[structopt(long)]
+    fork_url: Option<String>,
+
+    /// Block number to fork from (defaults to the latest block)
+    #[structopt(long)]
+    block: Option<u64>,
+
+    /// Directory to persist reads from the forked node in
+    #[structopt(long, parse(from_os_str), default_value = ".sutro-cache")]
+    cache_dir: PathBuf,
+
+    /// Remove the persisted reads of the forked block before forking
+    #[structopt(long)]
+    clear_cache: bool,
+
+    /// Hardfork to execute with (e.g. `istanbul`), detected from the block
+    /// number if not given
+    #[structopt(long)]
+    hardfork: Option<Hardfork>,
+
+    /// Number of accounts to create and fund with 10000 ether (defaults to
+    /// 10)
+    #[structopt(long)]
+    accounts: Option<usize>,
+
+    /// Mnemonic to derive the accounts from (defaults to the one of Hardhat
+    /// and Anvil)
+    #[structopt(long)]
+    mnemonic: Option<String>,
+
+    /// Defaults to `serve`
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, PartialEq, StructOpt)]
+enum Command {
+    /// Run an Ethereum JSON-RPC server
+    Serve,
+
+    /// Replay a mined transaction on the state before its block, requires
+    /// `--fork-url`
+    RunTx {
+        /// Hash of the transaction
+        #[structopt(parse(try_from_str = U256::from_hex))]
+        hash: U256,
+
+        /// Fetch the storage the transaction is predicted to read
+        /// concurrently before executing it
+        #[structopt(long)]
+        prefetch: bool,
+
+        /// Print the gas spent per opcode, basic block and storage slot
+        #[structopt(long)]
+        profile: bool,
+
+        /// Compare the execution to a `debug_traceTransaction` result of Geth
+        /// and report the first step that differs
+        #[structopt(long, parse(from_os_str))]
+        geth_trace: Option<PathBuf>,
+
+        /// Fail if the transaction executes an opcode its hardfork does not
+        /// have, instead of treating it as invalid
+        #[structopt(long)]
+        strict: bool,
+
+        /// Look up the signatures of unknown custom errors in the 4byte
+        /// directory
+        #[structopt(long)]
+        resolve_selectors: bool,
+    },
+
+    /// Run Ethereum state tests from JSON files
+    StateTest {
+        /// Files in the format of `ethereum/tests` `GeneralStateTests`
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Fetch a chain
+    Fetch {
+        /// JSON-RPC url to fetch from
+        #[structopt(long, default_value = "http://localhost:8545")]
+        node: String,
+
+        /// File to store blocks in
+        #[structopt(long, default_value = "blocks.rlp")]
+        file: PathBuf,
+    },
+}
+
+fn parse_hex_u64(src: &str) -> Result<u64, std::num::ParseIntError> {
+    u64::from_str_radix(src, 16)
+}
+
+static RNG: OnceCell<Mutex<Mcg128Xsl64>> = OnceCell::new();
+
+/// Random number generator seeded by [`run`]
+///
+/// # Panics
+///
+/// Panics if called before [`run`] seeded it.
+pub fn rng() -> MutexGuard<'static, Mcg128Xsl64> {
+    let mutex = RNG.get().expect("RNG is seeded by run()");
+    mutex.lock().expect("RNG mutex poisoned")
+}
+
+/// Random value from [`rng`]
+#[must_use]
+pub fn random<T>() -> T
+where
+    rand::distributions::Standard: rand::distributions::Distribution<T>,
+{
+    rng().gen()
+}
+
+/// Run the command line interface, used by the binary
+///
+/// # Panics
+///
+/// Panics when called more than once, the random number generator is only
+/// seeded once.
+#[allow(clippy::cognitive_complexity)]
+pub fn run() -> AnyResult<()> {
+    // Parse CLI and handle help and version (which will stop the application).
+    #[rustfmt::skip]
+    let version = format!("\
+        {version} {commit} ({commit_date})\n\
+        {target} ({build_date})\n\
+        {author}\n\
+        {homepage}\n\
+        {description}",
+        version     = env!("CARGO_PKG_VERSION"),
+        commit      = &env!("COMMIT_SHA")[..8],
+        commit_date = env!("COMMIT_DATE"),
+        author      = env!("CARGO_PKG_AUTHORS"),
+        description = env!("CARGO_PKG_DESCRIPTION"),
+        homepage    = env!("CARGO_PKG_HOMEPAGE"),
+        target      = env!("TARGET"),
+        build_date  = env!("BUILD_DATE"),
+    );
+    let matches = Options::clap().long_version(version.as_str()).get_matches();
+    let options = Options::from_clap(&matches);
+
+    // Initialize log output (prepend CLI verbosity to RUST_LOG)
+    let log_cli = match options.verbose {
+        0 => "info",
+        1 => "sutro=debug",
+        2 => "sutro=trace",
+        3 => "sutro=trace,debug,hyper=info,tokio_reactor=info",
+        4 => "sutro=trace,debug",
+        _ => "trace",
+    };
+    let log_filter = std::env::var("RUST_LOG").map_or_else(
+        |_| log_cli.to_string(),
+        |log_env| format!("{},{}", log_cli, log_env),
+    );
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(log_filter)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)
+        .context("setting default log subscriber")?;
+    tracing_log::LogTracer::init().context("adding log compatibility layer")?;
+
+    // Log version information
+    info!(
+        "{name} {version} {commit}",
+        name = env!("CARGO_CRATE_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        commit = &env!("COMMIT_SHA")[..8],
+    );
+
+    // Seed the random number generator
+    let rng_seed = options
+        .seed
+        .unwrap_or_else(|| rand::rngs::OsRng.next_u64());
+    info!("Using random seed {:16x}", rng_seed);
+    let rng = Mcg128Xsl64::seed_from_u64(rng_seed);
+    RNG.set(Mutex::new(rng)).expect("RNG already set.");
+
+    // Configure Rayon thread pool
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("Failed to build thread pool.")?;
+    }
+    info!(
+        "Using {} compute threads on {} cores",
+        rayon::current_num_threads(),
+        num_cpus::get()
+    );
+
+    // Launch Tokio runtime
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Error creating Tokio runtime")?
+        .block_on(server::async_main(options))
+        .context("Error in main thread")?;
+
+    // Terminate successfully
+    info!("program terminating normally");
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    pub mod prelude {
+        pub use pretty_assertions::{assert_eq, assert_ne};
+        pub use proptest::prelude::*;
+        pub use tracing_test::traced_test;
+    }
+
+    use super::*;
+    use crate::test::prelude::{assert_eq, *};
+
+    #[test]
+    fn parse_args() {
+        let cmd = "hello --threads 5 -vvv --seed d5c7b134723a63bf -v";
+        let options = Options::from_iter_safe(cmd.split(' ')).unwrap();
+        assert_eq!(options, Options {
+            verbose:     4,
+            seed:        Some(0xd5c7_b134_723a_63bf),
+            threads:     Some(5),
+            fork_url:    None,
+            block:       None,
+            cache_dir:   PathBuf::from(".sutro-cache"),
+            clear_cache: false,
+            hardfork:    None,
+            accounts:    None,
+            mnemonic:    None,
+            command:     None,
+        });
+    }
+
+    #[test]
+    fn parse_run_tx() {
+        let cmd = "sutro --fork-url http://localhost:8545 --block 12000000 --hardfork berlin \
+                   run-tx 0x1234 --prefetch --profile --geth-trace trace.json --strict \
+                   --resolve-selectors";
+        let options = Options::from_iter_safe(cmd.split_whitespace()).unwrap();
+        assert_eq!(options.fork_url.as_deref(), Some("http://localhost:8545"));
+        assert_eq!(options.block, Some(12_000_000));
+        assert_eq!(options.hardfork, Some(Hardfork::Berlin));
+        assert_eq!(options.command, Some(Command::RunTx {
+            hash:              U256::from(0x1234),
+            prefetch:          true,
+            profile:           true,
+            geth_trace:        Some(PathBuf::from("trace.json")),
+            strict:            true,
+            resolve_selectors: true,
+        }));
+    }
+
+    #[test]
+    fn parse_state_test() {
+        let options =
+            Options::from_iter_safe(&["sutro", "state-test", "a.json", "b.json"]).unwrap();
+        assert_eq!(options.command, Some(Command::StateTest {
+            files: vec![PathBuf::from("a.json"), PathBuf::from("b.json")],
+        }));
+        assert!(Options::from_iter_safe(&["sutro", "state-test"]).is_err());
+    }
+
+    #[test]
+    fn parse_cache_dir() {
+        let cmd = "sutro --fork-url http://localhost:8545 --cache-dir /tmp/cache --clear-cache";
+        let options = Options::from_iter_safe(cmd.split(' ')).unwrap();
+        assert_eq!(options.cache_dir, PathBuf::from("/tmp/cache"));
+        assert!(options.clear_cache);
+    }
+
+    #[test]
+    fn parse_accounts() {
+        let options =
+            Options::from_iter_safe(&["sutro", "--accounts", "3", "--mnemonic", "a b c"]).unwrap();
+        assert_eq!(options.accounts, Some(3));
+        assert_eq!(options.mnemonic.as_deref(), Some("a b c"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn test_with_log_output() {
+        error!("logged on the error level");
+        assert!(logs_contain("logged on the error level"));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn async_test_with_log() {
+        // Local log
+        info!("This is being logged on the info level");
+
+        // Log from a spawned task (which runs in a separate thread)
+        tokio::spawn(async {
+            warn!("This is being logged on the warn level from a spawned task");
+        })
+        .await
+        .unwrap();
+
+        // Ensure that `logs_contain` works as intended
+        assert!(logs_contain("logged on the info level"));
+        assert!(logs_contain("logged on the warn level"));
+        assert!(!logs_contain("logged on the error level"));
+    }
+}
+
+#[cfg(feature = "bench")]
+pub mod bench {
+    pub mod prelude {
+        pub use criterion::{black_box, Criterion};
+        pub use futures::executor::block_on;
+    }
+
+    use crate::bench::prelude::*;
+
+    #[cfg(feature = "bench")]
+    pub fn main(criterion: &mut Criterion) {
+        crate::chain::prefetch::bench::group(criterion);
+        crate::evm::interpreter::bench::group(criterion);
+        crate::evm::jit::bench::group(criterion);
+    }
+}
@@ -0,0 +1,449 @@
+//! Replay mined transactions on a fork of the chain
+
+use crate::{
+    chain::{
+        self,
+        types::{
+            rpc::{BlockNumber, TransactionStatus, TransactionTrace},
+            Address, RpcTransaction,
+        },
+        BlockInfo, DiskCache, RpcChain, WriteableChainState,
+    },
+    evm::{
+        interpreter::{create_address, evaluate_with_tracer},
+        intrinsic_gas,
+        signatures::SignatureResolver,
+        trace_diff::{self, DiffOptions},
+        tracer::{GasProfiler, StructLogger, Tracer},
+        CallInfo, CallResult, ExecutionResult, GasSchedule, Hardfork, RevertReason,
+        TransactionInfo,
+    },
+    prelude::*,
+    rpc,
+};
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// Options of [`run_tx`]
+#[derive(Clone, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ReplayOptions {
+    /// Defaults to the hardfork of the block
+    pub hardfork:          Option<Hardfork>,
+    /// Remove the persisted reads of the parent block first
+    pub clear_cache:       bool,
+    /// Make the reads that can be predicted concurrently first, see
+    /// [`chain::prefetch`]
+    pub prefetch:          bool,
+    /// Print the gas spent per opcode, basic block and storage slot, see
+    /// [`GasProfiler`]
+    pub profile:           bool,
+    /// Compare to a `debug_traceTransaction` result of Geth, see
+    /// [`trace_diff`]
+    pub geth_trace:        Option<PathBuf>,
+    /// Fail on opcodes the hardfork does not have instead of treating them
+    /// as invalid, see [`check_opcodes`]
+    pub strict:            bool,
+    /// Look up unknown custom error selectors online, see
+    /// [`SignatureResolver`]
+    pub resolve_selectors: bool,
+}
+
+/// Fetch a transaction and execute it on the state before its block
+///
+/// Transactions before it in the same block are not replayed, so the outcome
+/// can differ when it depends on them. The result is compared to the receipt.
+#[allow(clippy::too_many_lines)]
+pub async fn run_tx(
+    url: &str,
+    cache_dir: &Path,
+    hash: U256,
+    options: &ReplayOptions,
+) -> AnyResult<()> {
+    let geth_trace = match &options.geth_trace {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading {}", path.display()))?;
+            let mut value: serde_json::Value = serde_json::from_str(&json)
+                .with_context(|| format!("Parsing {}", path.display()))?;
+            // Accept the whole JSON-RPC response too
+            if let Some(result) = value.get_mut("result") {
+                value = result.take();
+            }
+            Some(
+                serde_json::from_value::<TransactionTrace>(value)
+                    .with_context(|| format!("Parsing {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+    let client = rpc::client(url).await?;
+    let transaction = client
+        .get_transaction_by_hash(hash.clone())
+        .await
+        .map_err(|err| anyhow!("Error: {}", err))
+        .context("Fetching transaction")?
+        .ok_or_else(|| anyhow!("Transaction not found"))?;
+    let receipt = client
+        .get_transaction_receipt(hash)
+        .await
+        .map_err(|err| anyhow!("Error: {}", err))
+        .context("Fetching transaction receipt")?
+        .ok_or_else(|| anyhow!("Transaction is not mined"))?;
+    let block = client
+        .get_block_by_number(BlockNumber::Number(transaction.block_number), true)
+        .await
+        .map_err(|err| anyhow!("Error: {}", err))
+        .context("Fetching block of transaction")?
+        .ok_or_else(|| anyhow!("Block of transaction not found"))?;
+    let chain_id = client
+        .chain_id()
+        .await
+        .map_err(|err| anyhow!("Error: {}", err))
+        .context("Fetching chain id")?
+        .into_inner();
+    let parent_hash = block.header.parent_hash.clone();
+    let block = BlockInfo {
+        chain_id,
+        ..BlockInfo::from(&block)
+    };
+    let hardfork = options
+        .hardfork
+        .unwrap_or_else(|| Hardfork::detect(chain_id, block.number));
+    info!("Replaying in block {} with hardfork {:?}", block.number, hardfork);
+
+    // Fork first, so clearing the cache does not discard prefetched reads
+    let parent = transaction.block_number.saturating_sub(1);
+    let mut chain = crate::chain::fork(url, cache_dir, Some(parent), options.clear_cache)
+        .await
+        .context("Forking chain")?;
+    // Contract creations execute their input, there is no code to predict from
+    if options.prefetch && transaction.transaction.to != Address::default() {
+        let start = Instant::now();
+        let cache = DiskCache::new(
+            RpcChain::new(client, parent),
+            cache_dir,
+            &parent_hash,
+        );
+        let to = U256::from(&transaction.transaction.to);
+        // Reads that were not prefetched are made during execution
+        match chain::prefetch::prefetch(&cache, &to, &transaction.access_list).await {
+            Ok(slots) => info!("Prefetched {} storage slots in {:?}", slots, start.elapsed()),
+            Err(err) => debug!("Prefetching failed: {:#}", err),
+        }
+    }
+
+    // Reading state may block on the node
+    let start = Instant::now();
+    let mut tracer = (
+        if options.profile {
+            Some(GasProfiler::default())
+        } else {
+            None
+        },
+        geth_trace.as_ref().map(|_| StructLogger::default()),
+    );
+    let (result, gas_used, tracer) = tokio::task::spawn_blocking(move || {
+        let (result, gas_used) = replay(
+            &mut chain,
+            &block,
+            &transaction,
+            &hardfork.schedule(),
+            &mut tracer,
+        )?;
+        AnyResult::<_>::Ok((result, gas_used, tracer))
+    })
+    .await??;
+    info!("Replayed in {:?}", start.elapsed());
+    check_opcodes(&result, hardfork, options.strict)?;
+    match &result.result {
+        CallResult::Return(output) => println!("Success 0x{}", hex::encode(output)),
+        CallResult::Revert(output) => {
+            let reason = RevertReason::decode(output);
+            let reason = if options.resolve_selectors {
+                SignatureResolver::new(true).describe(&reason).await
+            } else {
+                reason.to_string()
+            };
+            println!("Reverted: {}", reason);
+        }
+        CallResult::Halt(reason) => println!("Halted: {}", reason),
+    }
+    println!("Gas used {}", gas_used);
+    let (profiler, logger) = tracer;
+    if let Some(profiler) = profiler {
+        print!("{}", profiler);
+    }
+    if let (Some(expected), Some(logger)) = (geth_trace, logger) {
+        let actual = logger.into_trace(&result, gas_used);
+        match trace_diff::compare(&expected, &actual, DiffOptions::default()) {
+            Some(divergence) => println!("Differs from Geth: {}", divergence),
+            None => println!("Matches the trace of Geth"),
+        }
+    }
+
+    // Receipts from before Byzantium have no status
+    let success = matches!(result.result, CallResult::Return(_));
+    if let Some(status) = receipt.status {
+        if (status == TransactionStatus::Success) != success {
+            warn!("Outcome differs from the mined transaction");
+        }
+    }
+    let mined_gas_used = receipt.gas_used.into_inner();
+    if mined_gas_used != gas_used {
+        warn!(
+            "Gas used differs from the mined transaction, which used {}",
+            mined_gas_used
+        );
+    }
+    Ok(())
+}
+
+/// Execute a mined transaction, including the nonce increment, value
+/// transfer and fees, and return the result with the total gas used.
+///
+/// All gas is bought upfront at the gas price, the unused gas is refunded
+/// and the priority fee above the base fee is paid to the coinbase. The
+/// base fee is burned.
+///
+/// A `to` of zero creates a contract, like it does in the node's JSON. The
+/// access list of typed transactions is charged for and pre-warmed.
+pub fn replay(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    transaction: &RpcTransaction,
+    schedule: &GasSchedule,
+    tracer: &mut dyn Tracer,
+) -> AnyResult<(ExecutionResult, u64)> {
+    let sender = U256::from(&transaction.from);
+    let mined = &transaction.transaction;
+    let nonce = chain.nonce(&sender);
+    let (address, input, init_code) = if mined.to == Address::default() {
+        let address = create_address(&sender, nonce);
+        (address, Vec::new(), Some(mined.data.clone()))
+    } else {
+        (U256::from(&mined.to), mined.data.clone(), None)
+    };
+    let mut call = CallInfo {
+        sender: sender.clone(),
+        address: address.clone(),
+        code_address: address.clone(),
+        call_value: mined.value.clone(),
+        initial_gas: 0,
+        input,
+        is_static: false,
+        init_code,
+    };
+    let mut info = TransactionInfo {
+        access_list: transaction.access_list.clone(),
+        ..TransactionInfo::legacy(sender.clone(), U256::from(mined.gas_price))
+    };
+    let intrinsic = intrinsic_gas(&call, &info.access_list, schedule);
+    require!(
+        mined.gas_limit >= intrinsic,
+        anyhow!("Intrinsic gas exceeds gas limit")
+    );
+    call.initial_gas = usize::try_from(mined.gas_limit - intrinsic).unwrap_or(usize::MAX);
+    info.intrinsic_gas = intrinsic;
+    let gas_price = U256::from(mined.gas_price);
+    require!(
+        gas_price >= block.base_fee,
+        anyhow!("Gas price is below the base fee")
+    );
+    let upfront = U256::from(mined.gas_limit) * &gas_price;
+    let balance = chain.balance(&sender);
+    require!(
+        balance >= upfront.clone() + &mined.value,
+        anyhow!("Insufficient funds for gas and value")
+    );
+
+    chain.set_nonce(&sender, nonce + 1);
+    chain.set_balance(&sender, &(balance - upfront));
+    let snapshot = chain.snapshot();
+    let balance = chain.balance(&sender);
+    chain.set_balance(&sender, &(balance - &mined.value));
+    let balance = chain.balance(&address);
+    chain.set_balance(&address, &(balance + &mined.value));
+    let result = evaluate_with_tracer(chain, block, &info, &call, schedule, tracer);
+    if !matches!(result.result, CallResult::Return(_)) {
+        chain.revert_to(snapshot);
+    }
+    let gas_used = result.gas_used() as u64;
+
+    let refund = U256::from(mined.gas_limit - gas_used) * &gas_price;
+    let balance = chain.balance(&sender);
+    chain.set_balance(&sender, &(balance + refund));
+    let tip = U256::from(gas_used) * (gas_price - &block.base_fee);
+    let balance = chain.balance(&block.coinbase);
+    chain.set_balance(&block.coinbase, &(balance + tip));
+    Ok((result, gas_used))
+}
+
+/// Report opcodes that the hardfork does not have yet
+///
+/// They halt like INVALID, which is faithful to the chain at the time but
+/// usually means the hardfork is wrong. In `strict` mode this is an error,
+/// otherwise a warning.
+fn check_opcodes(result: &ExecutionResult, hardfork: Hardfork, strict: bool) -> AnyResult<()> {
+    if let Some(op) = result.unavailable_opcode {
+        let message = format!(
+            "{} was executed, but is not available in {:?}",
+            op.mnemonic(),
+            hardfork
+        );
+        require!(!strict, anyhow!(message));
+        warn!("{}, treating it as invalid", message);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{types::Transaction, ChainState, Empty, Fork},
+        evm::{HaltReason, Opcode},
+        test::prelude::assert_eq,
+    };
+
+    fn transaction(to: Address, data: &[u8]) -> RpcTransaction {
+        RpcTransaction {
+            transaction: Transaction {
+                gas_limit: 100_000,
+                to,
+                data: data.to_vec(),
+                ..Transaction::default()
+            },
+            from: Address::from(hex!("00000000000000000000000000000000000000aa")),
+            ..RpcTransaction::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_call() {
+        // REVERT(0, 0)
+        let to = Address::from(hex!("00000000000000000000000000000000000000bb"));
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(&to), &hex!("60006000fd"));
+        let (result, gas_used) = replay(
+            &mut chain,
+            &BlockInfo::default(),
+            &transaction(to, &hex!("01")),
+            &GasSchedule::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert!(matches!(result.result, CallResult::Revert(_)));
+        assert_eq!(gas_used, 21000 + 16 + 3 + 3);
+        // The nonce is incremented even though the call failed
+        assert_eq!(chain.nonce(&U256::from(0xaa)), 1);
+    }
+
+    #[test]
+    fn test_replay_create() {
+        // Init code returning the code 0x00
+        let mut chain = Fork::from(Empty);
+        let (result, _) = replay(
+            &mut chain,
+            &BlockInfo::default(),
+            &transaction(Address::default(), &hex!("6000 6000 53 6001 6000 f3")),
+            &GasSchedule::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert!(matches!(result.result, CallResult::Return(_)));
+        let address = create_address(&U256::from(0xaa), 0);
+        assert_eq!(chain.code(&address), vec![0x00]);
+    }
+
+    #[test]
+    fn test_replay_fees() {
+        let sender = U256::from(0xaa);
+        let to = Address::from(hex!("00000000000000000000000000000000000000bb"));
+        let block = BlockInfo {
+            base_fee: U256::from(7),
+            coinbase: U256::from(0xcc),
+            ..BlockInfo::default()
+        };
+        let mut transaction = transaction(to.clone(), &[]);
+        transaction.transaction.gas_price = 10;
+        transaction.transaction.value = U256::from(5);
+
+        // The balance must cover all the gas, not only the gas used
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&sender, &U256::from(100_000 * 10 + 4));
+        let error = replay(
+            &mut chain,
+            &block,
+            &transaction,
+            &GasSchedule::default(),
+            &mut (),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Insufficient funds for gas and value");
+
+        chain.set_balance(&sender, &U256::from(100_000 * 10 + 5));
+        let (_, gas_used) = replay(
+            &mut chain,
+            &block,
+            &transaction,
+            &GasSchedule::default(),
+            &mut (),
+        )
+        .unwrap();
+        assert_eq!(gas_used, 21000);
+        // The unused gas is refunded and the base fee is burned
+        assert_eq!(chain.balance(&sender), U256::from((100_000 - 21000) * 10));
+        assert_eq!(chain.balance(&U256::from(&to)), U256::from(5));
+        assert_eq!(chain.balance(&block.coinbase), U256::from(21000 * 3));
+    }
+
+    #[test]
+    fn test_replay_unavailable_opcode() {
+        // MSTORE(0, CHAINID) RETURN(0, 32)
+        let to = Address::from(hex!("00000000000000000000000000000000000000bb"));
+        let replay_in = |number: u64| {
+            let mut chain = Fork::from(Empty);
+            chain.set_code(&U256::from(&to), &hex!("46 600052 60206000f3"));
+            let block = BlockInfo {
+                chain_id: 1,
+                number,
+                ..BlockInfo::default()
+            };
+            let hardfork = Hardfork::detect(block.chain_id, block.number);
+            let (result, _) = replay(
+                &mut chain,
+                &block,
+                &transaction(to.clone(), &[]),
+                &hardfork.schedule(),
+                &mut (),
+            )
+            .unwrap();
+            (result, hardfork)
+        };
+
+        // Petersburg does not have CHAINID yet
+        let (result, hardfork) = replay_in(9_000_000);
+        assert_eq!(hardfork, Hardfork::Petersburg);
+        assert!(matches!(
+            result.result,
+            CallResult::Halt(HaltReason::InvalidOpcode)
+        ));
+        assert_eq!(result.unavailable_opcode, Some(Opcode::ChainId));
+        assert!(check_opcodes(&result, hardfork, false).is_ok());
+        let error = check_opcodes(&result, hardfork, true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "CHAINID was executed, but is not available in Petersburg"
+        );
+
+        let (result, hardfork) = replay_in(9_069_000);
+        assert_eq!(hardfork, Hardfork::Istanbul);
+        assert!(matches!(result.result, CallResult::Return(_)));
+        assert_eq!(result.unavailable_opcode, None);
+        assert!(check_opcodes(&result, hardfork, true).is_ok());
+    }
+}
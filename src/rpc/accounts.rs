@@ -0,0 +1,236 @@
+//! Development accounts derived from a mnemonic
+//!
+//! Uses the seed of BIP-39 and the derivation path `m/44'/60'/0'/0/i` of
+//! BIP-32 and BIP-44, like Ganache, Hardhat and Anvil.
+
+use crate::{chain::types::Address, evm::precompiles::keccak256, prelude::*};
+use secp256k1::{Message, PublicKey, SecretKey};
+use sha2::{Digest, Sha512};
+use std::{convert::TryFrom, fmt};
+
+/// Mnemonic the other development nodes use by default
+pub const DEFAULT_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+/// Number of accounts the other development nodes create by default
+pub const DEFAULT_ACCOUNTS: usize = 10;
+
+/// Balance of every account, 10000 ether
+pub const DEFAULT_BALANCE: U256 = U256::from_limbs([0x19e0_c9ba_b240_0000, 0x21e, 0, 0]);
+
+/// Child indices from this one on are hardened.
+const HARDENED: u32 = 0x8000_0000;
+
+/// Rounds of PBKDF2 to derive the seed from the mnemonic
+const SEED_ROUNDS: usize = 2048;
+
+/// Account the node signs transactions for
+#[derive(Clone)]
+pub struct DevAccount {
+    pub address:    Address,
+    pub secret_key: SecretKey,
+}
+
+impl DevAccount {
+    pub fn new(secret_key: SecretKey) -> Self {
+        let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+        let address = Address::from(&keccak256(&public_key[1..]));
+        Self {
+            address,
+            secret_key,
+        }
+    }
+
+    /// Sign a message prefixed like `eth_sign` and `personal_sign` do, see
+    /// EIP-191
+    ///
+    /// Returns `r`, `s` and `v` with `v` being 27 or 28.
+    pub fn sign_message(&self, message: &[u8]) -> [u8; 65] {
+        let (signature, recovery_id) =
+            secp256k1::sign(&Message::parse(&message_hash(message)), &self.secret_key);
+        let mut result = [0_u8; 65];
+        result[..64].copy_from_slice(&signature.serialize());
+        result[64] = 27 + recovery_id.serialize();
+        result
+    }
+}
+
+/// Does not show the secret key
+#[allow(clippy::missing_fields_in_debug)]
+impl fmt::Debug for DevAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DevAccount")
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+/// Hash of a message with the prefix of signed messages, which makes it
+/// differ from any transaction
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    keccak256(&prefixed).to_bytes_be()
+}
+
+/// Derive the first `count` accounts of the mnemonic
+///
+/// The mnemonic is not checked against the BIP-39 word list.
+pub fn derive_accounts(mnemonic: &str, count: usize) -> AnyResult<Vec<DevAccount>> {
+    let seed = pbkdf2_sha512(mnemonic.as_bytes(), b"mnemonic", SEED_ROUNDS);
+    let master = ExtendedKey::master(&seed)?;
+    let parent = [HARDENED + 44, HARDENED + 60, HARDENED, 0]
+        .iter()
+        .try_fold(master, |key, &index| key.child(index))?;
+    (0..count)
+        .map(|index| {
+            let index = u32::try_from(index).context("Too many accounts")?;
+            Ok(DevAccount::new(parent.child(index)?.secret_key))
+        })
+        .collect()
+}
+
+/// Secret key with chain code, see BIP-32
+struct ExtendedKey {
+    secret_key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn master(seed: &[u8]) -> AnyResult<Self> {
+        Self::from_hash(&hmac_sha512(b"Bitcoin seed", seed), None)
+    }
+
+    fn child(&self, index: u32) -> AnyResult<Self> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key.serialize());
+        } else {
+            let public_key = PublicKey::from_secret_key(&self.secret_key);
+            data.extend_from_slice(&public_key.serialize_compressed());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        Self::from_hash(&hmac_sha512(&self.chain_code, &data), Some(&self.secret_key))
+    }
+
+    /// The left half is the key, added to the parent key if any, the right
+    /// half the chain code.
+    fn from_hash(hash: &[u8; 64], parent: Option<&SecretKey>) -> AnyResult<Self> {
+        let mut secret_key =
+            SecretKey::parse_slice(&hash[..32]).map_err(|err| anyhow!("{:?}", err))?;
+        if let Some(parent) = parent {
+            secret_key
+                .tweak_add_assign(parent)
+                .map_err(|err| anyhow!("{:?}", err))?;
+        }
+        let mut chain_code = [0_u8; 32];
+        chain_code.copy_from_slice(&hash[32..]);
+        Ok(Self {
+            secret_key,
+            chain_code,
+        })
+    }
+}
+
+/// HMAC with SHA-512, see RFC 2104
+fn hmac_sha512(key: &[u8], message: &[u8]) -> [u8; 64] {
+    const BLOCK_SIZE: usize = 128;
+    let mut block = [0_u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..64].copy_from_slice(&Sha512::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+    let inner = Sha512::new().chain(pad(0x36)).chain(message).finalize();
+    let outer = Sha512::new().chain(pad(0x5c)).chain(inner).finalize();
+    let mut result = [0_u8; 64];
+    result.copy_from_slice(&outer);
+    result
+}
+
+/// PBKDF2 with HMAC-SHA-512 and a single block of output, see RFC 8018
+fn pbkdf2_sha512(password: &[u8], salt: &[u8], rounds: usize) -> [u8; 64] {
+    let mut message = salt.to_vec();
+    message.extend_from_slice(&1_u32.to_be_bytes());
+    let mut block = hmac_sha512(password, &message);
+    let mut result = block;
+    for _ in 1..rounds {
+        block = hmac_sha512(password, &block);
+        for (result, byte) in result.iter_mut().zip(block.iter()) {
+            *result ^= byte;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    #[test]
+    fn test_hmac_sha512() {
+        // Test case 2 from RFC 4231
+        assert_eq!(
+            hmac_sha512(b"Jefe", b"what do ya want for nothing?").to_vec(),
+            hex!(
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a
+                 6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+            )
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_seed() {
+        assert_eq!(
+            pbkdf2_sha512(DEFAULT_MNEMONIC.as_bytes(), b"mnemonic", SEED_ROUNDS).to_vec(),
+            hex!(
+                "9dfc3c64c2f8bede1533b6a79f8570e5943e0b8fd1cf77107adf7b72cef42185d564a3aee24cab43
+                 f80e3c4538087d70fc824eabbad596a23c97b6ee8322ccc0"
+            )
+            .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_default_accounts() {
+        let accounts = derive_accounts(DEFAULT_MNEMONIC, 2).unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(
+            accounts[0].address,
+            Address::from(hex!("f39fd6e51aad88f6f4ce6ab8827279cfffb92266"))
+        );
+        assert_eq!(
+            accounts[0].secret_key.serialize(),
+            hex!("ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80")
+        );
+        assert_eq!(
+            accounts[1].address,
+            Address::from(hex!("70997970c51812dc3a010c7d01b50e0d17dc79c8"))
+        );
+    }
+
+    #[test]
+    fn test_sign_message() {
+        let account = &derive_accounts(DEFAULT_MNEMONIC, 1).unwrap()[0];
+        let signature = account.sign_message(b"hello");
+        assert!(signature[64] == 27 || signature[64] == 28);
+        // Recover with the precompile, which takes the hash, `v`, `r` and `s`
+        let mut input = [0_u8; 128];
+        input[..32].copy_from_slice(&message_hash(b"hello"));
+        input[63] = signature[64];
+        input[64..].copy_from_slice(&signature[..64]);
+        let signer = crate::evm::precompiles::ecrecover(&input);
+        assert_eq!(&signer[12..], account.address.as_slice());
+    }
+
+    #[test]
+    fn test_default_balance() {
+        assert_eq!(
+            DEFAULT_BALANCE,
+            U256::from(10_u64.pow(18)) * U256::from(10_000)
+        );
+    }
+}
@@ -1,39 +1,108 @@
-use super::EthereumRpc;
+use super::{DevAccount, EthereumRpc, EthereumRpcClient};
 use crate::{
-    chain::types::{
-        rpc::{
-            AccountRange, BlockNumber, Bytes, CallRequest, GenesisConfig, Hex, Log, LogFilter,
-            StorageRange, StorageSlot, Transaction, TransactionReceipt,
+    chain::{
+        types::{
+            rpc::{
+                AccountProof, AccountRange, BlockNumber, Bytes, CallRequest, GenesisConfig, Hex,
+                HexData, HexMid, Log, LogBlock, LogFilter, StorageRange, StorageSlot, TraceConfig,
+                TransactionReceipt, TransactionStatus, TransactionTrace,
+            },
+            Address, Block, BlockHeader, BlockTransaction, BloomFilter, FullBlock, RpcBlock,
+            RpcTransaction, SignedTransaction, Transaction,
         },
-        Address, Block, FullBlock, RpcTransaction,
+        BlockInfo, ChainState, Fork, Recorder, Rewound, StateSet, WriteableChainState,
+    },
+    evm::{
+        interpreter::{create_address, evaluate},
+        intrinsic_gas,
+        tracer::{trace_outcome, JsonTraceWriter, StructLogger},
+        CallInfo, CallResult, ExecutionResult, GasSchedule, HaltReason, Hardfork, RevertReason,
+        TransactionInfo,
     },
     prelude::*,
+    replay::replay,
+    serde::rlp::to_rlp,
     utils::RlpHash,
 };
-use jsonrpc_core::Result as RpcResult;
-use std::{collections::HashMap, sync::RwLock};
+use futures::executor::block_on;
+use jsonrpc_core::{ErrorCode, Result as RpcResult};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fs::OpenOptions,
+    io::BufWriter,
+    sync::{Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Gas limit of `eth_call` when the request does not specify it.
+///
+/// Matches the default `--rpc.gascap` of Geth.
+const CALL_GAS: u64 = 50_000_000;
 
 #[allow(clippy::module_name_repetitions)]
 pub struct RpcHandler {
     pub client_version: String,
-    pub chain_id:       usize,
+    pub chain_id:       u64,
+    /// Lowest price `eth_gasPrice` suggests, the base fee if that is higher
     pub gas_price:      U256,
     pub genesis:        RwLock<Block>,
     pub header:         RwLock<Block>,
+    pub chain:          Mutex<Box<dyn WriteableChainState + Send>>,
+    /// Receipts of transactions sent to this node, by transaction hash
+    pub receipts:       RwLock<HashMap<U256, TransactionReceipt>>,
+    /// Logs of transactions sent to this node, in the order they were emitted
+    pub logs:           RwLock<Vec<Log>>,
+    /// Transactions sent to this node, in the order they were executed
+    pub transactions:   RwLock<Vec<RpcTransaction>>,
+    /// Values each of `transactions` overwrote, to trace it on the state
+    /// before it
+    pub replaced:       RwLock<Vec<StateSet>>,
+    /// Blocks mined locally before the current one, oldest first
+    pub blocks:         RwLock<Vec<Block>>,
+    /// Number of transactions sent from each account that are not mined yet
+    pub pending:        RwLock<HashMap<U256, usize>>,
+    /// Snapshots taken by `evm_snapshot`, the id is the index plus one.
+    pub snapshots:      Mutex<Vec<Snapshot>>,
+    /// Accounts `eth_sendTransaction` signs for, returned by `eth_accounts`
+    pub accounts:       Vec<DevAccount>,
+    /// Accounts `eth_sendTransaction` can send from without a signature
+    pub impersonated:   RwLock<HashSet<U256>>,
+    /// Fork to execute with, detected from the block number if not set
+    pub hardfork:       Option<Hardfork>,
+    /// Whether every transaction is mined right away, otherwise they are
+    /// queued in the current block until `evm_mine`
+    pub automine:       RwLock<bool>,
+    /// Node the chain is forked from, which has the blocks before the local
+    /// ones
+    pub fork:           Option<EthereumRpcClient>,
+}
+
+/// Node state to restore on `evm_revert`
+pub struct Snapshot {
+    chain:        usize,
+    pending:      HashMap<U256, usize>,
+    receipts:     HashMap<U256, TransactionReceipt>,
+    logs:         usize,
+    header:       Block,
+    blocks:       usize,
+    transactions: usize,
 }
 
 impl RpcHandler {
-    fn return_block(&self, block: Block) -> RpcResult<Option<FullBlock>> {
+    /// Block with the transactions it was imported with, followed by the
+    /// ones executed in it
+    fn full_block(&self, block: Block, base_fee_per_gas: Option<U256>) -> RpcResult<FullBlock> {
         let header = block.header;
         let hash = header.rlp_hash();
         let ommers = block.ommers.iter().map(RlpHash::rlp_hash).collect();
-        let transactions = block
+        let mut transactions = block
             .transactions
             .into_iter()
             .enumerate()
             .map(|(index, transaction)| {
                 let tx_hash = transaction.rlp_hash();
-                let from = crate::chain::types::Address::default(); // TODO
+                let from = Address::default(); // TODO
                 RpcTransaction {
                     transaction,
                     transaction_index: index as u64,
@@ -41,17 +110,379 @@ impl RpcHandler {
                     block_number: header.number,
                     from,
                     hash: tx_hash,
+                    access_list: Vec::new(),
                 }
             })
-            .collect();
-        Ok(Some(FullBlock {
+            .collect::<Vec<_>>();
+        // The hash changes while transactions are added to the block
+        transactions.extend(
+            self.transactions
+                .read()
+                .map_err(internal_error)?
+                .iter()
+                .filter(|transaction| transaction.block_number == header.number)
+                .map(|transaction| {
+                    RpcTransaction {
+                        block_hash: hash.clone(),
+                        ..transaction.clone()
+                    }
+                }),
+        );
+        Ok(FullBlock {
             header,
             hash,
             size: 0,
             total_difficulty: 0,
+            base_fee_per_gas: base_fee_per_gas.map(Hex::from),
             ommers,
             transactions,
-        }))
+        })
+    }
+
+    /// Genesis block, set by `test_setChainParams`
+    fn genesis_block(&self, full: bool) -> RpcResult<RpcBlock<BlockTransaction>> {
+        let genesis = self.genesis.read().map_err(internal_error)?.clone();
+        Ok(select_transactions(self.full_block(genesis, None)?, full))
+    }
+
+    /// Base fee of the blocks mined locally, which only exists from London on
+    fn base_fee_per_gas(&self) -> RpcResult<Option<U256>> {
+        let chain = self.chain.lock().map_err(internal_error)?;
+        let block = self.block_info(&**chain)?;
+        Ok(if self.hardfork(&block) >= Hardfork::London {
+            Some(block.base_fee)
+        } else {
+            None
+        })
+    }
+
+    /// Constants for executing in the current block
+    ///
+    /// The number and timestamp come from the current header, so they follow
+    /// mining and time adjustments.
+    fn block_info(&self, chain: &dyn WriteableChainState) -> RpcResult<BlockInfo> {
+        let header = self.header.read().map_err(internal_error)?;
+        Ok(BlockInfo {
+            number: header.header.number,
+            timestamp: header.header.timestamp,
+            chain_id: self.chain_id,
+            ..chain.block()
+        })
+    }
+
+    /// Fork active at `block`
+    fn hardfork(&self, block: &BlockInfo) -> Hardfork {
+        self.hardfork
+            .unwrap_or_else(|| Hardfork::detect(block.chain_id, block.number))
+    }
+
+    /// Gas costs and opcodes of the fork active at `block`
+    fn schedule(&self, block: &BlockInfo) -> GasSchedule {
+        self.hardfork(block).schedule()
+    }
+
+    /// Gas price for transactions that do not set one, so they pay at least
+    /// the base fee of `block`
+    fn suggested_gas_price(&self, block: &BlockInfo) -> U256 {
+        std::cmp::max(&self.gas_price, &block.base_fee).clone()
+    }
+
+    /// Execute a transaction, store its receipt and return its hash
+    ///
+    /// The gas limit at the maximum fee is deducted up front and the unused
+    /// gas refunded afterwards. State changes are reverted if it fails, but
+    /// the nonce is incremented and the gas paid regardless.
+    #[allow(clippy::too_many_lines)]
+    fn execute_transaction(
+        &self,
+        chain: &mut dyn WriteableChainState,
+        transaction: SignedTransaction,
+    ) -> RpcResult<U256> {
+        let from = U256::from(&transaction.sender);
+        let nonce = chain.nonce(&from);
+        if transaction.nonce != nonce as u64 {
+            return Err(jsonrpc_core::Error::invalid_params(
+                if transaction.nonce < nonce as u64 {
+                    "nonce too low"
+                } else {
+                    "nonce too high"
+                },
+            ));
+        }
+        let block = self.block_info(chain)?;
+        check_fees(&transaction, &block.base_fee)?;
+        let mut info = transaction.info();
+        let (address, input, init_code) = match &transaction.to {
+            Some(to) => (U256::from(to), transaction.data, None),
+            None => (create_address(&from, nonce), Vec::new(), Some(transaction.data)),
+        };
+        let mut call = CallInfo {
+            sender: from.clone(),
+            address: address.clone(),
+            code_address: address.clone(),
+            call_value: transaction.value.clone(),
+            initial_gas: 0,
+            input,
+            is_static: false,
+            init_code,
+        };
+        let schedule = self.schedule(&block);
+        let intrinsic = intrinsic_gas(&call, &info.access_list, &schedule);
+        if transaction.gas_limit < intrinsic {
+            return Err(jsonrpc_core::Error::invalid_params(
+                "intrinsic gas exceeds gas limit",
+            ));
+        }
+        call.initial_gas = usize::try_from(transaction.gas_limit - intrinsic).unwrap_or(usize::MAX);
+        info.intrinsic_gas = intrinsic;
+        let gas_price = info.effective_gas_price(&block.base_fee);
+        let max_gas_cost = U256::from(transaction.gas_limit) * &transaction.max_fee_per_gas;
+        let balance = chain.balance(&from);
+        if balance < max_gas_cost + &transaction.value {
+            return Err(jsonrpc_core::Error::invalid_params(
+                "insufficient funds for gas * price + value",
+            ));
+        }
+        let gas_cost = U256::from(transaction.gas_limit) * &gas_price;
+
+        let mut chain = Recorder::new(chain);
+        chain.set_nonce(&from, nonce + 1);
+        chain.set_balance(&from, &(balance - &gas_cost));
+        let snapshot = chain.snapshot();
+        let balance = chain.balance(&from);
+        chain.set_balance(&from, &(balance - &transaction.value));
+        let balance = chain.balance(&address);
+        chain.set_balance(&address, &(balance + &transaction.value));
+        let result = evaluate(&mut chain, &block, &info, &call, &schedule);
+        let success = matches!(result.result, CallResult::Return(_));
+        if !success {
+            chain.revert_to(snapshot);
+        }
+        let gas_used = result.gas_used() as u64;
+        let balance = chain.balance(&from);
+        chain.set_balance(
+            &from,
+            &(balance + U256::from(transaction.gas_limit - gas_used) * &gas_price),
+        );
+        self.replaced
+            .write()
+            .map_err(internal_error)?
+            .push(chain.into_replaced());
+
+        let hash = transaction.hash;
+        let logs_bloom = logs_bloom(&result.logs);
+        let mut header = self.header.write().map_err(internal_error)?;
+        header.header.logs_bloom.accrue_bloom(&logs_bloom);
+        header.header.gas_used += gas_used;
+        let block_hash = header.header.rlp_hash();
+        let block_number = header.header.number;
+        let mut transactions = self.transactions.write().map_err(internal_error)?;
+        let transaction_index = header.transactions.len()
+            + transactions
+                .iter()
+                .rev()
+                .take_while(|transaction| transaction.block_number == block_number)
+                .count();
+        transactions.push(RpcTransaction {
+            transaction: Transaction {
+                nonce: transaction.nonce,
+                gas_price: saturating_u64(&gas_price),
+                gas_limit: transaction.gas_limit,
+                to: transaction.to.clone().unwrap_or_default(),
+                value: transaction.value.clone(),
+                data: call.init_code.clone().unwrap_or_else(|| call.input.clone()),
+                ..Transaction::default()
+            },
+            block_hash: block_hash.clone(),
+            block_number,
+            transaction_index: transaction_index as u64,
+            from: transaction.sender.clone(),
+            hash: hash.clone(),
+            access_list: info.access_list.clone(),
+        });
+        let mut all_logs = self.logs.write().map_err(internal_error)?;
+        let first_index = all_logs.len();
+        let logs = result
+            .logs
+            .iter()
+            .enumerate()
+            .map(|(index, log)| {
+                Log {
+                    address: Address::from(&log.address),
+                    topics:  log.topics.clone(),
+                    data:    log.data.clone().into(),
+                    removed: false,
+                    block:   Some(LogBlock {
+                        block_hash:        block_hash.clone(),
+                        block_number:      block_number.into(),
+                        transaction_hash:  hash.clone(),
+                        transaction_index: (transaction_index as u64).into(),
+                        log_index:         U256::from(first_index + index),
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+        all_logs.extend(logs.iter().cloned());
+        let receipt = TransactionReceipt {
+            transaction_hash: hash.clone(),
+            transaction_index: (transaction_index as u64).into(),
+            block_hash: Some(block_hash),
+            block_number: Some(block_number.into()),
+            from: transaction.sender,
+            to: transaction.to.clone(),
+            cumulative_gas_used: header.header.gas_used.into(),
+            gas_used: gas_used.into(),
+            contract_address: transaction
+                .to
+                .map_or_else(|| Some(Address::from(&address)), |_| None),
+            logs,
+            logs_bloom,
+            status: Some(if success {
+                TransactionStatus::Success
+            } else {
+                TransactionStatus::Failure
+            }),
+            root: None,
+        };
+        self.receipts
+            .write()
+            .map_err(internal_error)?
+            .insert(hash.clone(), receipt);
+        *self
+            .pending
+            .write()
+            .map_err(internal_error)?
+            .entry(from)
+            .or_default() += 1;
+        Ok(hash)
+    }
+
+    /// Execute a transaction and mine it right away if automining
+    fn submit_transaction(
+        &self,
+        chain: &mut dyn WriteableChainState,
+        transaction: SignedTransaction,
+    ) -> RpcResult<U256> {
+        let hash = self.execute_transaction(chain, transaction)?;
+        if *self.automine.read().map_err(internal_error)? {
+            self.mine(None)?;
+        }
+        Ok(hash)
+    }
+
+    /// Seal the current block and start a new one on top of it
+    ///
+    /// The receipts and logs of the sealed block get its final hash. The new
+    /// block is at least a second later and not earlier than the clock.
+    fn mine(&self, timestamp: Option<u64>) -> RpcResult<()> {
+        self.pending.write().map_err(internal_error)?.clear();
+        let mut header = self.header.write().map_err(internal_error)?;
+        let mut parent = std::mem::take(&mut *header);
+        if let Some(timestamp) = timestamp {
+            parent.header.timestamp = timestamp;
+        }
+        let parent_hash = parent.header.rlp_hash();
+        let number = parent.header.number;
+        let seal = |log: &mut Log| {
+            if let Some(block) = &mut log.block {
+                if *block.block_number.as_ref() == number {
+                    block.block_hash = parent_hash.clone();
+                }
+            }
+        };
+        self.logs
+            .write()
+            .map_err(internal_error)?
+            .iter_mut()
+            .for_each(&seal);
+        for receipt in self.receipts.write().map_err(internal_error)?.values_mut() {
+            if receipt.block_number == Some(number.into()) {
+                receipt.block_hash = Some(parent_hash.clone());
+                receipt.logs.iter_mut().for_each(&seal);
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(internal_error)?
+            .as_secs();
+        header.header = BlockHeader {
+            parent_hash: parent_hash.clone(),
+            number: number + 1,
+            timestamp: std::cmp::max(parent.header.timestamp + 1, now),
+            gas_used: 0,
+            logs_bloom: BloomFilter::empty(),
+            ..parent.header.clone()
+        };
+        self.blocks.write().map_err(internal_error)?.push(parent);
+        Ok(())
+    }
+
+    /// Constants for executing in the sealed or current block `number`
+    fn block_info_at(&self, chain: &dyn WriteableChainState, number: u64) -> RpcResult<BlockInfo> {
+        let current = self.block_info(chain)?;
+        let timestamp = self
+            .blocks
+            .read()
+            .map_err(internal_error)?
+            .iter()
+            .find(|block| block.header.number == number)
+            .map_or(current.timestamp, |block| block.header.timestamp);
+        Ok(BlockInfo {
+            number,
+            timestamp,
+            ..current
+        })
+    }
+
+    /// Look up a transaction by hash in the executed ones and the known
+    /// blocks
+    ///
+    /// Also returns the index in `transactions` of executed transactions. The
+    /// sender of transactions of imported blocks is recovered from their
+    /// signature.
+    fn find_transaction(&self, hash: &U256) -> RpcResult<Option<(RpcTransaction, Option<usize>)>> {
+        {
+            let transactions = self.transactions.read().map_err(internal_error)?;
+            if let Some(index) = transactions
+                .iter()
+                .position(|transaction| &transaction.hash == hash)
+            {
+                return Ok(Some((transactions[index].clone(), Some(index))));
+            }
+        }
+        let genesis = self.genesis.read().map_err(internal_error)?;
+        let blocks = self.blocks.read().map_err(internal_error)?;
+        let header = self.header.read().map_err(internal_error)?;
+        let imported = std::iter::once(&*genesis)
+            .chain(blocks.iter())
+            .chain(std::iter::once(&*header));
+        for block in imported {
+            if let Some(transaction) = block
+                .transactions
+                .iter()
+                .find(|transaction| &transaction.rlp_hash() == hash)
+            {
+                let signed = to_rlp(transaction)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|raw| SignedTransaction::decode(&raw))
+                    .map_err(|err| {
+                        jsonrpc_core::Error::invalid_params(format!(
+                            "can not recover the sender: {}",
+                            err
+                        ))
+                    })?;
+                let transaction = RpcTransaction {
+                    transaction: transaction.clone(),
+                    block_number: block.header.number,
+                    from: signed.sender,
+                    hash: hash.clone(),
+                    ..RpcTransaction::default()
+                };
+                return Ok(Some((transaction, None)));
+            }
+        }
+        Ok(None)
     }
 }
 
@@ -61,17 +492,101 @@ impl EthereumRpc for RpcHandler {
     }
 
     fn gas_price(&self) -> RpcResult<Hex<U256>> {
-        Ok(U256::zero().into())
+        let chain = self.chain.lock().map_err(internal_error)?;
+        let block = self.block_info(&**chain)?;
+        Ok(self.suggested_gas_price(&block).into())
+    }
+
+    /// Accounts derived from the mnemonic the node was started with.
+    fn accounts(&self) -> RpcResult<Vec<Address>> {
+        Ok(self
+            .accounts
+            .iter()
+            .map(|account| account.address.clone())
+            .collect())
     }
 
-    fn send_transaction(&self, _tx: Transaction) -> RpcResult<Hex<U256>> {
-        Ok(U256::zero().into())
+    /// Signs with the prefix of EIP-191 for the accounts of `eth_accounts`.
+    fn sign(&self, address: Address, data: HexData) -> RpcResult<HexData> {
+        let account = self
+            .accounts
+            .iter()
+            .find(|account| account.address == address)
+            .ok_or_else(|| jsonrpc_core::Error::invalid_params("unknown account"))?;
+        Ok(HexData::from(&account.sign_message(data.as_slice())[..]))
+    }
+
+    fn personal_sign(&self, data: HexData, address: Address) -> RpcResult<HexData> {
+        self.sign(address, data)
+    }
+
+    /// Signs for the accounts of `eth_accounts`. Transactions from
+    /// impersonated accounts are not signed and hashed without signature.
+    fn send_transaction(&self, request: CallRequest) -> RpcResult<Hex<U256>> {
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        let from = U256::from(&request.from);
+        let account = self
+            .accounts
+            .iter()
+            .find(|account| account.address == request.from);
+        if account.is_none() && !self.impersonated.read().map_err(internal_error)?.contains(&from) {
+            return Err(jsonrpc_core::Error::invalid_params("unknown account"));
+        }
+        let nonce = chain.nonce(&from) as u64;
+        let data = request.data.as_slice().to_vec();
+        let gas_limit = request
+            .gas
+            .clone()
+            .map_or_else(|| chain.block().gas_limit, Hex::into_inner);
+        let value = request.value.clone().into_inner();
+        let block = self.block_info(&**chain)?;
+        let gas_price = request
+            .gas_price
+            .clone()
+            .map_or_else(|| self.suggested_gas_price(&block), Hex::into_inner);
+        let hash = Transaction {
+            nonce,
+            gas_price: saturating_u64(&gas_price),
+            gas_limit,
+            to: request.to.clone().unwrap_or_default(),
+            value: value.clone(),
+            data: data.clone(),
+            ..Transaction::default()
+        }
+        .rlp_hash();
+        let transaction = SignedTransaction {
+            hash,
+            sender: request.from,
+            chain_id: Some(self.chain_id),
+            nonce,
+            max_priority_fee_per_gas: gas_price.clone(),
+            max_fee_per_gas: gas_price,
+            gas_limit,
+            to: request.to,
+            value,
+            data,
+            access_list: Vec::new(),
+        };
+        let transaction = match account {
+            Some(account) => {
+                let raw = transaction
+                    .sign_legacy(&account.secret_key)
+                    .map_err(internal_error)?;
+                SignedTransaction::decode(&raw).map_err(internal_error)?
+            }
+            None => transaction,
+        };
+        Ok(self.submit_transaction(&mut **chain, transaction)?.into())
     }
 
     fn net_version(&self) -> RpcResult<String> {
         Ok(format!("{}", self.chain_id))
     }
 
+    fn chain_id(&self) -> RpcResult<Hex<u64>> {
+        Ok(self.chain_id.into())
+    }
+
     fn block_number(&self) -> RpcResult<Hex<u64>> {
         let lock = self.header.read().map_err(internal_error)?;
         let block = lock.clone();
@@ -79,104 +594,410 @@ impl EthereumRpc for RpcHandler {
         Ok(number.into())
     }
 
+    /// Blocks mined locally are synthesized from their header and the
+    /// transactions executed in them. Earlier blocks come from the node forked
+    /// from, or are the genesis block.
     fn get_block_by_number(
         &self,
         block_number: BlockNumber,
-        _full: bool,
-    ) -> RpcResult<Option<FullBlock>> {
-        // TODO: Use `block_number` and `full`
-        let mutex = match block_number {
-            BlockNumber::Number(0) => &self.genesis,
-            BlockNumber::Number(1) => &self.header,
-            _ => unimplemented!(),
-        };
-        let lock = mutex.read().map_err(internal_error)?;
-        let block = lock.clone();
-        self.return_block(block)
+        full: bool,
+    ) -> RpcResult<Option<RpcBlock<BlockTransaction>>> {
+        let base_fee_per_gas = self.base_fee_per_gas()?;
+        let header = self.header.read().map_err(internal_error)?.clone();
+        let number = match block_number {
+            BlockNumber::Earliest => 0,
+            BlockNumber::Latest | BlockNumber::Pending => header.header.number,
+            BlockNumber::Number(number) => number,
+        };
+        let blocks = self.blocks.read().map_err(internal_error)?;
+        let first_local = blocks
+            .first()
+            .map_or(header.header.number, |block| block.header.number);
+        let genesis = self.genesis.read().map_err(internal_error)?.header.number;
+        let block = if number == header.header.number {
+            Some(header)
+        } else {
+            blocks
+                .iter()
+                .find(|block| block.header.number == number)
+                .cloned()
+        };
+        drop(blocks);
+        match (block, &self.fork) {
+            (Some(block), _) => {
+                let block = self.full_block(block, base_fee_per_gas)?;
+                Ok(Some(select_transactions(block, full)))
+            }
+            (None, Some(client)) if number < first_local => {
+                block_on(client.get_block_by_number(BlockNumber::Number(number), full))
+                    .map_err(internal_error)
+            }
+            (None, None) if number == genesis => Ok(Some(self.genesis_block(full)?)),
+            (None, _) => Ok(None),
+        }
     }
 
-    fn get_block_by_hash(&self, _block_hash: U256, full: bool) -> RpcResult<Option<FullBlock>> {
-        // TODO: Use `block_hash` and `full`
-        let lock = self.header.read().map_err(internal_error)?;
-        self.return_block(lock.clone())
+    fn get_block_by_hash(
+        &self,
+        block_hash: U256,
+        full: bool,
+    ) -> RpcResult<Option<RpcBlock<BlockTransaction>>> {
+        let base_fee_per_gas = self.base_fee_per_gas()?;
+        let header = self.header.read().map_err(internal_error)?.clone();
+        let block = if header.header.rlp_hash() == block_hash {
+            Some(header)
+        } else {
+            self.blocks
+                .read()
+                .map_err(internal_error)?
+                .iter()
+                .find(|block| block.header.rlp_hash() == block_hash)
+                .cloned()
+        };
+        if let Some(block) = block {
+            let block = self.full_block(block, base_fee_per_gas)?;
+            return Ok(Some(select_transactions(block, full)));
+        }
+        if let Some(client) = &self.fork {
+            block_on(client.get_block_by_hash(block_hash, full)).map_err(internal_error)
+        } else {
+            let genesis = self.genesis_block(full)?;
+            Ok(if genesis.hash == block_hash {
+                Some(genesis)
+            } else {
+                None
+            })
+        }
     }
 
-    fn get_nonce(&self, address: Address, _block_number: BlockNumber) -> RpcResult<Hex<u64>> {
-        Ok(match address.to_array() {
-            hex!("a94f5374fce5edbc8e2a8697c15331677e6ebf0b") => 1,
-            _ => 0,
-        }
-        .into())
+    /// Only `pending` includes transactions that are not mined yet, all
+    /// other block numbers resolve to the latest block.
+    fn get_nonce(&self, address: Address, block_number: BlockNumber) -> RpcResult<Hex<u64>> {
+        let address = U256::from(&address);
+        let nonce = self.chain.lock().map_err(internal_error)?.nonce(&address);
+        let nonce = if block_number == BlockNumber::Pending {
+            nonce
+        } else {
+            let pending = self.pending.read().map_err(internal_error)?;
+            nonce - pending.get(&address).copied().unwrap_or_default()
+        };
+        Ok((nonce as u64).into())
     }
 
-    fn get_balance(&self, address: Address, block_number: BlockNumber) -> RpcResult<Hex<U256>> {
-        Ok(match address.to_array() {
-            hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6") => {
-                u256h!("00000000000000000000000000000000000000000000d3c21bcecceda100000b")
-            }
-            hex!("2adc25665018aa1fe0e6bc666dac8fc2697ff9ba") => {
-                u256h!("0000000000000000000000000000000000000000000000001bc16d674ecf8270")
-            }
-            hex!("a94f5374fce5edbc8e2a8697c15331677e6ebf0b") => {
-                u256h!("0000000000000000000000000000000000000000000000007ffffffffff87d75")
+    /// All block numbers resolve to the current state, which includes changes
+    /// made during this session.
+    fn get_balance(&self, address: Address, _block_number: BlockNumber) -> RpcResult<Hex<U256>> {
+        let chain = self.chain.lock().map_err(internal_error)?;
+        Ok(chain.balance(&U256::from(&address)).into())
+    }
+
+    /// All block numbers resolve to the current state, which includes changes
+    /// made during this session.
+    fn get_storage_at(
+        &self,
+        address: Address,
+        slot: HexMid,
+        _block_number: BlockNumber,
+    ) -> RpcResult<Hex<U256>> {
+        let chain = self.chain.lock().map_err(internal_error)?;
+        Ok(chain
+            .storage(&U256::from(&address), slot.as_ref())
+            .into())
+    }
+
+    /// All block numbers resolve to the current state, including contracts
+    /// created during this session.
+    fn get_code(&self, address: Address, _block_number: BlockNumber) -> RpcResult<Bytes> {
+        let chain = self.chain.lock().map_err(internal_error)?;
+        Ok(chain.code(&U256::from(&address)).into())
+    }
+
+    /// Accounts that were not written to in this session, and all accounts at
+    /// blocks before the fork, are proven by the node the chain is forked
+    /// from. Other accounts are proven against the trie of the accounts
+    /// written locally.
+    fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<Hex<U256>>,
+        block_number: BlockNumber,
+    ) -> RpcResult<AccountProof> {
+        let account = U256::from(&address);
+        let chain = self.chain.lock().map_err(internal_error)?;
+        if let Some(client) = &self.fork {
+            let header = self.header.read().map_err(internal_error)?.header.number;
+            let first_local = self
+                .blocks
+                .read()
+                .map_err(internal_error)?
+                .first()
+                .map_or(header, |block| block.header.number);
+            let number = match block_number {
+                BlockNumber::Earliest => Some(0),
+                BlockNumber::Number(number) if number < first_local => Some(number),
+                _ if !chain.is_written(&account) => Some(first_local - 1),
+                _ => None,
+            };
+            if let Some(number) = number {
+                drop(chain);
+                return block_on(client.get_proof(address, slots, BlockNumber::Number(number)))
+                    .map_err(internal_error);
             }
-            _ => U256::zero(),
         }
-        .into())
+        let slots = slots.into_iter().map(Hex::into_inner).collect::<Vec<_>>();
+        Ok(chain.proof(&account, &slots))
     }
 
-    fn get_code(&self, address: Address, _block_number: BlockNumber) -> RpcResult<Bytes> {
-        Ok(match address.to_array() {
-            hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6") => {
-                hex!("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7feeeeeeeeeeeeeeeeeeeeeeeeeeeeefeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee1860005500").to_vec()
+    fn call(&self, call: CallRequest, _block_number: Option<BlockNumber>) -> RpcResult<Bytes> {
+        // TODO: Use `block_number`
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        let gas_limit = call.gas.clone().map_or(CALL_GAS, Hex::into_inner);
+        let block = self.block_info(&**chain)?;
+        let schedule = self.schedule(&block);
+        let result = simulate(&mut **chain, &block, &schedule, &call, gas_limit)?;
+        match result.result {
+            CallResult::Return(output) => Ok(output.into()),
+            CallResult::Revert(output) => Err(revert_error(&output)),
+            CallResult::Halt(reason) => Err(halt_error(reason)),
+        }
+    }
+
+    /// Finds the lowest gas limit that does not fail by binary search.
+    fn estimate_gas(&self, call: CallRequest) -> RpcResult<Hex<u64>> {
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        let block = self.block_info(&**chain)?;
+        let schedule = self.schedule(&block);
+        let intrinsic = intrinsic_gas(&call_info(&**chain, &call), &[], &schedule);
+        let mut high = std::cmp::min(
+            call.gas
+                .clone()
+                .map_or(block.gas_limit, Hex::into_inner),
+            CALL_GAS,
+        );
+        if high < intrinsic {
+            return Err(jsonrpc_core::Error::invalid_params(
+                "intrinsic gas exceeds gas limit",
+            ));
+        }
+        let mut run = |gas_limit: u64| -> RpcResult<CallResult> {
+            Ok(simulate(&mut **chain, &block, &schedule, &call, gas_limit)?.result)
+        };
+
+        // Fail if it does not succeed with all gas available
+        match run(high)? {
+            CallResult::Return(_) => {}
+            CallResult::Revert(output) => return Err(revert_error(&output)),
+            CallResult::Halt(reason) => return Err(halt_error(reason)),
+        }
+        let mut low = intrinsic - 1;
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if matches!(run(mid)?, CallResult::Return(_)) {
+                high = mid;
+            } else {
+                low = mid;
             }
-            _ => Vec::new(),
         }
-        .into())
+        Ok(high.into())
     }
 
-    fn estimate_gas(&self, _call: CallRequest) -> RpcResult<Hex<U256>> {
-        Ok(U256::zero().into())
+    /// Executes the transaction right away and stores a receipt for it.
+    ///
+    /// Legacy, access list and dynamic fee transactions are supported. The
+    /// sender is recovered from the signature.
+    fn send_raw_transaction(&self, data: HexData) -> RpcResult<U256> {
+        let transaction = SignedTransaction::decode(data.as_slice())
+            .map_err(|err| jsonrpc_core::Error::invalid_params(err.to_string()))?;
+        if let Some(chain_id) = transaction.chain_id {
+            if chain_id != self.chain_id {
+                return Err(jsonrpc_core::Error::invalid_params("invalid chain id"));
+            }
+        }
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        self.submit_transaction(&mut **chain, transaction)
     }
 
-    fn send_raw_transaction(&self, _data: Vec<u8>) -> RpcResult<U256> {
-        Ok(U256::zero())
+    fn get_transaction_by_hash(
+        &self,
+        transaction_hash: U256,
+    ) -> RpcResult<Option<RpcTransaction>> {
+        let mut blocks = self.blocks.read().map_err(internal_error)?.clone();
+        blocks.push(self.genesis.read().map_err(internal_error)?.clone());
+        blocks.push(self.header.read().map_err(internal_error)?.clone());
+        for block in blocks {
+            if let Some(transaction) = self
+                .full_block(block, None)?
+                .transactions
+                .into_iter()
+                .find(|transaction| transaction.hash == transaction_hash)
+            {
+                return Ok(Some(transaction));
+            }
+        }
+        Ok(None)
     }
 
     fn get_transaction_receipt(
         &self,
-        _transaction_hash: U256,
+        transaction_hash: U256,
     ) -> RpcResult<Option<TransactionReceipt>> {
-        Ok(Some(TransactionReceipt::default()))
+        let receipts = self.receipts.read().map_err(internal_error)?;
+        Ok(receipts.get(&transaction_hash).cloned())
     }
 
-    fn get_logs(&self, _filter: LogFilter) -> RpcResult<Vec<Log>> {
-        Ok(Vec::new())
+    /// Logs of transactions sent to this node, in the order they were
+    /// emitted.
+    fn get_logs(&self, filter: LogFilter) -> RpcResult<Vec<Log>> {
+        let header = self.header.read().map_err(internal_error)?;
+        let latest = header.header.number;
+        // Skip blocks whose bloom filter rules out a match
+        let blocks = self.blocks.read().map_err(internal_error)?;
+        let may_match = blocks
+            .iter()
+            .chain(std::iter::once(&*header))
+            .filter(|block| filter.may_match(&block.header.logs_bloom))
+            .map(|block| block.header.number)
+            .collect::<HashSet<_>>();
+        let resolve = |block_number: Option<BlockNumber>| {
+            match block_number.unwrap_or(BlockNumber::Latest) {
+                BlockNumber::Earliest => 0,
+                BlockNumber::Latest | BlockNumber::Pending => latest,
+                BlockNumber::Number(number) => number,
+            }
+        };
+        let from_block = resolve(filter.from_block);
+        let to_block = resolve(filter.to_block);
+        let logs = self.logs.read().map_err(internal_error)?;
+        Ok(logs
+            .iter()
+            .filter(|log| {
+                log.block.as_ref().map_or(false, |block| {
+                    let number = *block.block_number.as_ref();
+                    let in_range = filter.block_hash.as_ref().map_or_else(
+                        || from_block <= number && number <= to_block,
+                        |hash| &block.block_hash == hash,
+                    );
+                    in_range && may_match.contains(&number)
+                })
+            })
+            .filter(|log| filter.matches(log))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
     }
 
     fn evm_snapshot(&self) -> RpcResult<Hex<u64>> {
-        Ok(1.into())
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        let snapshot = Snapshot {
+            chain:        chain.snapshot(),
+            pending:      self.pending.read().map_err(internal_error)?.clone(),
+            receipts:     self.receipts.read().map_err(internal_error)?.clone(),
+            logs:         self.logs.read().map_err(internal_error)?.len(),
+            header:       self.header.read().map_err(internal_error)?.clone(),
+            blocks:       self.blocks.read().map_err(internal_error)?.len(),
+            transactions: self.transactions.read().map_err(internal_error)?.len(),
+        };
+        let mut snapshots = self.snapshots.lock().map_err(internal_error)?;
+        snapshots.push(snapshot);
+        Ok((snapshots.len() as u64).into())
     }
 
-    fn evm_revert(&self, _snapshot: Hex<u64>) -> RpcResult<bool> {
+    /// Restores the state from when the snapshot was taken.
+    ///
+    /// The snapshot and all later ones are consumed. Returns `false` if the
+    /// snapshot does not exist.
+    fn evm_revert(&self, snapshot: Hex<u64>) -> RpcResult<bool> {
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        let mut snapshots = self.snapshots.lock().map_err(internal_error)?;
+        let index = match usize::try_from(snapshot.into_inner()) {
+            Ok(id) if (1..=snapshots.len()).contains(&id) => id - 1,
+            _ => return Ok(false),
+        };
+        let snapshot = snapshots.remove(index);
+        snapshots.truncate(index);
+        chain.revert_to(snapshot.chain);
+        *self.pending.write().map_err(internal_error)? = snapshot.pending;
+        *self.receipts.write().map_err(internal_error)? = snapshot.receipts;
+        self.logs
+            .write()
+            .map_err(internal_error)?
+            .truncate(snapshot.logs);
+        *self.header.write().map_err(internal_error)? = snapshot.header;
+        self.blocks
+            .write()
+            .map_err(internal_error)?
+            .truncate(snapshot.blocks);
+        self.transactions
+            .write()
+            .map_err(internal_error)?
+            .truncate(snapshot.transactions);
+        self.replaced
+            .write()
+            .map_err(internal_error)?
+            .truncate(snapshot.transactions);
         Ok(true)
     }
 
-    fn evm_increase_time(&self, _amount_sec: u64) -> RpcResult<u64> {
-        todo!()
+    /// Moves the clock forward and returns the new timestamp.
+    fn evm_increase_time(&self, amount_sec: u64) -> RpcResult<u64> {
+        let mut header = self.header.write().map_err(internal_error)?;
+        header.header.timestamp += amount_sec;
+        Ok(header.header.timestamp)
     }
 
-    fn evm_mine(&self, _timestamp: Option<u64>) -> RpcResult<Hex<u64>> {
+    fn evm_set_next_block_timestamp(&self, timestamp: u64) -> RpcResult<u64> {
+        self.header.write().map_err(internal_error)?.header.timestamp = timestamp;
+        Ok(timestamp)
+    }
+
+    /// Mines the pending transactions in a block with the given timestamp,
+    /// or the one of the current block.
+    fn evm_mine(&self, timestamp: Option<u64>) -> RpcResult<Hex<u64>> {
+        self.mine(timestamp)?;
         // Always returns zero
         Ok(0.into())
     }
 
-    fn evm_unlock_unknown_account(&self, _address: Address) -> RpcResult<bool> {
-        todo!()
+    fn evm_set_automine(&self, enabled: bool) -> RpcResult<bool> {
+        *self.automine.write().map_err(internal_error)? = enabled;
+        Ok(true)
     }
 
-    fn evm_lock_unknown_account(&self, _address: Address) -> RpcResult<bool> {
-        todo!()
+    fn evm_unlock_unknown_account(&self, address: Address) -> RpcResult<bool> {
+        self.hardhat_impersonate_account(address)
+    }
+
+    fn evm_lock_unknown_account(&self, address: Address) -> RpcResult<bool> {
+        self.hardhat_stop_impersonating_account(address)
+    }
+
+    fn hardhat_set_balance(&self, address: Address, balance: Hex<U256>) -> RpcResult<bool> {
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        chain.set_balance(&U256::from(&address), balance.as_ref());
+        Ok(true)
+    }
+
+    fn hardhat_set_storage_at(
+        &self,
+        address: Address,
+        slot: Hex<U256>,
+        value: Hex<U256>,
+    ) -> RpcResult<bool> {
+        let mut chain = self.chain.lock().map_err(internal_error)?;
+        chain.set_storage(&U256::from(&address), slot.as_ref(), value.as_ref());
+        Ok(true)
+    }
+
+    /// Returns `false` if the account was already impersonated.
+    fn hardhat_impersonate_account(&self, address: Address) -> RpcResult<bool> {
+        let mut impersonated = self.impersonated.write().map_err(internal_error)?;
+        Ok(impersonated.insert(U256::from(&address)))
+    }
+
+    /// Returns `false` if the account was not impersonated.
+    fn hardhat_stop_impersonating_account(&self, address: Address) -> RpcResult<bool> {
+        let mut impersonated = self.impersonated.write().map_err(internal_error)?;
+        Ok(impersonated.remove(&U256::from(&address)))
     }
 
     fn test_set_chain_params(&self, genesis: GenesisConfig) -> RpcResult<bool> {
@@ -254,6 +1075,61 @@ impl EthereumRpc for RpcHandler {
             })
         }
     }
+
+    /// Re-executes the transaction on the state before it.
+    ///
+    /// Transactions of imported blocks were not executed by this node, so
+    /// they run on the current state instead.
+    fn trace_transaction(
+        &self,
+        transaction_hash: U256,
+        config: Option<TraceConfig>,
+    ) -> RpcResult<TransactionTrace> {
+        let (transaction, index) = self
+            .find_transaction(&transaction_hash)?
+            .ok_or_else(|| jsonrpc_core::Error::invalid_params("transaction not found"))?;
+        let chain = self.chain.lock().map_err(internal_error)?;
+        let block = self.block_info_at(&**chain, transaction.block_number)?;
+        let schedule = self.schedule(&block);
+        // Undo this transaction and the ones after it
+        let replaced = match index {
+            Some(index) => self.replaced.read().map_err(internal_error)?[index..].to_vec(),
+            None => Vec::new(),
+        };
+        let mut state = Fork::from(Rewound::new(&**chain, replaced.into_iter()));
+        let execution_error =
+            |err: anyhow::Error| jsonrpc_core::Error::invalid_params(format!("{:#}", err));
+        let config = config.unwrap_or_default();
+        let trace = if let Some(path) = &config.output_file {
+            // Steps are streamed to the file, the response only has the outcome
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map_err(|err| {
+                    jsonrpc_core::Error::invalid_params(format!(
+                        "Can not create {}: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+            let mut tracer = JsonTraceWriter::new(config.clone(), BufWriter::new(file))
+                .map_err(internal_error)?;
+            let (result, gas_used) =
+                replay(&mut state, &block, &transaction, &schedule, &mut tracer)
+                    .map_err(execution_error)?;
+            tracer
+                .finish(&result, gas_used)
+                .map(|_| trace_outcome(&result, gas_used))
+        } else {
+            let mut tracer = StructLogger::new(config);
+            let (result, gas_used) =
+                replay(&mut state, &block, &transaction, &schedule, &mut tracer)
+                    .map_err(execution_error)?;
+            Ok(tracer.into_trace(&result, gas_used))
+        };
+        trace.map_err(internal_error)
+    }
 }
 
 fn parse_error<T: std::fmt::Display>(err: T) -> jsonrpc_core::Error {
@@ -265,3 +1141,1055 @@ fn internal_error<T: std::fmt::Display>(err: T) -> jsonrpc_core::Error {
     error!("Internal error in RPC handler: {}", err);
     jsonrpc_core::Error::internal_error()
 }
+
+/// Block with the transactions as objects if `full`, or as hashes
+fn select_transactions(block: FullBlock, full: bool) -> RpcBlock<BlockTransaction> {
+    let transactions = block
+        .transactions
+        .into_iter()
+        .map(|transaction| {
+            if full {
+                BlockTransaction::Full(Box::new(transaction))
+            } else {
+                BlockTransaction::Hash(transaction.hash)
+            }
+        })
+        .collect();
+    RpcBlock {
+        header: block.header,
+        hash: block.hash,
+        total_difficulty: block.total_difficulty,
+        size: block.size,
+        base_fee_per_gas: block.base_fee_per_gas,
+        transactions,
+        ommers: block.ommers,
+    }
+}
+
+/// The value, or `u64::MAX` if it does not fit
+fn saturating_u64(value: &U256) -> u64 {
+    if value.bits() > 64 {
+        u64::MAX
+    } else {
+        value.as_u64()
+    }
+}
+
+/// Reject fee caps that can not pay for the block, with the messages of
+/// Geth
+fn check_fees(transaction: &SignedTransaction, base_fee: &U256) -> RpcResult<()> {
+    if transaction.max_priority_fee_per_gas > transaction.max_fee_per_gas {
+        return Err(jsonrpc_core::Error::invalid_params(
+            "max priority fee per gas higher than max fee per gas",
+        ));
+    }
+    if &transaction.max_fee_per_gas < base_fee {
+        return Err(jsonrpc_core::Error::invalid_params(
+            "max fee per gas less than block base fee",
+        ));
+    }
+    Ok(())
+}
+
+/// Execute a call against the chain and discard any changes made.
+fn simulate(
+    chain: &mut dyn WriteableChainState,
+    block: &BlockInfo,
+    schedule: &GasSchedule,
+    call: &CallRequest,
+    gas_limit: u64,
+) -> RpcResult<ExecutionResult> {
+    let mut transaction = TransactionInfo::legacy(
+        U256::from(&call.from),
+        call.gas_price
+            .clone()
+            .map_or_else(U256::zero, Hex::into_inner),
+    );
+    let mut call = call_info(chain, call);
+    let intrinsic = intrinsic_gas(&call, &[], schedule);
+    if gas_limit < intrinsic {
+        return Err(jsonrpc_core::Error::invalid_params(
+            "intrinsic gas exceeds gas limit",
+        ));
+    }
+    call.initial_gas = usize::try_from(gas_limit - intrinsic).unwrap_or(usize::MAX);
+    transaction.intrinsic_gas = intrinsic;
+    let balance = chain.balance(&call.sender);
+    if balance < call.call_value {
+        return Err(jsonrpc_core::Error::invalid_params(
+            "insufficient funds for transfer",
+        ));
+    }
+    let snapshot = chain.snapshot();
+    // Like a transaction, so creations get the address of the next nonce
+    let nonce = chain.nonce(&call.sender);
+    chain.set_nonce(&call.sender, nonce + 1);
+    chain.set_balance(&call.sender, &(balance - &call.call_value));
+    let balance = chain.balance(&call.address);
+    chain.set_balance(&call.address, &(balance + &call.call_value));
+    let result = evaluate(chain, block, &transaction, &call, schedule);
+    chain.revert_to(snapshot);
+    Ok(result)
+}
+
+/// Call frame for a call request, without any gas
+///
+/// Requests without `to` create a contract with the data as init code, at
+/// the address of the sender's next nonce.
+fn call_info(chain: &dyn WriteableChainState, call: &CallRequest) -> CallInfo {
+    let sender = U256::from(&call.from);
+    let data = call.data.as_slice().to_vec();
+    let (address, input, init_code) = if let Some(to) = &call.to {
+        (U256::from(to), data, None)
+    } else {
+        let address = create_address(&sender, chain.nonce(&sender));
+        (address, Vec::new(), Some(data))
+    };
+    CallInfo {
+        sender,
+        address: address.clone(),
+        code_address: address,
+        call_value: call.value.clone().into_inner(),
+        initial_gas: 0,
+        input,
+        is_static: false,
+        init_code,
+    }
+}
+
+/// Bloom filter of the addresses and topics of the logs
+fn logs_bloom(logs: &[crate::evm::Log]) -> BloomFilter {
+    let mut bloom = BloomFilter::empty();
+    for log in logs {
+        bloom.accrue_log(&Address::from(&log.address), &log.topics);
+    }
+    bloom
+}
+
+/// Error for a reverted call, using the same error code as Geth.
+///
+/// The data field contains the decoded revert reason, see [`RevertReason`].
+fn revert_error(output: &[u8]) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code:    ErrorCode::ServerError(3),
+        message: "execution reverted".into(),
+        data:    Some(RevertReason::decode(output).to_string().into()),
+    }
+}
+
+/// Error for a call that halted exceptionally, using the same code and
+/// message as Geth.
+fn halt_error(reason: HaltReason) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code:    ErrorCode::ServerError(-32000),
+        message: reason.to_string(),
+        data:    None,
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use crate::{
+        chain::{
+            state_trie::{verify_proof, TrieAccount},
+            Empty, Fork,
+        },
+        rpc::{accounts::message_hash, derive_accounts, DEFAULT_MNEMONIC},
+        serde::rlp::{from_rlp, to_rlp},
+        test::prelude::assert_eq,
+        utils::keccak256,
+    };
+
+    const CONTRACT: [u8; 20] = hex!("0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6");
+
+    pub(in crate::rpc) fn handler(chain: Fork<Empty>) -> RpcHandler {
+        RpcHandler {
+            client_version: "sutro/test".into(),
+            chain_id:       1337,
+            gas_price:      U256::zero(),
+            genesis:        RwLock::new(Block::default()),
+            header:         RwLock::new(Block::default()),
+            chain:          Mutex::new(Box::new(chain)),
+            receipts:       RwLock::new(HashMap::new()),
+            logs:           RwLock::new(Vec::new()),
+            transactions:   RwLock::new(Vec::new()),
+            replaced:       RwLock::new(Vec::new()),
+            blocks:         RwLock::new(Vec::new()),
+            pending:        RwLock::new(HashMap::new()),
+            snapshots:      Mutex::new(Vec::new()),
+            accounts:       Vec::new(),
+            // Requests default to sending from the zero address
+            impersonated:   RwLock::new(std::iter::once(U256::zero()).collect()),
+            hardfork:       None,
+            // Transactions are queued, so tests can inspect the current block
+            automine:       RwLock::new(false),
+            fork:           None,
+        }
+    }
+
+    fn handler_with_code(code: &[u8]) -> RpcHandler {
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(&Address::from(CONTRACT)), code);
+        handler(chain)
+    }
+
+    fn call_contract() -> CallRequest {
+        CallRequest {
+            to: Some(CONTRACT.into()),
+            ..CallRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_call_return() {
+        // PUSH1 42 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        let handler = handler_with_code(&hex!("602a60005260206000f3"));
+        let result = handler.call(call_contract(), None).unwrap();
+        assert_eq!(result, U256::from(42).to_bytes_be().to_vec().into());
+    }
+
+    #[test]
+    fn test_call_revert_reason() {
+        // Copies the trailing `Error("nope")` revert data to memory and reverts.
+        let handler = handler_with_code(&hex!(
+            "6064600c60003960646000fd 08c379a0
+            0000000000000000000000000000000000000000000000000000000000000020
+            0000000000000000000000000000000000000000000000000000000000000004
+            6e6f706500000000000000000000000000000000000000000000000000000000"
+        ));
+        let error = handler.call(call_contract(), None).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ServerError(3));
+        assert_eq!(error.data, Some("nope".into()));
+    }
+
+    #[test]
+    fn test_call_value() {
+        // SELFBALANCE PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        let handler = handler_with_code(&hex!("4760005260206000f3"));
+        let call = CallRequest {
+            value: U256::from(7).into(),
+            ..call_contract()
+        };
+        let error = handler.call(call.clone(), None).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+
+        handler
+            .chain
+            .lock()
+            .unwrap()
+            .set_balance(&U256::zero(), &U256::from(10));
+        let result = handler.call(call, None).unwrap();
+        assert_eq!(result, U256::from(7).to_bytes_be().to_vec().into());
+        // The transfer is discarded with the other changes
+        let chain = handler.chain.lock().unwrap();
+        assert_eq!(chain.balance(&U256::zero()), U256::from(10));
+        assert_eq!(chain.balance(&U256::from(&Address::from(CONTRACT))), U256::zero());
+    }
+
+    #[test]
+    fn test_call_create() {
+        // Init code returning 0xff: MSTORE8(0, 0xff) RETURN(0, 1)
+        let handler = handler_with_code(&[]);
+        let call = CallRequest {
+            data: hex!("60ff600053 60016000f3").to_vec().into(),
+            ..CallRequest::default()
+        };
+        let result = handler.call(call.clone(), None).unwrap();
+        assert_eq!(result, vec![0xff].into());
+
+        // Two zero and eight nonzero bytes in one word of init code, then
+        // PUSH1 PUSH1 MSTORE8 (with one word of memory) PUSH1 PUSH1 RETURN
+        // and the deposit of one byte of code
+        let estimate = handler.estimate_gas(call).unwrap();
+        let intrinsic = 53000 + 2 * 4 + 8 * 16 + 2;
+        assert_eq!(estimate, (intrinsic + (3 + 3 + 6 + 3 + 3) + 200).into());
+    }
+
+    #[test]
+    fn test_get_balance() {
+        let balance = u256h!("00000000000000000000000000000000000000000000d3c21bcecceda100000b");
+        let mut chain = Fork::from(Empty);
+        chain.set_balance(&U256::from(&Address::from(CONTRACT)), &balance);
+        let handler = handler(chain);
+        for block_number in &[
+            BlockNumber::Latest,
+            BlockNumber::Pending,
+            BlockNumber::Number(5),
+        ] {
+            let result = handler.get_balance(CONTRACT.into(), *block_number);
+            assert_eq!(result.unwrap(), balance.clone().into());
+        }
+    }
+
+    #[test]
+    fn test_get_balance_missing_account() {
+        let handler = handler(Fork::from(Empty));
+        let result = handler.get_balance(Address::default(), BlockNumber::Latest);
+        assert_eq!(result.unwrap(), U256::zero().into());
+    }
+
+    #[test]
+    fn test_get_storage_at() {
+        // SSTORE(1, 42) STOP
+        let handler = handler_with_code(&hex!("602a600155 00"));
+        let storage_at = |slot: &str| {
+            let slot = serde_json::from_value(serde_json::json!(slot)).unwrap();
+            handler
+                .get_storage_at(CONTRACT.into(), slot, BlockNumber::Latest)
+                .unwrap()
+        };
+        assert_eq!(storage_at("0x1"), U256::zero().into());
+        handler.send_transaction(call_contract()).unwrap();
+        assert_eq!(storage_at("0x1"), U256::from(42).into());
+        assert_eq!(storage_at("1"), U256::from(42).into());
+        assert_eq!(storage_at("0x2"), U256::zero().into());
+    }
+
+    #[test]
+    fn test_get_proof() {
+        // SSTORE(1, 42) STOP
+        let handler = handler_with_code(&hex!("602a600155 00"));
+        handler.send_transaction(call_contract()).unwrap();
+        let proof = handler
+            .get_proof(CONTRACT.into(), vec![U256::one().into()], BlockNumber::Latest)
+            .unwrap();
+        let storage = &proof.storage_proof[0];
+        assert_eq!(storage.value, U256::from(42).into());
+        let nodes = storage.proof.iter().cloned().map(Bytes::to_vec).collect::<Vec<_>>();
+        let value = verify_proof(&proof.storage_hash, &U256::one().to_bytes_be(), &nodes);
+        assert_eq!(value.unwrap(), Some(vec![42]));
+
+        let nodes = proof
+            .account_proof
+            .iter()
+            .cloned()
+            .map(Bytes::to_vec)
+            .collect::<Vec<_>>();
+        let chain = handler.chain.lock().unwrap();
+        let code_hash = keccak256(&chain.code(&U256::from(&Address::from(CONTRACT))));
+        drop(chain);
+        let account = verify_proof(&keccak256(&nodes[0]), &CONTRACT, &nodes).unwrap();
+        assert_eq!(account, Some(to_rlp(&TrieAccount {
+            nonce:        0,
+            balance:      U256::zero(),
+            storage_root: proof.storage_hash,
+            code_hash,
+        })
+        .unwrap()));
+    }
+
+    #[test]
+    fn test_get_code() {
+        let handler = handler_with_code(&hex!("602a"));
+        let code = handler.get_code(CONTRACT.into(), BlockNumber::Latest).unwrap();
+        assert_eq!(code, hex!("602a").to_vec().into());
+        let code = handler.get_code(Address::default(), BlockNumber::Latest);
+        assert_eq!(serde_json::to_value(code.unwrap()).unwrap(), "0x");
+    }
+
+    #[test]
+    fn test_get_code_created() {
+        // Init code returning the runtime code `2a`
+        let handler = handler_with_code(&[]);
+        let request = CallRequest {
+            data: hex!("602a6000526001601ff3").to_vec().into(),
+            ..CallRequest::default()
+        };
+        let hash = handler.send_transaction(request).unwrap();
+        let address = handler
+            .get_transaction_receipt(hash.into_inner())
+            .unwrap()
+            .unwrap()
+            .contract_address
+            .unwrap();
+        let code = handler.get_code(address, BlockNumber::Latest).unwrap();
+        assert_eq!(code, vec![0x2a].into());
+    }
+
+    #[test]
+    fn test_get_nonce_pending() {
+        let handler = handler_with_code(&[]);
+        let sender = Address::from(hex!("00000000000000000000000000000000000000aa"));
+        handler.hardhat_impersonate_account(sender.clone()).unwrap();
+        let request = CallRequest {
+            from: sender.clone(),
+            ..call_contract()
+        };
+        handler.send_transaction(request).unwrap();
+        let nonce = |block_number| handler.get_nonce(sender.clone(), block_number).unwrap();
+        assert_eq!(nonce(BlockNumber::Pending), 1.into());
+        assert_eq!(nonce(BlockNumber::Latest), 0.into());
+        handler.evm_mine(None).unwrap();
+        assert_eq!(nonce(BlockNumber::Latest), 1.into());
+    }
+
+    #[test]
+    fn test_block_number_mine() {
+        let handler = handler_with_code(&[]);
+        handler.header.write().unwrap().header.number = 100;
+        assert_eq!(handler.block_number().unwrap(), 100.into());
+        handler.evm_mine(None).unwrap();
+        assert_eq!(handler.block_number().unwrap(), 101.into());
+    }
+
+    #[test]
+    fn test_get_block_by_number() {
+        let handler = handler_with_code(&hex!("00"));
+        let hash = handler.send_transaction(call_contract()).unwrap().into_inner();
+        handler.evm_mine(None).unwrap();
+        let block = |block_number, full| {
+            handler
+                .get_block_by_number(block_number, full)
+                .unwrap()
+                .unwrap()
+        };
+        let mined = block(BlockNumber::Number(0), false);
+        assert_eq!(mined.header.gas_used, 21000);
+        assert_eq!(mined.transactions, vec![BlockTransaction::Hash(hash.clone())]);
+        assert_eq!(mined.base_fee_per_gas, Some(U256::zero().into()));
+        let full = block(BlockNumber::Earliest, true);
+        assert_eq!(full.hash, mined.hash);
+        match &full.transactions[..] {
+            [BlockTransaction::Full(transaction)] => {
+                assert_eq!(transaction.hash, hash);
+                assert_eq!(transaction.block_hash, mined.hash);
+                assert_eq!(transaction.transaction.to, Address::from(CONTRACT));
+            }
+            transactions => panic!("unexpected transactions {:?}", transactions),
+        }
+
+        let latest = block(BlockNumber::Latest, true);
+        assert_eq!(latest.header.number, 1);
+        assert_eq!(latest.header.parent_hash, mined.hash);
+        assert_eq!(latest.header.gas_used, 0);
+        assert!(latest.transactions.is_empty());
+        let by_hash = handler.get_block_by_hash(mined.hash.clone(), false).unwrap();
+        assert_eq!(by_hash, Some(mined));
+        assert_eq!(
+            handler
+                .get_block_by_number(BlockNumber::Number(2), false)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mine_queued_transactions() {
+        let handler = handler_with_code(&hex!("00"));
+        let hashes = (0..2)
+            .map(|_| handler.send_transaction(call_contract()).unwrap().into_inner())
+            .collect::<Vec<_>>();
+        assert_eq!(handler.block_number().unwrap(), 0.into());
+        handler.evm_mine(Some(1000)).unwrap();
+        assert_eq!(handler.block_number().unwrap(), 1.into());
+
+        let mined = handler
+            .get_block_by_number(BlockNumber::Number(0), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            mined.transactions,
+            vec![
+                BlockTransaction::Hash(hashes[0].clone()),
+                BlockTransaction::Hash(hashes[1].clone())
+            ]
+        );
+        assert_eq!(mined.header.gas_used, 42000);
+        assert_eq!(mined.header.timestamp, 1000);
+        for (index, hash) in hashes.iter().enumerate() {
+            let receipt = handler.get_transaction_receipt(hash.clone()).unwrap().unwrap();
+            assert_eq!(receipt.block_hash, Some(mined.hash.clone()));
+            assert_eq!(receipt.transaction_index, (index as u64).into());
+            assert_eq!(receipt.cumulative_gas_used, (21000 * (index as u64 + 1)).into());
+        }
+        assert!(handler.header.read().unwrap().header.timestamp > 1000);
+    }
+
+    #[test]
+    fn test_automine() {
+        let handler = handler_with_code(&hex!("00"));
+        assert!(handler.evm_set_automine(true).unwrap());
+        let hash = handler.send_transaction(call_contract()).unwrap().into_inner();
+        assert_eq!(handler.block_number().unwrap(), 1.into());
+        let receipt = handler.get_transaction_receipt(hash).unwrap().unwrap();
+        assert_eq!(receipt.block_number, Some(0.into()));
+        let nonce = handler
+            .get_nonce(Address::default(), BlockNumber::Latest)
+            .unwrap();
+        assert_eq!(nonce, 1.into());
+    }
+
+    #[test]
+    fn test_chain_id() {
+        let handler = handler_with_code(&[]);
+        assert_eq!(handler.net_version().unwrap(), "1337");
+        assert_eq!(
+            serde_json::to_value(handler.chain_id().unwrap()).unwrap(),
+            "0x539"
+        );
+    }
+
+    #[test]
+    fn test_evm_snapshot_revert() {
+        // SSTORE(0, CALLVALUE) STOP
+        let mut chain = Fork::from(Empty);
+        chain.set_code(&U256::from(&Address::from(CONTRACT)), &hex!("34600055 00"));
+        chain.set_balance(&U256::zero(), &U256::from(100));
+        let handler = handler(chain);
+        let send = |value: u64| {
+            let request = CallRequest {
+                value: U256::from(value).into(),
+                ..call_contract()
+            };
+            handler.send_transaction(request).unwrap().into_inner()
+        };
+        let storage = || {
+            handler
+                .get_storage_at(CONTRACT.into(), U256::zero().into(), BlockNumber::Latest)
+                .unwrap()
+        };
+        let nonce = || {
+            handler
+                .get_nonce(Address::default(), BlockNumber::Pending)
+                .unwrap()
+        };
+        send(1);
+        let first = handler.evm_snapshot().unwrap();
+        send(2);
+        let second = handler.evm_snapshot().unwrap();
+        let hash = send(3);
+        assert_eq!(storage(), U256::from(3).into());
+        assert_eq!(nonce(), 3.into());
+
+        assert!(handler.evm_revert(second).unwrap());
+        assert_eq!(storage(), U256::from(2).into());
+        assert_eq!(handler.get_transaction_receipt(hash).unwrap(), None);
+        assert!(handler.evm_revert(first.clone()).unwrap());
+        assert_eq!(storage(), U256::from(1).into());
+        assert_eq!(nonce(), 1.into());
+        assert_eq!(
+            handler.get_balance(CONTRACT.into(), BlockNumber::Latest).unwrap(),
+            U256::from(1).into()
+        );
+
+        // Snapshots are consumed by reverting
+        assert!(!handler.evm_revert(first).unwrap());
+        assert!(!handler.evm_revert(0.into()).unwrap());
+    }
+
+    #[test]
+    fn test_evm_increase_time() {
+        // Vesting: reverts unless TIMESTAMP >= 1000
+        // PUSH2 1000 TIMESTAMP LT PUSH1 9 JUMPI STOP JUMPDEST REVERT(0, 0)
+        let handler = handler_with_code(&hex!("6103e8 42 10 6009 57 00 5b 60006000fd"));
+        handler.evm_set_next_block_timestamp(400).unwrap();
+        assert!(handler.call(call_contract(), None).is_err());
+        assert_eq!(handler.evm_increase_time(500).unwrap(), 900);
+        assert!(handler.call(call_contract(), None).is_err());
+        assert_eq!(handler.evm_increase_time(100).unwrap(), 1000);
+        assert!(handler.call(call_contract(), None).is_ok());
+    }
+
+    #[test]
+    fn test_hardhat_set_balance() {
+        let handler = handler(Fork::from(Empty));
+        let balance = U256::from(1_000_000);
+        assert!(handler
+            .hardhat_set_balance(CONTRACT.into(), balance.clone().into())
+            .unwrap());
+        let result = handler.get_balance(CONTRACT.into(), BlockNumber::Latest);
+        assert_eq!(result.unwrap(), balance.into());
+    }
+
+    #[test]
+    fn test_hardhat_set_storage_at() {
+        // MSTORE(0, SLOAD(3)) RETURN(0, 32)
+        let handler = handler_with_code(&hex!("600354 600052 60206000f3"));
+        assert!(handler
+            .hardhat_set_storage_at(CONTRACT.into(), U256::from(3).into(), U256::from(7).into())
+            .unwrap());
+        let result = handler.call(call_contract(), None).unwrap();
+        assert_eq!(result, U256::from(7).to_bytes_be().to_vec().into());
+    }
+
+    #[test]
+    fn test_impersonate_account() {
+        let handler = handler_with_code(&[]);
+        let sender = Address::from(hex!("00000000000000000000000000000000000000aa"));
+        let request = CallRequest {
+            from: sender.clone(),
+            ..call_contract()
+        };
+        let error = handler.send_transaction(request.clone()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+
+        assert!(handler.hardhat_impersonate_account(sender.clone()).unwrap());
+        assert!(!handler.hardhat_impersonate_account(sender.clone()).unwrap());
+        let hash = handler.send_transaction(request.clone()).unwrap();
+        let receipt = handler
+            .get_transaction_receipt(hash.into_inner())
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.from, sender);
+
+        assert!(handler
+            .hardhat_stop_impersonating_account(sender)
+            .unwrap());
+        assert!(handler.send_transaction(request).is_err());
+    }
+
+    /// Handle a JSON-RPC request the way the server does
+    fn request(handler: RpcHandler, method: &str, params: &serde_json::Value) -> serde_json::Value {
+        let mut io_handler = jsonrpc_core::IoHandler::new();
+        io_handler.extend_with(handler.to_delegate());
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response = io_handler
+            .handle_request_sync(&request.to_string())
+            .unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    fn test_send_raw_transaction() {
+        // Dynamic fee transaction on chain 1337 sending 5 wei to the contract,
+        // with a maximum fee of 2 and priority fee of 1, signed by `0x4646..46`
+        let raw = hex!(
+            "02f865820539800102830186a0940f572e5295c57f15886f9b263e2f6d2d6c7b5ec60580c080a0f973a0b8
+             7062c389d125d8199e803b832b6ac6bf7867a4f6cd87506060fc4c58a0705dd662b0da07c0bff3325b7a51
+             b585d967e0660c7ce8a7dd052bc7d87da8bb"
+        );
+        let sender = u256h!("0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f");
+        let handler = handler_with_code(&[]);
+        handler
+            .chain
+            .lock()
+            .unwrap()
+            .set_balance(&sender, &U256::from(1_000_000));
+        let hash = handler
+            .send_raw_transaction(raw.to_vec().into())
+            .unwrap();
+        assert_eq!(
+            hash,
+            u256h!("fae29c7a61cd06fd9f287a779cba28180600984dbc4ce4f0a551cefb2b202c5d")
+        );
+        let receipt = handler.get_transaction_receipt(hash).unwrap().unwrap();
+        assert_eq!(receipt.status, Some(TransactionStatus::Success));
+        assert_eq!(receipt.from, Address::from(&sender));
+        assert_eq!(receipt.gas_used, 21000.into());
+        {
+            let chain = handler.chain.lock().unwrap();
+            // Without a base fee only the priority fee is paid
+            assert_eq!(chain.balance(&sender), U256::from(1_000_000 - 5 - 21000));
+            assert_eq!(
+                chain.balance(&U256::from(&Address::from(CONTRACT))),
+                U256::from(5)
+            );
+            assert_eq!(chain.nonce(&sender), 1);
+        }
+
+        // Replaying it fails
+        let error = handler
+            .send_raw_transaction(raw.to_vec().into())
+            .unwrap_err();
+        assert_eq!(error.message, "nonce too low");
+    }
+
+    #[test]
+    fn test_send_transaction_signed() {
+        let handler = RpcHandler {
+            accounts: derive_accounts(DEFAULT_MNEMONIC, 1).unwrap(),
+            ..handler_with_code(&[])
+        };
+        let from = Address::from(hex!("f39fd6e51aad88f6f4ce6ab8827279cfffb92266"));
+        assert_eq!(handler.accounts().unwrap(), vec![from.clone()]);
+        let request = CallRequest {
+            from: from.clone(),
+            gas: Some(21000.into()),
+            ..call_contract()
+        };
+        let hash = handler.send_transaction(request).unwrap().into_inner();
+        // Hash of the raw transaction signed with EIP-155 replay protection
+        assert_eq!(
+            hash,
+            u256h!("634b1ccee7be26bb7403296f632acaa711a2eb8d3dd3306a7ebd2aa0eb57047f")
+        );
+        let receipt = handler.get_transaction_receipt(hash).unwrap().unwrap();
+        assert_eq!(receipt.from, from);
+    }
+
+    #[test]
+    fn test_send_transaction_default_gas_price() {
+        let handler = RpcHandler {
+            gas_price: U256::from(5),
+            ..handler_with_code(&[])
+        };
+        assert_eq!(handler.gas_price().unwrap(), U256::from(5).into());
+        handler
+            .chain
+            .lock()
+            .unwrap()
+            .set_balance(&U256::zero(), &U256::from(1_000_000));
+        let request = CallRequest {
+            gas: Some(21000.into()),
+            ..call_contract()
+        };
+        let hash = handler.send_transaction(request).unwrap().into_inner();
+        let transaction = handler.get_transaction_by_hash(hash).unwrap().unwrap();
+        assert_eq!(transaction.transaction.gas_price, 5);
+        let balance = handler.chain.lock().unwrap().balance(&U256::zero());
+        assert_eq!(balance, U256::from(1_000_000 - 21000 * 5));
+    }
+
+    #[test]
+    fn test_sign() {
+        let handler = RpcHandler {
+            accounts: derive_accounts(DEFAULT_MNEMONIC, 1).unwrap(),
+            ..handler_with_code(&[])
+        };
+        let from = handler.accounts[0].address.clone();
+        let signature = handler.sign(from.clone(), b"hello".to_vec().into()).unwrap();
+        assert_eq!(signature.as_slice().len(), 65);
+        let personal = handler
+            .personal_sign(b"hello".to_vec().into(), from.clone())
+            .unwrap();
+        assert_eq!(personal, signature);
+
+        // Recover the signer with the precompile
+        let signature = signature.as_slice();
+        let mut input = [0_u8; 128];
+        input[..32].copy_from_slice(&message_hash(b"hello"));
+        input[63] = signature[64];
+        input[64..].copy_from_slice(&signature[..64]);
+        let signer = crate::evm::precompiles::ecrecover(&input);
+        assert_eq!(&signer[12..], from.as_slice());
+
+        let error = handler
+            .sign(Address::default(), b"hello".to_vec().into())
+            .unwrap_err();
+        assert_eq!(error.message, "unknown account");
+    }
+
+    #[test]
+    fn test_send_raw_transaction_wrong_chain() {
+        // Example from EIP-155, signed for mainnet
+        let raw = hex!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        let handler = handler_with_code(&[]);
+        let error = handler
+            .send_raw_transaction(raw.to_vec().into())
+            .unwrap_err();
+        assert_eq!(error.message, "invalid chain id");
+        let error = handler
+            .send_raw_transaction(hex!("02c0").to_vec().into())
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_send_transaction_malformed() {
+        let malformed = serde_json::json!([
+            [],
+            [{ "value": "0xzz" }],
+            [{ "value": format!("0x1{}", "0".repeat(64)) }],
+            [{ "to": "0x1234" }],
+            [{ "data": "0xabc" }],
+            [{ "gas": "many" }],
+        ]);
+        for params in malformed.as_array().unwrap() {
+            let response = request(handler_with_code(&[]), "eth_sendTransaction", params);
+            assert_eq!(
+                response["error"]["code"],
+                serde_json::json!(-32602),
+                "params {}",
+                params
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_gas() {
+        // PUSH1 42 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        let handler = handler_with_code(&hex!("602a60005260206000f3"));
+        let call = CallRequest {
+            data: hex!("0100").to_vec().into(),
+            ..call_contract()
+        };
+        let estimate = handler.estimate_gas(call).unwrap();
+        // PUSH1 PUSH1 MSTORE (with one word of memory) PUSH1 PUSH1 RETURN
+        assert_eq!(estimate, (21000 + 16 + 4 + (3 + 3 + 6 + 3 + 3)).into());
+    }
+
+    #[test]
+    fn test_estimate_gas_always_reverts() {
+        // REVERT(0, 0)
+        let handler = handler_with_code(&hex!("60006000fd"));
+        let error = handler.estimate_gas(call_contract()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ServerError(3));
+    }
+
+    #[test]
+    fn test_trace_transaction() {
+        // SSTORE(0, SLOAD(0) + CALLER) STOP
+        let handler = RpcHandler {
+            accounts: derive_accounts(DEFAULT_MNEMONIC, 1).unwrap(),
+            ..handler_with_code(&hex!("33 600054 01 600055 00"))
+        };
+        let request = CallRequest {
+            from: handler.accounts[0].address.clone(),
+            gas: Some(100_000.into()),
+            ..call_contract()
+        };
+        let hash = handler.send_transaction(request.clone()).unwrap().into_inner();
+        handler.evm_mine(None).unwrap();
+        let later = handler.send_transaction(request).unwrap().into_inner();
+        let gas_used = |hash: &U256| {
+            let receipt = handler.get_transaction_receipt(hash.clone()).unwrap();
+            *receipt.unwrap().gas_used.as_ref()
+        };
+        // Setting the slot costs more than changing it again
+        assert!(gas_used(&hash) > gas_used(&later));
+
+        // Executed by the same sender on the state before it, even though
+        // its block was sealed and another transaction changed the slot
+        let config = TraceConfig {
+            disable_stack: true,
+            ..TraceConfig::default()
+        };
+        let trace = handler.trace_transaction(hash.clone(), Some(config)).unwrap();
+        assert_eq!(trace.gas, gas_used(&hash));
+        assert!(!trace.failed);
+        assert_eq!(trace.return_value, "");
+        let ops = trace
+            .struct_logs
+            .iter()
+            .map(|log| log.op.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["CALLER", "PUSH1", "SLOAD", "ADD", "PUSH1", "SSTORE", "STOP"]);
+        assert!(trace.struct_logs.iter().all(|log| log.stack.is_none()));
+        // Tracing does not change the state
+        assert_eq!(
+            handler
+                .get_storage_at(CONTRACT.into(), U256::zero().into(), BlockNumber::Latest)
+                .unwrap(),
+            (U256::from(&handler.accounts[0].address) * U256::from(2)).into()
+        );
+
+        let path = std::env::temp_dir().join(format!("sutro-trace-{}.json", std::process::id()));
+        let config = TraceConfig {
+            output_file: Some(path.clone()),
+            ..TraceConfig::default()
+        };
+        let outcome = handler
+            .trace_transaction(hash.clone(), Some(config.clone()))
+            .unwrap();
+        let written = std::fs::read(&path).unwrap();
+        // Existing files are not overwritten
+        let error = handler
+            .trace_transaction(hash.clone(), Some(config))
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert!(outcome.struct_logs.is_empty());
+        assert_eq!(outcome.gas, gas_used(&hash));
+        let written: TransactionTrace = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written.gas, outcome.gas);
+        assert_eq!(written.struct_logs.len(), 7);
+        assert!(written.struct_logs.iter().all(|log| log.stack.is_some()));
+    }
+
+    #[test]
+    fn test_transaction_by_hash() {
+        let handler = handler_with_code(&hex!("00"));
+        let hash = handler.send_transaction(call_contract()).unwrap();
+        let transaction = handler
+            .get_transaction_by_hash(hash.clone().into_inner())
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.hash, hash.into_inner());
+        assert_eq!(transaction.transaction.to, Address::from(CONTRACT));
+        assert!(handler
+            .get_transaction_by_hash(U256::one())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_trace_transaction_imported() {
+        // SSTORE(0, CALLER) STOP
+        let handler = handler_with_code(&hex!("33 600055 00"));
+        let account = &derive_accounts(DEFAULT_MNEMONIC, 1).unwrap()[0];
+        let raw = SignedTransaction {
+            gas_limit: 100_000,
+            to: Some(CONTRACT.into()),
+            ..SignedTransaction::default()
+        }
+        .sign_legacy(&account.secret_key)
+        .unwrap();
+        let transaction: Transaction = from_rlp(&raw).unwrap();
+        let hash = transaction.rlp_hash();
+        handler
+            .header
+            .write()
+            .unwrap()
+            .transactions
+            .push(transaction);
+        handler.evm_mine(None).unwrap();
+
+        // Found in the sealed block, with the sender from the signature
+        let trace = handler.trace_transaction(hash, None).unwrap();
+        assert!(!trace.failed);
+        assert_eq!(
+            trace.struct_logs[1].stack,
+            Some(vec![hex::encode(U256::from(&account.address).to_bytes_be())])
+        );
+    }
+
+    #[test]
+    fn test_check_fees() {
+        let transaction = SignedTransaction {
+            max_priority_fee_per_gas: U256::from(2),
+            max_fee_per_gas: U256::from(10),
+            ..SignedTransaction::default()
+        };
+        assert!(check_fees(&transaction, &U256::from(10)).is_ok());
+        let error = check_fees(&transaction, &U256::from(11)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert_eq!(error.message, "max fee per gas less than block base fee");
+        let transaction = SignedTransaction {
+            max_priority_fee_per_gas: U256::from(11),
+            ..transaction
+        };
+        let error = check_fees(&transaction, &U256::zero()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert_eq!(
+            error.message,
+            "max priority fee per gas higher than max fee per gas"
+        );
+    }
+
+    #[test]
+    fn test_trace_transaction_unknown() {
+        let handler = handler_with_code(&[]);
+        let error = handler.trace_transaction(U256::one(), None).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_transaction_receipt() {
+        // LOG1(0, 0, 7)
+        let handler = handler_with_code(&hex!("600760006000a1"));
+        let hash = handler.send_transaction(call_contract()).unwrap();
+        let receipt = handler
+            .get_transaction_receipt(hash.into_inner())
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.status, Some(TransactionStatus::Success));
+        assert_eq!(receipt.gas_used, (21000 + 3 + 3 + 3 + 750).into());
+        assert_eq!(receipt.contract_address, None);
+        assert_eq!(receipt.logs.len(), 1);
+        assert_eq!(receipt.logs[0].topics, vec![U256::from(7)]);
+        assert!(receipt.logs_bloom.contains(&CONTRACT));
+        assert!(receipt.logs_bloom.contains(&U256::from(7).to_bytes_be()));
+        // The block includes the logs of its receipts
+        let header = &handler.header.read().unwrap().header;
+        assert_eq!(header.logs_bloom, receipt.logs_bloom);
+        assert_eq!(receipt.block_hash, Some(header.rlp_hash()));
+    }
+
+    #[test]
+    fn test_get_logs_by_topic() {
+        // LOG1(0, 0, CALLDATALOAD(0))
+        let handler = handler_with_code(&hex!("60003560006000a1"));
+        let hashes = [7, 8]
+            .iter()
+            .map(|&topic| {
+                let request = CallRequest {
+                    data: U256::from(topic).to_bytes_be().to_vec().into(),
+                    ..call_contract()
+                };
+                handler.send_transaction(request).unwrap().into_inner()
+            })
+            .collect::<Vec<_>>();
+        let filter: LogFilter = serde_json::from_value(serde_json::json!({
+            "fromBlock": "earliest",
+            "topics": ["0x0000000000000000000000000000000000000000000000000000000000000008"]
+        }))
+        .unwrap();
+        let logs = handler.get_logs(filter).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].address, Address::from(CONTRACT));
+        assert_eq!(logs[0].topics, vec![U256::from(8)]);
+        let block = logs[0].block.as_ref().unwrap();
+        assert_eq!(block.transaction_hash, hashes[1]);
+        assert_eq!(block.log_index, U256::one());
+        let logs = handler.get_logs(LogFilter::default()).unwrap();
+        assert_eq!(logs.len(), 2);
+    }
+
+    #[test]
+    fn test_get_logs_bloom() {
+        // LOG1(0, 0, 7)
+        let handler = handler_with_code(&hex!("600760006000a1"));
+        for _ in 0..2 {
+            handler.send_transaction(call_contract()).unwrap();
+            handler.evm_mine(None).unwrap();
+        }
+        let filter: LogFilter = serde_json::from_value(serde_json::json!({
+            "fromBlock": "earliest",
+            "topics": ["0x0000000000000000000000000000000000000000000000000000000000000007"]
+        }))
+        .unwrap();
+        assert_eq!(handler.get_logs(filter.clone()).unwrap().len(), 2);
+        // Only the bloom filter of the first block is consulted, not its logs
+        handler.blocks.write().unwrap()[0].header.logs_bloom = BloomFilter::empty();
+        let logs = handler.get_logs(filter).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block.as_ref().unwrap().block_number, 1.into());
+    }
+
+    #[test]
+    fn test_get_logs_after_revert() {
+        // LOG1(0, 0, 7)
+        let handler = handler_with_code(&hex!("600760006000a1"));
+        handler.send_transaction(call_contract()).unwrap();
+        let snapshot = handler.evm_snapshot().unwrap();
+        handler.send_transaction(call_contract()).unwrap();
+        assert_eq!(handler.get_logs(LogFilter::default()).unwrap().len(), 2);
+        assert!(handler.evm_revert(snapshot).unwrap());
+        assert_eq!(handler.get_logs(LogFilter::default()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_transaction_receipt_create() {
+        // Init code returning the runtime code `2a`
+        let handler = handler_with_code(&[]);
+        let request = CallRequest {
+            data: hex!("602a6000526001601ff3").to_vec().into(),
+            ..CallRequest::default()
+        };
+        let hash = handler.send_transaction(request).unwrap();
+        let receipt = handler
+            .get_transaction_receipt(hash.into_inner())
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.status, Some(TransactionStatus::Success));
+        let address = receipt.contract_address.unwrap();
+        let chain = handler.chain.lock().unwrap();
+        assert_eq!(chain.code(&U256::from(&address)), vec![0x2a]);
+    }
+
+    #[test]
+    fn test_transaction_receipt_unknown() {
+        let handler = handler_with_code(&[]);
+        assert_eq!(handler.get_transaction_receipt(U256::one()).unwrap(), None);
+    }
+}
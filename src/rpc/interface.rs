@@ -1,10 +1,11 @@
 use crate::{
     chain::types::{
         rpc::{
-            AccountRange, BlockNumber, Bytes, CallRequest, GenesisConfig, Hex, Log, LogFilter,
-            StorageRange, Transaction, TransactionReceipt,
+            AccountProof, AccountRange, BlockNumber, Bytes, CallRequest, GenesisConfig, Hex,
+            HexData, HexMid, Log, LogFilter, StorageRange, TraceConfig, TransactionReceipt,
+            TransactionTrace,
         },
-        Address, FullBlock,
+        Address, BlockTransaction, RpcBlock, RpcTransaction,
     },
     prelude::*,
 };
@@ -18,26 +19,54 @@ pub trait EthereumRpc {
     fn client_version(&self) -> RpcResult<String>;
 
     /// See <https://eth.wiki/json-rpc/API#net_version>
-    #[rpc(name = "net_version", alias("eth_chainId"))]
+    #[rpc(name = "net_version")]
     fn net_version(&self) -> RpcResult<String>;
 
+    /// See <https://eips.ethereum.org/EIPS/eip-695>
+    #[rpc(name = "eth_chainId")]
+    fn chain_id(&self) -> RpcResult<Hex<u64>>;
+
+    /// See <https://eth.wiki/json-rpc/API#eth_accounts>
+    #[rpc(name = "eth_accounts")]
+    fn accounts(&self) -> RpcResult<Vec<Address>>;
+
+    /// See <https://eth.wiki/json-rpc/API#eth_sign>
+    #[rpc(name = "eth_sign")]
+    fn sign(&self, address: Address, data: HexData) -> RpcResult<HexData>;
+
+    /// Like `eth_sign` with the arguments swapped, see
+    /// <https://geth.ethereum.org/docs/rpc/ns-personal#personal_sign>
+    #[rpc(name = "personal_sign")]
+    fn personal_sign(&self, data: HexData, address: Address) -> RpcResult<HexData>;
+
     /// See <https://eth.wiki/json-rpc/API#eth_sendtransaction>
+    ///
+    /// The request has the same fields as for `eth_call`, a missing `to`
+    /// creates a contract.
     #[rpc(name = "eth_sendTransaction")]
-    fn send_transaction(&self, tx: Transaction) -> RpcResult<Hex<U256>>;
+    fn send_transaction(&self, tx: CallRequest) -> RpcResult<Hex<U256>>;
 
     #[rpc(name = "eth_blockNumber")]
     fn block_number(&self) -> RpcResult<Hex<u64>>;
 
+    /// See <https://eth.wiki/json-rpc/API#eth_getblockbynumber>
+    ///
+    /// With `full` the transactions are returned as objects, otherwise as
+    /// hashes.
     #[rpc(name = "eth_getBlockByNumber")]
     fn get_block_by_number(
         &self,
         block_number: BlockNumber,
         full: bool,
-    ) -> RpcResult<Option<FullBlock>>;
+    ) -> RpcResult<Option<RpcBlock<BlockTransaction>>>;
 
     /// See <https://eth.wiki/json-rpc/API#eth_getblockbyhash>
     #[rpc(name = "eth_getBlockByHash")]
-    fn get_block_by_hash(&self, block_hash: U256, full: bool) -> RpcResult<Option<FullBlock>>;
+    fn get_block_by_hash(
+        &self,
+        block_hash: U256,
+        full: bool,
+    ) -> RpcResult<Option<RpcBlock<BlockTransaction>>>;
 
     #[rpc(name = "eth_gasPrice")]
     fn gas_price(&self) -> RpcResult<Hex<U256>>;
@@ -49,6 +78,15 @@ pub trait EthereumRpc {
     #[rpc(name = "eth_getBalance")]
     fn get_balance(&self, address: Address, block_number: BlockNumber) -> RpcResult<Hex<U256>>;
 
+    /// See <https://eth.wiki/json-rpc/API#eth_getstorageat>
+    #[rpc(name = "eth_getStorageAt")]
+    fn get_storage_at(
+        &self,
+        address: Address,
+        slot: HexMid,
+        block_number: BlockNumber,
+    ) -> RpcResult<Hex<U256>>;
+
     /// See <https://eth.wiki/json-rpc/API#eth_getlogs>
     #[rpc(name = "eth_getLogs")]
     fn get_logs(&self, filter: LogFilter) -> RpcResult<Vec<Log>>;
@@ -57,13 +95,33 @@ pub trait EthereumRpc {
     #[rpc(name = "eth_getCode")]
     fn get_code(&self, address: Address, block_number: BlockNumber) -> RpcResult<Bytes>;
 
+    /// See <https://eips.ethereum.org/EIPS/eip-1186>
+    #[rpc(name = "eth_getProof")]
+    fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<Hex<U256>>,
+        block_number: BlockNumber,
+    ) -> RpcResult<AccountProof>;
+
+    /// See <https://eth.wiki/json-rpc/API#eth_call>
+    #[rpc(name = "eth_call")]
+    fn call(&self, call: CallRequest, block_number: Option<BlockNumber>) -> RpcResult<Bytes>;
+
     /// See <https://eth.wiki/json-rpc/API#eth_estimategas>
     #[rpc(name = "eth_estimateGas")]
-    fn estimate_gas(&self, call: CallRequest) -> RpcResult<Hex<U256>>;
+    fn estimate_gas(&self, call: CallRequest) -> RpcResult<Hex<u64>>;
 
     /// See <https://eth.wiki/json-rpc/API#eth_sendrawtransaction>
     #[rpc(name = "eth_sendRawTransaction")]
-    fn send_raw_transaction(&self, data: Vec<u8>) -> RpcResult<U256>;
+    fn send_raw_transaction(&self, data: HexData) -> RpcResult<U256>;
+
+    /// See <https://eth.wiki/json-rpc/API#eth_gettransactionbyhash>
+    #[rpc(name = "eth_getTransactionByHash")]
+    fn get_transaction_by_hash(
+        &self,
+        transaction_hash: U256,
+    ) -> RpcResult<Option<RpcTransaction>>;
 
     /// See <https://eth.wiki/json-rpc/API#eth_gettransactionreceipt>
     #[rpc(name = "eth_getTransactionReceipt")]
@@ -85,15 +143,44 @@ pub trait EthereumRpc {
     #[rpc(name = "evm_increaseTime")]
     fn evm_increase_time(&self, amount_sec: u64) -> RpcResult<u64>;
 
+    /// See <https://hardhat.org/hardhat-network/docs/reference#evm_setnextblocktimestamp>
+    #[rpc(name = "evm_setNextBlockTimestamp")]
+    fn evm_set_next_block_timestamp(&self, timestamp: u64) -> RpcResult<u64>;
+
     #[rpc(name = "evm_mine")]
     fn evm_mine(&self, timestamp: Option<u64>) -> RpcResult<Hex<u64>>;
 
+    /// See <https://hardhat.org/hardhat-network/docs/reference#evm_setautomine>
+    #[rpc(name = "evm_setAutomine")]
+    fn evm_set_automine(&self, enabled: bool) -> RpcResult<bool>;
+
     #[rpc(name = "evm_unlockUnknownAccount")]
     fn evm_unlock_unknown_account(&self, address: Address) -> RpcResult<bool>;
 
     #[rpc(name = "evm_lockUnknownAccount")]
     fn evm_lock_unknown_account(&self, address: Address) -> RpcResult<bool>;
 
+    // Hardhat extensions for testing
+    //
+    // See <https://hardhat.org/hardhat-network/docs/reference#hardhat-network-methods>
+
+    #[rpc(name = "hardhat_impersonateAccount")]
+    fn hardhat_impersonate_account(&self, address: Address) -> RpcResult<bool>;
+
+    #[rpc(name = "hardhat_stopImpersonatingAccount")]
+    fn hardhat_stop_impersonating_account(&self, address: Address) -> RpcResult<bool>;
+
+    #[rpc(name = "hardhat_setBalance")]
+    fn hardhat_set_balance(&self, address: Address, balance: Hex<U256>) -> RpcResult<bool>;
+
+    #[rpc(name = "hardhat_setStorageAt")]
+    fn hardhat_set_storage_at(
+        &self,
+        address: Address,
+        slot: Hex<U256>,
+        value: Hex<U256>,
+    ) -> RpcResult<bool>;
+
     // Ethereum Test
     //
     // See <https://github.com/ethereum/retesteth/wiki/RPC-Methods>
@@ -133,4 +220,14 @@ pub trait EthereumRpc {
     /// will accept both with and without, but will always encode with.
     #[rpc(name = "debug_getBlockRlp")]
     fn get_block_rlp(&self, block_number: u64) -> RpcResult<Bytes>;
+
+    /// Note: Only the struct logger is supported, the `tracer` option is
+    /// ignored. With the `outputFile` option the struct logs are written to
+    /// that file during execution and left out of the result.
+    #[rpc(name = "debug_traceTransaction")]
+    fn trace_transaction(
+        &self,
+        transaction_hash: U256,
+        config: Option<TraceConfig>,
+    ) -> RpcResult<TransactionTrace>;
 }
@@ -46,18 +46,24 @@ impl<M: Metadata> Middleware<M> for Logger {
                         request_method,
                         HumanDuration::from(start.elapsed())
                     );
-                    if let Some(Response::Single(Output::Failure(response))) = &response {
-                        // TODO: Batch responses
-                        warn!(
-                            "Responding error {}",
-                            to_string_pretty(&response).unwrap_or_default()
-                        );
-                    } else {
-                        trace!(
-                            "Response {}",
-                            to_string_pretty(&response).unwrap_or_default()
-                        );
+                    // Calls in a batch fail independently
+                    let outputs = match &response {
+                        Some(Response::Single(output)) => std::slice::from_ref(output),
+                        Some(Response::Batch(outputs)) => outputs.as_slice(),
+                        None => &[],
+                    };
+                    for output in outputs {
+                        if let Output::Failure(failure) = output {
+                            warn!(
+                                "Responding error {}",
+                                to_string_pretty(failure).unwrap_or_default()
+                            );
+                        }
                     }
+                    trace!(
+                        "Response {}",
+                        to_string_pretty(&response).unwrap_or_default()
+                    );
                     response
                 })
                 .in_current_span(),
@@ -4,11 +4,15 @@
 //!
 //! Uses <https://github.com/paritytech/jsonrpc>
 
+mod accounts;
 mod handler;
 mod interface;
 mod logger;
 
 pub use self::{
+    accounts::{
+        derive_accounts, DevAccount, DEFAULT_ACCOUNTS, DEFAULT_BALANCE, DEFAULT_MNEMONIC,
+    },
     handler::RpcHandler,
     interface::{EthereumRpc, EthereumRpcClient},
     logger::Logger,
@@ -18,6 +22,10 @@ use jsonrpc_core::MetaIoHandler;
 use jsonrpc_core_client::transports::http;
 use jsonrpc_http_server::{AccessControlAllowOrigin, DomainsValidation, Server, ServerBuilder};
 
+/// Start a HTTP server for the handler
+///
+/// Batch requests are answered with an array of the responses in order, a
+/// failing call does not fail the others.
 pub fn serve(addr: &std::net::SocketAddr, rpc_handler: RpcHandler) -> AnyResult<Server> {
     let mut io_handler = MetaIoHandler::<(), Logger>::with_middleware(Logger::default());
     io_handler.extend_with(rpc_handler.to_delegate());
@@ -36,3 +44,44 @@ pub async fn client(url: &str) -> AnyResult<EthereumRpcClient> {
         .map_err(|err| anyhow!("Error: {}", err))
         .context("Connecting to RPC client")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chain::{Empty, Fork},
+        test::prelude::assert_eq,
+    };
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_batch_request() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let server = serve(&addr, handler::test::handler(Fork::from(Empty))).unwrap();
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": [] },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_noSuchMethod", "params": [] },
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_blockNumber", "params": [] },
+        ]);
+        let response: Value = reqwest::blocking::Client::new()
+            .post(format!("http://{}", server.address()))
+            .json(&batch)
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(
+            response,
+            json!([
+                { "jsonrpc": "2.0", "id": 1, "result": "0x539" },
+                {
+                    "jsonrpc": "2.0",
+                    "id": 2,
+                    "error": { "code": -32601, "message": "Method not found" },
+                },
+                { "jsonrpc": "2.0", "id": 3, "result": "0x0" },
+            ])
+        );
+        server.close();
+    }
+}
@@ -20,6 +20,10 @@ impl<'de> Deserializer<'de> {
         Self { input }
     }
 
+    pub const fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
     pub fn finish(&mut self) -> Result<(), Error> {
         if self.input.is_empty() {
             Ok(())
@@ -56,6 +60,17 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    /// Read the next item, bytes or a list, and return its full encoding
+    pub fn parse_item(&mut self) -> Result<&'de [u8], Error> {
+        let start = self.input;
+        if start.first().map_or(false, |prefix| *prefix >= 0xc0) {
+            self.parse_list()?;
+        } else {
+            self.parse_bytes()?;
+        }
+        Ok(&start[..start.len() - self.input.len()])
+    }
+
     pub fn parse_list(&mut self) -> Result<&'de [u8], Error> {
         match self.read(1)?[0] {
             b if b <= 0xbf => Err(Error::UnexpectedBytes),
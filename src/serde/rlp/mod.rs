@@ -2,4 +2,8 @@ pub mod de;
 mod error;
 pub mod ser;
 
-pub use self::{de::from_rlp, error::Error, ser::to_rlp};
+pub use self::{
+    de::from_rlp,
+    error::Error,
+    ser::{encode_list, to_rlp},
+};
@@ -57,21 +57,33 @@ impl<W: Write> Serializer<W> {
 
     fn pop(&mut self) -> Result<(), Error> {
         let v = self.stack.pop().unwrap();
-        match v.len() as u64 {
-            n if n <= 55 => self.write(&[0xc0 + (n as u8)])?,
-            n => {
-                let bytes = n.to_be_bytes();
-                let zeros = n.leading_zeros() as usize / 8;
-                let bytes = &bytes[zeros..];
-                self.write(&[0xf7 + (bytes.len() as u8)])?;
-                self.write(bytes)?;
-            }
-        }
+        self.write(&list_header(v.len() as u64))?;
         self.write(v.as_slice())?;
         Ok(())
     }
 }
 
+/// Encode a list from the concatenated encodings of its items
+pub fn encode_list(payload: &[u8]) -> Vec<u8> {
+    let mut result = list_header(payload.len() as u64);
+    result.extend_from_slice(payload);
+    result
+}
+
+fn list_header(length: u64) -> Vec<u8> {
+    match length {
+        n if n <= 55 => vec![0xc0 + (n as u8)],
+        n => {
+            let bytes = n.to_be_bytes();
+            let zeros = n.leading_zeros() as usize / 8;
+            let bytes = &bytes[zeros..];
+            let mut result = vec![0xf7 + (bytes.len() as u8)];
+            result.extend_from_slice(bytes);
+            result
+        }
+    }
+}
+
 impl<'a, W: Write> serde::Serializer for &'a mut Serializer<W> {
     type Error = Error;
     type Ok = ();
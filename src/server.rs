@@ -1,34 +1,176 @@
-use std::sync::RwLock;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Mutex, RwLock},
+};
 
-use super::{fetch::fetch, Command, Options};
-use crate::{chain::types::Block, prelude::*, rpc};
+use super::{
+    fetch::fetch,
+    replay::{run_tx, ReplayOptions},
+    state_test, Command, Options,
+};
+use crate::{
+    chain::{
+        types::{Address, Block, BlockHeader},
+        WriteableChainState,
+    },
+    evm::Hardfork,
+    prelude::*,
+    rpc,
+};
+
+/// Chain id used when not forking
+const CHAIN_ID: u64 = 1337;
 
 pub(super) async fn async_main(options: Options) -> AnyResult<()> {
+    require!(
+        options.fork_url.is_some() || options.block.is_none(),
+        anyhow!("--block requires --fork-url")
+    );
+    require!(
+        options.fork_url.is_some() || !options.clear_cache,
+        anyhow!("--clear-cache requires --fork-url")
+    );
     match options.command {
         Some(Command::Fetch { node, file }) => fetch(node, file).await,
-        Some(Command::Chain { fork }) => chain(fork).await,
-        None => unimplemented!(),
+        Some(Command::RunTx {
+            hash,
+            prefetch,
+            profile,
+            geth_trace,
+            strict,
+            resolve_selectors,
+        }) => {
+            let url = options
+                .fork_url
+                .ok_or_else(|| anyhow!("run-tx requires --fork-url"))?;
+            let replay_options = ReplayOptions {
+                hardfork:    options.hardfork,
+                clear_cache: options.clear_cache,
+                prefetch,
+                profile,
+                geth_trace,
+                strict,
+                resolve_selectors,
+            };
+            run_tx(&url, &options.cache_dir, hash, &replay_options).await
+        }
+        Some(Command::StateTest { files }) => state_test::run_files(&files),
+        Some(Command::Serve) | None => {
+            let accounts = rpc::derive_accounts(
+                options.mnemonic.as_deref().unwrap_or(rpc::DEFAULT_MNEMONIC),
+                options.accounts.unwrap_or(rpc::DEFAULT_ACCOUNTS),
+            )
+            .context("Deriving accounts")?;
+            serve(
+                options.fork_url,
+                options.block,
+                &options.cache_dir,
+                options.clear_cache,
+                options.hardfork,
+                accounts,
+            )
+            .await
+        }
     }
 }
 
-async fn chain(url: String) -> AnyResult<()> {
-    use crate::chain::ChainState;
+#[allow(clippy::too_many_lines)]
+async fn serve(
+    url: Option<String>,
+    block_number: Option<u64>,
+    cache_dir: &Path,
+    clear_cache: bool,
+    hardfork: Option<Hardfork>,
+    accounts: Vec<rpc::DevAccount>,
+) -> AnyResult<()> {
+    // Create a forked chain, or an empty one
+    let (mut chain, chain_id, fork): (Box<dyn WriteableChainState + Send>, _, _) =
+        if let Some(url) = url {
+            let client = rpc::client(&url).await?;
+            let chain_id = client
+                .chain_id()
+                .await
+                .map_err(|err| anyhow!("Error: {}", err))
+                .context("Fetching chain id")?
+                .into_inner();
+            let chain = crate::chain::fork(&url, cache_dir, block_number, clear_cache)
+                .await
+                .context("Forking chain")?;
+            (Box::new(chain), chain_id, Some(client))
+        } else {
+            let chain = crate::chain::new().await.context("Creating chain")?;
+            (Box::new(chain), CHAIN_ID, None)
+        };
+    info!("Using chain id {}", chain_id);
 
-    // Create a forked chain
-    // let chain = crate::chain::fork(&url).await.context("Forking chain")?;
-    // let block = chain.block();
-    // info!("Block info: {:#?}", block);
+    // Fund the accounts in the overlay
+    info!("Available accounts (address, private key):");
+    for account in &accounts {
+        info!(
+            "0x{} 0x{}",
+            hex::encode(account.address.as_slice()),
+            hex::encode(account.secret_key.serialize())
+        );
+    }
+    let addresses = accounts
+        .iter()
+        .map(|account| U256::from(&account.address))
+        .collect::<Vec<_>>();
 
-    // Create an empty chain
-    let chain = crate::chain::new();
+    // Reading the block and writing the balances may block on the node
+    let (chain, block, block_hash) = tokio::task::spawn_blocking(move || {
+        for address in &addresses {
+            chain.set_balance(address, &rpc::DEFAULT_BALANCE);
+        }
+        let block = chain.block();
+        let block_hash = chain.block_hash(block.number);
+        (chain, block, block_hash)
+    })
+    .await?;
+    info!("Block info: {:#?}", block);
+    info!(
+        "Using hardfork {:?}",
+        hardfork.unwrap_or_else(|| Hardfork::detect(chain_id, block.number))
+    );
+    // When forking, blocks are mined locally on top of the pinned block
+    let (number, parent_hash) = if fork.is_some() {
+        (block.number + 1, block_hash)
+    } else {
+        (block.number, U256::zero())
+    };
+    let header = Block {
+        header: BlockHeader {
+            parent_hash,
+            number,
+            timestamp: block.timestamp,
+            gas_limit: block.gas_limit,
+            beneficiary: Address::from(&block.coinbase),
+            ..BlockHeader::default()
+        },
+        ..Block::default()
+    };
 
     // Create an RPC server
     let rpc_handler = rpc::RpcHandler {
         client_version: "sutro/0.0.0".into(),
-        chain_id:       1337,
-        gas_price:      U256::zero(),
-        genesis:        RwLock::new(Block::default()),
-        header:         RwLock::new(Block::default()),
+        chain_id,
+        gas_price: U256::zero(),
+        genesis: RwLock::new(Block::default()),
+        header: RwLock::new(header),
+        chain: Mutex::new(chain),
+        receipts: RwLock::new(HashMap::new()),
+        logs: RwLock::new(Vec::new()),
+        transactions: RwLock::new(Vec::new()),
+        replaced: RwLock::new(Vec::new()),
+        blocks: RwLock::new(Vec::new()),
+        pending: RwLock::new(HashMap::new()),
+        snapshots: Mutex::new(Vec::new()),
+        accounts,
+        impersonated: RwLock::new(HashSet::new()),
+        hardfork,
+        automine: RwLock::new(true),
+        fork,
     };
     let addr = "0.0.0.0:8545".parse()?;
     let server = rpc::serve(&addr, rpc_handler)?;
@@ -0,0 +1,404 @@
+//! Runner for the JSON state tests of `ethereum/tests`
+//!
+//! See <https://ethereum-tests.readthedocs.io/en/latest/test_types/state_tests.html>
+//!
+//! Every post state of a test is run on its own: the `pre` accounts are
+//! written to a [`Fork`] of an empty chain, the transaction selected by the
+//! indexes is applied with the fork's rules and the state root and logs hash
+//! are compared to the expected ones. Tests the interpreter cannot run yet,
+//! because of the fork or the kind of transaction, are skipped with the
+//! reason.
+
+use crate::{
+    chain::{
+        types::{
+            rpc::{Bytes, Hex, Hexable},
+            Address, RpcTransaction, Transaction,
+        },
+        BlockInfo, ChainState, Empty, Fork, WriteableChainState,
+    },
+    evm::{Hardfork, Log},
+    prelude::*,
+    replay::replay,
+    rpc::DevAccount,
+    serde::{bytes, fixed_u256},
+};
+use secp256k1::SecretKey;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+struct StateTest {
+    env:         Env,
+    pre:         BTreeMap<Address, PreAccount>,
+    transaction: TestTransaction,
+    post:        BTreeMap<String, Vec<PostState>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_field_names)] // Field names of the test format
+struct Env {
+    current_coinbase:   Address,
+    current_difficulty: Hex<U256>,
+    current_gas_limit:  Hex<u64>,
+    current_number:     Hex<u64>,
+    current_timestamp:  Hex<u64>,
+    #[serde(default)]
+    current_base_fee:   Option<Hex<U256>>,
+}
+
+/// Transaction with the alternatives for data, gas limit and value
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestTransaction {
+    data:            Vec<Bytes>,
+    gas_limit:       Vec<Hex<u64>>,
+    #[serde(default)]
+    gas_price:       Option<Hex<U256>>,
+    #[serde(default)]
+    max_fee_per_gas: Option<Hex<U256>>,
+    nonce:           Hex<u64>,
+    secret_key:      Bytes,
+    /// Empty for contract creation
+    to:              String,
+    value:           Vec<Hex<U256>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostState {
+    /// State root after the transaction
+    hash:             Hex<U256>,
+    /// Hash of the RLP encoded logs
+    logs:             Hex<U256>,
+    indexes:          Indexes,
+    #[serde(default)]
+    expect_exception: Option<String>,
+}
+
+/// Indexes into the alternatives of [`TestTransaction`]
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct Indexes {
+    data:  usize,
+    gas:   usize,
+    value: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PreAccount {
+    balance: Hex<U256>,
+    nonce:   Hex<u64>,
+    code:    Bytes,
+    storage: HashMap<Hex<U256>, Hex<U256>>,
+}
+
+/// Outcome of one post state of a test
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    pub name:    String,
+    pub fork:    String,
+    /// Index of the post state in the fork's list
+    pub index:   usize,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}[{}]: ", self.name, self.fork, self.index)?;
+        match &self.outcome {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed(reason) => write!(f, "FAILED {}", reason),
+            Outcome::Skipped(reason) => write!(f, "skipped, {}", reason),
+        }
+    }
+}
+
+/// Run all tests in the JSON of a state test file
+pub fn run(json: &str) -> AnyResult<Vec<TestResult>> {
+    // Going through `Value` lets the hex visitors see strings
+    let value: serde_json::Value = serde_json::from_str(json).context("Parsing state tests")?;
+    let tests: BTreeMap<String, StateTest> =
+        serde_json::from_value(value).context("Parsing state tests")?;
+    let mut results = Vec::new();
+    for (name, test) in &tests {
+        for (fork, posts) in &test.post {
+            for (index, post) in posts.iter().enumerate() {
+                let outcome = run_post(test, fork, post)
+                    .unwrap_or_else(|err| Outcome::Failed(format!("{:#}", err)));
+                results.push(TestResult {
+                    name: name.clone(),
+                    fork: fork.clone(),
+                    index,
+                    outcome,
+                });
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Run the state test files and print the results
+///
+/// Fails if any test failed.
+pub fn run_files(paths: &[impl AsRef<Path>]) -> AnyResult<()> {
+    let mut results = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading {}", path.display()))?;
+        results.extend(run(&json).with_context(|| format!("Running {}", path.display()))?);
+    }
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for result in &results {
+        println!("{}", result);
+        match result.outcome {
+            Outcome::Passed => passed += 1,
+            Outcome::Failed(_) => failed += 1,
+            Outcome::Skipped(_) => skipped += 1,
+        }
+    }
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+    require!(failed == 0, anyhow!("{} state tests failed", failed));
+    Ok(())
+}
+
+/// Hardfork of a fork name used in the tests
+fn hardfork(fork: &str) -> Option<Hardfork> {
+    Some(match fork {
+        "Frontier" => Hardfork::Frontier,
+        "Homestead" => Hardfork::Homestead,
+        "EIP150" => Hardfork::Tangerine,
+        "EIP158" => Hardfork::Spurious,
+        "Byzantium" => Hardfork::Byzantium,
+        "Constantinople" | "ConstantinopleFix" | "Petersburg" => Hardfork::Petersburg,
+        "Istanbul" => Hardfork::Istanbul,
+        "Berlin" => Hardfork::Berlin,
+        "London" => Hardfork::London,
+        _ => return None,
+    })
+}
+
+#[allow(clippy::too_many_lines)]
+fn run_post(test: &StateTest, fork: &str, post: &PostState) -> AnyResult<Outcome> {
+    let hardfork = match hardfork(fork) {
+        Some(hardfork) => hardfork,
+        None => return Ok(Outcome::Skipped(format!("fork {} is not supported", fork))),
+    };
+    if let Some(exception) = &post.expect_exception {
+        return Ok(Outcome::Skipped(format!(
+            "expecting exception {} is not supported",
+            exception
+        )));
+    }
+    let tx = &test.transaction;
+    let gas_price = match (&tx.gas_price, &tx.max_fee_per_gas) {
+        (Some(gas_price), None) => gas_price.as_ref().clone(),
+        _ => return Ok(Outcome::Skipped("EIP-1559 transactions are not supported".into())),
+    };
+    let data = tx
+        .data
+        .get(post.indexes.data)
+        .context("Data index out of range")?;
+    let gas_limit = tx
+        .gas_limit
+        .get(post.indexes.gas)
+        .context("Gas index out of range")?
+        .as_ref();
+    let value = tx
+        .value
+        .get(post.indexes.value)
+        .context("Value index out of range")?
+        .as_ref();
+    let to = if tx.to.is_empty() {
+        Address::default()
+    } else {
+        Address::from(<[u8; 20]>::from_hex(&tx.to).context("Parsing transaction recipient")?)
+    };
+
+    // Write all accounts to the fork, so its state root covers them
+    let mut chain = Fork::from(Empty);
+    for (address, account) in &test.pre {
+        let address = U256::from(address);
+        chain.set_nonce(&address, *account.nonce.as_ref() as usize);
+        chain.set_balance(&address, account.balance.as_ref());
+        chain.set_code(&address, account.code.as_ref());
+        for (slot, value) in &account.storage {
+            chain.set_storage(&address, slot.as_ref(), value.as_ref());
+        }
+    }
+    let secret_key =
+        SecretKey::parse_slice(tx.secret_key.as_ref()).map_err(|err| anyhow!("{:?}", err))?;
+    let sender = DevAccount::new(secret_key).address;
+    let sender_account = U256::from(&sender);
+    require!(
+        chain.nonce(&sender_account) as u64 == *tx.nonce.as_ref(),
+        anyhow!("Nonce does not match the sender")
+    );
+    let base_fee = if hardfork >= Hardfork::London {
+        test.env
+            .current_base_fee
+            .as_ref()
+            .map_or_else(U256::zero, |base_fee| base_fee.as_ref().clone())
+    } else {
+        U256::zero()
+    };
+    require!(
+        gas_price.bits() <= 64,
+        anyhow!("Gas price does not fit 64 bits")
+    );
+    let block = BlockInfo {
+        number: *test.env.current_number.as_ref(),
+        timestamp: *test.env.current_timestamp.as_ref(),
+        base_fee,
+        gas_limit: *test.env.current_gas_limit.as_ref(),
+        coinbase: U256::from(&test.env.current_coinbase),
+        difficulty: test.env.current_difficulty.as_ref().clone(),
+        chain_id: 1,
+    };
+    let transaction = RpcTransaction {
+        transaction: Transaction {
+            nonce: *tx.nonce.as_ref(),
+            gas_price: gas_price.as_u64(),
+            gas_limit: *gas_limit,
+            to,
+            value: value.clone(),
+            data: data.as_ref().to_vec(),
+            ..Transaction::default()
+        },
+        from: sender,
+        ..RpcTransaction::default()
+    };
+    let (result, _) = replay(
+        &mut chain,
+        &block,
+        &transaction,
+        &hardfork.schedule(),
+        &mut (),
+    )?;
+
+    let root = chain.state_root();
+    if &root != post.hash.as_ref() {
+        return Ok(Outcome::Failed(format!(
+            "state root is {} instead of {}",
+            root.to_hex(),
+            post.hash.as_ref().to_hex()
+        )));
+    }
+    let logs = logs_hash(&result.logs);
+    if &logs != post.logs.as_ref() {
+        return Ok(Outcome::Failed(format!(
+            "logs hash is {} instead of {}",
+            logs.to_hex(),
+            post.logs.as_ref().to_hex()
+        )));
+    }
+    Ok(Outcome::Passed)
+}
+
+/// Log as it is RLP encoded in receipts
+#[derive(Serialize)]
+struct RlpLog {
+    address: Address,
+    topics:  Vec<Topic>,
+    #[serde(with = "bytes")]
+    data:    Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct Topic(#[serde(with = "fixed_u256")] U256);
+
+/// Keccak of the RLP encoded list of logs
+fn logs_hash(logs: &[Log]) -> U256 {
+    logs.iter()
+        .map(|log| {
+            RlpLog {
+                address: Address::from(&log.address),
+                topics:  log.topics.iter().cloned().map(Topic).collect(),
+                data:    log.data.clone(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .rlp_hash()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::prelude::assert_eq;
+
+    // The fixtures are written for these tests, not taken from
+    // `ethereum/tests`, see `fixtures/state/README.md`
+
+    fn outcomes(json: &str) -> Vec<(String, Outcome)> {
+        run(json)
+            .unwrap()
+            .into_iter()
+            .map(|result| (format!("{}[{}]", result.fork, result.index), result.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn test_logs_hash() {
+        assert_eq!(
+            logs_hash(&[]),
+            u256h!("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347")
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let skipped = Outcome::Skipped("fork Cancun is not supported".into());
+        assert_eq!(outcomes(include_str!("../fixtures/state/add.json")), vec![
+            ("Berlin[0]".into(), Outcome::Passed),
+            ("Cancun[0]".into(), skipped),
+            ("London[0]".into(), Outcome::Passed),
+            ("London[1]".into(), Outcome::Passed),
+        ]);
+    }
+
+    #[test]
+    fn test_mstore() {
+        assert_eq!(outcomes(include_str!("../fixtures/state/mstore.json")), vec![
+            ("London[0]".into(), Outcome::Passed),
+            ("London[1]".into(), Outcome::Passed),
+        ]);
+    }
+
+    #[test]
+    fn test_log() {
+        assert_eq!(outcomes(include_str!("../fixtures/state/log.json")), vec![(
+            "London[0]".into(),
+            Outcome::Passed
+        )]);
+    }
+
+    /// Run the arithmetic and memory tests of an `ethereum/tests` checkout
+    /// in `ETHEREUM_TESTS`, if set
+    #[test]
+    fn test_ethereum_tests() {
+        let root = match std::env::var_os("ETHEREUM_TESTS") {
+            Some(root) => Path::new(&root).join("GeneralStateTests"),
+            None => return,
+        };
+        for suite in &["stArithmeticTest", "stMemoryTest"] {
+            let mut paths = std::fs::read_dir(root.join(suite))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+                .collect::<Vec<_>>();
+            paths.sort();
+            run_files(&paths).unwrap();
+        }
+    }
+}